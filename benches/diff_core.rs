@@ -0,0 +1,54 @@
+// Throughput of the diff math at the page sizes common DPI settings produce,
+// to catch regressions before raising DPI defaults. Run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use image::DynamicImage;
+
+use lib::benchmark::synthetic_pair;
+use lib::diff_core::{count_changed, fill_change_mask, Pixels};
+use lib::image_utils::{content_bbox, ContentDetection};
+use lib::regions::{change_ratio, find_changed_regions, PIXEL_TOLERANCE};
+
+// Letter pages, in pixels
+const PAGE_SIZES: &[(&str, u32, u32)] = &[("72dpi", 612, 792), ("150dpi", 1275, 1650), ("300dpi", 2550, 3300)];
+
+fn diff_core(c: &mut Criterion) {
+    let mut group = c.benchmark_group("diff_core");
+    for &(label, width, height) in PAGE_SIZES {
+        let (old, new) = synthetic_pair(width, height);
+        group.throughput(Throughput::Elements(width as u64 * height as u64));
+
+        group.bench_with_input(BenchmarkId::new("count_changed", label), &(&old, &new), |b, (old, new)| {
+            b.iter(|| count_changed(Pixels::of(old), Pixels::of(new), PIXEL_TOLERANCE))
+        });
+
+        let mut mask = vec![];
+        group.bench_with_input(BenchmarkId::new("fill_change_mask", label), &(&old, &new), |b, (old, new)| {
+            b.iter(|| fill_change_mask(Pixels::of(old), Pixels::of(new), PIXEL_TOLERANCE, &mut mask))
+        });
+    }
+    group.finish();
+}
+
+fn page_metrics(c: &mut Criterion) {
+    let mut group = c.benchmark_group("page_metrics");
+    for &(label, width, height) in PAGE_SIZES {
+        let (old, new) = synthetic_pair(width, height);
+        let (old, new) = (DynamicImage::ImageRgba8(old), DynamicImage::ImageRgba8(new));
+        group.throughput(Throughput::Elements(width as u64 * height as u64));
+
+        group.bench_with_input(BenchmarkId::new("change_ratio", label), &(&old, &new), |b, (old, new)| {
+            b.iter(|| change_ratio(old, new, PIXEL_TOLERANCE))
+        });
+        group.bench_with_input(BenchmarkId::new("find_changed_regions", label), &(&old, &new), |b, (old, new)| {
+            b.iter(|| find_changed_regions(old, new, PIXEL_TOLERANCE))
+        });
+        group.bench_with_input(BenchmarkId::new("content_bbox", label), &new, |b, new| {
+            b.iter(|| content_bbox(new, &ContentDetection::default()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, diff_core, page_metrics);
+criterion_main!(benches);
@@ -0,0 +1,77 @@
+# Deutsche Meldungen und Berichtsbeschriftungen
+
+## Seitenstatus
+status-unchanged = unverändert
+status-changed = geändert
+status-added = hinzugefügt
+status-removed = entfernt
+status-blank = leer
+status-skipped = übersprungen
+status-approved = genehmigt
+
+## Welche Seiten an einer Position verglichen wurden
+pair-compared = alte Seite { $old } mit neuer Seite { $new }
+pair-inserted = neue Seite { $new } (eingefügt)
+pair-removed = alte Seite { $old } (entfernt)
+
+## Berichte
+report-title = PDF-Vergleich: { $title }
+report-comparing = Vergleich von { $old } mit { $new }
+report-sampled = Stichprobenlauf: { $sampling }
+report-interrupted = Abgebrochener Lauf: nur die vor dem Abbruch verglichenen Seiten werden gezeigt
+report-reordered = Die Seiten wurden umsortiert; jede neue Seite wird mit der alten Seite verglichen, von der sie verschoben wurde
+report-page-list = Seiten mit Unterschieden
+report-table-caption = Status aller verglichenen Seiten
+report-parts = Berichtsseiten
+report-part = Seiten { $first }–{ $last }
+report-part-one = Seite { $page }
+report-theme-toggle = Hell/dunkel
+report-print-page = Drucken
+report-page-heading = Seite { $page } ({ $status })
+report-column-page = Seite
+report-column-compared = Verglichen
+report-column-status = Status
+report-column-changed = Geändert
+report-column-severity = Schweregrad
+report-column-warnings = Warnungen
+report-column-kinds = Art der Änderung
+report-changed-pages = Geänderte Seiten
+report-changed-pages-count = { $changed } von { $total }
+report-blank-pages = Leere Seiten
+report-approved-pages = Genehmigte Seiten
+report-changed-area = Geänderte Fläche
+report-square-inches = { $area } Quadratzoll
+report-no-differences = Keine Unterschiede.
+report-warning = Warnung: { $message }
+report-rendered-with = Gerendert mit { $pdfium }
+report-run = Verglichen von { $started } bis { $finished } mit pdf_diff { $version }
+report-run-host = Rechner: { $host }
+report-run-arguments = Argumente: { $arguments }
+report-change-regions-one = { $status }, { $percent } % der Pixel in 1 Bereich
+report-change-regions-other = { $status }, { $percent } % der Pixel in { $regions } Bereichen
+report-kind-count = { $count } { $kind }
+kind-text = Text
+kind-image = Bild
+kind-graphic = Grafik
+report-image-old = Seite { $page }, alte Fassung
+report-image-new = Seite { $page }, neue Fassung
+report-image-source-old = Seite { $number } der alten PDF
+report-image-source-new = Seite { $number } der neuen PDF
+report-slider = Vorher-nachher-Vergleich von Seite { $page }
+report-slider-position = Anteil von Seite { $page }, der die alte Fassung zeigt, in Prozent
+
+## Terminalausgabe
+table-page = Seite
+table-status = Status
+table-diff = Diff %
+table-no-pages = Keine Seiten verglichen
+cli-page-warning = Warnung: Seite { $page }: { $message }
+cli-pages-reordered = Seiten umsortiert: { $order }
+cli-page-moved = neue Seite { $new } ist alte Seite { $old }
+cli-diff-images-saved = Diff-Bilder in '{ $dir }' gespeichert
+cli-report-saved = { $kind }-Bericht in { $path } gespeichert
+cli-contact-sheet-saved = Übersichtsbild in { $path } gespeichert
+cli-summary-saved = Zusammenfassung in { $path } gespeichert
+cli-consolidated-report-saved = Gesamtbericht in { $path } gespeichert
+cli-compared-documents = { $total } Dokumentpaare verglichen: { $changed } mit Unterschieden, { $failed } fehlgeschlagen
+cli-changed-pages = Geänderte Seiten: { $changed } von { $total }, leere Seiten: { $blank }, geänderte Fläche: { $area } Quadratzoll
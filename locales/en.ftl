@@ -0,0 +1,78 @@
+# English messages and report labels. Every catalog defines the same ids;
+# `{ $name }` is replaced with the named value.
+
+## Page statuses
+status-unchanged = unchanged
+status-changed = changed
+status-added = added
+status-removed = removed
+status-blank = blank
+status-skipped = skipped
+status-approved = approved
+
+## Which pages were compared at a position
+pair-compared = old page { $old } vs new page { $new }
+pair-inserted = new page { $new } (inserted)
+pair-removed = old page { $old } (removed)
+
+## Reports
+report-title = PDF diff: { $title }
+report-comparing = Comparing { $old } with { $new }
+report-sampled = Sampled run: { $sampling }
+report-interrupted = Interrupted run: only pages compared before cancelling are shown
+report-reordered = Pages were reordered; each new page is compared with the old page it was moved from
+report-page-list = Pages with differences
+report-table-caption = Status of every compared page
+report-parts = Report pages
+report-part = Pages { $first }–{ $last }
+report-part-one = Page { $page }
+report-theme-toggle = Light/dark
+report-print-page = Print
+report-page-heading = Page { $page } ({ $status })
+report-column-page = Page
+report-column-compared = Compared
+report-column-status = Status
+report-column-changed = Changed
+report-column-severity = Severity
+report-column-warnings = Warnings
+report-column-kinds = Kind of change
+report-changed-pages = Changed pages
+report-changed-pages-count = { $changed } of { $total }
+report-blank-pages = Blank pages
+report-approved-pages = Approved pages
+report-changed-area = Changed area
+report-square-inches = { $area } sq in
+report-no-differences = No differences.
+report-warning = Warning: { $message }
+report-rendered-with = Rendered with { $pdfium }
+report-run = Compared from { $started } to { $finished } with pdf_diff { $version }
+report-run-host = Host: { $host }
+report-run-arguments = Arguments: { $arguments }
+report-change-regions-one = { $status }, { $percent }% of pixels in 1 region
+report-change-regions-other = { $status }, { $percent }% of pixels in { $regions } regions
+report-kind-count = { $count } { $kind }
+kind-text = text
+kind-image = image
+kind-graphic = graphic
+report-image-old = Page { $page }, old version
+report-image-new = Page { $page }, new version
+report-image-source-old = page { $number } of the old PDF
+report-image-source-new = page { $number } of the new PDF
+report-slider = Before/after comparison of page { $page }
+report-slider-position = Percentage of page { $page } showing the old version
+
+## Terminal output
+table-page = Page
+table-status = Status
+table-diff = Diff %
+table-no-pages = No pages compared
+cli-page-warning = Warning: page { $page }: { $message }
+cli-pages-reordered = Pages reordered: { $order }
+cli-page-moved = new page { $new } is old page { $old }
+cli-diff-images-saved = Diff images saved to '{ $dir }'
+cli-report-saved = { $kind } report saved to { $path }
+cli-contact-sheet-saved = Contact sheet saved to { $path }
+cli-summary-saved = Summary saved to { $path }
+cli-consolidated-report-saved = Consolidated report saved to { $path }
+cli-compared-documents = Compared { $total } document pairs: { $changed } with differences, { $failed } failed
+cli-changed-pages = Changed pages: { $changed } of { $total }, blank pages: { $blank }, changed area: { $area } sq in
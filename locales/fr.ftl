@@ -0,0 +1,77 @@
+# Messages et libellés des rapports en français
+
+## Statuts des pages
+status-unchanged = inchangée
+status-changed = modifiée
+status-added = ajoutée
+status-removed = supprimée
+status-blank = vide
+status-skipped = ignorée
+status-approved = approuvée
+
+## Pages comparées à une position
+pair-compared = ancienne page { $old } et nouvelle page { $new }
+pair-inserted = nouvelle page { $new } (insérée)
+pair-removed = ancienne page { $old } (supprimée)
+
+## Rapports
+report-title = Comparaison PDF : { $title }
+report-comparing = Comparaison de { $old } avec { $new }
+report-sampled = Exécution par échantillon : { $sampling }
+report-interrupted = Exécution interrompue : seules les pages comparées avant l'annulation sont affichées
+report-reordered = Les pages ont été réordonnées ; chaque nouvelle page est comparée à l'ancienne page dont elle provient
+report-page-list = Pages avec des différences
+report-table-caption = Statut de chaque page comparée
+report-parts = Pages du rapport
+report-part = Pages { $first } à { $last }
+report-part-one = Page { $page }
+report-theme-toggle = Clair/sombre
+report-print-page = Imprimer
+report-page-heading = Page { $page } ({ $status })
+report-column-page = Page
+report-column-compared = Comparées
+report-column-status = Statut
+report-column-changed = Modifié
+report-column-severity = Gravité
+report-column-warnings = Avertissements
+report-column-kinds = Nature du changement
+report-changed-pages = Pages modifiées
+report-changed-pages-count = { $changed } sur { $total }
+report-blank-pages = Pages vides
+report-approved-pages = Pages approuvées
+report-changed-area = Surface modifiée
+report-square-inches = { $area } po²
+report-no-differences = Aucune différence.
+report-warning = Avertissement : { $message }
+report-rendered-with = Rendu avec { $pdfium }
+report-run = Comparé de { $started } à { $finished } avec pdf_diff { $version }
+report-run-host = Machine : { $host }
+report-run-arguments = Arguments : { $arguments }
+report-change-regions-one = { $status }, { $percent } % des pixels dans 1 zone
+report-change-regions-other = { $status }, { $percent } % des pixels dans { $regions } zones
+report-kind-count = { $count } { $kind }
+kind-text = texte
+kind-image = image
+kind-graphic = graphique
+report-image-old = Page { $page }, ancienne version
+report-image-new = Page { $page }, nouvelle version
+report-image-source-old = page { $number } de l'ancien PDF
+report-image-source-new = page { $number } du nouveau PDF
+report-slider = Comparaison avant/après de la page { $page }
+report-slider-position = Pourcentage de la page { $page } montrant l'ancienne version
+
+## Sortie du terminal
+table-page = Page
+table-status = Statut
+table-diff = Diff %
+table-no-pages = Aucune page comparée
+cli-page-warning = Avertissement : page { $page } : { $message }
+cli-pages-reordered = Pages réordonnées : { $order }
+cli-page-moved = la nouvelle page { $new } est l'ancienne page { $old }
+cli-diff-images-saved = Images de différences enregistrées dans '{ $dir }'
+cli-report-saved = Rapport { $kind } enregistré dans { $path }
+cli-contact-sheet-saved = Planche contact enregistrée dans { $path }
+cli-summary-saved = Résumé enregistré dans { $path }
+cli-consolidated-report-saved = Rapport consolidé enregistré dans { $path }
+cli-compared-documents = { $total } paires de documents comparées : { $changed } avec des différences, { $failed } en échec
+cli-changed-pages = Pages modifiées : { $changed } sur { $total }, pages vides : { $blank }, surface modifiée : { $area } po²
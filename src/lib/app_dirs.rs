@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+
+// Subdirectory of the platform's configuration and data directories
+const APP_DIR: &str = "diff-pdf";
+
+// Per-user configuration file, for settings such as profiles that apply to
+// every project
+pub const USER_CONFIG_FILE: &str = "config.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Platform {
+    // Linux and other Unix systems, following the XDG base directory spec
+    Xdg,
+    MacOs,
+    Windows,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Config,
+    Data,
+}
+
+fn current_platform() -> Platform {
+    if cfg!(target_os = "macos") {
+        Platform::MacOs
+    } else if cfg!(windows) {
+        Platform::Windows
+    } else {
+        Platform::Xdg
+    }
+}
+
+// Directory of `kind` for this tool, given a way to read environment
+// variables. A `DIFF_PDF_*_DIR` variable overrides the platform's location.
+// Relative paths are ignored, as the XDG spec asks, so the result doesn't
+// depend on the working directory.
+fn resolve(kind: Kind, platform: Platform, env: &dyn Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let dir = |name: &str| env(name).map(PathBuf::from).filter(|path| path.is_absolute());
+    let override_var = match kind {
+        Kind::Config => "DIFF_PDF_CONFIG_DIR",
+        Kind::Data => "DIFF_PDF_DATA_DIR",
+    };
+    if let Some(path) = dir(override_var) {
+        return Some(path);
+    }
+
+    let base = match (platform, kind) {
+        (Platform::Xdg, Kind::Config) => dir("XDG_CONFIG_HOME").or_else(|| Some(dir("HOME")?.join(".config"))),
+        (Platform::Xdg, Kind::Data) => dir("XDG_DATA_HOME").or_else(|| Some(dir("HOME")?.join(".local/share"))),
+        (Platform::MacOs, _) => Some(dir("HOME")?.join("Library/Application Support")),
+        (Platform::Windows, Kind::Config) => dir("APPDATA"),
+        (Platform::Windows, Kind::Data) => dir("LOCALAPPDATA"),
+    };
+    Some(base?.join(APP_DIR))
+}
+
+fn process_env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+// Per-user configuration directory: `DIFF_PDF_CONFIG_DIR`, else
+// `$XDG_CONFIG_HOME/diff-pdf` (`~/.config/diff-pdf`) on Linux,
+// `~/Library/Application Support/diff-pdf` on macOS and `%APPDATA%\diff-pdf`
+// on Windows
+pub fn config_dir() -> Option<PathBuf> {
+    resolve(Kind::Config, current_platform(), &process_env)
+}
+
+// Per-user data directory, where a PDFium library can be installed:
+// `DIFF_PDF_DATA_DIR`, else `$XDG_DATA_HOME/diff-pdf`
+// (`~/.local/share/diff-pdf`) on Linux, `~/Library/Application
+// Support/diff-pdf` on macOS and `%LOCALAPPDATA%\diff-pdf` on Windows
+pub fn data_dir() -> Option<PathBuf> {
+    resolve(Kind::Data, current_platform(), &process_env)
+}
+
+// The per-user configuration file, if there is one
+pub fn user_config_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(USER_CONFIG_FILE)).filter(|path| path.is_file())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn env(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        move |name| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string())
+    }
+
+    #[test]
+    fn test_resolve() {
+        let home = env(&[("HOME", "/home/ada")]);
+        assert_eq!(resolve(Kind::Config, Platform::Xdg, &home), Some(PathBuf::from("/home/ada/.config/diff-pdf")));
+        assert_eq!(resolve(Kind::Data, Platform::Xdg, &home), Some(PathBuf::from("/home/ada/.local/share/diff-pdf")));
+        assert_eq!(
+            resolve(Kind::Config, Platform::MacOs, &home),
+            Some(PathBuf::from("/home/ada/Library/Application Support/diff-pdf"))
+        );
+
+        let xdg = env(&[("HOME", "/home/ada"), ("XDG_CONFIG_HOME", "/etc/ada"), ("XDG_DATA_HOME", "relative")]);
+        assert_eq!(resolve(Kind::Config, Platform::Xdg, &xdg), Some(PathBuf::from("/etc/ada/diff-pdf")));
+        assert_eq!(
+            resolve(Kind::Data, Platform::Xdg, &xdg),
+            Some(PathBuf::from("/home/ada/.local/share/diff-pdf")),
+            "Relative XDG directories are ignored"
+        );
+
+        let overridden = env(&[("HOME", "/home/ada"), ("DIFF_PDF_DATA_DIR", "/opt/diff-pdf")]);
+        assert_eq!(resolve(Kind::Data, Platform::Xdg, &overridden), Some(PathBuf::from("/opt/diff-pdf")));
+        assert_eq!(resolve(Kind::Data, Platform::Windows, &overridden), Some(PathBuf::from("/opt/diff-pdf")));
+
+        assert_eq!(resolve(Kind::Config, Platform::Xdg, &env(&[])), None);
+        assert_eq!(resolve(Kind::Config, Platform::Windows, &home), None);
+    }
+}
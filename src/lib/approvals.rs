@@ -0,0 +1,169 @@
+use std::error::Error;
+use std::path::Path;
+use std::sync::Mutex;
+
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+use crate::image_utils::page_changed;
+
+// Serializes read-modify-write cycles of approval files between comparisons
+// running in parallel
+static APPROVALS_LOCK: Mutex<()> = Mutex::new(());
+
+// A page difference someone has accepted. It stays accepted for as long as the
+// page renders exactly as it did when approved, on both sides.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Approval {
+    // Name of the comparison (`<old>_vs_<new>` or the manifest's output name)
+    pub document: String,
+    // 1-based page number
+    pub page: usize,
+    pub fingerprint: String,
+}
+
+// FNV-1a, which unlike std's hasher is guaranteed to stay the same between
+// releases, so fingerprints written today still match next year
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+fn fnv1a(bytes: impl IntoIterator<Item = u8>, mut hash: u64) -> u64 {
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn render_fingerprint(img: Option<&DynamicImage>) -> String {
+    match img {
+        Some(img) => {
+            let rgba = img.to_rgba8();
+            let (width, height) = rgba.dimensions();
+            let size = fnv1a(format!("{}x{}", width, height).bytes(), FNV_OFFSET);
+            let hash = fnv1a(rgba.as_raw().iter().copied(), size);
+            format!("{:016x}", hash)
+        }
+        None => "none".to_string(),
+    }
+}
+
+// Fingerprint of a page's old and new renders
+pub fn pair_fingerprint(old_image: Option<&DynamicImage>, new_image: Option<&DynamicImage>) -> String {
+    format!("{}:{}", render_fingerprint(old_image), render_fingerprint(new_image))
+}
+
+// Approvals stored in `path`; a missing file has none
+pub fn load_approvals(path: &Path) -> Result<Vec<Approval>, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let text = std::fs::read_to_string(path)?;
+    serde_json::from_str(&text).map_err(|e| format!("Invalid approvals file {}: {}", path.display(), e).into())
+}
+
+// 1-based numbers of the changed pages of `document` whose difference was approved
+pub fn approved_pages(
+    approvals: &[Approval],
+    document: &str,
+    images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+) -> Vec<usize> {
+    images
+        .iter()
+        .enumerate()
+        .filter(|(_, (old, new))| page_changed(old.as_ref(), new.as_ref()))
+        .filter(|(index, (old, new))| {
+            let fingerprint = pair_fingerprint(old.as_ref(), new.as_ref());
+            approvals
+                .iter()
+                .any(|a| a.document == document && a.page == index + 1 && a.fingerprint == fingerprint)
+        })
+        .map(|(index, _)| index + 1)
+        .collect()
+}
+
+// Approve every changed page of `document`, replacing its earlier approvals
+// in the file at `path`. Returns the number of pages approved.
+pub fn approve_changes(
+    path: &Path,
+    document: &str,
+    images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+) -> Result<usize, Box<dyn Error>> {
+    let changed: Vec<usize> = images
+        .iter()
+        .enumerate()
+        .filter(|(_, (old, new))| page_changed(old.as_ref(), new.as_ref()))
+        .map(|(index, _)| index + 1)
+        .collect();
+    approve_pages(path, document, images, &changed)?;
+    Ok(changed.len())
+}
+
+// Approve the given 1-based pages of `document` as they render now, replacing
+// its earlier approvals in the file at `path`
+pub fn approve_pages(
+    path: &Path,
+    document: &str,
+    images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+    pages: &[usize],
+) -> Result<(), Box<dyn Error>> {
+    let _guard = APPROVALS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut approvals = load_approvals(path)?;
+    approvals.retain(|approval| approval.document != document);
+
+    for &page in pages {
+        let Some((old, new)) = images.get(page - 1) else {
+            continue;
+        };
+        approvals.push(Approval {
+            document: document.to_string(),
+            page,
+            fingerprint: pair_fingerprint(old.as_ref(), new.as_ref()),
+        });
+    }
+
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&approvals)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::create_solid_color_image;
+    use image::Rgba;
+    use std::fs;
+
+    #[test]
+    fn test_pair_fingerprint_is_stable() {
+        let solid = |color| Some(create_solid_color_image(8, 8, Rgba(color)));
+        let red = solid([255, 0, 0, 255]);
+        assert_eq!(pair_fingerprint(red.as_ref(), None), pair_fingerprint(red.as_ref(), None));
+        assert_ne!(pair_fingerprint(red.as_ref(), None), pair_fingerprint(None, red.as_ref()));
+        assert_eq!(pair_fingerprint(None, None), "none:none");
+        assert_eq!(fnv1a(*b"a", FNV_OFFSET), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_approve_changes_then_match() {
+        let solid = |color| Some(create_solid_color_image(8, 8, Rgba(color)));
+        let test_dir = "test_output_approvals";
+        let path = Path::new(test_dir).join("approvals.json");
+        fs::remove_dir_all(test_dir).ok();
+
+        let (red, green, blue) = (solid([255, 0, 0, 255]), solid([0, 255, 0, 255]), solid([0, 0, 255, 255]));
+        let images = vec![(red.clone(), red.clone()), (red.clone(), green.clone()), (None, None)];
+
+        assert_eq!(approve_changes(&path, "a_vs_b", &images).unwrap(), 1);
+        let approvals = load_approvals(&path).unwrap();
+        assert_eq!(approved_pages(&approvals, "a_vs_b", &images), vec![2]);
+        assert!(approved_pages(&approvals, "a_vs_c", &images).is_empty());
+
+        // A further change to an approved page needs a new approval
+        let changed_again = vec![(red.clone(), red.clone()), (red, blue)];
+        assert!(approved_pages(&approvals, "a_vs_b", &changed_again).is_empty());
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+}
@@ -0,0 +1,420 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+use crate::regions::{change_ratio, PIXEL_TOLERANCE};
+
+// Rendering resolution used to rank baseline candidates; coarse is plenty
+// for picking the closest document and keeps large baseline sets fast
+pub const MATCH_DPI: f32 = 50.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ComparisonMode {
+    /// Compare the first document against each of the others
+    AgainstFirst,
+    /// Compare every document against every other document
+    Pairwise,
+}
+
+// One comparison between two input documents, identified by their paths
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Comparison {
+    pub old_pdf: String,
+    pub new_pdf: String,
+    // Output directory name relative to the run's output directory
+    pub name: String,
+    // Per-comparison overrides of the command line settings
+    pub dpi: Option<f32>,
+    pub sensitivity: Option<f32>,
+}
+
+// A row of a batch manifest. Options left out fall back to the command line.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ManifestEntry {
+    pub old: String,
+    pub new: String,
+    #[serde(default)]
+    pub output: Option<String>,
+    #[serde(default)]
+    pub dpi: Option<f32>,
+    #[serde(default)]
+    pub sensitivity: Option<f32>,
+}
+
+fn stem(path: &str) -> &str {
+    Path::new(path).file_stem().and_then(|s| s.to_str()).unwrap_or("document")
+}
+
+// Work out which document pairs to compare. `documents[0]` is the old/baseline
+// document. Each comparison gets a unique directory name built from file stems.
+pub fn plan_comparisons(documents: &[String], mode: ComparisonMode) -> Vec<Comparison> {
+    let mut pairs = vec![];
+    match mode {
+        ComparisonMode::AgainstFirst => {
+            for j in 1..documents.len() {
+                pairs.push((0, j));
+            }
+        }
+        ComparisonMode::Pairwise => {
+            for i in 0..documents.len() {
+                for j in i + 1..documents.len() {
+                    pairs.push((i, j));
+                }
+            }
+        }
+    }
+
+    let mut used = HashSet::new();
+    pairs
+        .into_iter()
+        .map(|(i, j)| Comparison {
+            old_pdf: documents[i].clone(),
+            new_pdf: documents[j].clone(),
+            name: unique_name(&mut used, &documents[i], &documents[j], None),
+            dpi: None,
+            sensitivity: None,
+        })
+        .collect()
+}
+
+// Directory name for a comparison: the explicit name if given, otherwise
+// `<old>_vs_<new>`, with a numeric suffix when it has been used already
+fn unique_name(used: &mut HashSet<String>, old_pdf: &str, new_pdf: &str, explicit: Option<&str>) -> String {
+    let base = match explicit {
+        Some(name) => name.to_string(),
+        None => format!("{}_vs_{}", stem(old_pdf), stem(new_pdf)),
+    };
+    let mut name = base.clone();
+    let mut suffix = 2;
+    while !used.insert(name.clone()) {
+        name = format!("{}_{}", base, suffix);
+        suffix += 1;
+    }
+    name
+}
+
+// Split one CSV line into fields, honouring double-quoted fields with "" escapes
+fn parse_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field).trim().to_string()),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+// Parse a CSV manifest. The header row names the columns; `old` and `new`
+// are required, `output`, `dpi` and `sensitivity` are optional.
+pub fn parse_csv_manifest(text: &str) -> Result<Vec<ManifestEntry>, Box<dyn Error>> {
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty() && !l.starts_with('#'));
+    let header = parse_csv_line(lines.next().ok_or("Manifest is empty")?);
+    let column = |name: &str| header.iter().position(|h| h.eq_ignore_ascii_case(name));
+
+    let old_column = column("old").ok_or("Manifest is missing an 'old' column")?;
+    let new_column = column("new").ok_or("Manifest is missing a 'new' column")?;
+    let (output_column, dpi_column, sensitivity_column) = (column("output"), column("dpi"), column("sensitivity"));
+
+    let mut entries = vec![];
+    for (row, line) in lines.enumerate() {
+        let fields = parse_csv_line(line);
+        let get = |index: Option<usize>| index.and_then(|i| fields.get(i)).filter(|f| !f.is_empty()).cloned();
+        let number = |index: Option<usize>, name: &str| -> Result<Option<f32>, Box<dyn Error>> {
+            get(index)
+                .map(|value| value.parse::<f32>().map_err(|_| format!("Invalid {} '{}' in manifest row {}", name, value, row + 1).into()))
+                .transpose()
+        };
+
+        entries.push(ManifestEntry {
+            old: get(Some(old_column)).ok_or(format!("Missing old PDF in manifest row {}", row + 1))?,
+            new: get(Some(new_column)).ok_or(format!("Missing new PDF in manifest row {}", row + 1))?,
+            output: get(output_column),
+            dpi: number(dpi_column, "dpi")?,
+            sensitivity: number(sensitivity_column, "sensitivity")?,
+        });
+    }
+
+    Ok(entries)
+}
+
+// Load a manifest from a `.json` file (an array of entries) or a CSV file
+pub fn load_manifest(path: &Path) -> Result<Vec<ManifestEntry>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)?;
+    let is_json = path.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("json"));
+
+    if is_json {
+        Ok(serde_json::from_str(&text)?)
+    } else {
+        parse_csv_manifest(&text)
+    }
+}
+
+// Turn manifest entries into comparisons with unique output directory names
+pub fn manifest_comparisons(entries: &[ManifestEntry]) -> Vec<Comparison> {
+    let mut used = HashSet::new();
+    entries
+        .iter()
+        .map(|entry| Comparison {
+            old_pdf: entry.old.clone(),
+            new_pdf: entry.new.clone(),
+            name: unique_name(&mut used, &entry.old, &entry.new, entry.output.as_deref()),
+            dpi: entry.dpi,
+            sensitivity: entry.sensitivity,
+        })
+        .collect()
+}
+
+// All PDF files directly inside `dir`, sorted by path
+pub fn list_pdfs(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut pdfs = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_pdf = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("pdf"));
+        if path.is_file() && is_pdf {
+            pdfs.push(path.to_string_lossy().into_owned());
+        }
+    }
+    pdfs.sort();
+    Ok(pdfs)
+}
+
+// How different two documents are, from 0.0 (identical) to 1.0: the mean
+// changed-pixel ratio over all pages, with unpaired pages counting as fully changed
+pub fn document_distance(images: &[(Option<DynamicImage>, Option<DynamicImage>)]) -> f64 {
+    if images.is_empty() {
+        return 0.0;
+    }
+
+    let total: f64 = images
+        .iter()
+        .map(|pair| match pair {
+            (Some(old), Some(new)) => change_ratio(old, new, PIXEL_TOLERANCE),
+            (None, None) => 0.0,
+            _ => 1.0,
+        })
+        .sum();
+
+    total / images.len() as f64
+}
+
+// Make `new_pdf` the baseline: it replaces the file at `target`, or is copied
+// into `target` under its own name when that is a directory. Returns the path
+// of the baseline written.
+pub fn approve_baseline(new_pdf: &Path, target: &Path) -> std::io::Result<PathBuf> {
+    let destination = if target.is_dir() {
+        let name = new_pdf
+            .file_name()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "new PDF path has no file name"))?;
+        target.join(name)
+    } else {
+        target.to_path_buf()
+    };
+
+    std::fs::copy(new_pdf, &destination)?;
+    Ok(destination)
+}
+
+// Resolve a `--jobs` value, where 0 means one worker per available CPU
+pub fn effective_jobs(jobs: usize) -> usize {
+    match jobs {
+        0 => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        n => n,
+    }
+}
+
+// Apply `f` to every item using up to `jobs` worker threads pulling from a
+// shared queue. Results are returned in the order of `items`.
+pub fn run_parallel<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers = jobs.clamp(1, items.len().max(1));
+    if workers == 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(item) = items.get(index) else {
+                        break;
+                    };
+                    let result = f(item);
+                    results.lock().unwrap()[index] = Some(result);
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every item is processed by a worker"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+    use std::fs;
+
+    fn docs(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_plan_against_first() {
+        let plan = plan_comparisons(&docs(&["a.pdf", "b.pdf", "c.pdf"]), ComparisonMode::AgainstFirst);
+        let names: Vec<_> = plan.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["a_vs_b", "a_vs_c"]);
+    }
+
+    #[test]
+    fn test_plan_pairwise() {
+        let plan = plan_comparisons(&docs(&["a.pdf", "b.pdf", "c.pdf"]), ComparisonMode::Pairwise);
+        let names: Vec<_> = plan.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["a_vs_b", "a_vs_c", "b_vs_c"]);
+    }
+
+    #[test]
+    fn test_plan_deduplicates_names() {
+        let plan = plan_comparisons(&docs(&["old/a.pdf", "x/b.pdf", "y/b.pdf"]), ComparisonMode::AgainstFirst);
+        assert_eq!(plan[0].name, "a_vs_b");
+        assert_eq!(plan[1].name, "a_vs_b_2");
+        assert_eq!(plan[1].new_pdf, "y/b.pdf");
+    }
+
+    #[test]
+    fn test_plan_single_document() {
+        assert!(plan_comparisons(&docs(&["a.pdf"]), ComparisonMode::Pairwise).is_empty());
+    }
+
+    #[test]
+    fn test_parse_csv_manifest() {
+        let text = "old,new,dpi,output\n# comment\na.pdf,b.pdf,150,\n\"c, d.pdf\",e.pdf,,custom\n";
+        let entries = parse_csv_manifest(text).expect("manifest should parse");
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].old, "a.pdf");
+        assert_eq!(entries[0].dpi, Some(150.0));
+        assert_eq!(entries[0].output, None);
+        assert_eq!(entries[1].old, "c, d.pdf");
+        assert_eq!(entries[1].dpi, None);
+        assert_eq!(entries[1].output.as_deref(), Some("custom"));
+    }
+
+    #[test]
+    fn test_parse_csv_manifest_errors() {
+        assert!(parse_csv_manifest("").is_err());
+        assert!(parse_csv_manifest("old,dpi\na.pdf,1").is_err());
+        assert!(parse_csv_manifest("old,new,dpi\na.pdf,b.pdf,high").is_err());
+    }
+
+    #[test]
+    fn test_load_json_manifest() {
+        let test_dir = "test_output_manifest";
+        fs::create_dir_all(test_dir).unwrap();
+        let path = format!("{}/manifest.json", test_dir);
+        fs::write(&path, r#"[{"old": "a.pdf", "new": "b.pdf", "sensitivity": 0.05}, {"old": "a.pdf", "new": "b.pdf"}]"#).unwrap();
+
+        let entries = load_manifest(Path::new(&path)).expect("manifest should load");
+        assert_eq!(entries[0].sensitivity, Some(0.05));
+
+        let comparisons = manifest_comparisons(&entries);
+        assert_eq!(comparisons[0].name, "a_vs_b");
+        assert_eq!(comparisons[1].name, "a_vs_b_2");
+        assert_eq!(comparisons[0].sensitivity, Some(0.05));
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_document_distance() {
+        let white = DynamicImage::ImageRgba8(RgbaImage::from_pixel(10, 10, Rgba([255, 255, 255, 255])));
+        let black = DynamicImage::ImageRgba8(RgbaImage::from_pixel(10, 10, Rgba([0, 0, 0, 255])));
+
+        assert_eq!(document_distance(&[]), 0.0);
+        assert_eq!(document_distance(&[(Some(white.clone()), Some(white.clone()))]), 0.0);
+        assert_eq!(document_distance(&[(Some(white.clone()), Some(black))]), 1.0);
+        assert_eq!(document_distance(&[(Some(white.clone()), Some(white.clone())), (None, Some(white))]), 0.5);
+    }
+
+    #[test]
+    fn test_list_pdfs() {
+        let test_dir = "test_output_list_pdfs";
+        fs::create_dir_all(format!("{}/nested.pdf", test_dir)).unwrap();
+        fs::write(format!("{}/b.pdf", test_dir), "").unwrap();
+        fs::write(format!("{}/a.PDF", test_dir), "").unwrap();
+        fs::write(format!("{}/notes.txt", test_dir), "").unwrap();
+
+        let pdfs = list_pdfs(Path::new(test_dir)).unwrap();
+        assert_eq!(pdfs, vec![format!("{}/a.PDF", test_dir), format!("{}/b.pdf", test_dir)]);
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_approve_baseline() {
+        let test_dir = "test_output_approve";
+        fs::create_dir_all(format!("{}/baselines", test_dir)).unwrap();
+        let new_pdf = format!("{}/report.pdf", test_dir);
+        fs::write(&new_pdf, "v2").unwrap();
+        fs::write(format!("{}/old.pdf", test_dir), "v1").unwrap();
+
+        let copied = approve_baseline(Path::new(&new_pdf), Path::new(&format!("{}/baselines", test_dir))).unwrap();
+        assert_eq!(copied, PathBuf::from(format!("{}/baselines/report.pdf", test_dir)));
+        assert_eq!(fs::read_to_string(copied).unwrap(), "v2");
+
+        let replaced = approve_baseline(Path::new(&new_pdf), Path::new(&format!("{}/old.pdf", test_dir))).unwrap();
+        assert_eq!(fs::read_to_string(replaced).unwrap(), "v2");
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_run_parallel_preserves_order() {
+        let items: Vec<u64> = (0..50).collect();
+        let results = run_parallel(&items, 4, |n| {
+            thread::sleep(std::time::Duration::from_millis(50 - n));
+            n * 2
+        });
+        assert_eq!(results, items.iter().map(|n| n * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_run_parallel_single_job_and_empty() {
+        assert_eq!(run_parallel(&[1, 2, 3], 1, |n| n + 1), vec![2, 3, 4]);
+        assert!(run_parallel(&[] as &[i32], 8, |n| *n).is_empty());
+        assert!(effective_jobs(0) >= 1);
+        assert_eq!(effective_jobs(3), 3);
+    }
+}
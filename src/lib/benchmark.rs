@@ -0,0 +1,90 @@
+use std::fmt;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+// Work done by one phase of a comparison and how long it took, for `--benchmark`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Throughput {
+    pub pages: usize,
+    pub pixels: u64,
+    pub seconds: f64,
+}
+
+impl Throughput {
+    pub fn pages_per_second(&self) -> f64 {
+        if self.seconds > 0.0 { self.pages as f64 / self.seconds } else { 0.0 }
+    }
+
+    pub fn megapixels_per_second(&self) -> f64 {
+        if self.seconds > 0.0 { self.pixels as f64 / 1e6 / self.seconds } else { 0.0 }
+    }
+}
+
+impl fmt::Display for Throughput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} pages, {:.1} MPix in {:.3}s ({:.2} pages/sec, {:.1} MPix/sec)",
+            self.pages,
+            self.pixels as f64 / 1e6,
+            self.seconds,
+            self.pages_per_second(),
+            self.megapixels_per_second()
+        )
+    }
+}
+
+// Pages with a render and the pixels compared for them, over the union of
+// both renders' sizes
+pub fn compared_pixels(images: &[(Option<DynamicImage>, Option<DynamicImage>)]) -> (usize, u64) {
+    let mut pages = 0;
+    let mut pixels = 0;
+    for (old_image, new_image) in images {
+        let sizes = old_image.iter().chain(new_image).map(|image| (image.width(), image.height()));
+        let Some((width, height)) = sizes.reduce(|(w1, h1), (w2, h2)| (w1.max(w2), h1.max(h2))) else {
+            continue;
+        };
+        pages += 1;
+        pixels += width as u64 * height as u64;
+    }
+    (pages, pixels)
+}
+
+// A page-like pair of renders for benchmarks: black text lines on white, with
+// the new side's lines shifted on every tenth line so a small share differs
+pub fn synthetic_pair(width: u32, height: u32) -> (RgbaImage, RgbaImage) {
+    let page = |shift: u32| {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let line = y / 24;
+            let offset = if line % 10 == 9 { shift } else { 0 };
+            let inked = y % 24 < 12 && (x + offset) % 16 < 10 && x > width / 10 && x < width - width / 10;
+            if inked { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) }
+        })
+    };
+    (page(0), page(5))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throughput() {
+        let throughput = Throughput {
+            pages: 4,
+            pixels: 8_000_000,
+            seconds: 2.0,
+        };
+        assert_eq!(throughput.pages_per_second(), 2.0);
+        assert_eq!(throughput.megapixels_per_second(), 4.0);
+        assert_eq!(throughput.to_string(), "4 pages, 8.0 MPix in 2.000s (2.00 pages/sec, 4.0 MPix/sec)");
+        assert_eq!(Throughput { seconds: 0.0, ..throughput }.pages_per_second(), 0.0);
+    }
+
+    #[test]
+    fn test_compared_pixels() {
+        let page = |width, height| Some(DynamicImage::ImageRgba8(RgbaImage::new(width, height)));
+        let images = vec![(page(10, 20), page(12, 10)), (None, None), (None, page(5, 5))];
+        assert_eq!(compared_pixels(&images), (2, 12 * 20 + 25));
+    }
+}
@@ -0,0 +1,211 @@
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use image::DynamicImage;
+
+use crate::image_utils::{save_png_with, PngCompression, PngOptions};
+
+// Scale of images saved over budget with `OverBudget::Downscale`
+const DOWNSCALE: f32 = 0.5;
+
+// Levels per color channel left by `OverBudget::Lossy`
+const LOSSY_LEVELS: u16 = 16;
+
+// A number of bytes, as given to `--max-output-size`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize {
+    pub bytes: u64,
+}
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    // Accepts a number with an optional unit: B, KB, MB, GB and TB count in
+    // powers of 1000, KiB, MiB, GiB and TiB in powers of 1024
+    fn from_str(value: &str) -> Result<ByteSize, String> {
+        let invalid = || format!("Invalid size '{}', expected a number with a unit like '500MB', '1.5GB' or '64MiB'", value);
+        let split = value.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(value.len());
+        let (number, unit) = value.split_at(split);
+        let number: f64 = number.trim().parse().map_err(|_| invalid())?;
+        let bytes_per_unit: f64 = match unit.to_ascii_lowercase().as_str() {
+            "" | "b" => 1.0,
+            "kb" => 1e3,
+            "mb" => 1e6,
+            "gb" => 1e9,
+            "tb" => 1e12,
+            "kib" => 1024.0,
+            "mib" => 1024.0 * 1024.0,
+            "gib" => 1024.0 * 1024.0 * 1024.0,
+            "tib" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+            _ => return Err(invalid()),
+        };
+        match number.is_finite() && number >= 0.0 {
+            true => Ok(ByteSize { bytes: (number * bytes_per_unit).round() as u64 }),
+            false => Err(invalid()),
+        }
+    }
+}
+
+// How images are made smaller once `--max-output-size` is running out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OverBudget {
+    /// Save images at half their size
+    #[default]
+    Downscale,
+    /// Save images at full size with fewer colors, which compress better
+    Lossy,
+}
+
+impl OverBudget {
+    // What happened to a reduced page, for its warning
+    pub fn describe(self) -> &'static str {
+        match self {
+            OverBudget::Downscale => "saved at half size",
+            OverBudget::Lossy => "saved with reduced colors",
+        }
+    }
+}
+
+// Bytes of images a run may write. Once the next image, at the average size
+// of those saved so far, would go over the limit, the rest are saved reduced.
+// Images are saved from several threads, so which page is the first reduced
+// one depends on timing.
+#[derive(Debug)]
+pub struct OutputBudget {
+    limit: u64,
+    mode: OverBudget,
+    written: AtomicU64,
+    images: AtomicU64,
+}
+
+impl OutputBudget {
+    pub fn new(limit: u64, mode: OverBudget) -> OutputBudget {
+        OutputBudget {
+            limit,
+            mode,
+            written: AtomicU64::new(0),
+            images: AtomicU64::new(0),
+        }
+    }
+
+    pub fn mode(&self) -> OverBudget {
+        self.mode
+    }
+
+    // Bytes left before the limit
+    pub fn remaining(&self) -> u64 {
+        self.limit.saturating_sub(self.written.load(Ordering::Relaxed))
+    }
+
+    // Count bytes written without `save_png`, such as by a worker process
+    pub fn record(&self, bytes: u64) {
+        self.written.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn is_running_out(&self) -> bool {
+        let written = self.written.load(Ordering::Relaxed);
+        let average = written.checked_div(self.images.load(Ordering::Relaxed)).unwrap_or(0);
+        written + average > self.limit
+    }
+
+    // Save an image like `save_png_with`, reduced if the budget is running
+    // out. Returns whether it was reduced.
+    pub fn save_png(&self, image: &DynamicImage, path: &Path, options: PngOptions) -> Result<bool, Box<dyn Error>> {
+        let reduced = self.is_running_out();
+        match (reduced, self.mode) {
+            (false, _) => save_png_with(image, path, options)?,
+            // The pHYs chunk follows the scale, so the image keeps its true size
+            (true, OverBudget::Downscale) => save_png_with(image, path, PngOptions { scale: options.scale * DOWNSCALE, ..options })?,
+            (true, OverBudget::Lossy) => save_png_with(&posterize(image), path, PngOptions { compression: PngCompression::Best, ..options })?,
+        }
+        self.record(std::fs::metadata(path)?.len());
+        self.images.fetch_add(1, Ordering::Relaxed);
+        Ok(reduced)
+    }
+}
+
+// Save through `budget` if there is one. Returns whether the image was reduced.
+pub fn save_png_within(budget: Option<&OutputBudget>, image: &DynamicImage, path: &Path, options: PngOptions) -> Result<bool, Box<dyn Error>> {
+    match budget {
+        Some(budget) => budget.save_png(image, path, options),
+        None => save_png_with(image, path, options).map(|_| false),
+    }
+}
+
+// `image` with its color channels rounded to `LOSSY_LEVELS` levels. Alpha is
+// kept as it was.
+fn posterize(image: &DynamicImage) -> DynamicImage {
+    let step = 255 / (LOSSY_LEVELS - 1);
+    let mut rgba = image.to_rgba8();
+    for pixel in rgba.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+            *channel = ((*channel as u16 + step / 2) / step * step) as u8;
+        }
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+// Bytes of the files below `dir`, for outputs written by another process
+pub fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => dir_size(&entry.path()),
+            Ok(_) => entry.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgba, RgbaImage};
+
+    #[test]
+    fn test_byte_size() {
+        assert_eq!("500MB".parse(), Ok(ByteSize { bytes: 500_000_000 }));
+        assert_eq!("1.5 GiB".parse(), Ok(ByteSize { bytes: 1_610_612_736 }));
+        assert_eq!("4096".parse(), Ok(ByteSize { bytes: 4096 }));
+        assert!("500 parsecs".parse::<ByteSize>().is_err());
+        assert!("-1MB".parse::<ByteSize>().is_err());
+    }
+
+    #[test]
+    fn test_output_budget() {
+        let test_dir = std::env::temp_dir().join("diff_pdf_test_budget");
+        std::fs::create_dir_all(&test_dir).unwrap();
+        let mut page = RgbaImage::from_pixel(64, 64, Rgba([255, 255, 255, 255]));
+        for x in 0..64 {
+            page.put_pixel(x, x, Rgba([200, 30, 90, 255]));
+        }
+        let page = DynamicImage::ImageRgba8(page);
+
+        let first = test_dir.join("first.png");
+        save_png_with(&page, &first, PngOptions::default()).unwrap();
+        let size = std::fs::metadata(&first).unwrap().len();
+
+        // Room for one and a half pages: a second full one would go over
+        let budget = OutputBudget::new(size * 3 / 2, OverBudget::Downscale);
+        assert!(!budget.save_png(&page, &first, PngOptions::default()).unwrap());
+        assert_eq!(budget.remaining(), size / 2);
+        let second = test_dir.join("second.png");
+        assert!(budget.save_png(&page, &second, PngOptions::default()).unwrap());
+        assert_eq!(image::open(&second).unwrap().dimensions(), (32, 32));
+
+        let lossy = OutputBudget::new(0, OverBudget::Lossy);
+        lossy.record(1);
+        assert!(lossy.save_png(&page, &second, PngOptions::default()).unwrap());
+        let saved = image::open(&second).unwrap();
+        assert_eq!(saved.dimensions(), (64, 64));
+        assert_eq!(saved.get_pixel(0, 0), Rgba([204, 34, 85, 255]));
+
+        assert_eq!(dir_size(&test_dir), std::fs::metadata(&first).unwrap().len() + std::fs::metadata(&second).unwrap().len());
+        std::fs::remove_dir_all(&test_dir).ok();
+    }
+}
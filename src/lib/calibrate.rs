@@ -0,0 +1,140 @@
+use image::{imageops, DynamicImage, GenericImageView};
+use pdfium_render::prelude::PdfDocument;
+
+use crate::pdf::{render_page, PdfError, RenderOptions};
+
+// Most pages rendered to measure noise, spread evenly over the document
+const CALIBRATION_PAGES: usize = 5;
+
+// Scale of the jittered re-render, which is scaled back to the page's size so
+// its edges land between pixels like another rasterizer's would
+const JITTER: f32 = 1.01;
+
+// Share of pixels whose noise the suggested sensitivity has to cover
+const NOISE_QUANTILE: f64 = 0.999;
+
+// Headroom over the measured noise, so a slightly noisier machine still passes
+const MARGIN: f32 = 1.25;
+
+// Lowest suggested sensitivity; at 0 any difference at all is a change
+const MIN_SENSITIVITY: f32 = 0.01;
+
+// What `--calibrate` does with the measured sensitivity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum CalibrationMode {
+    /// Print the suggested sensitivity and stop
+    #[default]
+    Suggest,
+    /// Compare with the suggested sensitivity in place of --sensitivity
+    Apply,
+}
+
+// Rendering noise measured on a document and the sensitivity that rides over it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    // Pages the noise was measured on
+    pub pages: usize,
+    // Color difference, from 0 to 1, that `NOISE_QUANTILE` of pixels stay within
+    pub noise: f32,
+    pub sensitivity: f32,
+}
+
+impl Calibration {
+    // Calibration from a histogram of per-pixel noise levels
+    pub fn from_histogram(histogram: &[u64; 256], pages: usize) -> Calibration {
+        let total: u64 = histogram.iter().sum();
+        let covered = (total as f64 * NOISE_QUANTILE).ceil() as u64;
+        let mut seen = 0;
+        let level = (0..256)
+            .find(|&level| {
+                seen += histogram[level];
+                seen >= covered
+            })
+            .unwrap_or(255);
+
+        let noise = level as f32 / 255.0;
+        let sensitivity = ((noise * MARGIN).max(MIN_SENSITIVITY) * 100.0).ceil() / 100.0;
+        Calibration {
+            pages,
+            noise,
+            sensitivity: sensitivity.min(1.0),
+        }
+    }
+}
+
+// Count the pixels of two renders of the same page by their largest channel
+// difference. Renders of different sizes are compared over their overlap.
+pub fn add_noise(histogram: &mut [u64; 256], first: &DynamicImage, second: &DynamicImage) {
+    let (width, height) = (first.width().min(second.width()), first.height().min(second.height()));
+    for y in 0..height {
+        for x in 0..width {
+            let (a, b) = (first.get_pixel(x, y), second.get_pixel(x, y));
+            let difference = a.0.iter().zip(b.0.iter()).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0);
+            histogram[difference as usize] += 1;
+        }
+    }
+}
+
+// Pages calibration renders: up to `CALIBRATION_PAGES`, spread evenly
+fn calibration_pages(page_count: usize) -> Vec<usize> {
+    let count = page_count.min(CALIBRATION_PAGES);
+    let mut pages: Vec<usize> = (0..count).map(|i| i * page_count / count).collect();
+    pages.dedup();
+    pages
+}
+
+// Measure the rendering noise of a document by comparing it against itself:
+// each page is rendered twice, for noise from nondeterministic rendering, and
+// once more slightly larger and scaled back, for the antialiasing differences
+// between machines and PDFium versions
+pub fn calibrate(document: &PdfDocument, options: &RenderOptions) -> Result<Calibration, PdfError> {
+    let pages = calibration_pages(document.pages().len() as usize);
+    let jittered_options = RenderOptions { dpi: options.dpi * JITTER, ..options.clone() };
+
+    let mut histogram = [0u64; 256];
+    for &index in &pages {
+        let first = render_page(document, index, options)?;
+        let second = render_page(document, index, options)?;
+        add_noise(&mut histogram, &first, &second);
+
+        let jittered = render_page(document, index, &jittered_options)?;
+        let jittered = imageops::resize(&jittered, first.width(), first.height(), imageops::FilterType::Triangle);
+        add_noise(&mut histogram, &first, &DynamicImage::ImageRgba8(jittered));
+    }
+    Ok(Calibration::from_histogram(&histogram, pages.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_calibration() {
+        let page = DynamicImage::ImageRgba8(RgbaImage::from_pixel(100, 100, Rgba([255, 255, 255, 255])));
+        let mut noisy = page.to_rgba8();
+        // Antialiased edges a little off on 0.3% of the page, and one pixel
+        // far off that the quantile leaves out
+        for x in 0..30 {
+            noisy.put_pixel(x, 0, Rgba([230, 230, 230, 255]));
+        }
+        noisy.put_pixel(50, 50, Rgba([0, 0, 0, 255]));
+
+        let mut histogram = [0u64; 256];
+        add_noise(&mut histogram, &page, &page);
+        assert_eq!(Calibration::from_histogram(&histogram, 1).sensitivity, MIN_SENSITIVITY);
+
+        let mut histogram = [0u64; 256];
+        add_noise(&mut histogram, &page, &DynamicImage::ImageRgba8(noisy));
+        let calibration = Calibration::from_histogram(&histogram, 1);
+        assert_eq!(calibration.noise, 25.0 / 255.0);
+        assert_eq!(calibration.sensitivity, 0.13);
+    }
+
+    #[test]
+    fn test_calibration_pages() {
+        assert_eq!(calibration_pages(3), vec![0, 1, 2]);
+        assert_eq!(calibration_pages(20), vec![0, 4, 8, 12, 16]);
+        assert!(calibration_pages(0).is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use signal_hook::consts::SIGINT;
+
+// Exit status of a process stopped by Ctrl-C, as shells report it
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+// Shared flag asking a comparison to stop. Rendering and diffing check it
+// between pages and hand back what they finished, so partial results can
+// still be reported.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    // Clear a cancellation so the token can be used for another run
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+// Tokens are equal when they share a flag
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &CancelToken) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// Cancel `token` on Ctrl-C. Pressing it again while the token is cancelled
+// exits right away, so a run that is slow to stop can still be killed.
+pub fn cancel_on_interrupt(token: &CancelToken) -> std::io::Result<()> {
+    // Registered first, so it sees the flag as it was before this press
+    signal_hook::flag::register_conditional_shutdown(SIGINT, INTERRUPTED_EXIT_CODE, token.0.clone())?;
+    signal_hook::flag::register(SIGINT, token.0.clone())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_token_is_shared() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+
+        token.cancel();
+        assert!(clone.is_cancelled());
+        assert_eq!(token, clone);
+        assert_ne!(token, CancelToken::new());
+
+        clone.reset();
+        assert!(!token.is_cancelled());
+    }
+}
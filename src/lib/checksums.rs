@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Written into the output directory, listing everything else in it
+pub const CHECKSUMS_FILE: &str = "checksums.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileDigest {
+    // Inputs as given on the command line; outputs relative to the output
+    // directory, with `/` separators
+    pub path: String,
+    pub sha256: String,
+    pub bytes: u64,
+}
+
+// What a run read, how it was configured and what it wrote, so archived
+// outputs can be checked against tampering and the run repeated
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChecksumManifest {
+    pub tool_version: String,
+    pub pdfium: String,
+    // Command line arguments, as given
+    pub arguments: Vec<String>,
+    // `DIFF_PDF_*` variables, which supply options too
+    pub environment: BTreeMap<String, String>,
+    // Settings that shape the outputs, as compared by `--resume`
+    pub settings: String,
+    pub inputs: Vec<FileDigest>,
+    pub outputs: Vec<FileDigest>,
+}
+
+// Hex SHA-256 and size of a file, read in chunks
+pub fn sha256_file(path: &Path) -> std::io::Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let bytes = std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+
+    let mut hex = String::new();
+    for byte in hasher.finalize() {
+        write!(hex, "{:02x}", byte).expect("writing to a String");
+    }
+    Ok((hex, bytes))
+}
+
+// Every file below `dir`, sorted
+pub(crate) fn files_below(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                walk(&path, files)?;
+            } else {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = vec![];
+    walk(dir, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+pub fn digest_inputs(paths: &[String]) -> std::io::Result<Vec<FileDigest>> {
+    paths
+        .iter()
+        .map(|path| {
+            let (sha256, bytes) = sha256_file(Path::new(path))?;
+            Ok(FileDigest { path: path.clone(), sha256, bytes })
+        })
+        .collect()
+}
+
+// Digests of every file in `output_dir` except the checksum manifest
+pub fn digest_outputs(output_dir: &str) -> Result<Vec<FileDigest>, Box<dyn Error>> {
+    let root = Path::new(output_dir);
+    let mut digests = vec![];
+    for file in files_below(root)? {
+        let relative = file.strip_prefix(root)?;
+        let parts: Vec<&str> = relative.iter().filter_map(|part| part.to_str()).collect();
+        let path = parts.join("/");
+        if path == CHECKSUMS_FILE {
+            continue;
+        }
+
+        let (sha256, bytes) = sha256_file(&file)?;
+        digests.push(FileDigest { path, sha256, bytes });
+    }
+    Ok(digests)
+}
+
+// `DIFF_PDF_*` environment variables, sorted by name
+pub fn option_environment() -> BTreeMap<String, String> {
+    std::env::vars().filter(|(name, _)| name.starts_with("DIFF_PDF_")).collect()
+}
+
+// Write the manifest into `output_dir`, returning its path
+pub fn write_checksums(manifest: &ChecksumManifest, output_dir: &str) -> Result<PathBuf, Box<dyn Error>> {
+    std::fs::create_dir_all(output_dir)?;
+    let path = Path::new(output_dir).join(CHECKSUMS_FILE);
+    std::fs::write(&path, serde_json::to_string_pretty(manifest)?)?;
+    Ok(path)
+}
+
+// Check the files of `output_dir` against its manifest. Returns the number of
+// files checked and a description of every mismatch. Inputs are only checked
+// where they still exist at their recorded path.
+pub fn verify_checksums(output_dir: &str) -> Result<(usize, Vec<String>), Box<dyn Error>> {
+    let path = Path::new(output_dir).join(CHECKSUMS_FILE);
+    let text = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let manifest: ChecksumManifest = serde_json::from_str(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+
+    let actual: BTreeMap<String, FileDigest> =
+        digest_outputs(output_dir)?.into_iter().map(|digest| (digest.path.clone(), digest)).collect();
+    let mut problems = vec![];
+    for expected in &manifest.outputs {
+        match actual.get(&expected.path) {
+            None => problems.push(format!("missing: {}", expected.path)),
+            Some(found) if found.sha256 != expected.sha256 => problems.push(format!("modified: {}", expected.path)),
+            Some(_) => {}
+        }
+    }
+    for path in actual.keys() {
+        if !manifest.outputs.iter().any(|expected| &expected.path == path) {
+            problems.push(format!("not listed: {}", path));
+        }
+    }
+
+    let mut checked = manifest.outputs.len();
+    for input in &manifest.inputs {
+        if let Ok((sha256, _)) = sha256_file(Path::new(&input.path)) {
+            checked += 1;
+            if sha256 != input.sha256 {
+                problems.push(format!("input modified: {}", input.path));
+            }
+        }
+    }
+    Ok((checked, problems))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_file() {
+        let path = std::env::temp_dir().join(format!("pdf_diff_sha256_{}", std::process::id()));
+        std::fs::write(&path, "abc").unwrap();
+        let (sha256, bytes) = sha256_file(&path).unwrap();
+        assert_eq!(sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(bytes, 3);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_verify_checksums() {
+        let test_dir = "test_output_checksums";
+        std::fs::remove_dir_all(test_dir).ok();
+        std::fs::create_dir_all(format!("{}/a_vs_b", test_dir)).unwrap();
+        std::fs::write(format!("{}/report.html", test_dir), "<html>").unwrap();
+        std::fs::write(format!("{}/a_vs_b/a_1.png", test_dir), "png").unwrap();
+
+        let manifest = ChecksumManifest {
+            tool_version: "0.1.0".to_string(),
+            pdfium: "PDFium 140.0.7350.0".to_string(),
+            arguments: vec!["--old".to_string(), "a.pdf".to_string()],
+            environment: BTreeMap::new(),
+            settings: "dpi=300".to_string(),
+            inputs: vec![],
+            outputs: digest_outputs(test_dir).unwrap(),
+        };
+        let paths: Vec<&str> = manifest.outputs.iter().map(|digest| digest.path.as_str()).collect();
+        assert_eq!(paths, vec!["a_vs_b/a_1.png", "report.html"]);
+
+        write_checksums(&manifest, test_dir).unwrap();
+        assert_eq!(verify_checksums(test_dir).unwrap(), (2, vec![]), "The manifest doesn't list itself");
+
+        std::fs::write(format!("{}/report.html", test_dir), "<html>edited").unwrap();
+        std::fs::remove_file(format!("{}/a_vs_b/a_1.png", test_dir)).unwrap();
+        std::fs::write(format!("{}/extra.png", test_dir), "png").unwrap();
+        let (_, problems) = verify_checksums(test_dir).unwrap();
+        assert_eq!(problems, vec!["missing: a_vs_b/a_1.png", "modified: report.html", "not listed: extra.png"]);
+
+        std::fs::remove_dir_all(test_dir).ok();
+    }
+}
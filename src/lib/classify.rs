@@ -0,0 +1,221 @@
+use std::error::Error;
+
+use image::DynamicImage;
+use pdfium_render::prelude::{PdfDocument, PdfPage, PdfPageIndex, PdfPageObject, PdfPageObjectCommon, PdfPageObjectsCommon, PdfPageObjectsIterator};
+use serde::{Deserialize, Serialize};
+
+use crate::ignore::IgnoreRegion;
+use crate::layers::text_boxes;
+use crate::regions::Region;
+use crate::tr;
+
+// Share of a changed region a kind of content must cover for the region to
+// count as a change of that kind
+const MIN_COVERAGE: f32 = 0.25;
+
+// What a changed region of a page is made of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegionKind {
+    Text,
+    Image,
+    // Vector content such as lines, rules and fills
+    Graphic,
+}
+
+impl RegionKind {
+    pub const ALL: [RegionKind; 3] = [RegionKind::Text, RegionKind::Image, RegionKind::Graphic];
+
+    // Stable name, used in CSV headers and JSON
+    pub fn label(&self) -> &'static str {
+        match self {
+            RegionKind::Text => "text",
+            RegionKind::Image => "image",
+            RegionKind::Graphic => "graphic",
+        }
+    }
+
+    // Name shown to people, in the chosen language
+    pub fn display_label(&self) -> String {
+        tr!(format!("kind-{}", self.label()))
+    }
+}
+
+// Number of changed regions of each kind on a page
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegionKinds {
+    pub text: usize,
+    pub image: usize,
+    pub graphic: usize,
+}
+
+impl RegionKinds {
+    pub fn count(&self, kind: RegionKind) -> usize {
+        match kind {
+            RegionKind::Text => self.text,
+            RegionKind::Image => self.image,
+            RegionKind::Graphic => self.graphic,
+        }
+    }
+
+    fn add(&mut self, kind: RegionKind) {
+        match kind {
+            RegionKind::Text => self.text += 1,
+            RegionKind::Image => self.image += 1,
+            RegionKind::Graphic => self.graphic += 1,
+        }
+    }
+}
+
+// Where a page has text and images, in points from the top-left corner of
+// the page like ignore regions
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PageContent {
+    pub text: Vec<IgnoreRegion>,
+    pub images: Vec<IgnoreRegion>,
+}
+
+impl PageContent {
+    // Content of both pages compared at a position, so a change is placed
+    // whether the content was there before or is there now
+    pub fn merged(mut self, other: PageContent) -> PageContent {
+        self.text.extend(other.text);
+        self.images.extend(other.images);
+        self
+    }
+}
+
+fn contains_image(objects: PdfPageObjectsIterator) -> bool {
+    objects.into_iter().any(|object| match &object {
+        PdfPageObject::Image(_) => true,
+        PdfPageObject::XObjectForm(form) => contains_image(form.iter()),
+        _ => false,
+    })
+}
+
+// Text boxes and image object bounds of a page (1-based `number`). Objects
+// inside a form XObject are positioned relative to the form, so a form with
+// images in it counts as one image as large as the form.
+pub fn page_content(page: &PdfPage, number: usize) -> Result<PageContent, Box<dyn Error>> {
+    let page_height = page.height().value;
+    let images = page
+        .objects()
+        .iter()
+        .filter(|object| match object {
+            PdfPageObject::Image(_) => true,
+            PdfPageObject::XObjectForm(form) => contains_image(form.iter()),
+            _ => false,
+        })
+        .filter_map(|object| object.bounds().ok())
+        .map(|bounds| IgnoreRegion {
+            x: bounds.left().value,
+            y: page_height - bounds.top().value,
+            width: bounds.width().value,
+            height: bounds.height().value,
+        })
+        .collect();
+
+    Ok(PageContent {
+        text: text_boxes(page, number)?,
+        images,
+    })
+}
+
+// Content of the pages compared at each position of `pairing`, merged from
+// both documents. Positions without renders are left empty.
+pub fn pair_contents<'a>(
+    old_document: &PdfDocument<'a>,
+    new_document: Option<&PdfDocument<'a>>,
+    pairing: &[(Option<usize>, Option<usize>)],
+    images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+) -> Result<Vec<PageContent>, Box<dyn Error>> {
+    let mut contents = vec![];
+    for ((old_image, new_image), &(old_index, new_index)) in images.iter().zip(pairing) {
+        let mut content = PageContent::default();
+        if old_image.is_some() || new_image.is_some() {
+            for (document, index) in [(Some(old_document), old_index), (new_document, new_index)] {
+                let (Some(document), Some(index)) = (document, index) else {
+                    continue;
+                };
+                let page = document.pages().get(index as PdfPageIndex)?;
+                content = content.merged(page_content(&page, index + 1)?);
+            }
+        }
+        contents.push(content);
+    }
+    Ok(contents)
+}
+
+// Area of `area` covered by `boxes`, overlaps between boxes counted once per box
+fn covered_area(area: &IgnoreRegion, boxes: &[IgnoreRegion]) -> f32 {
+    boxes
+        .iter()
+        .map(|b| {
+            let width = (area.x + area.width).min(b.x + b.width) - area.x.max(b.x);
+            let height = (area.y + area.height).min(b.y + b.height) - area.y.max(b.y);
+            width.max(0.0) * height.max(0.0)
+        })
+        .sum()
+}
+
+// Kind of a changed region of a render at `dpi`. Text is drawn over images,
+// so a region counts as text when enough of it is covered by text boxes, then
+// as an image, and otherwise as a graphic.
+pub fn classify_region(region: &Region, dpi: f32, content: &PageContent) -> RegionKind {
+    let points = 72.0 / dpi;
+    let area = IgnoreRegion {
+        x: region.x as f32 * points,
+        y: region.y as f32 * points,
+        width: region.width as f32 * points,
+        height: region.height as f32 * points,
+    };
+    let size = (area.width * area.height).max(f32::EPSILON);
+
+    if covered_area(&area, &content.text) / size >= MIN_COVERAGE {
+        RegionKind::Text
+    } else if covered_area(&area, &content.images) / size >= MIN_COVERAGE {
+        RegionKind::Image
+    } else {
+        RegionKind::Graphic
+    }
+}
+
+pub fn classify_regions(regions: &[Region], dpi: f32, content: &PageContent) -> RegionKinds {
+    let mut kinds = RegionKinds::default();
+    for region in regions {
+        kinds.add(classify_region(region, dpi, content));
+    }
+    kinds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_regions() {
+        // At 144 DPI a point is two pixels
+        let content = PageContent {
+            text: vec![IgnoreRegion { x: 10.0, y: 10.0, width: 100.0, height: 12.0 }],
+            images: vec![IgnoreRegion { x: 0.0, y: 100.0, width: 200.0, height: 200.0 }],
+        };
+        let word = Region { x: 40, y: 20, width: 40, height: 24 };
+        let photo = Region { x: 100, y: 300, width: 50, height: 50 };
+        let rule = Region { x: 0, y: 700, width: 400, height: 4 };
+        let caption = PageContent {
+            text: vec![IgnoreRegion { x: 50.0, y: 150.0, width: 40.0, height: 12.0 }],
+            ..PageContent::default()
+        };
+
+        assert_eq!(classify_region(&word, 144.0, &content), RegionKind::Text);
+        assert_eq!(classify_region(&photo, 144.0, &content), RegionKind::Image);
+        assert_eq!(classify_region(&rule, 144.0, &content), RegionKind::Graphic);
+        // Text set over an image is a text change
+        let caption_region = Region { x: 100, y: 300, width: 80, height: 24 };
+        assert_eq!(classify_region(&caption_region, 144.0, &content.clone().merged(caption)), RegionKind::Text);
+
+        let kinds = classify_regions(&[word, photo, rule, rule], 144.0, &content);
+        assert_eq!(kinds, RegionKinds { text: 1, image: 1, graphic: 2 });
+        assert_eq!(kinds.count(RegionKind::Graphic), 2);
+    }
+}
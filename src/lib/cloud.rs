@@ -0,0 +1,129 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::checksums::files_below;
+
+// Location in an object store that outputs are uploaded to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectStoreUri {
+    pub bucket: String,
+    // Key prefix without leading or trailing slashes; may be empty
+    pub prefix: String,
+}
+
+impl ObjectStoreUri {
+    // Parse `s3://bucket/prefix`
+    pub fn parse(uri: &str) -> Result<ObjectStoreUri, String> {
+        let rest = uri
+            .strip_prefix("s3://")
+            .ok_or_else(|| format!("Unsupported object store URI '{}', expected s3://bucket/prefix", uri))?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(format!("Missing bucket in '{}'", uri));
+        }
+
+        Ok(ObjectStoreUri {
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+        })
+    }
+
+    // URI of an object at `relative` (slash-separated) below the prefix
+    pub fn object(&self, relative: &str) -> String {
+        match self.prefix.as_str() {
+            "" => format!("s3://{}/{}", self.bucket, relative),
+            prefix => format!("s3://{}/{}/{}", self.bucket, prefix, relative),
+        }
+    }
+}
+
+// Output directories given as URIs are written to a local staging directory
+// and uploaded after each run
+pub fn is_object_store_uri(output_dir: &str) -> bool {
+    output_dir.starts_with("s3://")
+}
+
+// Content type stored with an object, so reports and images open in a
+// browser instead of downloading
+pub fn content_type(path: &Path) -> &'static str {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => "image/png",
+        "html" => "text/html; charset=utf-8",
+        "json" => "application/json",
+        "csv" => "text/csv; charset=utf-8",
+        "txt" | "prom" => "text/plain; charset=utf-8",
+        "pdf" => "application/pdf",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+// Local directory outputs for `uri` are written to before uploading
+pub fn staging_dir(uri: &ObjectStoreUri) -> PathBuf {
+    let name = format!("{}_{}", uri.bucket, uri.prefix.replace('/', "_"));
+    std::env::temp_dir().join(format!("pdf_diff_{}_{}", std::process::id(), name.trim_end_matches('_')))
+}
+
+// Upload every file below `local` to `uri`, keeping relative paths, using the
+// AWS CLI so credentials, regions and endpoints (`AWS_ENDPOINT_URL` for
+// S3-compatible stores) are configured the usual way. Returns the number of
+// files uploaded.
+pub fn upload_dir(local: &Path, uri: &ObjectStoreUri) -> Result<usize, Box<dyn Error>> {
+    let files = files_below(local)?;
+    for file in &files {
+        let relative = file.strip_prefix(local)?;
+        let key: Vec<&str> = relative.iter().filter_map(|part| part.to_str()).collect();
+        let target = uri.object(&key.join("/"));
+
+        let output = Command::new("aws")
+            .args(["s3", "cp", "--only-show-errors", "--content-type", content_type(file)])
+            .arg(file)
+            .arg(&target)
+            .output()
+            .map_err(|e| format!("Failed to run aws (is the AWS CLI installed and on PATH?): {}", e))?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "aws exited with {} while uploading {}: {}",
+                output.status,
+                target,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+    }
+
+    Ok(files.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_object_store_uri() {
+        let uri = ObjectStoreUri::parse("s3://ci-artifacts/diffs/run-42/").unwrap();
+        assert_eq!(uri.bucket, "ci-artifacts");
+        assert_eq!(uri.prefix, "diffs/run-42");
+        assert_eq!(uri.object("report.html"), "s3://ci-artifacts/diffs/run-42/report.html");
+
+        let root = ObjectStoreUri::parse("s3://ci-artifacts").unwrap();
+        assert_eq!(root.object("a/b.png"), "s3://ci-artifacts/a/b.png");
+
+        assert!(ObjectStoreUri::parse("s3:///prefix").is_err());
+        assert!(ObjectStoreUri::parse("gs://bucket").is_err());
+        assert!(is_object_store_uri("s3://bucket"));
+        assert!(!is_object_store_uri("output"));
+    }
+
+    #[test]
+    fn test_content_type() {
+        assert_eq!(content_type(Path::new("out/doc_page1.png")), "image/png");
+        assert_eq!(content_type(Path::new("out/report.HTML")), "text/html; charset=utf-8");
+        assert_eq!(content_type(Path::new("out/summary.json")), "application/json");
+        assert_eq!(content_type(Path::new("out/.completed")), "application/octet-stream");
+    }
+}
@@ -0,0 +1,128 @@
+use std::error::Error;
+use std::path::Path;
+
+use image::{DynamicImage, RgbaImage};
+use moxcms::{ColorProfile, DataColorSpace, Layout, RenderingIntent, Transform8BitExecutor, TransformOptions};
+
+// How colors outside the profile's gamut are brought into it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Intent {
+    /// Compress the whole gamut, keeping relations between colors
+    Perceptual,
+    /// Keep in-gamut colors exact and clip the rest, as proofing usually does
+    #[default]
+    RelativeColorimetric,
+    /// Keep colors vivid at the cost of accuracy
+    Saturation,
+    /// Like relative colorimetric, but also simulating the paper white
+    AbsoluteColorimetric,
+}
+
+impl From<Intent> for RenderingIntent {
+    fn from(intent: Intent) -> RenderingIntent {
+        match intent {
+            Intent::Perceptual => RenderingIntent::Perceptual,
+            Intent::RelativeColorimetric => RenderingIntent::RelativeColorimetric,
+            Intent::Saturation => RenderingIntent::Saturation,
+            Intent::AbsoluteColorimetric => RenderingIntent::AbsoluteColorimetric,
+        }
+    }
+}
+
+// Round trip of sRGB renders through an output profile, so both documents'
+// colors end up in the same gamut whether their PDF tagged them or not
+pub struct ColorProof {
+    to_profile: Box<Transform8BitExecutor>,
+    from_profile: Box<Transform8BitExecutor>,
+    // Samples per pixel in the profile's color space
+    channels: usize,
+}
+
+impl ColorProof {
+    // Proof through the ICC profile in `path`; RGB, CMYK and gray output
+    // profiles are supported
+    pub fn from_file(path: &Path, intent: Intent) -> Result<ColorProof, Box<dyn Error>> {
+        let bytes = std::fs::read(path).map_err(|e| format!("Failed to read ICC profile {}: {}", path.display(), e))?;
+        let profile = ColorProfile::new_from_slice(&bytes).map_err(|e| format!("Invalid ICC profile {}: {}", path.display(), e))?;
+        ColorProof::new(&profile, intent).map_err(|e| format!("Unusable ICC profile {}: {}", path.display(), e).into())
+    }
+
+    pub fn new(profile: &ColorProfile, intent: Intent) -> Result<ColorProof, Box<dyn Error>> {
+        // CMYK samples use the same layout as RGBA ones. Alpha is put back
+        // afterwards, so gray goes without.
+        let (layout, channels) = match profile.color_space {
+            DataColorSpace::Rgb | DataColorSpace::Cmyk => (Layout::Rgba, 4),
+            DataColorSpace::Gray => (Layout::Gray, 1),
+            other => return Err(format!("unsupported color space {:?}, expected RGB, CMYK or gray", other).into()),
+        };
+        let srgb = ColorProfile::new_srgb();
+        let options = TransformOptions {
+            rendering_intent: intent.into(),
+            ..TransformOptions::default()
+        };
+        Ok(ColorProof {
+            to_profile: srgb.create_transform_8bit(Layout::Rgba, profile, layout, options)?,
+            from_profile: profile.create_transform_8bit(layout, &srgb, Layout::Rgba, options)?,
+            channels,
+        })
+    }
+
+    // The render as it would look printed or shown through the profile. Alpha
+    // is kept as it was.
+    pub fn proof(&self, image: &DynamicImage) -> Result<DynamicImage, Box<dyn Error>> {
+        let source = image.to_rgba8();
+        let pixels = (source.width() * source.height()) as usize;
+        let mut converted = vec![0; pixels * self.channels];
+        self.to_profile.transform(source.as_raw(), &mut converted)?;
+        let mut proofed = vec![0; pixels * 4];
+        self.from_profile.transform(&converted, &mut proofed)?;
+
+        for (pixel, original) in proofed.chunks_exact_mut(4).zip(source.as_raw().chunks_exact(4)) {
+            pixel[3] = original[3];
+        }
+        let proofed = RgbaImage::from_raw(source.width(), source.height(), proofed).ok_or("Proofed render has the wrong size")?;
+        Ok(DynamicImage::ImageRgba8(proofed))
+    }
+}
+
+// Proof every render in place
+pub fn proof_pages(images: &mut [(Option<DynamicImage>, Option<DynamicImage>)], proof: &ColorProof) -> Result<(), Box<dyn Error>> {
+    for (old_image, new_image) in images.iter_mut() {
+        for image in [old_image, new_image].into_iter().flatten() {
+            *image = proof.proof(image)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_proof() {
+        let path = std::env::temp_dir().join("diff_pdf_test_gray.icc");
+        std::fs::write(&path, ColorProfile::new_gray_with_gamma(2.2).encode().unwrap()).unwrap();
+        let proof = ColorProof::from_file(&path, Intent::default()).unwrap();
+
+        // Through a gray profile every color becomes a gray
+        let mut page = RgbaImage::from_pixel(4, 1, Rgba([255, 255, 255, 255]));
+        page.put_pixel(1, 0, Rgba([200, 40, 40, 255]));
+        page.put_pixel(2, 0, Rgba([0, 0, 0, 128]));
+        let page = DynamicImage::ImageRgba8(page);
+        let proofed = proof.proof(&page).unwrap().to_rgba8();
+        let red = proofed.get_pixel(1, 0).0;
+        assert!(red[0] == red[1] && red[1] == red[2], "{:?}", red);
+        assert!(proofed.get_pixel(0, 0).0[0] > 250);
+        assert_eq!(proofed.get_pixel(2, 0).0[3], 128, "Alpha is kept");
+
+        let mut images = vec![(Some(page), None)];
+        proof_pages(&mut images, &proof).unwrap();
+        assert_eq!(images[0].0.as_ref().unwrap().to_rgba8(), proofed);
+
+        std::fs::write(&path, b"not a profile").unwrap();
+        assert!(ColorProof::from_file(&path, Intent::default()).err().unwrap().to_string().starts_with("Invalid ICC profile"));
+        std::fs::remove_file(&path).ok();
+    }
+}
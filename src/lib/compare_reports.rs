@@ -0,0 +1,174 @@
+use std::error::Error;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::report::ComparisonSummary;
+
+// Smallest change of a page's diff ratio counted as better or worse by default
+pub const MIN_RATIO_CHANGE: f64 = 0.0001;
+
+// The part of `summary.json` compared across runs
+#[derive(Deserialize)]
+struct SummaryFile {
+    comparisons: Vec<ComparisonSummary>,
+}
+
+// Comparisons of a run's `summary.json`
+pub fn read_summary(path: &Path) -> Result<Vec<ComparisonSummary>, Box<dyn Error>> {
+    let json = std::fs::read_to_string(path)?;
+    let summary: SummaryFile = serde_json::from_str(&json)?;
+    Ok(summary.comparisons)
+}
+
+// How a page's difference moved from the old run to the new one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Trend {
+    // Less of the page differs
+    Better,
+    // More of the page differs
+    Worse,
+    // The page was only compared in the old run
+    OnlyOld,
+    // The page was only compared in the new run
+    OnlyNew,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageTrend {
+    // 1-based position of the page in the comparison
+    pub page: usize,
+    pub old_ratio: Option<f64>,
+    pub new_ratio: Option<f64>,
+    pub trend: Trend,
+}
+
+// Pages of one pair of documents whose difference moved between the runs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ComparisonTrend {
+    pub old_pdf: String,
+    pub new_pdf: String,
+    pub pages: Vec<PageTrend>,
+    // Errors the comparison failed with in each run
+    pub old_error: Option<String>,
+    pub new_error: Option<String>,
+    // Set when either run left pages out, by sampling or by being interrupted,
+    // so pages it didn't compare may look better than they are
+    pub incomplete: bool,
+}
+
+impl ComparisonTrend {
+    pub fn count(&self, trend: Trend) -> usize {
+        self.pages.iter().filter(|page| page.trend == trend).count()
+    }
+}
+
+// Differences between two runs over the same documents
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunTrends {
+    pub comparisons: Vec<ComparisonTrend>,
+    // Pairs of documents only one of the runs compared, as (old, new) PDFs
+    pub only_old: Vec<(String, String)>,
+    pub only_new: Vec<(String, String)>,
+}
+
+impl RunTrends {
+    pub fn count(&self, trend: Trend) -> usize {
+        self.comparisons.iter().map(|comparison| comparison.count(trend)).sum()
+    }
+}
+
+// Pages whose diff ratio moved by at least `min_change` between two runs of
+// one pair of documents. Pages are matched by position.
+pub fn compare_pages(old: &ComparisonSummary, new: &ComparisonSummary, min_change: f64) -> ComparisonTrend {
+    let pages = (0..old.page_ratios.len().max(new.page_ratios.len()))
+        .filter_map(|index| {
+            let (old_ratio, new_ratio) = (old.page_ratios.get(index).copied(), new.page_ratios.get(index).copied());
+            let trend = match (old_ratio, new_ratio) {
+                (Some(before), Some(after)) if after <= before - min_change => Trend::Better,
+                (Some(before), Some(after)) if after >= before + min_change => Trend::Worse,
+                (Some(_), None) => Trend::OnlyOld,
+                (None, Some(_)) => Trend::OnlyNew,
+                _ => return None,
+            };
+            Some(PageTrend {
+                page: index + 1,
+                old_ratio,
+                new_ratio,
+                trend,
+            })
+        })
+        .collect();
+
+    ComparisonTrend {
+        old_pdf: new.old_pdf.clone(),
+        new_pdf: new.new_pdf.clone(),
+        pages,
+        old_error: old.error.clone(),
+        new_error: new.error.clone(),
+        incomplete: [old, new].iter().any(|run| run.sampling.is_some() || run.interrupted),
+    }
+}
+
+// Compare two runs' summaries. Comparisons are matched by their old and new
+// PDF paths, so both runs have to be given the documents the same way.
+pub fn compare_runs(old: &[ComparisonSummary], new: &[ComparisonSummary], min_change: f64) -> RunTrends {
+    let key = |summary: &ComparisonSummary| (summary.old_pdf.clone(), summary.new_pdf.clone());
+    let mut comparisons = vec![];
+    let mut only_new = vec![];
+    for after in new {
+        match old.iter().find(|before| key(before) == key(after)) {
+            Some(before) => comparisons.push(compare_pages(before, after, min_change)),
+            None => only_new.push(key(after)),
+        }
+    }
+    let only_old = old.iter().map(key).filter(|pair| !new.iter().any(|after| key(after) == *pair)).collect();
+
+    RunTrends {
+        comparisons,
+        only_old,
+        only_new,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::summary_with_ratios;
+
+    #[test]
+    fn test_compare_runs() {
+        let old = vec![
+            summary_with_ratios("a.pdf", "new.pdf", vec![0.1, 0.0, 0.02, 0.5]),
+            summary_with_ratios("gone.pdf", "new.pdf", vec![]),
+        ];
+        let new = vec![
+            summary_with_ratios("a.pdf", "new.pdf", vec![0.0, 0.00005, 0.03]),
+            summary_with_ratios("added.pdf", "new.pdf", vec![0.0]),
+        ];
+
+        let trends = compare_runs(&old, &new, MIN_RATIO_CHANGE);
+        let trend = |page: usize| trends.comparisons[0].pages.iter().find(|p| p.page == page).map(|p| p.trend);
+        assert_eq!(trend(1), Some(Trend::Better));
+        assert_eq!(trend(2), None, "Changes under the minimum are left out");
+        assert_eq!(trend(3), Some(Trend::Worse));
+        assert_eq!(trend(4), Some(Trend::OnlyOld));
+        assert_eq!(trends.count(Trend::Better), 1);
+        assert_eq!(trends.only_old, vec![("gone.pdf".to_string(), "new.pdf".to_string())]);
+        assert_eq!(trends.only_new, vec![("added.pdf".to_string(), "new.pdf".to_string())]);
+        assert!(!trends.comparisons[0].incomplete);
+    }
+
+    #[test]
+    fn test_read_summary() {
+        let test_dir = std::env::temp_dir().join("diff_pdf_test_compare_reports");
+        std::fs::create_dir_all(&test_dir).unwrap();
+        let summaries = vec![summary_with_ratios("a.pdf", "new.pdf", vec![0.25])];
+        let aggregate = crate::report::AggregateSummary::from_summaries(&summaries);
+        let path = crate::report::write_summary_json(&aggregate, &summaries, test_dir.to_str().unwrap()).unwrap();
+
+        assert_eq!(read_summary(&path).unwrap(), summaries);
+        std::fs::remove_dir_all(&test_dir).ok();
+    }
+}
@@ -0,0 +1,123 @@
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+
+use crate::image_utils::page_changed;
+use crate::thumbnails::{fit_thumbnail, ThumbnailSize};
+
+// Maximum number of thumbnails per row
+pub const MAX_COLUMNS: u32 = 10;
+
+// Bounding box every page thumbnail is scaled to fit
+pub const THUMB_WIDTH: u32 = 160;
+pub const THUMB_HEIGHT: u32 = 226;
+const THUMB_SIZE: ThumbnailSize = ThumbnailSize { width: THUMB_WIDTH, height: THUMB_HEIGHT };
+
+// Space between thumbnails, also used for the changed-page outline
+const MARGIN: u32 = 12;
+const OUTLINE: u32 = 4;
+
+// Blend an image towards white so unchanged pages recede visually
+fn dim(img: &mut RgbaImage) {
+    for pixel in img.pixels_mut() {
+        for channel in 0..3 {
+            pixel[channel] = 255 - (255 - pixel[channel]) / 3;
+        }
+    }
+}
+
+fn draw_outline(sheet: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32) {
+    let red = Rgba([220, 0, 0, 255]);
+    let left = x.saturating_sub(OUTLINE);
+    let top = y.saturating_sub(OUTLINE);
+    let right = (x + width + OUTLINE).min(sheet.width());
+    let bottom = (y + height + OUTLINE).min(sheet.height());
+
+    for py in top..bottom {
+        for px in left..right {
+            let inside = px >= x && px < x + width && py >= y && py < y + height;
+            if !inside {
+                sheet.put_pixel(px, py, red);
+            }
+        }
+    }
+}
+
+// Build a single grid image of page thumbnails. Changed pages (including pages
+// present in only one document) are outlined in red, unchanged pages are dimmed.
+pub fn create_contact_sheet(images: &[(Option<DynamicImage>, Option<DynamicImage>)]) -> DynamicImage {
+    let count = images.len() as u32;
+    let columns = count.clamp(1, MAX_COLUMNS);
+    let rows = count.div_ceil(columns).max(1);
+
+    let cell_width = THUMB_WIDTH + MARGIN;
+    let cell_height = THUMB_HEIGHT + MARGIN;
+    let mut sheet = RgbaImage::from_pixel(
+        columns * cell_width + MARGIN,
+        rows * cell_height + MARGIN,
+        Rgba([255, 255, 255, 255]),
+    );
+
+    for (index, (old_image, new_image)) in images.iter().enumerate() {
+        let Some(page) = new_image.as_ref().or(old_image.as_ref()) else {
+            continue;
+        };
+
+        let mut thumb = fit_thumbnail(page, THUMB_SIZE).to_rgba8();
+        let changed = page_changed(old_image.as_ref(), new_image.as_ref());
+        if !changed {
+            dim(&mut thumb);
+        }
+
+        let column = index as u32 % columns;
+        let row = index as u32 / columns;
+        // Centre the thumbnail inside its cell
+        let x = MARGIN + column * cell_width + (THUMB_WIDTH - thumb.width()) / 2;
+        let y = MARGIN + row * cell_height + (THUMB_HEIGHT - thumb.height()) / 2;
+
+        imageops::overlay(&mut sheet, &thumb, x as i64, y as i64);
+        if changed {
+            draw_outline(&mut sheet, x, y, thumb.width(), thumb.height());
+        }
+    }
+
+    DynamicImage::ImageRgba8(sheet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::create_solid_color_image;
+    use image::GenericImageView;
+
+    #[test]
+    fn test_contact_sheet_grid_size() {
+        let page = create_solid_color_image(320, 452, Rgba([0, 0, 0, 255]));
+        let images: Vec<_> = (0..12).map(|_| (Some(page.clone()), Some(page.clone()))).collect();
+
+        let sheet = create_contact_sheet(&images);
+        let (width, height) = sheet.dimensions();
+
+        assert_eq!(width, MAX_COLUMNS * (THUMB_WIDTH + MARGIN) + MARGIN);
+        assert_eq!(height, 2 * (THUMB_HEIGHT + MARGIN) + MARGIN);
+    }
+
+    #[test]
+    fn test_contact_sheet_marks_changed_pages() {
+        let old = create_solid_color_image(320, 452, Rgba([0, 0, 0, 255]));
+        let new = create_solid_color_image(320, 452, Rgba([0, 0, 255, 255]));
+        let images = vec![(Some(old.clone()), Some(old.clone())), (Some(old), Some(new))];
+
+        let sheet = create_contact_sheet(&images).to_rgba8();
+
+        // Unchanged page is dimmed, so no pure black remains
+        assert_ne!(*sheet.get_pixel(MARGIN + 10, MARGIN + 10), Rgba([0, 0, 0, 255]));
+        // Changed page gets a red outline just outside its thumbnail
+        let x = MARGIN + THUMB_WIDTH + MARGIN - 1;
+        assert_eq!(*sheet.get_pixel(x, MARGIN + 10), Rgba([220, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_contact_sheet_empty() {
+        let sheet = create_contact_sheet(&[]);
+        assert!(sheet.width() > 0 && sheet.height() > 0);
+    }
+}
@@ -0,0 +1,291 @@
+use std::error::Error;
+use std::path::Path;
+use std::time::Instant;
+
+use image::DynamicImage;
+use pdfium_render::prelude::Pdfium;
+use serde::{Deserialize, Serialize};
+
+use crate::classify::{classify_regions, pair_contents, PageContent, RegionKinds};
+use crate::image_utils::{diff_image_numbers, diff_image_path, DiffOutput};
+use crate::layout::Layout;
+use crate::metadata::RunMetadata;
+use crate::pairing::PagePairing;
+use crate::pdf::{create_images_from_pdf_with_options, index_pairing, load_pdf_documents, RenderOptions};
+use crate::regions::{find_changed_regions, PIXEL_TOLERANCE};
+use crate::severity::{page_severities, SeverityWeights};
+use crate::report::{ComparisonSummary, PageStatus, PageWarning};
+
+// Document-level counts of one comparison
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentStats {
+    pub total_pages: usize,
+    pub changed_pages: usize,
+    // 1-based numbers of pages with a blank render; not counted as changed
+    pub blank_pages: Vec<usize>,
+    // 1-based numbers of changed pages whose difference was approved
+    pub approved_pages: Vec<usize>,
+    // Changed area summed over all pages, in square inches
+    pub changed_area: f64,
+}
+
+// Outcome of one position of the comparison
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageResult {
+    // 1-based position in the comparison
+    pub page: usize,
+    // 1-based pages compared at this position, `None` for a missing side
+    pub old_page: Option<usize>,
+    pub new_page: Option<usize>,
+    pub status: PageStatus,
+    // Fraction of changed pixels; unpaired pages count as 1.0
+    pub diff_ratio: f64,
+    // Severity of the change from 0 to 1, when pages were scored
+    #[serde(default)]
+    pub severity: Option<f64>,
+    // Only counted where both sides were rendered and compared
+    pub changed_regions: Option<usize>,
+    // What the changed regions are made of, when the pages' content was known
+    #[serde(default)]
+    pub region_kinds: Option<RegionKinds>,
+    // Diff image saved for the page, if any
+    pub output_path: Option<String>,
+}
+
+// Seconds spent in each phase of a comparison
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Timings {
+    // Loading, rendering and preprocessing the pages
+    pub render_seconds: f64,
+    // Diffing the renders and saving the diff images
+    pub diff_seconds: f64,
+}
+
+// Everything the reports of one comparison show, in one serializable value.
+// The JSON, HTML, CSV and Markdown reports are all rendered from it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiffReport {
+    // Name the comparison's outputs are saved under
+    pub title: String,
+    pub old_pdf: String,
+    pub new_pdf: String,
+    pub output_dir: String,
+    pub stats: DocumentStats,
+    pub pages: Vec<PageResult>,
+    pub warnings: Vec<PageWarning>,
+    // Set when only a subset of pages was compared, describing how it was picked
+    pub sampling: Option<String>,
+    // PDFium library and version the pages were rendered with
+    pub pdfium: Option<String>,
+    // Set when pages were paired by text similarity rather than by position
+    pub paired_by_text: bool,
+    // Set when the new document has the old pages in another order
+    #[serde(default)]
+    pub reordered: bool,
+    pub interrupted: bool,
+    // Left out with `--no-metadata`, like `metadata`, as they differ between runs
+    #[serde(default)]
+    pub timings: Option<Timings>,
+    // When, where and how the comparison ran
+    #[serde(default)]
+    pub metadata: Option<RunMetadata>,
+}
+
+impl DiffReport {
+    // Report on compared renders and their summary. `saved` is how diff images
+    // were written to the summary's output directory, if they were.
+    pub fn new(
+        images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+        summary: &ComparisonSummary,
+        title: &str,
+        saved: Option<(DiffOutput, Layout)>,
+        timings: Timings,
+    ) -> DiffReport {
+        let numbers = match saved {
+            Some((output, layout)) => diff_image_numbers(images, output, layout),
+            None => vec![None; images.len()],
+        };
+
+        let pages = images
+            .iter()
+            .enumerate()
+            .map(|(index, (old_image, new_image))| {
+                let (old_page, new_page) = match summary.page_pairs.get(index) {
+                    Some(&(old, new)) => (old.map(|page| page + 1), new.map(|page| page + 1)),
+                    None => (old_image.as_ref().map(|_| index + 1), new_image.as_ref().map(|_| index + 1)),
+                };
+                let status = summary.page_status(index, old_image.as_ref(), new_image.as_ref());
+                let changed_regions = match (old_image, new_image) {
+                    (Some(old), Some(new)) if status != PageStatus::Skipped => Some(find_changed_regions(old, new, PIXEL_TOLERANCE).len()),
+                    _ => None,
+                };
+
+                PageResult {
+                    page: index + 1,
+                    old_page,
+                    new_page,
+                    status,
+                    diff_ratio: summary.page_ratios.get(index).copied().unwrap_or(0.0),
+                    severity: summary.page_severities.get(index).copied(),
+                    changed_regions,
+                    region_kinds: None,
+                    output_path: numbers[index].map(|number| diff_image_path(&summary.output_dir, title, number).display().to_string()),
+                }
+            })
+            .collect();
+
+        DiffReport {
+            title: title.to_string(),
+            old_pdf: summary.old_pdf.clone(),
+            new_pdf: summary.new_pdf.clone(),
+            output_dir: summary.output_dir.clone(),
+            stats: DocumentStats {
+                total_pages: summary.total_pages,
+                changed_pages: summary.changed_pages,
+                blank_pages: summary.blank_pages.clone(),
+                approved_pages: summary.approved_pages.clone(),
+                changed_area: summary.changed_area,
+            },
+            pages,
+            warnings: summary.warnings.clone(),
+            sampling: summary.sampling.clone(),
+            pdfium: summary.pdfium.clone(),
+            paired_by_text: !summary.page_pairs.is_empty(),
+            reordered: summary.reordered,
+            interrupted: summary.interrupted,
+            timings: Some(timings),
+            metadata: None,
+        }
+    }
+
+    // Classify the changed regions of every page both sides were compared on.
+    // `contents` holds the text and images of the pages at each position, and
+    // the renders are at `dpi`.
+    pub fn classify_regions(&mut self, images: &[(Option<DynamicImage>, Option<DynamicImage>)], contents: &[PageContent], dpi: f32) {
+        for ((page, (old_image, new_image)), content) in self.pages.iter_mut().zip(images).zip(contents) {
+            page.region_kinds = match (old_image, new_image, page.changed_regions) {
+                (_, _, Some(0)) => Some(RegionKinds::default()),
+                (Some(old), Some(new), Some(_)) => Some(classify_regions(&find_changed_regions(old, new, PIXEL_TOLERANCE), dpi, content)),
+                _ => None,
+            };
+        }
+    }
+
+    // Warnings about a 1-based page
+    pub fn page_warnings(&self, page: usize) -> impl Iterator<Item = &str> {
+        self.warnings.iter().filter(move |warning| warning.page == page).map(|warning| warning.message.as_str())
+    }
+}
+
+// Render and compare two PDFs in memory, without writing any outputs. For
+// embedders that want the numbers `pdf_diff diff` reports.
+pub fn compare_documents(pdfium: &Pdfium, old_pdf: &Path, new_pdf: &Path, options: &RenderOptions) -> Result<DiffReport, Box<dyn Error>> {
+    compare_documents_with(pdfium, old_pdf, new_pdf, options, None)
+}
+
+// Like `compare_documents`, with pages matched up by `pairing` in place of
+// `options.pairing`
+pub fn compare_documents_paired(
+    pdfium: &Pdfium,
+    old_pdf: &Path,
+    new_pdf: &Path,
+    options: &RenderOptions,
+    pairing: &PagePairing,
+) -> Result<DiffReport, Box<dyn Error>> {
+    compare_documents_with(pdfium, old_pdf, new_pdf, options, Some(pairing))
+}
+
+fn compare_documents_with(
+    pdfium: &Pdfium,
+    old_pdf: &Path,
+    new_pdf: &Path,
+    options: &RenderOptions,
+    pairing: Option<&PagePairing>,
+) -> Result<DiffReport, Box<dyn Error>> {
+    let started = Instant::now();
+    let (old_document, new_document) = load_pdf_documents(pdfium, old_pdf, new_pdf)?;
+    let paired_options;
+    let options = match pairing {
+        Some(pairing) => {
+            paired_options = RenderOptions {
+                pairing: Some(pairing.pair(&old_document, &new_document)?),
+                ..options.clone()
+            };
+            &paired_options
+        }
+        None => options,
+    };
+    let images = create_images_from_pdf_with_options(&old_document, &new_document, options)?;
+    let render_seconds = started.elapsed().as_secs_f64();
+
+    let started = Instant::now();
+    let title = old_pdf.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+    let mut summary = ComparisonSummary::new(&old_pdf.to_string_lossy(), &new_pdf.to_string_lossy(), "", &images, options.dpi);
+    summary.page_severities = page_severities(&images, &SeverityWeights::default());
+    let mut report = DiffReport::new(&images, &summary, title, None, Timings::default());
+    let pairing = match &options.pairing {
+        Some(pairing) => pairing.clone(),
+        None => index_pairing(old_document.pages().len() as usize, new_document.pages().len() as usize),
+    };
+    let contents = pair_contents(&old_document, Some(&new_document), &pairing, &images)?;
+    report.classify_regions(&images, &contents, options.dpi);
+    report.timings = Some(Timings {
+        render_seconds,
+        diff_seconds: started.elapsed().as_secs_f64(),
+    });
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::create_solid_color_image;
+    use image::Rgba;
+
+    #[test]
+    fn test_diff_report() {
+        let solid = |color| Some(create_solid_color_image(20, 20, Rgba(color)));
+        let red = solid([255, 0, 0, 255]);
+        let green = solid([0, 255, 0, 255]);
+        let images = vec![(red.clone(), red.clone()), (red, green.clone()), (None, green), (None, None)];
+        let mut summary = ComparisonSummary::new("a.pdf", "b.pdf", "out", &images, 72.0);
+        summary.warnings = vec![PageWarning {
+            page: 2,
+            message: "Font 'Arial' is not embedded".to_string(),
+        }];
+        summary.approve_pages(&[3]);
+
+        let report = DiffReport::new(&images, &summary, "doc", Some((DiffOutput::DiffOnly, Layout::Separate)), Timings::default());
+        assert_eq!(report.stats.changed_pages, 1);
+        assert_eq!(report.stats.approved_pages, vec![3]);
+        assert!(!report.paired_by_text);
+        assert!(!report.reordered);
+
+        let statuses: Vec<PageStatus> = report.pages.iter().map(|page| page.status).collect();
+        assert_eq!(statuses, vec![PageStatus::Unchanged, PageStatus::Changed, PageStatus::Approved, PageStatus::Skipped]);
+        assert_eq!(report.pages[1].changed_regions, Some(1));
+        assert_eq!(report.pages[1].output_path.as_deref(), Some("out/doc_1.png"));
+        assert_eq!((report.pages[2].old_page, report.pages[2].new_page), (None, Some(3)));
+        assert_eq!(report.pages[2].changed_regions, None);
+        assert_eq!(report.pages[0].output_path, None);
+        assert_eq!(report.pages[1].severity, None, "The summary has no severities");
+        assert_eq!(report.page_warnings(2).collect::<Vec<_>>(), vec!["Font 'Arial' is not embedded"]);
+
+        // The second page is covered by text
+        let mut report = report;
+        let text = PageContent {
+            text: vec![crate::ignore::IgnoreRegion { x: 0.0, y: 0.0, width: 20.0, height: 20.0 }],
+            ..PageContent::default()
+        };
+        report.classify_regions(&images, &[PageContent::default(), text, PageContent::default()], 72.0);
+        assert_eq!(report.pages[0].region_kinds, Some(RegionKinds::default()));
+        assert_eq!(report.pages[1].region_kinds, Some(RegionKinds { text: 1, image: 0, graphic: 0 }));
+        assert_eq!(report.pages[2].region_kinds, None);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["pages"][2]["status"], "approved");
+        assert_eq!(json["stats"]["total_pages"], 4);
+        let parsed: DiffReport = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, report);
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use image::DynamicImage;
+
+use crate::image_utils::is_blank;
+use crate::report::PageWarning;
+
+// Hash of a page render's size and pixels. Pages with the same content render
+// to the same pixels, so equal hashes mean duplicated pages.
+pub fn render_hash(img: &DynamicImage) -> u64 {
+    let rgba = img.to_rgba8();
+    let mut hasher = DefaultHasher::new();
+    rgba.dimensions().hash(&mut hasher);
+    rgba.as_raw().hash(&mut hasher);
+    hasher.finish()
+}
+
+// Render hashes of one side of the page pairs. Blank pages are left out: they
+// are reported on their own and duplex filler pages would all match.
+fn side_hashes<'a>(renders: impl Iterator<Item = Option<&'a DynamicImage>>) -> Vec<Option<u64>> {
+    renders
+        .map(|render| render.filter(|img| !is_blank(img)).map(render_hash))
+        .collect()
+}
+
+// Map each hash to the first 0-based position it appears at
+fn first_positions(hashes: &[Option<u64>]) -> HashMap<u64, usize> {
+    let mut first = HashMap::new();
+    for (position, hash) in hashes.iter().enumerate() {
+        if let Some(hash) = hash {
+            first.entry(*hash).or_insert(position);
+        }
+    }
+    first
+}
+
+// Positions whose old and new renders both repeat an earlier position's, so
+// diffing them again would only repeat that page's result. Returned as
+// (0-based position, 0-based earlier position).
+pub fn redundant_pairs(images: &[(Option<DynamicImage>, Option<DynamicImage>)]) -> Vec<(usize, usize)> {
+    let old = side_hashes(images.iter().map(|(old, _)| old.as_ref()));
+    let new = side_hashes(images.iter().map(|(_, new)| new.as_ref()));
+
+    let mut seen = HashMap::new();
+    let mut redundant = vec![];
+    for (position, pair) in old.iter().zip(&new).enumerate() {
+        if let (Some(_), Some(_)) = pair {
+            match seen.get(&pair) {
+                Some(&earlier) => redundant.push((position, earlier)),
+                None => {
+                    seen.insert(pair, position);
+                }
+            }
+        }
+    }
+    redundant
+}
+
+// Warnings for pages that repeat an earlier page of the same document, and for
+// changed new pages that match an old page elsewhere (usually a moved page)
+pub fn duplicate_page_warnings(images: &[(Option<DynamicImage>, Option<DynamicImage>)]) -> Vec<PageWarning> {
+    let old = side_hashes(images.iter().map(|(old, _)| old.as_ref()));
+    let new = side_hashes(images.iter().map(|(_, new)| new.as_ref()));
+    let mut warnings = vec![];
+
+    for (label, hashes) in [("old", &old), ("new", &new)] {
+        let first = first_positions(hashes);
+        for (position, hash) in hashes.iter().enumerate() {
+            if let Some(hash) = hash
+                && first[hash] != position
+            {
+                warnings.push(PageWarning {
+                    page: position + 1,
+                    message: format!("Page duplicates page {} of the {} PDF", first[hash] + 1, label),
+                });
+            }
+        }
+    }
+
+    // Only the first copy of a duplicated new page is checked for a move
+    let (old_first, new_first) = (first_positions(&old), first_positions(&new));
+    for (position, (old_hash, new_hash)) in old.iter().zip(&new).enumerate() {
+        if let Some(new_hash) = new_hash
+            && new_first[new_hash] == position
+            && old_hash.as_ref() != Some(new_hash)
+            && let Some(&old_position) = old_first.get(new_hash)
+            && old_position != position
+        {
+            warnings.push(PageWarning {
+                page: position + 1,
+                message: format!("New page is identical to page {} of the old PDF (moved page?)", old_position + 1),
+            });
+        }
+    }
+
+    warnings.sort_by_key(|warning| warning.page);
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::create_solid_color_image;
+    use image::Rgba;
+
+    #[test]
+    fn test_duplicate_page_warnings_within_document() {
+        let solid = |color| Some(create_solid_color_image(4, 4, Rgba(color)));
+        let (red, green, white) = ([255, 0, 0, 255], [0, 255, 0, 255], [255, 255, 255, 255]);
+        let images = vec![
+            (solid(red), solid(red)),
+            (solid(white), solid(white)),
+            (solid(white), solid(red)),
+            (solid(green), solid(green)),
+        ];
+
+        let warnings = duplicate_page_warnings(&images);
+        let messages: Vec<(usize, &str)> = warnings.iter().map(|w| (w.page, w.message.as_str())).collect();
+        assert_eq!(messages, vec![(3, "Page duplicates page 1 of the new PDF")], "Blank pages are never duplicates");
+    }
+
+    #[test]
+    fn test_duplicate_page_warnings_moved_page() {
+        let solid = |color| Some(create_solid_color_image(4, 4, Rgba(color)));
+        let (red, green) = ([255, 0, 0, 255], [0, 255, 0, 255]);
+        let images = vec![(solid(red), solid(green)), (solid(green), None)];
+
+        let warnings = duplicate_page_warnings(&images);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].page, 1);
+        assert!(warnings[0].message.contains("page 2 of the old PDF"));
+    }
+
+    #[test]
+    fn test_redundant_pairs() {
+        let solid = |color| Some(create_solid_color_image(4, 4, Rgba(color)));
+        let (red, green) = ([255, 0, 0, 255], [0, 255, 0, 255]);
+        let images = vec![
+            (solid(red), solid(green)),
+            (solid(red), solid(red)),
+            (solid(red), solid(green)),
+            (None, solid(green)),
+        ];
+        assert_eq!(redundant_pairs(&images), vec![(2, 0)]);
+    }
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cancel::INTERRUPTED_EXIT_CODE;
+
+// What a run's exit status tells a script, so "different" can be told apart
+// from "broken"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExitCode {
+    // Every compared page matched
+    Identical,
+    // The documents differ, or a check on the differences failed them
+    Differences,
+    // The command line is invalid
+    Usage,
+    // An input is missing, unreadable or not a PDF
+    Input,
+    // A page couldn't be rendered
+    Rendering,
+    // Anything else, such as an output that couldn't be written
+    Internal,
+}
+
+impl ExitCode {
+    pub fn code(self) -> i32 {
+        match self {
+            ExitCode::Identical => 0,
+            ExitCode::Differences => 1,
+            ExitCode::Usage => 2,
+            ExitCode::Input => 3,
+            ExitCode::Rendering => 4,
+            ExitCode::Internal => 5,
+        }
+    }
+
+    pub fn exit(self) -> ! {
+        std::process::exit(self.code())
+    }
+}
+
+// Exit statuses as listed at the end of `--help`
+pub fn exit_codes_help() -> String {
+    let codes = [
+        (ExitCode::Identical.code(), "no differences"),
+        (ExitCode::Differences.code(), "differences found, or rejected by --fail-* options or --verdict-command"),
+        (ExitCode::Usage.code(), "usage error"),
+        (ExitCode::Input.code(), "input missing, unreadable or not a PDF"),
+        (ExitCode::Rendering.code(), "rendering error"),
+        (ExitCode::Internal.code(), "internal error, e.g. an output that couldn't be written"),
+        (INTERRUPTED_EXIT_CODE, "interrupted with Ctrl-C"),
+    ];
+    let mut help = "Exit status:".to_string();
+    for (code, meaning) in codes {
+        help.push_str(&format!("\n  {:<5}{}", code, meaning));
+    }
+    help
+}
+
+// An error that ends a run, with the exit status it ends it with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Failure {
+    pub code: ExitCode,
+    pub message: String,
+}
+
+impl Failure {
+    pub fn new(code: ExitCode, message: impl Into<String>) -> Failure {
+        Failure { code, message: message.into() }
+    }
+
+    pub fn usage(message: impl Into<String>) -> Failure {
+        Failure::new(ExitCode::Usage, message)
+    }
+
+    pub fn input(message: impl Into<String>) -> Failure {
+        Failure::new(ExitCode::Input, message)
+    }
+
+    pub fn rendering(message: impl Into<String>) -> Failure {
+        Failure::new(ExitCode::Rendering, message)
+    }
+
+    pub fn differences(message: impl Into<String>) -> Failure {
+        Failure::new(ExitCode::Differences, message)
+    }
+}
+
+// Errors are messages throughout the command line tool; those that don't say
+// otherwise are internal
+impl From<String> for Failure {
+    fn from(message: String) -> Failure {
+        Failure::new(ExitCode::Internal, message)
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        let codes: Vec<i32> = [ExitCode::Identical, ExitCode::Differences, ExitCode::Usage, ExitCode::Input, ExitCode::Rendering, ExitCode::Internal]
+            .iter()
+            .map(|code| code.code())
+            .collect();
+        assert_eq!(codes, vec![0, 1, 2, 3, 4, 5]);
+
+        let help = exit_codes_help();
+        assert!(help.contains("\n  3    input missing"), "{}", help);
+        assert!(help.contains("\n  130  interrupted"), "{}", help);
+
+        let failure: Failure = "Error writing CSV report".to_string().into();
+        assert_eq!(failure.code, ExitCode::Internal);
+        assert_eq!(Failure::input("Missing").to_string(), "Missing");
+    }
+}
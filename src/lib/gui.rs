@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use eframe::egui;
+use image::DynamicImage;
+
+use crate::regions::{highlight_changes_with, OverlayStyle, PIXEL_TOLERANCE};
+use crate::report::{ComparisonSummary, PageStatus};
+
+// How long each render stays up while flickering between old and new
+const FLICKER_INTERVAL: Duration = Duration::from_millis(500);
+
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 4.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum View {
+    Old,
+    New,
+    Diff,
+}
+
+// Window showing the renders of one comparison, page by page
+struct PreviewApp<'a> {
+    images: &'a [(Option<DynamicImage>, Option<DynamicImage>)],
+    statuses: Vec<PageStatus>,
+    // 0-based positions of the pages that were compared
+    pages: Vec<usize>,
+    current: usize,
+    view: View,
+    zoom: f32,
+    flicker: bool,
+    overlay: OverlayStyle,
+    // Textures are uploaded on first display and kept for going back
+    textures: HashMap<(usize, View), egui::TextureHandle>,
+}
+
+impl<'a> PreviewApp<'a> {
+    fn new(images: &'a [(Option<DynamicImage>, Option<DynamicImage>)], summary: &ComparisonSummary, overlay: OverlayStyle) -> PreviewApp<'a> {
+        let statuses: Vec<PageStatus> = images
+            .iter()
+            .enumerate()
+            .map(|(index, (old, new))| summary.page_status(index, old.as_ref(), new.as_ref()))
+            .collect();
+        let pages = (0..images.len()).filter(|&i| statuses[i] != PageStatus::Skipped).collect();
+
+        PreviewApp {
+            images,
+            statuses,
+            pages,
+            current: 0,
+            view: View::Diff,
+            zoom: 0.25,
+            flicker: false,
+            overlay,
+            textures: HashMap::new(),
+        }
+    }
+
+    fn is_change(&self, position: usize) -> bool {
+        matches!(self.statuses[position], PageStatus::Changed | PageStatus::Added | PageStatus::Removed)
+    }
+
+    // Move to the next (or previous) page that counts as a change
+    fn jump_to_change(&mut self, forward: bool) {
+        let found = if forward {
+            (self.current + 1..self.pages.len()).find(|&i| self.is_change(self.pages[i]))
+        } else {
+            (0..self.current).rev().find(|&i| self.is_change(self.pages[i]))
+        };
+        if let Some(found) = found {
+            self.current = found;
+        }
+    }
+
+    // Render shown for a page; pages on one side only show that side in every view
+    fn render(&self, position: usize, view: View) -> Option<DynamicImage> {
+        let (old, new) = &self.images[position];
+        match (view, old, new) {
+            (View::Diff, Some(old), Some(new)) => Some(highlight_changes_with(old, new, PIXEL_TOLERANCE, self.overlay)),
+            (View::Old, Some(old), _) => Some(old.clone()),
+            (View::New, _, Some(new)) => Some(new.clone()),
+            (_, Some(only), None) | (_, None, Some(only)) => Some(only.clone()),
+            _ => None,
+        }
+    }
+
+    fn texture(&mut self, ctx: &egui::Context, position: usize, view: View) -> Option<egui::TextureHandle> {
+        if let Some(texture) = self.textures.get(&(position, view)) {
+            return Some(texture.clone());
+        }
+
+        let rgba = self.render(position, view)?.to_rgba8();
+        let size = [rgba.width() as usize, rgba.height() as usize];
+        let image = egui::ColorImage::from_rgba_unmultiplied(size, rgba.as_raw());
+        let texture = ctx.load_texture(format!("page{}_{:?}", position + 1, view), image, egui::TextureOptions::LINEAR);
+        self.textures.insert((position, view), texture.clone());
+        Some(texture)
+    }
+
+    fn handle_keys(&mut self, ctx: &egui::Context) {
+        ctx.input(|input| {
+            if input.key_pressed(egui::Key::ArrowRight) || input.key_pressed(egui::Key::PageDown) {
+                self.current = (self.current + 1).min(self.pages.len().saturating_sub(1));
+            }
+            if input.key_pressed(egui::Key::ArrowLeft) || input.key_pressed(egui::Key::PageUp) {
+                self.current = self.current.saturating_sub(1);
+            }
+            if input.key_pressed(egui::Key::Space) {
+                self.flicker = !self.flicker;
+            }
+            if input.key_pressed(egui::Key::Plus) || input.key_pressed(egui::Key::Equals) {
+                self.zoom = (self.zoom * 1.25).min(MAX_ZOOM);
+            }
+            if input.key_pressed(egui::Key::Minus) {
+                self.zoom = (self.zoom / 1.25).max(MIN_ZOOM);
+            }
+            for (key, view) in [(egui::Key::Num1, View::Old), (egui::Key::Num2, View::New), (egui::Key::Num3, View::Diff)] {
+                if input.key_pressed(key) {
+                    self.view = view;
+                    self.flicker = false;
+                }
+            }
+        });
+    }
+}
+
+impl eframe::App for PreviewApp<'_> {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.handle_keys(ctx);
+
+        egui::TopBottomPanel::top("toolbar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("◀").clicked() {
+                    self.current = self.current.saturating_sub(1);
+                }
+                if let Some(&position) = self.pages.get(self.current) {
+                    ui.label(format!("Page {} of {} ({})", position + 1, self.images.len(), self.statuses[position].label()));
+                }
+                if ui.button("▶").clicked() {
+                    self.current = (self.current + 1).min(self.pages.len().saturating_sub(1));
+                }
+                if ui.button("Previous change").clicked() {
+                    self.jump_to_change(false);
+                }
+                if ui.button("Next change").clicked() {
+                    self.jump_to_change(true);
+                }
+
+                ui.separator();
+                ui.add_enabled_ui(!self.flicker, |ui| {
+                    ui.selectable_value(&mut self.view, View::Old, "Old");
+                    ui.selectable_value(&mut self.view, View::New, "New");
+                    ui.selectable_value(&mut self.view, View::Diff, "Diff");
+                });
+                ui.checkbox(&mut self.flicker, "Flicker");
+
+                ui.separator();
+                ui.add(egui::Slider::new(&mut self.zoom, MIN_ZOOM..=MAX_ZOOM).logarithmic(true).text("Zoom"));
+            });
+        });
+
+        let Some(&position) = self.pages.get(self.current) else {
+            egui::CentralPanel::default().show(ctx, |ui| ui.label("No pages were compared"));
+            return;
+        };
+
+        let view = if self.flicker {
+            ctx.request_repaint_after(FLICKER_INTERVAL);
+            let phase = (ctx.input(|input| input.time) / FLICKER_INTERVAL.as_secs_f64()) as u64;
+            if phase.is_multiple_of(2) { View::Old } else { View::New }
+        } else {
+            self.view
+        };
+
+        let texture = self.texture(ctx, position, view);
+        let zoom = self.zoom;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::both().show(ui, |ui| match texture {
+                Some(texture) => {
+                    let size = texture.size_vec2() * zoom;
+                    ui.add(egui::Image::new(egui::load::SizedTexture::new(texture.id(), size)));
+                }
+                None => {
+                    ui.label("Nothing to show for this page");
+                }
+            });
+        });
+    }
+}
+
+// Open a window showing the comparison's pages and block until it is closed
+pub fn show_preview(
+    images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+    summary: &ComparisonSummary,
+    title: &str,
+    overlay: OverlayStyle,
+) -> Result<(), String> {
+    let app = PreviewApp::new(images, summary, overlay);
+    let options = eframe::NativeOptions::default();
+    eframe::run_native(&format!("PDF diff: {}", title), options, Box::new(|_| Ok(Box::new(app))))
+        .map_err(|e| format!("Error opening preview window: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::create_solid_color_image;
+    use image::Rgba;
+
+    #[test]
+    fn test_preview_navigation() {
+        let solid = |color| Some(create_solid_color_image(4, 4, Rgba(color)));
+        let (red, green) = (solid([255, 0, 0, 255]), solid([0, 255, 0, 255]));
+        let images = vec![
+            (red.clone(), red.clone()),
+            (None, None),
+            (red.clone(), red.clone()),
+            (red.clone(), green.clone()),
+            (None, green),
+        ];
+        let summary = ComparisonSummary::new("a.pdf", "b.pdf", "out", &images, 72.0);
+        let mut app = PreviewApp::new(&images, &summary, OverlayStyle::default());
+
+        assert_eq!(app.pages, vec![0, 2, 3, 4], "Pages left out by sampling aren't shown");
+        app.jump_to_change(true);
+        assert_eq!(app.pages[app.current], 3);
+        app.jump_to_change(true);
+        assert_eq!(app.pages[app.current], 4);
+        app.jump_to_change(true);
+        assert_eq!(app.pages[app.current], 4, "Stays on the last change");
+        app.jump_to_change(false);
+        assert_eq!(app.pages[app.current], 3);
+
+        assert_eq!(app.render(4, View::Old), images[4].1, "An added page shows its new render");
+    }
+}
@@ -0,0 +1,218 @@
+use std::error::Error;
+use std::path::Path;
+
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::Serialize;
+
+use crate::report::ComparisonSummary;
+
+// Version of the schema below, kept in the database's `user_version`
+const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    recorded TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS comparisons (
+    id INTEGER PRIMARY KEY,
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    old_pdf TEXT NOT NULL,
+    new_pdf TEXT NOT NULL,
+    total_pages INTEGER NOT NULL,
+    changed_pages INTEGER NOT NULL,
+    changed_area REAL NOT NULL,
+    error TEXT,
+    incomplete INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS pages (
+    comparison_id INTEGER NOT NULL REFERENCES comparisons (id),
+    page INTEGER NOT NULL,
+    diff_ratio REAL NOT NULL,
+    PRIMARY KEY (comparison_id, page)
+);
+CREATE INDEX IF NOT EXISTS comparisons_by_documents ON comparisons (old_pdf, new_pdf);
+";
+
+// One comparison of a pair of documents in one recorded run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrendPoint {
+    // When the run was recorded, in RFC 3339
+    pub recorded: String,
+    pub old_pdf: String,
+    pub new_pdf: String,
+    pub total_pages: usize,
+    pub changed_pages: usize,
+    // Mean and highest diff ratio of the compared pages
+    pub mean_ratio: f64,
+    pub max_ratio: f64,
+    pub error: Option<String>,
+    // Set when the run sampled pages or was interrupted
+    pub incomplete: bool,
+}
+
+// How often a page of a pair of documents had changed pixels
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageFrequency {
+    pub old_pdf: String,
+    pub new_pdf: String,
+    // 1-based position of the page in the comparison
+    pub page: usize,
+    // Runs in which the page had changed pixels, out of the runs that compared it
+    pub changed_runs: usize,
+    pub runs: usize,
+    pub mean_ratio: f64,
+}
+
+// Run summaries kept across runs in a SQLite database, for `--history`
+pub struct History {
+    connection: Connection,
+}
+
+impl History {
+    // Open the database at `path` for recording, creating it if needed
+    pub fn open(path: &Path) -> Result<History, Box<dyn Error>> {
+        let connection = Connection::open(path)?;
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(format!("its schema version {} is newer than this pdf_diff's {}", version, SCHEMA_VERSION).into());
+        }
+        connection.execute_batch(SCHEMA)?;
+        connection.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))?;
+        Ok(History { connection })
+    }
+
+    // Open an existing database for queries only
+    pub fn open_read_only(path: &Path) -> rusqlite::Result<History> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(History { connection })
+    }
+
+    // Add a run's comparisons, recorded at `recorded`. Returns the run's id.
+    pub fn record_run(&mut self, recorded: &str, summaries: &[ComparisonSummary]) -> rusqlite::Result<i64> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute("INSERT INTO runs (recorded) VALUES (?1)", params![recorded])?;
+        let run_id = transaction.last_insert_rowid();
+        for summary in summaries {
+            transaction.execute(
+                "INSERT INTO comparisons (run_id, old_pdf, new_pdf, total_pages, changed_pages, changed_area, error, incomplete)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    run_id,
+                    summary.old_pdf,
+                    summary.new_pdf,
+                    summary.total_pages as i64,
+                    summary.changed_pages as i64,
+                    summary.changed_area,
+                    summary.error,
+                    summary.sampling.is_some() || summary.interrupted
+                ],
+            )?;
+            let comparison_id = transaction.last_insert_rowid();
+            let mut insert = transaction.prepare_cached("INSERT INTO pages (comparison_id, page, diff_ratio) VALUES (?1, ?2, ?3)")?;
+            for (index, ratio) in summary.page_ratios.iter().enumerate() {
+                insert.execute(params![comparison_id, index as i64 + 1, ratio])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(run_id)
+    }
+
+    // Every recorded comparison of documents whose old or new path contains
+    // `document` (all of them without one), oldest first per pair
+    pub fn trend(&self, document: Option<&str>) -> rusqlite::Result<Vec<TrendPoint>> {
+        let mut statement = self.connection.prepare(
+            "SELECT r.recorded, c.old_pdf, c.new_pdf, c.total_pages, c.changed_pages,
+                    COALESCE(AVG(p.diff_ratio), 0), COALESCE(MAX(p.diff_ratio), 0), c.error, c.incomplete
+             FROM comparisons c
+             JOIN runs r ON r.id = c.run_id
+             LEFT JOIN pages p ON p.comparison_id = c.id
+             WHERE ?1 IS NULL OR instr(c.old_pdf, ?1) > 0 OR instr(c.new_pdf, ?1) > 0
+             GROUP BY c.id
+             ORDER BY c.old_pdf, c.new_pdf, r.id",
+        )?;
+        let points = statement.query_map(params![document], |row| {
+            Ok(TrendPoint {
+                recorded: row.get(0)?,
+                old_pdf: row.get(1)?,
+                new_pdf: row.get(2)?,
+                total_pages: row.get::<_, i64>(3)? as usize,
+                changed_pages: row.get::<_, i64>(4)? as usize,
+                mean_ratio: row.get(5)?,
+                max_ratio: row.get(6)?,
+                error: row.get(7)?,
+                incomplete: row.get(8)?,
+            })
+        })?;
+        points.collect()
+    }
+
+    // The `limit` pages that most often had changed pixels, of documents
+    // matched like `trend`
+    pub fn most_changed_pages(&self, document: Option<&str>, limit: usize) -> rusqlite::Result<Vec<PageFrequency>> {
+        let mut statement = self.connection.prepare(
+            "SELECT c.old_pdf, c.new_pdf, p.page, SUM(p.diff_ratio > 0) AS changed_runs, COUNT(*), AVG(p.diff_ratio) AS mean_ratio
+             FROM pages p
+             JOIN comparisons c ON c.id = p.comparison_id
+             WHERE ?1 IS NULL OR instr(c.old_pdf, ?1) > 0 OR instr(c.new_pdf, ?1) > 0
+             GROUP BY c.old_pdf, c.new_pdf, p.page
+             HAVING changed_runs > 0
+             ORDER BY changed_runs DESC, mean_ratio DESC, c.old_pdf, c.new_pdf, p.page
+             LIMIT ?2",
+        )?;
+        let pages = statement.query_map(params![document, limit as i64], |row| {
+            Ok(PageFrequency {
+                old_pdf: row.get(0)?,
+                new_pdf: row.get(1)?,
+                page: row.get::<_, i64>(2)? as usize,
+                changed_runs: row.get::<_, i64>(3)? as usize,
+                runs: row.get::<_, i64>(4)? as usize,
+                mean_ratio: row.get(5)?,
+            })
+        })?;
+        pages.collect()
+    }
+
+    // When the last run was recorded, if any was
+    pub fn last_recorded(&self) -> rusqlite::Result<Option<String>> {
+        self.connection
+            .query_row("SELECT recorded FROM runs ORDER BY id DESC LIMIT 1", [], |row| row.get(0))
+            .optional()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::summary_with_ratios;
+
+    #[test]
+    fn test_history() {
+        let path = std::env::temp_dir().join("diff_pdf_test_history.sqlite");
+        std::fs::remove_file(&path).ok();
+
+        let mut history = History::open(&path).unwrap();
+        let first = [summary_with_ratios("old.pdf", "a.pdf", vec![0.0, 0.2]), summary_with_ratios("old.pdf", "b.pdf", vec![0.5])];
+        history.record_run("2024-05-01T09:00:00Z", &first).unwrap();
+        history.record_run("2024-05-02T09:00:00Z", &[summary_with_ratios("old.pdf", "a.pdf", vec![0.1, 0.4])]).unwrap();
+        drop(history);
+
+        let history = History::open_read_only(&path).unwrap();
+        assert_eq!(history.last_recorded().unwrap().as_deref(), Some("2024-05-02T09:00:00Z"));
+        let trend = history.trend(Some("a.pdf")).unwrap();
+        assert_eq!(trend.len(), 2);
+        assert_eq!((trend[0].mean_ratio, trend[0].max_ratio), (0.1, 0.2));
+        assert_eq!((trend[1].recorded.as_str(), trend[1].changed_pages), ("2024-05-02T09:00:00Z", 2));
+        assert_eq!(history.trend(None).unwrap().len(), 3);
+
+        let pages = history.most_changed_pages(Some("a.pdf"), 10).unwrap();
+        assert_eq!((pages[0].page, pages[0].changed_runs, pages[0].runs), (2, 2, 2));
+        assert_eq!((pages[1].page, pages[1].changed_runs, pages[1].runs), (1, 1, 2));
+        assert_eq!(history.most_changed_pages(None, 1).unwrap().len(), 1);
+
+        // Reopening for recording keeps what is there
+        History::open(&path).unwrap().record_run("2024-05-03T09:00:00Z", &[]).unwrap();
+        assert_eq!(History::open_read_only(&path).unwrap().trend(None).unwrap().len(), 3);
+        std::fs::remove_file(&path).ok();
+    }
+}
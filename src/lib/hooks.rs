@@ -0,0 +1,73 @@
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set when stdout is for machine-readable output alone
+static OUTPUT_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+// Send the output of user commands to stderr for the rest of the run, so it
+// can't get mixed into `--json` output
+pub fn set_output_to_stderr(enabled: bool) {
+    OUTPUT_TO_STDERR.store(enabled, Ordering::Relaxed);
+}
+
+// Shell running a user command line
+pub fn shell(command_line: &str) -> Command {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C");
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    command.arg(command_line);
+    if OUTPUT_TO_STDERR.load(Ordering::Relaxed) {
+        command.stdout(std::io::stderr());
+    }
+    command
+}
+
+// Shell running a user command line with paths appended as arguments. On Unix
+// the paths are passed as positional parameters, so they reach the command
+// unchanged whatever characters they contain.
+fn shell_with_arguments(command_line: &str, arguments: &[String]) -> Command {
+    if cfg!(windows) {
+        let mut line = command_line.to_string();
+        for argument in arguments {
+            line.push_str(&format!(" \"{}\"", argument));
+        }
+        shell(&line)
+    } else {
+        let mut command = shell(&format!("{} \"$@\"", command_line));
+        command.arg("pdf_diff").args(arguments);
+        command
+    }
+}
+
+// Run a `--pre-hook` or `--post-hook` command with `arguments`, failing the
+// run when it exits with a non-zero status. Its output goes to the terminal.
+pub fn run_hook(name: &str, command_line: &str, arguments: &[String]) -> Result<(), String> {
+    let status = shell_with_arguments(command_line, arguments)
+        .status()
+        .map_err(|e| format!("Failed to start {} '{}': {}", name, command_line, e))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(format!("{} '{}' failed ({})", name, command_line, status)),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_hook() {
+        let arguments = vec!["old file.pdf".to_string(), "new $HOME.pdf".to_string()];
+        assert!(run_hook("Pre-hook", "f() { test \"$1\" = 'old file.pdf' && test \"$2\" = 'new $HOME.pdf'; }; f", &arguments).is_ok());
+
+        let error = run_hook("Post-hook", "exit 4;", &[]).unwrap_err();
+        assert!(error.starts_with("Post-hook 'exit 4;' failed"), "{}", error);
+        assert!(error.contains("exit status: 4"), "{}", error);
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+// Language of terminal messages and report labels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Language {
+    #[default]
+    English,
+    German,
+    French,
+}
+
+pub const LANGUAGES: [Language; 3] = [Language::English, Language::German, Language::French];
+
+impl Language {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+            Language::French => "fr",
+        }
+    }
+
+    // Message catalog in Fluent syntax, compiled into the binary
+    fn source(&self) -> &'static str {
+        match self {
+            Language::English => include_str!("../../locales/en.ftl"),
+            Language::German => include_str!("../../locales/de.ftl"),
+            Language::French => include_str!("../../locales/fr.ftl"),
+        }
+    }
+
+    // Language of a POSIX locale such as `de_DE.UTF-8`, from the first of
+    // `LC_ALL`, `LC_MESSAGES` and `LANG` that is set. `None` for the `C`
+    // locale and languages without a catalog.
+    pub fn from_locale(env: &dyn Fn(&str) -> Option<String>) -> Option<Language> {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"].iter().find_map(|name| env(name).filter(|value| !value.is_empty()))?;
+        locale.parse().ok()
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    // Accepts a language code with an optional region and encoding, e.g. `de`,
+    // `de-AT` or `de_AT.UTF-8`
+    fn from_str(value: &str) -> Result<Language, String> {
+        let primary = value.split(['-', '_', '.', '@']).next().unwrap_or_default().to_lowercase();
+        LANGUAGES
+            .into_iter()
+            .find(|language| language.code() == primary)
+            .ok_or_else(|| {
+                let codes: Vec<&str> = LANGUAGES.iter().map(Language::code).collect();
+                format!("Unsupported language '{}', expected one of {}", value, codes.join(", "))
+            })
+    }
+}
+
+impl Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+// Messages of a catalog by id. Only the part of Fluent the catalogs use is
+// understood: `# comments` and single-line `id = text` messages with
+// `{ $name }` placeables.
+pub fn parse_catalog(source: &str) -> HashMap<&str, &str> {
+    source
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(id, text)| (id.trim(), text.trim()))
+        .filter(|(id, _)| !id.is_empty())
+        .collect()
+}
+
+// Fill in the `{ $name }` placeables of a message; unknown names are left as written
+pub fn format_message(text: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut message = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        message.push_str(&rest[..start]);
+        let placeable = &rest[start..=start + end];
+        let name = placeable[1..placeable.len() - 1].trim().trim_start_matches('$');
+        match args.iter().find(|(arg, _)| *arg == name) {
+            Some((_, value)) => message.push_str(&value.to_string()),
+            None => message.push_str(placeable),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    message.push_str(rest);
+    message
+}
+
+static LANGUAGE: OnceLock<Language> = OnceLock::new();
+static CATALOGS: [OnceLock<HashMap<&str, &str>>; LANGUAGES.len()] = [OnceLock::new(), OnceLock::new(), OnceLock::new()];
+
+// Pick the language for the rest of the run; only the first call counts
+pub fn set_language(language: Language) {
+    LANGUAGE.set(language).ok();
+}
+
+pub fn language() -> Language {
+    LANGUAGE.get().copied().unwrap_or_default()
+}
+
+fn catalog(language: Language) -> &'static HashMap<&'static str, &'static str> {
+    let index = LANGUAGES.iter().position(|&known| known == language).expect("every language has a catalog");
+    CATALOGS[index].get_or_init(|| parse_catalog(language.source()))
+}
+
+// Message `id` in the chosen language, falling back to English and then to the id itself
+pub fn message(id: &str, args: &[(&str, &dyn Display)]) -> String {
+    let text = catalog(language())
+        .get(id)
+        .or_else(|| catalog(Language::English).get(id))
+        .copied()
+        .unwrap_or(id);
+    format_message(text, args)
+}
+
+// `tr!("id", name = value, ...)`: message `id` with its placeables filled in
+#[macro_export]
+macro_rules! tr {
+    ($id:expr $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::message(&$id, &[$((stringify!($name), &$value as &dyn ::std::fmt::Display)),*])
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_from_str() {
+        assert_eq!("de".parse(), Ok(Language::German));
+        assert_eq!("fr-CA".parse(), Ok(Language::French));
+        assert_eq!("EN_us.UTF-8".parse(), Ok(Language::English));
+        assert!("xx".parse::<Language>().is_err());
+    }
+
+    #[test]
+    fn test_language_from_locale() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string())
+        };
+        assert_eq!(Language::from_locale(&env(&[("LANG", "de_DE.UTF-8")])), Some(Language::German));
+        assert_eq!(Language::from_locale(&env(&[("LC_ALL", "fr_FR"), ("LANG", "de_DE")])), Some(Language::French));
+        assert_eq!(Language::from_locale(&env(&[("LC_ALL", ""), ("LANG", "de_DE")])), Some(Language::German));
+        assert_eq!(Language::from_locale(&env(&[("LANG", "C.UTF-8")])), None);
+        assert_eq!(Language::from_locale(&env(&[])), None);
+    }
+
+    #[test]
+    fn test_format_message() {
+        let args: &[(&str, &dyn Display)] = &[("page", &3), ("name", &"a.pdf")];
+        assert_eq!(format_message("Page { $page } of {$name}", args), "Page 3 of a.pdf");
+        assert_eq!(format_message("Missing { $other }", args), "Missing { $other }");
+        assert_eq!(format_message("Unclosed { $page", args), "Unclosed { $page");
+    }
+
+    #[test]
+    fn test_catalogs_translate_every_message() {
+        let english = parse_catalog(Language::English.source());
+        assert_eq!(english.get("status-changed"), Some(&"changed"));
+        for language in LANGUAGES {
+            let catalog = parse_catalog(language.source());
+            for id in english.keys() {
+                assert!(catalog.contains_key(id), "{} catalog lacks '{}'", language, id);
+            }
+            assert_eq!(catalog.len(), english.len(), "{} catalog has messages English doesn't", language);
+        }
+    }
+
+    #[test]
+    fn test_messages_default_to_english() {
+        assert_eq!(tr!("cli-page-warning", page = 2, message = "Blank"), "Warning: page 2: Blank");
+        assert_eq!(message("no-such-message", &[]), "no-such-message");
+    }
+}
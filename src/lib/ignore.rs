@@ -0,0 +1,276 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use image::{DynamicImage, Rgba};
+use pdfium_render::prelude::PdfPage;
+
+use crate::sampling::PageRanges;
+use crate::text::page_text;
+
+// Project file of ignore rules, looked up in the working directory and its parents
+pub const IGNORE_FILE: &str = ".diffpdfignore";
+
+// Area of a page left out of comparisons, in points from the top-left corner
+// of the page like text segments
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IgnoreRegion {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl FromStr for IgnoreRegion {
+    type Err = String;
+
+    // Accepts `X,Y,WIDTH,HEIGHT`
+    fn from_str(value: &str) -> Result<IgnoreRegion, String> {
+        let invalid = || format!("Invalid region '{}', expected 'X,Y,WIDTH,HEIGHT' in points", value);
+        let numbers: Vec<f32> = value
+            .split(',')
+            .map(|part| part.trim().parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+
+        match numbers.as_slice() {
+            &[x, y, width, height] if width > 0.0 && height > 0.0 => Ok(IgnoreRegion { x, y, width, height }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IgnoreRule {
+    Region(IgnoreRegion),
+    // Text segments matching this pattern (`*` and `?` wildcards) are masked
+    Text(String),
+}
+
+// Rules of one `[FILES pages=RANGES]` section of an ignore file. Rules before
+// the first section header apply to every document and page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IgnoreSection {
+    pub files: Option<String>,
+    pub pages: Option<PageRanges>,
+    pub rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreSection {
+    // Whether the section covers a 1-based page of a comparison of the two files
+    pub fn applies_to(&self, old_pdf: &str, new_pdf: &str, page: usize) -> bool {
+        let files = match &self.files {
+            Some(files) => matches_document(files, old_pdf) || matches_document(files, new_pdf),
+            None => true,
+        };
+        files && self.pages.as_ref().is_none_or(|pages| pages.contains(page))
+    }
+}
+
+// Shell-style wildcard match: `*` matches any run of characters, `?` any one
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` and the text position it currently covers up to
+    let mut backtrack = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p + 1, t));
+            p += 1;
+        } else if let Some((star, covered)) = backtrack {
+            p = star;
+            t = covered + 1;
+            backtrack = Some((star, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Patterns without a slash match the file name, others the path as given
+fn matches_document(pattern: &str, path: &str) -> bool {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    if pattern.contains('/') {
+        glob_match(pattern.strip_prefix("./").unwrap_or(pattern), path)
+    } else {
+        let name = Path::new(path).file_name().and_then(|name| name.to_str()).unwrap_or(path);
+        glob_match(pattern, name)
+    }
+}
+
+// Parse a section header's contents, e.g. `reports/*.pdf pages=1-2`
+fn parse_header(header: &str) -> Result<IgnoreSection, String> {
+    let mut section = IgnoreSection { files: None, pages: None, rules: vec![] };
+    for part in header.split_whitespace() {
+        match part.strip_prefix("pages=") {
+            Some(pages) => section.pages = Some(pages.parse()?),
+            None if section.files.is_none() => section.files = Some(part.to_string()),
+            None => return Err(format!("Unexpected '{}' in section header", part)),
+        }
+    }
+    Ok(section)
+}
+
+pub fn parse_ignore_file(text: &str) -> Result<Vec<IgnoreSection>, String> {
+    let mut sections = vec![IgnoreSection { files: None, pages: None, rules: vec![] }];
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let at_line = |message: String| format!("line {}: {}", number + 1, message);
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            sections.push(parse_header(header).map_err(at_line)?);
+            continue;
+        }
+
+        let rule = match line.split_once(char::is_whitespace) {
+            Some(("region", region)) => IgnoreRule::Region(region.parse().map_err(at_line)?),
+            Some(("text", pattern)) => IgnoreRule::Text(pattern.trim().to_string()),
+            _ => return Err(at_line(format!("Expected 'region X,Y,WIDTH,HEIGHT' or 'text PATTERN', got '{}'", line))),
+        };
+        sections.last_mut().expect("there is always a section").rules.push(rule);
+    }
+
+    sections.retain(|section| !section.rules.is_empty());
+    Ok(sections)
+}
+
+pub fn load_ignore_file(path: &Path) -> Result<Vec<IgnoreSection>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)?;
+    parse_ignore_file(&text).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+// The nearest ignore file in `dir` or one of its parents, so every job run
+// anywhere in a repository picks up the same rules
+pub fn find_ignore_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors().map(|dir| dir.join(IGNORE_FILE)).find(|path| path.is_file())
+}
+
+// Regions covered by text segments of a page (1-based `number`) that match any
+// of the patterns
+pub fn text_regions(page: &PdfPage, number: usize, patterns: &[String]) -> Result<Vec<IgnoreRegion>, Box<dyn Error>> {
+    if patterns.is_empty() {
+        return Ok(vec![]);
+    }
+
+    Ok(page_text(page, number, true)?
+        .segments
+        .iter()
+        .filter(|segment| patterns.iter().any(|pattern| glob_match(pattern, segment.text.trim())))
+        .map(|segment| IgnoreRegion {
+            x: segment.x,
+            y: segment.y,
+            width: segment.width,
+            height: segment.height,
+        })
+        .collect())
+}
+
+// Paint the regions of a render rendered at `dpi` white, so whatever is there
+// never shows up as a difference
+pub fn mask_regions(img: &mut DynamicImage, regions: &[IgnoreRegion], dpi: f32) {
+    if regions.is_empty() {
+        return;
+    }
+
+    let scale = dpi / 72.0;
+    let mut rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    for region in regions {
+        let to_pixels = |points: f32, max: u32| ((points * scale).max(0.0) as u32).min(max);
+        let (left, top) = (to_pixels(region.x, width), to_pixels(region.y, height));
+        let right = to_pixels((region.x + region.width).max(0.0), width).max(left);
+        let bottom = to_pixels((region.y + region.height).max(0.0), height).max(top);
+        for y in top..bottom {
+            for x in left..right {
+                rgba.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+            }
+        }
+    }
+    *img = DynamicImage::ImageRgba8(rgba);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+    use std::fs;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.pdf", "invoice.pdf"));
+        assert!(glob_match("invoice-??.pdf", "invoice-07.pdf"));
+        assert!(glob_match("Printed on *", "Printed on 2024-05-01"));
+        assert!(glob_match("*a*b", "xxaxxab"));
+        assert!(!glob_match("*.pdf", "invoice.pdf.bak"));
+        assert!(!glob_match("invoice-?.pdf", "invoice-07.pdf"));
+
+        assert!(matches_document("*.pdf", "./out/v2/invoice.pdf"));
+        assert!(matches_document("v2/*.pdf", "./v2/invoice.pdf"));
+        assert!(!matches_document("v1/*.pdf", "v2/invoice.pdf"));
+    }
+
+    #[test]
+    fn test_parse_ignore_file() {
+        let text = "\
+# Every page carries the render date
+text Generated on *
+
+[invoices/*.pdf pages=1-2]
+region 400, 36, 150, 20
+
+[pages=3]
+";
+        let sections = parse_ignore_file(text).unwrap();
+        assert_eq!(sections.len(), 2, "Sections without rules are dropped");
+        assert_eq!(sections[0].rules, vec![IgnoreRule::Text("Generated on *".to_string())]);
+        assert_eq!(sections[1].files.as_deref(), Some("invoices/*.pdf"));
+        assert_eq!(
+            sections[1].rules,
+            vec![IgnoreRule::Region(IgnoreRegion { x: 400.0, y: 36.0, width: 150.0, height: 20.0 })]
+        );
+
+        assert!(sections[0].applies_to("a.pdf", "b.pdf", 7));
+        assert!(sections[1].applies_to("old/x.pdf", "invoices/x.pdf", 2));
+        assert!(!sections[1].applies_to("old/x.pdf", "invoices/x.pdf", 3));
+        assert!(!sections[1].applies_to("a.pdf", "b.pdf", 1));
+
+        let error = parse_ignore_file("\n\nregion 1,2,3").unwrap_err();
+        assert!(error.starts_with("line 3:"), "{}", error);
+        assert!(parse_ignore_file("mask 1,2,3,4").is_err());
+        assert!(parse_ignore_file("[a.pdf b.pdf]").is_err());
+    }
+
+    #[test]
+    fn test_find_ignore_file() {
+        let test_dir = Path::new("test_output_ignore");
+        fs::remove_dir_all(test_dir).ok();
+        fs::create_dir_all(test_dir.join("jobs/nightly")).unwrap();
+        fs::write(test_dir.join(IGNORE_FILE), "text *").unwrap();
+
+        assert_eq!(find_ignore_file(&test_dir.join("jobs/nightly")), Some(test_dir.join(IGNORE_FILE)));
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_mask_regions() {
+        let mut img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(20, 20, Rgba([0, 0, 0, 255])));
+        let region = IgnoreRegion { x: 3.6, y: 0.0, width: 3.6, height: 100.0 };
+
+        // At 144 DPI a point is two pixels
+        mask_regions(&mut img, &[region], 144.0);
+        let rgba = img.to_rgba8();
+        assert_eq!(*rgba.get_pixel(6, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*rgba.get_pixel(7, 19), Rgba([255, 255, 255, 255]));
+        assert_eq!(*rgba.get_pixel(13, 5), Rgba([255, 255, 255, 255]));
+        assert_eq!(*rgba.get_pixel(14, 5), Rgba([0, 0, 0, 255]));
+    }
+}
@@ -1,97 +1,601 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
 use diff_img::lcs_diff;
-use image::{DynamicImage, GenericImageView};
+use image::{DynamicImage, GenericImageView, Pixel, Rgba};
+
+use crate::batch::{effective_jobs, run_parallel};
+use crate::budget::{save_png_within, OutputBudget};
+use crate::cancel::CancelToken;
+use crate::diff_core;
+use crate::label::{placeholder_page, with_banner};
+use crate::layout::{triptych, Layout};
+use crate::pdf::PagePair;
+use crate::regions::Region;
+
+// Banners on pages found in only one document, colored like the new and old
+// captions of a triptych
+const ADDED_BANNER: Rgba<u8> = Rgba([0, 130, 0, 255]);
+const REMOVED_BANNER: Rgba<u8> = Rgba([180, 0, 0, 255]);
+
+// Per-channel distance from pure white still treated as background
+pub const WHITE_TOLERANCE: u8 = 10;
+
+// Settings deciding which pixels count as page background when looking for content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentDetection {
+    // Allowed distance from white per channel; raise it to ignore light watermarks
+    pub white_tolerance: u8,
+    // Pixels with alpha at or below this are background whatever their colour
+    pub alpha_threshold: u8,
+}
+
+impl Default for ContentDetection {
+    fn default() -> ContentDetection {
+        ContentDetection {
+            white_tolerance: WHITE_TOLERANCE,
+            alpha_threshold: 0,
+        }
+    }
+}
+
+impl ContentDetection {
+    pub fn is_background(&self, pixel: &Rgba<u8>) -> bool {
+        let min = 255 - self.white_tolerance;
+        pixel[3] <= self.alpha_threshold || pixel.0.iter().all(|channel| *channel >= min)
+    }
+}
+
 // Crop a DynamicImage to its non-white content (tolerant to near-white)
 pub fn crop_to_content(img: &DynamicImage) -> DynamicImage {
+    crop_to_content_with(img, &ContentDetection::default())
+}
+
+// Crop a DynamicImage to the pixels that aren't background under `detection`
+pub fn crop_to_content_with(img: &DynamicImage, detection: &ContentDetection) -> DynamicImage {
+    match content_bbox(img, detection) {
+        Some(bbox) => crop_to_region(img, bbox),
+        None => img.clone(),
+    }
+}
+
+// Smallest rectangle holding every pixel that isn't background under
+// `detection`, or `None` for a page without content. Scans the raw rows inward
+// from each edge and stops at the first content, so a page with narrow margins
+// costs little more than its margins.
+pub fn content_bbox(img: &DynamicImage, detection: &ContentDetection) -> Option<Region> {
+    let rgba = diff_core::rgba(img);
+    let (width, height) = rgba.dimensions();
+    let stride = width as usize * 4;
+    let rows: Vec<&[u8]> = rgba.as_raw().chunks_exact(stride.max(1)).take(height as usize).collect();
+    let is_content = |pixel: &[u8]| !detection.is_background(Rgba::from_slice(pixel));
+    let has_content = |row: &&[u8]| row.chunks_exact(4).any(is_content);
+
+    let top = rows.iter().position(has_content)?;
+    let bottom = rows.iter().rposition(has_content)?;
+
+    // Each row only needs scanning up to the edges found so far
+    let (mut left, mut right) = (width as usize, None);
+    for row in &rows[top..=bottom] {
+        if let Some(x) = row[..left * 4].chunks_exact(4).position(is_content) {
+            left = x;
+        }
+        let from = right.map_or(0, |right| right + 1);
+        if let Some(x) = row[from * 4..].chunks_exact(4).rposition(is_content) {
+            right = Some(from + x);
+        }
+        if left == 0 && right == Some(width as usize - 1) {
+            break;
+        }
+    }
+    let right = right?;
+
+    Some(Region {
+        x: left as u32,
+        y: top as u32,
+        width: (right - left + 1) as u32,
+        height: (bottom - top + 1) as u32,
+    })
+}
+
+// Smallest rectangle holding both
+fn union_region(a: Region, b: Region) -> Region {
+    let (x, y) = (a.x.min(b.x), a.y.min(b.y));
+    Region {
+        x,
+        y,
+        width: (a.x + a.width).max(b.x + b.width) - x,
+        height: (a.y + a.height).max(b.y + b.height) - y,
+    }
+}
+
+// Crop to `region`, clipped to the image for renders of another size
+fn crop_to_region(img: &DynamicImage, region: Region) -> DynamicImage {
     let (width, height) = img.dimensions();
-    let rgba = img.to_rgba8();
-
-    let mut min_x = width;
-    let mut min_y = height;
-    let mut max_x = 0;
-    let mut max_y = 0;
-    let mut found = false;
-
-    // Tolerance for "white" (255,255,255,255). Allow a little off-white.
-    let tolerance = 10u8;
-
-    for y in 0..height {
-        for x in 0..width {
-            let pixel = rgba.get_pixel(x, y);
-            // If not close to white, mark as content
-            if !(pixel[0] >= 255 - tolerance
-                && pixel[1] >= 255 - tolerance
-                && pixel[2] >= 255 - tolerance
-                && pixel[3] >= 255 - tolerance)
-            {
-                min_x = min_x.min(x);
-                min_y = min_y.min(y);
-                max_x = max_x.max(x);
-                max_y = max_y.max(y);
-                found = true;
+    if region.x >= width || region.y >= height {
+        return img.clone();
+    }
+    let right = (region.x + region.width).min(width);
+    let bottom = (region.y + region.height).min(height);
+    img.crop_imm(region.x, region.y, right - region.x, bottom - region.y)
+}
+
+// Trim the margins of every page render. Both renders of a page are cropped to
+// the union of their content, so content that moved towards an edge in one of
+// them is kept in both instead of being cropped away from one.
+pub fn crop_pages_to_content(
+    images: Vec<(Option<DynamicImage>, Option<DynamicImage>)>,
+    detection: &ContentDetection,
+) -> Vec<(Option<DynamicImage>, Option<DynamicImage>)> {
+    images
+        .into_iter()
+        .map(|(old_image, new_image)| {
+            let old_bbox = old_image.as_ref().and_then(|img| content_bbox(img, detection));
+            let new_bbox = new_image.as_ref().and_then(|img| content_bbox(img, detection));
+            let bbox = match (old_bbox, new_bbox) {
+                (Some(old), Some(new)) => Some(union_region(old, new)),
+                (old, new) => old.or(new),
+            };
+            let crop = |img: DynamicImage| match bbox {
+                Some(bbox) => crop_to_region(&img, bbox),
+                None => img,
+            };
+            (old_image.map(crop), new_image.map(crop))
+        })
+        .collect()
+}
+
+// How hard the PNG encoder works to shrink saved images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum PngCompression {
+    /// Quick to encode, larger files
+    #[default]
+    Fast,
+    /// Zlib's default level
+    Default,
+    /// Smallest files, slowest to encode
+    Best,
+}
+
+// Row filter applied before compression
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum PngFilter {
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    /// Pick a filter per row
+    #[default]
+    Adaptive,
+}
+
+// Settings for saved PNGs. The defaults match what `save_png` has always
+// written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PngOptions {
+    pub compression: PngCompression,
+    pub filter: PngFilter,
+    // Factor images are resized by when saved, so pages can be compared at a
+    // high DPI but saved smaller; 1.0 keeps the comparison resolution
+    pub scale: f32,
+    // Resolution the images were rendered at, before `scale`. Recorded in a
+    // pHYs chunk so print tools show them at true size; without it viewers
+    // assume 72 DPI.
+    pub dpi: Option<f32>,
+}
+
+impl Default for PngOptions {
+    fn default() -> PngOptions {
+        PngOptions {
+            compression: PngCompression::default(),
+            filter: PngFilter::default(),
+            scale: 1.0,
+            dpi: None,
+        }
+    }
+}
+
+// Size of a `width` by `height` image once resized by `scale`
+pub fn scaled_size(width: u32, height: u32, scale: f32) -> (u32, u32) {
+    (((width as f32 * scale).round() as u32).max(1), ((height as f32 * scale).round() as u32).max(1))
+}
+
+// An image resized by `scale`, or the image itself at 1.0
+pub fn scale_image(img: &DynamicImage, scale: f32) -> std::borrow::Cow<'_, DynamicImage> {
+    if scale == 1.0 {
+        return std::borrow::Cow::Borrowed(img);
+    }
+    let (width, height) = scaled_size(img.width(), img.height(), scale);
+    std::borrow::Cow::Owned(img.resize_exact(width, height, image::imageops::FilterType::Triangle))
+}
+
+pub fn save_png(img: &DynamicImage, output_path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+    save_png_with(img, output_path, PngOptions::default())
+}
+
+pub fn save_png_with(img: &DynamicImage, output_path: impl AsRef<Path>, options: PngOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::fs::File::create(output_path)?;
+    write_png(img, std::io::BufWriter::new(file), options)
+}
+
+// Encode an image as PNG into any writer, e.g. a buffer or an HTTP response
+pub fn write_png<W: std::io::Write>(img: &DynamicImage, writer: W, options: PngOptions) -> Result<(), Box<dyn std::error::Error>> {
+    use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+
+    let compression = match options.compression {
+        PngCompression::Fast => CompressionType::Fast,
+        PngCompression::Default => CompressionType::Default,
+        PngCompression::Best => CompressionType::Best,
+    };
+    let filter = match options.filter {
+        PngFilter::None => FilterType::NoFilter,
+        PngFilter::Sub => FilterType::Sub,
+        PngFilter::Up => FilterType::Up,
+        PngFilter::Avg => FilterType::Avg,
+        PngFilter::Paeth => FilterType::Paeth,
+        PngFilter::Adaptive => FilterType::Adaptive,
+    };
+
+    let mut writer = ChunkInserter {
+        inner: writer,
+        chunk: options.dpi.map(|dpi| phys_chunk(dpi * options.scale)),
+        written: 0,
+    };
+    scale_image(img, options.scale).write_with_encoder(PngEncoder::new_with_quality(&mut writer, compression, filter))?;
+    Ok(())
+}
+
+// Length of the PNG signature and the IHDR chunk, which always comes first
+const PNG_HEADER_LEN: usize = 8 + 25;
+
+// A pHYs chunk giving the resolution in pixels per metre, which the `image`
+// encoder has no setting for
+fn phys_chunk(dpi: f32) -> Vec<u8> {
+    let pixels_per_metre = (dpi as f64 / 0.0254).round() as u32;
+    let mut body = b"pHYs".to_vec();
+    body.extend(pixels_per_metre.to_be_bytes());
+    body.extend(pixels_per_metre.to_be_bytes());
+    body.push(1); // unit: metre
+
+    let mut chunk = ((body.len() - 4) as u32).to_be_bytes().to_vec();
+    chunk.extend(&body);
+    chunk.extend(crc32fast::hash(&body).to_be_bytes());
+    chunk
+}
+
+// Passes an encoded PNG through, adding `chunk` right after the header
+struct ChunkInserter<W> {
+    inner: W,
+    chunk: Option<Vec<u8>>,
+    written: usize,
+}
+
+impl<W: std::io::Write> std::io::Write for ChunkInserter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Some(chunk) = &self.chunk {
+            if self.written < PNG_HEADER_LEN {
+                let count = self.inner.write(&buf[..buf.len().min(PNG_HEADER_LEN - self.written)])?;
+                self.written += count;
+                return Ok(count);
             }
+            self.inner.write_all(chunk)?;
+            self.chunk = None;
         }
+        self.inner.write(buf)
     }
 
-    if found {
-        img.crop_imm(min_x, min_y, max_x - min_x + 1, max_y - min_y + 1)
-    } else {
-        img.clone()
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
     }
 }
 
+// Images saved for each compared page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum DiffOutput {
+    /// The diff followed by the new page for changed pages, the page alone for all others
+    #[default]
+    DiffAndPage,
+    /// One image per changed page: the diff, or the page itself if added or removed
+    DiffOnly,
+}
+
+impl DiffOutput {
+    // Images saved for one page pair
+    pub fn image_count(self, old_image: Option<&DynamicImage>, new_image: Option<&DynamicImage>) -> usize {
+        match (old_image, new_image) {
+            (None, None) => 0,
+            (Some(_), Some(_)) => match (page_changed(old_image, new_image), self) {
+                (true, DiffOutput::DiffAndPage) => 2,
+                (false, DiffOutput::DiffOnly) => 0,
+                _ => 1,
+            },
+            _ => 1,
+        }
+    }
+}
+
+// Path `save_images` writes the 1-based `number`th image to
+pub fn diff_image_path(output_dir: &str, pdf_title: &str, number: usize) -> PathBuf {
+    Path::new(output_dir).join(format!("{}_{}.png", pdf_title, number))
+}
+
 pub fn save_images(
     images: Vec<DynamicImage>,
     pdf_title: &str,
     output_dir: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use image::ImageFormat;
-    use std::fs::File;
-    use std::io::BufWriter;
+    std::fs::create_dir_all(output_dir)?;
+
+    // PNG encoding dominates for high-DPI pages, so images are encoded on
+    // one thread per CPU
+    let numbered: Vec<(usize, &DynamicImage)> = images.iter().enumerate().map(|(i, img)| (i + 1, img)).collect();
+    let results = run_parallel(&numbered, effective_jobs(0), |&(number, img)| {
+        let output_path = diff_image_path(output_dir, pdf_title, number);
+        save_png(img, &output_path).map_err(|e| format!("{}: {}", output_path.display(), e))?;
+        println!("Saved diff image to {}", output_path.display());
+        Ok::<(), String>(())
+    });
+
+    results.into_iter().collect::<Result<(), String>>()?;
+    Ok(())
+}
+
+// Outcome of `diff_and_save_images`
+#[derive(Debug, Default)]
+pub struct SavedDiffs {
+    pub written: usize,
+    // Pages whose diff failed, as (1-based page, message)
+    pub failures: Vec<(usize, String)>,
+    // 0-based positions left undiffed because the run was cancelled
+    pub cancelled: Vec<usize>,
+    // 1-based pages with images reduced to stay within the output budget
+    pub reduced: Vec<usize>,
+}
+
+// How `diff_and_save_images` writes its images
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SaveOptions<'a> {
+    pub output: DiffOutput,
+    pub layout: Layout,
+    pub png: PngOptions,
+    // Make the first page whose diff fails an error instead of a placeholder
+    pub strict: bool,
+    // Don't print a line for every saved image
+    pub quiet: bool,
+    // Bytes the images may take before the rest are saved reduced
+    pub budget: Option<&'a OutputBudget>,
+}
 
+// Diff every page and write its images as soon as they are ready, on one
+// thread per CPU, so a large document never holds all diffs in memory. Files
+// are numbered as `diff_image_numbers` says; with the default output, as
+// `save_images(diff_images(...))` would number them. The renders are
+// borrowed and left as they are; like `diff_page`, only those of pages that
+// differ are copied for `lcs_diff`. Pages whose diff fails get a placeholder
+// like `diff_images_resilient`, unless `strict`. Once `cancel` is cancelled,
+// pages not yet started are skipped and listed in the result.
+pub fn diff_and_save_images(
+    images: &[PagePair],
+    sensitivity: f32,
+    pdf_title: &str,
+    output_dir: &str,
+    options: SaveOptions<'_>,
+    cancel: Option<&CancelToken>,
+) -> Result<SavedDiffs, Box<dyn std::error::Error>> {
     std::fs::create_dir_all(output_dir)?;
+    let pages: Vec<(usize, Option<usize>)> = diff_image_numbers(images, options.output, options.layout).into_iter().enumerate().collect();
+
+    let results = run_parallel(&pages, effective_jobs(0), |&(index, number)| {
+        let (old_image, new_image) = &images[index];
+        let Some(first) = number else {
+            return Ok((0, None, false, false));
+        };
+        if cancel.is_some_and(|cancel| cancel.is_cancelled()) {
+            return Ok((0, None, true, false));
+        }
+
+        let changed = page_changed(old_image.as_ref(), new_image.as_ref());
+        let (diff, failure) = match diff_page(old_image.as_ref(), new_image.as_ref(), sensitivity) {
+            Ok(diff) => (diff, None),
+            Err(e) if options.strict => return Err(format!("Error diffing page {}: {}", index + 1, e)),
+            Err(e) => {
+                let placeholder = new_image.as_ref().map(|new| placeholder_page(new.width(), new.height(), "DIFF FAILED"));
+                (placeholder, Some((index + 1, format!("Failed to diff page: {}", e))))
+            }
+        };
+
+        let page_images: Vec<Cow<DynamicImage>> = match options.layout {
+            Layout::Separate => {
+                // The page itself follows its diff, or the placeholder of a failed one
+                let page = match (old_image, new_image) {
+                    (Some(_), Some(new)) if options.output == DiffOutput::DiffAndPage => Some(new),
+                    _ => None,
+                };
+                diff.map(Cow::Owned).into_iter().chain(page.map(Cow::Borrowed)).collect()
+            }
+            Layout::Triptych => {
+                let diff = diff.as_ref().filter(|_| changed);
+                vec![Cow::Owned(triptych(old_image.as_ref(), diff, new_image.as_ref(), index + 1))]
+            }
+        };
+
+        let mut reduced = false;
+        for (offset, img) in page_images.iter().enumerate() {
+            let output_path = diff_image_path(output_dir, pdf_title, first + offset);
+            reduced |= save_png_within(options.budget, img, &output_path, options.png).map_err(|e| format!("{}: {}", output_path.display(), e))?;
+            if !options.quiet {
+                println!("Saved diff image to {}", output_path.display());
+            }
+        }
+        Ok((page_images.len(), failure, false, reduced))
+    });
+
+    let mut saved = SavedDiffs::default();
+    for (index, result) in results.into_iter().enumerate() {
+        let (count, failure, cancelled, reduced) = result?;
+        saved.written += count;
+        saved.failures.extend(failure);
+        if cancelled {
+            saved.cancelled.push(index);
+        }
+        if reduced {
+            saved.reduced.push(index + 1);
+        }
+    }
+    Ok(saved)
+}
+
+// A render without any content. Either the page is genuinely empty or pdfium
+// failed to draw it, which would otherwise pass a diff silently.
+pub fn is_blank(img: &DynamicImage) -> bool {
+    content_bbox(img, &ContentDetection::default()).is_none()
+}
+
+// A page counts as changed when it exists on only one side or its renders differ
+pub fn page_changed(old_image: Option<&DynamicImage>, new_image: Option<&DynamicImage>) -> bool {
+    match (old_image, new_image) {
+        (Some(old), Some(new)) => {
+            let (old, new) = (diff_core::rgba(old), diff_core::rgba(new));
+            diff_core::count_changed(diff_core::Pixels::of(&old), diff_core::Pixels::of(&new), 0) > 0
+        }
+        (None, None) => false,
+        _ => true,
+    }
+}
 
-    for (i, img) in images.iter().enumerate() {
-        let output_path = format!("{}/{}_{}.png", output_dir, pdf_title, i + 1);
-        let file = File::create(&output_path)?;
-        let w = BufWriter::new(file);
-        img.write_to(&mut BufWriter::new(w), ImageFormat::Png)?;
-        println!("Saved diff image to {}", output_path);
+// The diff image of one page pair: the `lcs_diff` of renders that differ, or
+// the one render of a page found in only one document with a banner on it.
+// `lcs_diff` works on the renders in place, so they are left as it leaves
+// them; only callers done with the renders hand them over.
+fn diff_pair(
+    old_image: Option<&mut DynamicImage>,
+    new_image: Option<&mut DynamicImage>,
+    sensitivity: f32,
+) -> Result<Option<DynamicImage>, Box<dyn std::error::Error>> {
+    match (old_image, new_image) {
+        (Some(old), Some(new)) => match page_changed(Some(old), Some(new)) {
+            true => Ok(Some(lcs_diff(old, new, sensitivity)?)),
+            false => Ok(None),
+        },
+        (old_image, new_image) => Ok(one_sided(old_image.as_deref(), new_image.as_deref())),
     }
+}
 
-    Ok(())
+// The render of a page found in only one document, with a banner saying which
+fn one_sided(old_image: Option<&DynamicImage>, new_image: Option<&DynamicImage>) -> Option<DynamicImage> {
+    match (old_image, new_image) {
+        (None, Some(new)) => Some(with_banner(new, "ADDED IN NEW", ADDED_BANNER)),
+        (Some(old), None) => Some(with_banner(old, "REMOVED IN NEW", REMOVED_BANNER)),
+        _ => None,
+    }
+}
+
+// Like `diff_pair`, for a caller that keeps its renders unchanged: only the
+// renders of a changed page are copied for `lcs_diff`
+pub(crate) fn diff_page(
+    old_image: Option<&DynamicImage>,
+    new_image: Option<&DynamicImage>,
+    sensitivity: f32,
+) -> Result<Option<DynamicImage>, Box<dyn std::error::Error>> {
+    match (old_image, new_image) {
+        (Some(old), Some(new)) if page_changed(Some(old), Some(new)) => {
+            diff_pair(Some(&mut old.clone()), Some(&mut new.clone()), sensitivity)
+        }
+        (Some(_), Some(_)) => Ok(None),
+        (old_image, new_image) => Ok(one_sided(old_image, new_image)),
+    }
+}
+
+// A failed `diff_page_owned`, with the new render it hands back
+type OwnedDiffError = (Box<dyn std::error::Error>, Option<DynamicImage>);
+
+// The images `DiffOutput::DiffAndPage` saves for a page pair the caller is
+// done with: the diff, if any, then the new render, moved into the result so
+// nothing is copied. A failed diff hands the new render back along with the
+// error.
+fn diff_page_owned(
+    mut old_image: Option<DynamicImage>,
+    mut new_image: Option<DynamicImage>,
+    sensitivity: f32,
+) -> Result<Vec<DynamicImage>, OwnedDiffError> {
+    let diff = match diff_pair(old_image.as_mut(), new_image.as_mut(), sensitivity) {
+        Ok(diff) => diff,
+        Err(e) => return Err((e, new_image)),
+    };
+    match (diff, old_image, new_image) {
+        (diff, Some(_), Some(new)) => Ok(diff.into_iter().chain([new]).collect()),
+        (diff, _, _) => Ok(diff.into_iter().collect()),
+    }
+}
+
+// 1-based number of the first image saved for each page with `output`: the
+// diff where the renders differ, otherwise the page itself. A triptych
+// combines a page's images into one.
+pub fn diff_image_numbers(
+    images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+    output: DiffOutput,
+    layout: Layout,
+) -> Vec<Option<usize>> {
+    let mut next = 1;
+    images
+        .iter()
+        .map(|(old_image, new_image)| {
+            let count = match (output.image_count(old_image.as_ref(), new_image.as_ref()), layout) {
+                (count, Layout::Separate) => count,
+                (count, Layout::Triptych) => count.min(1),
+            };
+            let number = (count > 0).then_some(next);
+            next += count;
+            number
+        })
+        .collect()
 }
 
+// Diff every page, consuming the renders so each page's diff reuses them
 pub fn diff_images(
     images: Vec<(Option<DynamicImage>, Option<DynamicImage>)>,
     sensitivity: f32,
 ) -> Result<Vec<DynamicImage>, Box<dyn std::error::Error>> {
     let mut diff = vec![];
 
-    for (old_image, new_image) in &images {
-        match (old_image, new_image) {
-            (Some(old), Some(new)) => {
-                let mut old = old.clone();
-                let mut new = new.clone();
-
-                let diff_ratio = diff_img::calculate_diff_ratio(&old, &new);
-                if diff_ratio > 0.0 {
-                    let diff_image = lcs_diff(&mut old, &mut new, sensitivity)?;
-                    diff.push(diff_image);
-                }
+    for (old_image, new_image) in images {
+        diff.extend(diff_page_owned(old_image, new_image, sensitivity).map_err(|(e, _)| e)?);
+    }
 
-                diff.push(new);
-            }
-            (None, Some(new)) => {
-                diff.push(new.clone());
-            }
-            (Some(old), None) => {
-                diff.push(old.clone());
+    Ok(diff)
+}
+
+// Like `diff_images`, but a page whose diff fails gets a placeholder in place
+// of its diff image and the run carries on. Failures are returned as
+// (1-based page, message).
+pub fn diff_images_resilient(
+    images: Vec<(Option<DynamicImage>, Option<DynamicImage>)>,
+    sensitivity: f32,
+) -> (Vec<DynamicImage>, Vec<(usize, String)>) {
+    let mut diff = vec![];
+    let mut errors = vec![];
+
+    for (index, (old_image, new_image)) in images.into_iter().enumerate() {
+        match diff_page_owned(old_image, new_image, sensitivity) {
+            Ok(images) => diff.extend(images),
+            Err((e, new_image)) => {
+                errors.push((index + 1, format!("Failed to diff page: {}", e)));
+                if let Some(new) = new_image {
+                    diff.push(placeholder_page(new.width(), new.height(), "DIFF FAILED"));
+                    diff.push(new);
+                }
             }
-            (None, None) => {}
         }
     }
 
-    Ok(diff)
+    (diff, errors)
+}
+
+// A page of one colour, shared by the tests of every module
+#[cfg(test)]
+pub(crate) fn create_solid_color_image(width: u32, height: u32, color: Rgba<u8>) -> DynamicImage {
+    DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(width, height, color))
 }
 
 #[cfg(test)]
@@ -99,7 +603,6 @@ mod tests {
     use super::*;
     use image::{ImageBuffer, Rgba, RgbaImage};
     use std::fs;
-    use std::path::Path;
 
     // Helper function to create a test image with white background and colored content
     fn create_test_image_with_content(
@@ -127,12 +630,6 @@ mod tests {
         DynamicImage::ImageRgba8(img)
     }
 
-    // Helper function to create a solid color image
-    fn create_solid_color_image(width: u32, height: u32, color: Rgba<u8>) -> DynamicImage {
-        let img: RgbaImage = ImageBuffer::from_pixel(width, height, color);
-        DynamicImage::ImageRgba8(img)
-    }
-
     #[test]
     fn test_crop_to_content_with_content() {
         let img = create_test_image_with_content(100, 100, 20, 30, 40, 25);
@@ -176,6 +673,85 @@ mod tests {
         assert_eq!(cropped_height, original_height);
     }
 
+    #[test]
+    fn test_crop_to_content_transparent_background() {
+        let mut img: RgbaImage = ImageBuffer::from_pixel(60, 60, Rgba([0, 0, 0, 0]));
+        for y in 10..20 {
+            for x in 5..30 {
+                img.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+            }
+        }
+
+        let cropped = crop_to_content(&DynamicImage::ImageRgba8(img));
+        assert_eq!(cropped.dimensions(), (25, 10));
+    }
+
+    #[test]
+    fn test_crop_to_content_with_watermark_tolerance() {
+        // Light gray watermark across the page, dark content in one corner
+        let mut img: RgbaImage = ImageBuffer::from_pixel(80, 80, Rgba([225, 225, 225, 255]));
+        for y in 0..8 {
+            for x in 0..12 {
+                img.put_pixel(x, y, Rgba([20, 20, 20, 255]));
+            }
+        }
+        let img = DynamicImage::ImageRgba8(img);
+
+        assert_eq!(crop_to_content(&img).dimensions(), (80, 80), "Default tolerance keeps the watermark");
+
+        let detection = ContentDetection {
+            white_tolerance: 40,
+            ..ContentDetection::default()
+        };
+        assert_eq!(crop_to_content_with(&img, &detection).dimensions(), (12, 8));
+    }
+
+    #[test]
+    fn test_crop_pages_to_content_union() {
+        // The same block, moved right towards the edge in the new render
+        let old = create_test_image_with_content(100, 100, 20, 30, 40, 25);
+        let new = create_test_image_with_content(100, 100, 55, 30, 40, 25);
+
+        let cropped = crop_pages_to_content(vec![(Some(old.clone()), Some(new)), (Some(old), None)], &ContentDetection::default());
+        let (old, new) = (cropped[0].0.as_ref().unwrap(), cropped[0].1.as_ref().unwrap());
+        assert_eq!(old.dimensions(), (75, 25), "Both renders get the union of their content");
+        assert_eq!(new.dimensions(), (75, 25));
+        assert_eq!(old.get_pixel(74, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(new.get_pixel(74, 0), Rgba([100, 100, 100, 255]));
+        assert_eq!(cropped[1].0.as_ref().unwrap().dimensions(), (40, 25), "A lone page is cropped to its own content");
+    }
+
+    #[test]
+    fn test_content_bbox() {
+        let detection = ContentDetection::default();
+        // Same box as visiting every pixel, for content touching and away from the edges
+        let mut img = create_test_image_with_content(60, 40, 0, 5, 1, 1).to_rgba8();
+        for (x, y) in [(30, 0), (59, 20), (12, 39), (40, 10)] {
+            img.put_pixel(x, y, Rgba([100, 100, 100, 255]));
+        }
+        let img = DynamicImage::ImageRgba8(img);
+        assert_eq!(content_bbox(&img, &detection), Some(Region { x: 0, y: 0, width: 60, height: 40 }));
+
+        let img = create_test_image_with_content(60, 40, 7, 9, 3, 20);
+        assert_eq!(content_bbox(&img, &detection), Some(Region { x: 7, y: 9, width: 3, height: 20 }));
+
+        // Content further right on a lower row widens the box
+        let mut img = img.to_rgba8();
+        img.put_pixel(50, 25, Rgba([0, 0, 0, 255]));
+        let img = DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(img).to_rgb8());
+        assert_eq!(content_bbox(&img, &detection), Some(Region { x: 7, y: 9, width: 44, height: 20 }));
+
+        assert_eq!(content_bbox(&create_solid_color_image(20, 20, Rgba([255, 255, 255, 255])), &detection), None);
+        assert_eq!(content_bbox(&create_solid_color_image(0, 0, Rgba([0, 0, 0, 255])), &detection), None);
+    }
+
+    #[test]
+    fn test_is_blank() {
+        assert!(is_blank(&create_solid_color_image(20, 20, Rgba([255, 255, 255, 255]))));
+        assert!(is_blank(&create_solid_color_image(20, 20, Rgba([0, 0, 0, 0]))));
+        assert!(!is_blank(&create_test_image_with_content(20, 20, 5, 5, 1, 1)));
+    }
+
     #[test]
     fn test_crop_to_content_edge_content() {
         // Test content at the very edges
@@ -227,6 +803,7 @@ mod tests {
 
         assert_eq!(result.len(), 1, "Should return only new image");
         assert_eq!(result[0].dimensions(), img.dimensions());
+        assert_eq!(result[0].get_pixel(0, 0), ADDED_BANNER, "Should be labeled as added");
     }
 
     #[test]
@@ -238,6 +815,7 @@ mod tests {
 
         assert_eq!(result.len(), 1, "Should return only old image");
         assert_eq!(result[0].dimensions(), img.dimensions());
+        assert_eq!(result[0].get_pixel(0, 0), REMOVED_BANNER, "Should be labeled as removed");
     }
 
     #[test]
@@ -268,6 +846,171 @@ mod tests {
         assert_eq!(result.len(), 3, "Should return correct number of images");
     }
 
+    #[test]
+    fn test_diff_page() {
+        let img1 = create_solid_color_image(50, 50, Rgba([255, 0, 0, 255]));
+        let img2 = create_solid_color_image(50, 50, Rgba([0, 255, 0, 255]));
+
+        assert!(diff_page(Some(&img1), Some(&img2), 0.12).unwrap().is_some());
+        assert!(diff_page(Some(&img1), Some(&img1), 0.12).unwrap().is_none(), "Unchanged pages have no diff");
+        assert!(diff_page(None, Some(&img1), 0.12).unwrap().is_some(), "Added pages get a banner");
+    }
+
+    #[test]
+    fn test_diff_images_resilient_matches_strict() {
+        let img1 = create_solid_color_image(50, 50, Rgba([255, 0, 0, 255]));
+        let img2 = create_solid_color_image(50, 50, Rgba([0, 255, 0, 255]));
+        let images = vec![(Some(img1.clone()), Some(img2.clone())), (Some(img1), None)];
+
+        let strict = diff_images(images.clone(), 0.12).expect("diff_images should succeed");
+        let (resilient, errors) = diff_images_resilient(images, 0.12);
+
+        assert!(errors.is_empty());
+        assert_eq!(resilient.len(), strict.len());
+    }
+
+    #[test]
+    fn test_diff_image_numbers() {
+        let img1 = create_solid_color_image(50, 50, Rgba([255, 0, 0, 255]));
+        let img2 = create_solid_color_image(50, 50, Rgba([0, 255, 0, 255]));
+        let images = vec![
+            (Some(img1.clone()), Some(img2.clone())),
+            (None, None),
+            (Some(img1.clone()), Some(img1)),
+            (None, Some(img2)),
+        ];
+
+        // The changed page yields a diff and the page, the next pages one image each
+        assert_eq!(diff_image_numbers(&images, DiffOutput::DiffAndPage, Layout::Separate), vec![Some(1), None, Some(3), Some(4)]);
+        assert_eq!(diff_images(images.clone(), 0.12).unwrap().len(), 4);
+
+        // Only changed pages get an image, one each
+        assert_eq!(diff_image_numbers(&images, DiffOutput::DiffOnly, Layout::Separate), vec![Some(1), None, None, Some(2)]);
+
+        // A triptych per page, or per changed page
+        assert_eq!(diff_image_numbers(&images, DiffOutput::DiffAndPage, Layout::Triptych), vec![Some(1), None, Some(2), Some(3)]);
+        assert_eq!(diff_image_numbers(&images, DiffOutput::DiffOnly, Layout::Triptych), vec![Some(1), None, None, Some(2)]);
+    }
+
+    #[test]
+    fn test_diff_and_save_images_matches_save_images() {
+        let test_dir = "test_output_diff_and_save";
+        fs::remove_dir_all(test_dir).ok();
+        let img1 = create_solid_color_image(50, 50, Rgba([255, 0, 0, 255]));
+        let img2 = create_solid_color_image(50, 50, Rgba([0, 255, 0, 255]));
+        let images = vec![(Some(img1.clone()), Some(img2.clone())), (None, None), (Some(img1), None), (None, Some(img2))];
+
+        let options = SaveOptions { strict: true, ..SaveOptions::default() };
+        let saved = diff_and_save_images(&images, 0.12, "doc", test_dir, options, None).unwrap();
+        assert_eq!(saved.written, 4);
+        assert!(saved.failures.is_empty());
+
+        let streamed: Vec<Vec<u8>> = (1..=4).map(|n| fs::read(diff_image_path(test_dir, "doc", n)).unwrap()).collect();
+        save_images(diff_images(images, 0.12).unwrap(), "doc", test_dir).unwrap();
+        for (n, bytes) in streamed.iter().enumerate() {
+            assert_eq!(&fs::read(diff_image_path(test_dir, "doc", n + 1)).unwrap(), bytes);
+        }
+        assert!(!Path::new(&diff_image_path(test_dir, "doc", 5)).exists());
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_diff_and_save_images_diff_only() {
+        let test_dir = "test_output_diff_only";
+        fs::remove_dir_all(test_dir).ok();
+        let img1 = create_solid_color_image(50, 50, Rgba([255, 0, 0, 255]));
+        let img2 = create_solid_color_image(50, 50, Rgba([0, 255, 0, 255]));
+        let images = vec![
+            (Some(img1.clone()), Some(img2.clone())),
+            (Some(img1.clone()), Some(img1.clone())),
+            (Some(img1), None),
+            (None, Some(img2)),
+        ];
+
+        let options = SaveOptions { output: DiffOutput::DiffOnly, ..SaveOptions::default() };
+        let saved = diff_and_save_images(&images, 0.12, "doc", test_dir, options, None).unwrap();
+        assert_eq!(saved.written, 3, "One image per changed page");
+        assert!(Path::new(&diff_image_path(test_dir, "doc", 3)).exists());
+        assert!(!Path::new(&diff_image_path(test_dir, "doc", 4)).exists());
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_diff_and_save_images_triptych() {
+        let test_dir = "test_output_diff_triptych";
+        fs::remove_dir_all(test_dir).ok();
+        let img1 = create_solid_color_image(50, 50, Rgba([255, 0, 0, 255]));
+        let img2 = create_solid_color_image(50, 50, Rgba([0, 255, 0, 255]));
+        let images = vec![(Some(img1.clone()), Some(img2)), (Some(img1.clone()), Some(img1))];
+
+        let options = SaveOptions { layout: Layout::Triptych, ..SaveOptions::default() };
+        let saved = diff_and_save_images(&images, 0.12, "doc", test_dir, options, None).unwrap();
+        assert_eq!(saved.written, 2, "One image per page");
+        let first = image::open(diff_image_path(test_dir, "doc", 1)).unwrap();
+        assert!(first.width() > 150, "Old, diff and new side by side");
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_diff_and_save_images_cancelled() {
+        let test_dir = "test_output_diff_cancelled";
+        fs::remove_dir_all(test_dir).ok();
+        let img = create_solid_color_image(50, 50, Rgba([255, 0, 0, 255]));
+        let images = vec![(Some(img.clone()), Some(img.clone())), (None, None), (None, Some(img))];
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let saved = diff_and_save_images(&images, 0.12, "doc", test_dir, SaveOptions::default(), Some(&cancel)).unwrap();
+        assert_eq!(saved.written, 0);
+        assert_eq!(saved.cancelled, vec![0, 2], "Pages without images aren't listed");
+        assert!(!Path::new(&diff_image_path(test_dir, "doc", 1)).exists());
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_save_png_with_options() {
+        let test_dir = "test_output_png_options";
+        fs::create_dir_all(test_dir).unwrap();
+        let img = create_test_image_with_content(200, 200, 20, 30, 100, 50);
+
+        let fast = format!("{}/fast.png", test_dir);
+        let best = format!("{}/best.png", test_dir);
+        save_png(&img, &fast).unwrap();
+        let options = PngOptions { compression: PngCompression::Best, filter: PngFilter::Up, ..PngOptions::default() };
+        save_png_with(&img, &best, options).unwrap();
+
+        assert!(fs::metadata(&best).unwrap().len() <= fs::metadata(&fast).unwrap().len());
+        assert_eq!(image::open(&best).unwrap().to_rgba8(), img.to_rgba8(), "Compression is lossless");
+
+        let half = format!("{}/half.png", test_dir);
+        save_png_with(&img, &half, PngOptions { scale: 0.5, ..PngOptions::default() }).unwrap();
+        assert_eq!(image::open(&half).unwrap().dimensions(), (100, 100));
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_write_png_records_dpi() {
+        let img = create_solid_color_image(10, 10, Rgba([255, 0, 0, 255]));
+        let phys = |png: &[u8]| png.windows(4).position(|window| window == b"pHYs");
+
+        let mut plain = vec![];
+        write_png(&img, &mut plain, PngOptions::default()).unwrap();
+        assert_eq!(phys(&plain), None);
+
+        let mut png = vec![];
+        write_png(&img, &mut png, PngOptions { dpi: Some(300.0), scale: 0.5, ..PngOptions::default() }).unwrap();
+        let at = phys(&png).expect("pHYs chunk");
+        assert_eq!(at, PNG_HEADER_LEN + 4, "Right after IHDR");
+        // 150 DPI after scaling is 5906 pixels per metre
+        assert_eq!(&png[at + 4..at + 13], &[0, 0, 0x17, 0x12, 0, 0, 0x17, 0x12, 1]);
+        assert_eq!(image::load_from_memory(&png).unwrap().dimensions(), (5, 5), "Still a valid PNG");
+    }
+
     #[test]
     fn test_save_images() {
         let test_dir = "test_output";
@@ -293,6 +1036,20 @@ mod tests {
         fs::remove_dir_all(test_dir).ok();
     }
 
+    #[test]
+    fn test_save_images_non_ascii_names() {
+        let test_dir = "test_output_非ASCII/出力";
+        let pdf_title = "契約書 v2";
+        fs::remove_dir_all("test_output_非ASCII").ok();
+
+        let images = vec![create_solid_color_image(10, 10, Rgba([255, 0, 0, 255]))];
+        save_images(images, pdf_title, test_dir).unwrap();
+        assert_eq!(diff_image_path(test_dir, pdf_title, 1), Path::new(test_dir).join("契約書 v2_1.png"));
+        assert!(diff_image_path(test_dir, pdf_title, 1).exists());
+
+        fs::remove_dir_all("test_output_非ASCII").ok();
+    }
+
     #[test]
     fn test_save_images_empty_vec() {
         let test_dir = "test_output_empty";
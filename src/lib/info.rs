@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use pdfium_render::prelude::{
+    PdfDocument, PdfDocumentMetadataTagType, PdfDocumentVersion, PdfPageRenderRotation, PdfSecurityHandlerRevision,
+};
+use serde::Serialize;
+
+// Document information dictionary entries reported by `info`
+const METADATA_TAGS: [(PdfDocumentMetadataTagType, &str); 8] = [
+    (PdfDocumentMetadataTagType::Title, "Title"),
+    (PdfDocumentMetadataTagType::Author, "Author"),
+    (PdfDocumentMetadataTagType::Subject, "Subject"),
+    (PdfDocumentMetadataTagType::Keywords, "Keywords"),
+    (PdfDocumentMetadataTagType::Creator, "Creator"),
+    (PdfDocumentMetadataTagType::Producer, "Producer"),
+    (PdfDocumentMetadataTagType::CreationDate, "CreationDate"),
+    (PdfDocumentMetadataTagType::ModificationDate, "ModificationDate"),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageInfo {
+    // 1-based page number
+    pub page: usize,
+    pub label: Option<String>,
+    // Page size in points (1/72 inch)
+    pub width: f32,
+    pub height: f32,
+    // Rotation applied when the page is displayed, in degrees clockwise
+    pub rotation: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FontInfo {
+    pub name: String,
+    pub embedded: bool,
+    // One of the 14 standard fonts pdfium ships itself
+    pub built_in: bool,
+    // 1-based numbers of the pages using the font
+    pub pages: Vec<usize>,
+}
+
+// What `info` reports about a document: the properties that most often
+// explain surprising comparisons (page sizes, rotation, missing fonts)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DocumentInfo {
+    pub path: String,
+    pub pdf_version: String,
+    pub page_count: usize,
+    // Standard security handler revision, or "none" for unencrypted documents
+    pub encryption: String,
+    pub metadata: BTreeMap<String, String>,
+    pub pages: Vec<PageInfo>,
+    pub fonts: Vec<FontInfo>,
+}
+
+fn version_name(version: PdfDocumentVersion) -> String {
+    match version {
+        PdfDocumentVersion::Unset => "unknown".to_string(),
+        PdfDocumentVersion::Pdf1_0 => "1.0".to_string(),
+        PdfDocumentVersion::Pdf1_1 => "1.1".to_string(),
+        PdfDocumentVersion::Pdf1_2 => "1.2".to_string(),
+        PdfDocumentVersion::Pdf1_3 => "1.3".to_string(),
+        PdfDocumentVersion::Pdf1_4 => "1.4".to_string(),
+        PdfDocumentVersion::Pdf1_5 => "1.5".to_string(),
+        PdfDocumentVersion::Pdf1_6 => "1.6".to_string(),
+        PdfDocumentVersion::Pdf1_7 => "1.7".to_string(),
+        PdfDocumentVersion::Pdf2_0 => "2.0".to_string(),
+        PdfDocumentVersion::Other(raw) => format!("{}.{}", raw / 10, raw % 10),
+    }
+}
+
+fn rotation_degrees(rotation: PdfPageRenderRotation) -> u16 {
+    match rotation {
+        PdfPageRenderRotation::None => 0,
+        PdfPageRenderRotation::Degrees90 => 90,
+        PdfPageRenderRotation::Degrees180 => 180,
+        PdfPageRenderRotation::Degrees270 => 270,
+    }
+}
+
+fn encryption_name(document: &PdfDocument) -> String {
+    match document.permissions().security_handler_revision() {
+        Ok(PdfSecurityHandlerRevision::Unprotected) => "none".to_string(),
+        Ok(PdfSecurityHandlerRevision::Revision2) => "standard security handler, revision 2".to_string(),
+        Ok(PdfSecurityHandlerRevision::Revision3) => "standard security handler, revision 3".to_string(),
+        Ok(PdfSecurityHandlerRevision::Revision4) => "standard security handler, revision 4".to_string(),
+        Err(_) => "unknown".to_string(),
+    }
+}
+
+pub fn document_info(document: &PdfDocument, path: &str) -> DocumentInfo {
+    let metadata = METADATA_TAGS
+        .iter()
+        .filter_map(|(tag, name)| {
+            let value = document.metadata().get(*tag)?;
+            (!value.value().is_empty()).then(|| (name.to_string(), value.value().to_string()))
+        })
+        .collect();
+
+    let mut pages = vec![];
+    let mut fonts: Vec<FontInfo> = vec![];
+
+    for (index, page) in document.pages().iter().enumerate() {
+        pages.push(PageInfo {
+            page: index + 1,
+            label: page.label().map(str::to_string),
+            width: page.width().value,
+            height: page.height().value,
+            rotation: page.rotation().map(rotation_degrees).unwrap_or(0),
+        });
+
+        for font in page.fonts() {
+            let name = font.family();
+            let embedded = matches!(font.is_embedded(), Ok(true));
+            match fonts.iter_mut().find(|f| f.name == name && f.embedded == embedded) {
+                Some(existing) if existing.pages.last() == Some(&(index + 1)) => {}
+                Some(existing) => existing.pages.push(index + 1),
+                None => fonts.push(FontInfo {
+                    name,
+                    embedded,
+                    built_in: font.is_built_in(),
+                    pages: vec![index + 1],
+                }),
+            }
+        }
+    }
+
+    fonts.sort_by(|a, b| a.name.cmp(&b.name));
+
+    DocumentInfo {
+        path: path.to_string(),
+        pdf_version: version_name(document.version()),
+        page_count: pages.len(),
+        encryption: encryption_name(document),
+        metadata,
+        pages,
+        fonts,
+    }
+}
+
+// Compress sorted page numbers into ranges, e.g. "1-3, 5"
+fn page_list(pages: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for &page in pages {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == page => *last = page,
+            _ => ranges.push((page, page)),
+        }
+    }
+
+    ranges
+        .iter()
+        .map(|&(first, last)| if first == last { first.to_string() } else { format!("{}-{}", first, last) })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for DocumentInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "File: {}", self.path)?;
+        writeln!(f, "PDF version: {}", self.pdf_version)?;
+        writeln!(f, "Pages: {}", self.page_count)?;
+        writeln!(f, "Encryption: {}", self.encryption)?;
+
+        for (name, value) in &self.metadata {
+            writeln!(f, "{}: {}", name, value)?;
+        }
+
+        writeln!(f, "\nPage sizes:")?;
+        for page in &self.pages {
+            write!(
+                f,
+                "  {:>4}: {:.1} x {:.1} pt ({:.2} x {:.2} in)",
+                page.page,
+                page.width,
+                page.height,
+                page.width / 72.0,
+                page.height / 72.0
+            )?;
+            if page.rotation != 0 {
+                write!(f, ", rotated {}°", page.rotation)?;
+            }
+            if let Some(label) = &page.label {
+                write!(f, ", label \"{}\"", label)?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, "\nFonts:")?;
+        if self.fonts.is_empty() {
+            writeln!(f, "  (none)")?;
+        }
+        for font in &self.fonts {
+            let kind = match (font.embedded, font.built_in) {
+                (true, _) => "embedded",
+                (false, true) => "standard",
+                (false, false) => "not embedded, substituted",
+            };
+            writeln!(f, "  {} ({}): pages {}", font.name, kind, page_list(&font.pages))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::{create_pdfium, load_pdf_document};
+    use std::path::Path;
+
+    #[test]
+    fn test_page_list() {
+        assert_eq!(page_list(&[1, 2, 3, 5, 7, 8]), "1-3, 5, 7-8");
+        assert_eq!(page_list(&[4]), "4");
+        assert_eq!(page_list(&[]), "");
+    }
+
+    #[test]
+    fn test_display_document_info() {
+        let info = DocumentInfo {
+            path: "doc.pdf".to_string(),
+            pdf_version: "1.7".to_string(),
+            page_count: 2,
+            encryption: "none".to_string(),
+            metadata: BTreeMap::from([("Title".to_string(), "Quarterly report".to_string())]),
+            pages: vec![
+                PageInfo { page: 1, label: None, width: 612.0, height: 792.0, rotation: 0 },
+                PageInfo { page: 2, label: Some("ii".to_string()), width: 792.0, height: 612.0, rotation: 90 },
+            ],
+            fonts: vec![FontInfo { name: "Arial".to_string(), embedded: false, built_in: false, pages: vec![1, 2] }],
+        };
+
+        let text = info.to_string();
+        assert!(text.contains("Title: Quarterly report"));
+        assert!(text.contains("1: 612.0 x 792.0 pt (8.50 x 11.00 in)\n"));
+        assert!(text.contains("rotated 90°, label \"ii\""));
+        assert!(text.contains("Arial (not embedded, substituted): pages 1-2"));
+    }
+
+    #[test]
+    fn test_document_info_of_sample() {
+        let pdfium = create_pdfium().expect("Failed to create Pdfium instance");
+        let doc = load_pdf_document(&pdfium, Path::new("./samples/old.pdf")).expect("Failed to load PDF document");
+
+        let info = document_info(&doc, "./samples/old.pdf");
+        assert_eq!(info.page_count, doc.pages().len() as usize);
+        assert_eq!(info.pages.len(), info.page_count);
+        assert_eq!(info.encryption, "none");
+        assert!(info.fonts.iter().all(|font| font.embedded || font.built_in));
+    }
+}
@@ -0,0 +1,127 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::batch::Comparison;
+use crate::exit_code::Failure;
+use crate::report::ComparisonSummary;
+
+// Outcome a worker reports back; errors keep their exit status, as in `run_all`
+pub type WorkerResult = Result<ComparisonSummary, Failure>;
+
+// One comparison handed to a worker process, and where it reports back
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkerJob {
+    pub comparison: Comparison,
+    pub output_dir: String,
+    // Where `output_dir` is moved when the run is over, if it is staged
+    #[serde(default)]
+    pub final_output_dir: Option<String>,
+    // Bytes of `--max-output-size` left for the job's outputs
+    #[serde(default)]
+    pub output_budget: Option<u64>,
+    // File the worker writes its `WorkerResult` to, as JSON
+    pub result_file: PathBuf,
+}
+
+impl WorkerJob {
+    pub fn new(comparison: &Comparison, output_dir: &str) -> WorkerJob {
+        let name = comparison.name.replace(['/', '\\'], "_");
+        WorkerJob {
+            comparison: comparison.clone(),
+            output_dir: output_dir.to_string(),
+            final_output_dir: None,
+            output_budget: None,
+            result_file: std::env::temp_dir().join(format!("pdf_diff_{}_{}.json", std::process::id(), name)),
+        }
+    }
+}
+
+// Record a worker's outcome for its parent
+pub fn write_worker_result(job: &WorkerJob, result: &WorkerResult) -> Result<(), Box<dyn Error>> {
+    std::fs::write(&job.result_file, serde_json::to_string(result)?)?;
+    Ok(())
+}
+
+// Outcome written by a worker, if it got as far as writing one
+fn read_worker_result(result_file: &Path) -> Option<WorkerResult> {
+    let text = std::fs::read_to_string(result_file).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+// Command starting a worker: the current executable with `args`, the
+// parent's own command line. Callers may confine it further before
+// `run_worker` starts it.
+pub fn worker_command(args: impl IntoIterator<Item = OsString>) -> Result<Command, String> {
+    let executable = std::env::current_exe().map_err(|e| format!("Failed to locate the pdf_diff executable: {}", e))?;
+    let mut command = Command::new(executable);
+    command.args(args);
+    Ok(command)
+}
+
+// Run `job` in a child process started from `command` with `--worker <job>`
+// added. A worker that dies without reporting back, say from a PDFium crash
+// on a malformed PDF, fails only this comparison.
+pub fn run_worker(mut command: Command, job: &WorkerJob) -> WorkerResult {
+    let job_json = serde_json::to_string(job).map_err(|e| format!("Failed to describe worker job: {}", e))?;
+
+    std::fs::remove_file(&job.result_file).ok();
+    let status = command
+        .arg("--worker")
+        .arg(job_json)
+        .status()
+        .map_err(|e| format!("Failed to start worker comparing {}: {}", job.comparison.name, e))?;
+
+    let result = read_worker_result(&job.result_file);
+    std::fs::remove_file(&job.result_file).ok();
+    result.unwrap_or_else(|| {
+        Err(Failure::rendering(format!(
+            "Worker comparing {} with {} stopped without a result ({})",
+            job.comparison.old_pdf, job.comparison.new_pdf, status
+        )))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(name: &str) -> WorkerJob {
+        let comparison = Comparison {
+            old_pdf: "a.pdf".to_string(),
+            new_pdf: "b.pdf".to_string(),
+            name: name.to_string(),
+            dpi: Some(150.0),
+            sensitivity: None,
+        };
+        WorkerJob::new(&comparison, "output/a_vs_b")
+    }
+
+    #[test]
+    fn test_worker_job_round_trip() {
+        let job = job("nested/a_vs_b");
+        assert!(job.result_file.file_name().unwrap().to_str().unwrap().ends_with("_nested_a_vs_b.json"));
+
+        let json = serde_json::to_string(&job).unwrap();
+        assert_eq!(serde_json::from_str::<WorkerJob>(&json).unwrap(), job);
+    }
+
+    #[test]
+    fn test_worker_result_file() {
+        let job = job("test_worker_result_file");
+        std::fs::remove_file(&job.result_file).ok();
+        assert!(read_worker_result(&job.result_file).is_none(), "A worker that crashed leaves no result");
+
+        let summary = ComparisonSummary::failed("a.pdf", "b.pdf", &job.output_dir, "broken");
+        write_worker_result(&job, &Ok(summary.clone())).unwrap();
+        assert_eq!(read_worker_result(&job.result_file), Some(Ok(summary)));
+
+        write_worker_result(&job, &Err(Failure::input("Error loading PDF files"))).unwrap();
+        assert_eq!(read_worker_result(&job.result_file), Some(Err(Failure::input("Error loading PDF files"))));
+
+        std::fs::remove_file(&job.result_file).ok();
+    }
+}
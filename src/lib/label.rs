@@ -0,0 +1,213 @@
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+
+// Built-in 5x7 bitmap font so captions don't depend on system fonts.
+// Each glyph is seven rows, the low five bits of each row are pixels (MSB = left).
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1E],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        ' ' => [0x00; 7],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '|' => [0x04; 7],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        '<' => [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02],
+        '>' => [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+// Width in pixels of `text` drawn at the given scale
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    let chars = text.chars().count() as u32;
+    if chars == 0 {
+        return 0;
+    }
+    (chars * (GLYPH_WIDTH + 1) - 1) * scale
+}
+
+pub fn text_height(scale: u32) -> u32 {
+    GLYPH_HEIGHT * scale
+}
+
+// Draw `text` with its top-left corner at (x, y); pixels outside the image are skipped
+pub fn draw_text(img: &mut RgbaImage, x: u32, y: u32, text: &str, scale: u32, color: Rgba<u8>) {
+    for (i, c) in text.chars().enumerate() {
+        let origin_x = x + i as u32 * (GLYPH_WIDTH + 1) * scale;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (0x10 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = origin_x + col * scale + dx;
+                        let py = y + row as u32 * scale + dy;
+                        if px < img.width() && py < img.height() {
+                            img.put_pixel(px, py, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Pick a text scale that stays readable on high-DPI page renders
+pub fn scale_for_width(width: u32) -> u32 {
+    (width / 400).max(2)
+}
+
+// Return a copy of `img` with a solid caption bar containing `text` above it
+pub fn with_caption(img: &DynamicImage, text: &str, background: Rgba<u8>) -> DynamicImage {
+    let page = img.to_rgba8();
+    let scale = scale_for_width(page.width());
+    let padding = 2 * scale;
+    let bar_height = text_height(scale) + 2 * padding;
+
+    let mut out = RgbaImage::from_pixel(page.width(), page.height() + bar_height, background);
+    draw_text(&mut out, padding, padding, text, scale, Rgba([255, 255, 255, 255]));
+    imageops::overlay(&mut out, &page, 0, bar_height as i64);
+
+    DynamicImage::ImageRgba8(out)
+}
+
+// Stand-in for a page that couldn't be produced: a tinted page with `text`
+// centred on it, so the failure is visible wherever the page ends up
+pub fn placeholder_page(width: u32, height: u32, text: &str) -> DynamicImage {
+    let (width, height) = (width.max(1), height.max(1));
+    let mut img = RgbaImage::from_pixel(width, height, Rgba([255, 230, 230, 255]));
+    let scale = scale_for_width(width);
+    let x = width.saturating_sub(text_width(text, scale)) / 2;
+    let y = height.saturating_sub(text_height(scale)) / 2;
+    draw_text(&mut img, x, y, text, scale, Rgba([200, 0, 0, 255]));
+    DynamicImage::ImageRgba8(img)
+}
+
+// A page present in only one document: the render inside a frame, with a
+// band across its middle reading `text`, so a lone page in the outputs says
+// at a glance whether it was added or removed
+pub fn with_banner(img: &DynamicImage, text: &str, color: Rgba<u8>) -> DynamicImage {
+    let mut page = img.to_rgba8();
+    let (width, height) = page.dimensions();
+
+    // As large as fits in most of the page width
+    let scale = (width * 4 / 5 / text_width(text, 1).max(1)).max(1);
+    let padding = 2 * scale;
+    let band_height = (text_height(scale) + 2 * padding).min(height);
+    let band_top = (height - band_height) / 2;
+    let frame = scale_for_width(width);
+
+    for (x, y, pixel) in page.enumerate_pixels_mut() {
+        let in_band = y >= band_top && y < band_top + band_height;
+        let in_frame = x < frame || y < frame || x + frame >= width || y + frame >= height;
+        if in_band || in_frame {
+            *pixel = color;
+        }
+    }
+    let x = width.saturating_sub(text_width(text, scale)) / 2;
+    draw_text(&mut page, x, band_top + padding, text, scale, Rgba([255, 255, 255, 255]));
+
+    DynamicImage::ImageRgba8(page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    #[test]
+    fn test_text_width() {
+        assert_eq!(text_width("", 2), 0);
+        assert_eq!(text_width("A", 1), 5);
+        assert_eq!(text_width("AB", 2), 22);
+    }
+
+    #[test]
+    fn test_draw_text_marks_pixels() {
+        let mut img = RgbaImage::from_pixel(20, 10, Rgba([255, 255, 255, 255]));
+        draw_text(&mut img, 0, 0, "I", 1, Rgba([0, 0, 0, 255]));
+
+        // Top bar of the "I" glyph spans columns 1..4
+        assert_eq!(*img.get_pixel(2, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_with_caption_adds_bar() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(100, 50, Rgba([255, 255, 255, 255])));
+        let captioned = with_caption(&img, "PAGE 1", Rgba([0, 0, 0, 255]));
+
+        let (width, height) = captioned.dimensions();
+        assert_eq!(width, 100);
+        assert_eq!(height, 50 + text_height(2) + 8);
+    }
+
+    #[test]
+    fn test_placeholder_page() {
+        let img = placeholder_page(400, 500, "RENDER FAILED");
+        assert_eq!(img.dimensions(), (400, 500));
+        assert_eq!(img.get_pixel(0, 0), Rgba([255, 230, 230, 255]));
+        assert!(img.to_rgba8().pixels().any(|p| *p == Rgba([200, 0, 0, 255])), "Text should be drawn");
+    }
+
+    #[test]
+    fn test_with_banner() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(400, 500, Rgba([255, 255, 255, 255])));
+        let red = Rgba([180, 0, 0, 255]);
+        let bannered = with_banner(&img, "REMOVED IN NEW", red);
+
+        assert_eq!(bannered.dimensions(), (400, 500));
+        assert_eq!(bannered.get_pixel(0, 0), red, "Frame");
+        assert_eq!(bannered.get_pixel(200, 100), Rgba([255, 255, 255, 255]), "Page shows above the band");
+        let band_row: Vec<Rgba<u8>> = (0..400).map(|x| bannered.get_pixel(x, 250)).collect();
+        assert!(band_row.contains(&red) && band_row.contains(&Rgba([255, 255, 255, 255])), "Text on the band");
+    }
+}
@@ -0,0 +1,98 @@
+use std::error::Error;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use pdfium_render::prelude::PdfPage;
+
+use crate::ignore::{mask_regions, IgnoreRegion};
+use crate::text::page_text;
+
+// Points added around every text box so anti-aliased glyph edges and accents
+// reaching past the box are treated as text too
+const TEXT_BOX_MARGIN: f32 = 1.0;
+
+// Which content of the pages is compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ContentLayer {
+    /// Everything on the page
+    #[default]
+    All,
+    /// Only the text; everything outside text boxes is left out
+    TextLayer,
+    /// Everything but the text; text boxes are left out
+    GraphicsLayer,
+}
+
+// Areas of a page (1-based `number`) covered by text, with a margin
+pub fn text_boxes(page: &PdfPage, number: usize) -> Result<Vec<IgnoreRegion>, Box<dyn Error>> {
+    Ok(page_text(page, number, true)?
+        .segments
+        .iter()
+        .filter(|segment| !segment.text.trim().is_empty())
+        .map(|segment| IgnoreRegion {
+            x: segment.x - TEXT_BOX_MARGIN,
+            y: segment.y - TEXT_BOX_MARGIN,
+            width: segment.width + 2.0 * TEXT_BOX_MARGIN,
+            height: segment.height + 2.0 * TEXT_BOX_MARGIN,
+        })
+        .collect())
+}
+
+// Paint everything outside the regions of a render rendered at `dpi` white
+pub fn keep_regions(img: &mut DynamicImage, regions: &[IgnoreRegion], dpi: f32) {
+    let mut kept = img.to_rgba8();
+    let mut blank = DynamicImage::ImageRgba8(RgbaImage::from_pixel(kept.width(), kept.height(), Rgba([0, 0, 0, 0])));
+    mask_regions(&mut blank, regions, dpi);
+
+    // Regions show up white on the transparent sheet
+    for (pixel, mask) in kept.pixels_mut().zip(blank.to_rgba8().pixels()) {
+        if mask[3] == 0 {
+            *pixel = Rgba([255, 255, 255, 255]);
+        }
+    }
+    *img = DynamicImage::ImageRgba8(kept);
+}
+
+// Reduce a render to one layer, given the text boxes of both pages of its
+// pair. Boxes from both sides are used so text that moved is compared where
+// it was as well as where it is now.
+pub fn isolate_layer(img: &mut DynamicImage, layer: ContentLayer, text_boxes: &[IgnoreRegion], dpi: f32) {
+    match layer {
+        ContentLayer::All => {}
+        ContentLayer::TextLayer => keep_regions(img, text_boxes, dpi),
+        ContentLayer::GraphicsLayer => mask_regions(img, text_boxes, dpi),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    #[test]
+    fn test_isolate_layer() {
+        let page = DynamicImage::ImageRgba8(RgbaImage::from_pixel(20, 20, Rgba([0, 0, 0, 255])));
+        // At 144 DPI a point is two pixels
+        let boxes = [IgnoreRegion { x: 0.0, y: 0.0, width: 5.0, height: 5.0 }];
+
+        let mut text = page.clone();
+        isolate_layer(&mut text, ContentLayer::TextLayer, &boxes, 144.0);
+        assert_eq!(text.get_pixel(9, 9), Rgba([0, 0, 0, 255]));
+        assert_eq!(text.get_pixel(10, 10), Rgba([255, 255, 255, 255]));
+
+        let mut graphics = page.clone();
+        isolate_layer(&mut graphics, ContentLayer::GraphicsLayer, &boxes, 144.0);
+        assert_eq!(graphics.get_pixel(9, 9), Rgba([255, 255, 255, 255]));
+        assert_eq!(graphics.get_pixel(10, 10), Rgba([0, 0, 0, 255]));
+
+        let mut all = page.clone();
+        isolate_layer(&mut all, ContentLayer::All, &boxes, 144.0);
+        assert_eq!(all.to_rgba8(), page.to_rgba8());
+    }
+
+    #[test]
+    fn test_keep_regions_without_regions() {
+        let mut page = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255])));
+        keep_regions(&mut page, &[], 72.0);
+        assert_eq!(page.get_pixel(2, 2), Rgba([255, 255, 255, 255]), "A page without text has no text layer");
+    }
+}
@@ -0,0 +1,99 @@
+use image::{imageops, DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+use crate::label::{scale_for_width, with_caption};
+
+const OLD_CAPTION: Rgba<u8> = Rgba([180, 0, 0, 255]);
+const DIFF_CAPTION: Rgba<u8> = Rgba([60, 60, 60, 255]);
+const NEW_CAPTION: Rgba<u8> = Rgba([0, 130, 0, 255]);
+const GUTTER: Rgba<u8> = Rgba([200, 200, 200, 255]);
+
+// How the images saved for a page are arranged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Layout {
+    /// Diff and page as separate images, as `--diff-output` picks them
+    #[default]
+    Separate,
+    /// One wide image per page: old | diff | new, each captioned
+    Triptych,
+}
+
+// Old, diff and new renders of a page side by side with captions, so one
+// image tells the whole story. A side missing from the comparison is shown
+// as a blank sheet, as is the diff of a page without changes.
+pub fn triptych(
+    old_image: Option<&DynamicImage>,
+    diff_image: Option<&DynamicImage>,
+    new_image: Option<&DynamicImage>,
+    page: usize,
+) -> DynamicImage {
+    let (width, height) = match (new_image, old_image) {
+        (Some(img), _) | (None, Some(img)) => img.dimensions(),
+        (None, None) => diff_image.map_or((1, 1), |img| img.dimensions()),
+    };
+    let blank = DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255])));
+
+    let panes = [
+        match old_image {
+            Some(img) => with_caption(img, &format!("PAGE {} - OLD", page), OLD_CAPTION),
+            None => with_caption(&blank, &format!("PAGE {} - OLD (MISSING)", page), OLD_CAPTION),
+        },
+        match diff_image {
+            Some(img) => with_caption(img, &format!("PAGE {} - DIFF", page), DIFF_CAPTION),
+            None => with_caption(&blank, &format!("PAGE {} - DIFF (NO CHANGES)", page), DIFF_CAPTION),
+        },
+        match new_image {
+            Some(img) => with_caption(img, &format!("PAGE {} - NEW", page), NEW_CAPTION),
+            None => with_caption(&blank, &format!("PAGE {} - NEW (MISSING)", page), NEW_CAPTION),
+        },
+    ];
+
+    let gutter = 4 * scale_for_width(width);
+    let total_width = panes.iter().map(|pane| pane.width()).sum::<u32>() + 2 * gutter;
+    let total_height = panes.iter().map(|pane| pane.height()).max().unwrap_or(1);
+    let mut out = RgbaImage::from_pixel(total_width, total_height, GUTTER);
+
+    let mut x = 0;
+    for pane in &panes {
+        imageops::overlay(&mut out, &pane.to_rgba8(), x as i64, 0);
+        x += pane.width() + gutter;
+    }
+
+    DynamicImage::ImageRgba8(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::create_solid_color_image;
+
+    #[test]
+    fn test_triptych() {
+        let old = create_solid_color_image(100, 80, Rgba([255, 0, 0, 255]));
+        let diff = create_solid_color_image(100, 80, Rgba([0, 0, 255, 255]));
+        let new = create_solid_color_image(100, 80, Rgba([0, 255, 0, 255]));
+
+        let image = triptych(Some(&old), Some(&diff), Some(&new), 3).to_rgba8();
+        let gutter = 4 * scale_for_width(100);
+        assert_eq!(image.width(), 300 + 2 * gutter);
+        assert!(image.height() > 80, "Panes have caption bars");
+
+        // Panes in order, each below its caption
+        let y = image.height() - 1;
+        assert_eq!(*image.get_pixel(0, y), Rgba([255, 0, 0, 255]));
+        assert_eq!(*image.get_pixel(100, y), GUTTER);
+        assert_eq!(*image.get_pixel(100 + gutter, y), Rgba([0, 0, 255, 255]));
+        assert_eq!(*image.get_pixel(image.width() - 1, y), Rgba([0, 255, 0, 255]));
+        assert_eq!(*image.get_pixel(0, 0), OLD_CAPTION);
+    }
+
+    #[test]
+    fn test_triptych_of_added_page() {
+        let new = create_solid_color_image(60, 40, Rgba([0, 255, 0, 255]));
+        let image = triptych(None, Some(&new), Some(&new), 1).to_rgba8();
+
+        // The missing old page is a blank sheet of the new page's size
+        let y = image.height() - 1;
+        assert_eq!(*image.get_pixel(0, y), Rgba([255, 255, 255, 255]));
+        assert_eq!(image.width(), 180 + 2 * 4 * scale_for_width(60));
+    }
+}
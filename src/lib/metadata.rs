@@ -0,0 +1,103 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+// When, where and how a report was produced, for audit trails. Left out of
+// reports with `--no-metadata` so the same inputs give byte-identical reports.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunMetadata {
+    // UTC times the comparison started and finished, in RFC 3339
+    pub started: String,
+    pub finished: String,
+    // Name of the machine the comparison ran on, when it can be found
+    pub host: Option<String>,
+    // Version of pdf_diff the comparison ran with
+    pub version: String,
+    // Command line arguments, without the program name
+    pub arguments: Vec<String>,
+}
+
+impl RunMetadata {
+    pub fn new(started: SystemTime, finished: SystemTime, arguments: Vec<String>) -> RunMetadata {
+        RunMetadata {
+            started: rfc3339(started),
+            finished: rfc3339(finished),
+            host: host_name(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            arguments,
+        }
+    }
+
+    // The arguments as one line, with those a shell would split in quotes
+    pub fn command_line(&self) -> String {
+        let quote = |argument: &String| match argument.is_empty() || argument.contains(|c: char| c.is_whitespace() || "'\"\\$`".contains(c)) {
+            true => format!("'{}'", argument.replace('\'', "'\\''")),
+            false => argument.clone(),
+        };
+        self.arguments.iter().map(quote).collect::<Vec<_>>().join(" ")
+    }
+}
+
+// `time` in UTC to the second, like `2024-05-01T09:30:00Z`
+pub fn rfc3339(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+    let (days, time_of_day) = (seconds / 86_400, seconds % 86_400);
+    let (year, month, day) = civil_date(days as i64);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60
+    )
+}
+
+// Year, month and day of the Gregorian calendar `days` after 1970-01-01
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    // Counted in 400-year eras starting on 0000-03-01, so leap days fall at
+    // the end of each year
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Name of this machine, from the environment or the kernel
+pub fn host_name() -> Option<String> {
+    ["HOSTNAME", "COMPUTERNAME"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .chain(["/proc/sys/kernel/hostname", "/etc/hostname"].iter().filter_map(|path| std::fs::read_to_string(path).ok()))
+        .map(|name| name.trim().to_string())
+        .find(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(UNIX_EPOCH + Duration::from_secs(951_782_400)), "2000-02-29T00:00:00Z");
+        assert_eq!(rfc3339(UNIX_EPOCH + Duration::from_secs(1_714_555_800)), "2024-05-01T09:30:00Z");
+        assert_eq!(rfc3339(UNIX_EPOCH + Duration::from_secs(4_102_444_799)), "2099-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn test_command_line() {
+        let arguments = ["--old", "a.pdf", "--new", "my file.pdf", "--name", "it's"].map(String::from).to_vec();
+        let metadata = RunMetadata::new(UNIX_EPOCH, UNIX_EPOCH, arguments);
+        assert_eq!(metadata.command_line(), "--old a.pdf --new 'my file.pdf' --name 'it'\\''s'");
+        assert_eq!(metadata.version, env!("CARGO_PKG_VERSION"));
+    }
+}
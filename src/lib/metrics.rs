@@ -0,0 +1,181 @@
+use std::error::Error;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::report::ComparisonSummary;
+
+// Content type of the Prometheus text exposition format
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// Upper bounds of the per-page changed pixel fraction histogram
+const RATIO_BUCKETS: &[f64] = &[0.0, 0.0001, 0.001, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+// Upper bounds, in seconds, of the per-comparison duration histogram
+const DURATION_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Histogram {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        // Writing to a String can't fail
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", name, help, name);
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum);
+        let _ = writeln!(out, "{}_count {}", name, self.count);
+    }
+}
+
+struct MetricsState {
+    comparisons: u64,
+    failed_comparisons: u64,
+    pages: u64,
+    changed_pages: u64,
+    blank_pages: u64,
+    page_diff_ratio: Histogram,
+    comparison_duration: Histogram,
+}
+
+// Counters and histograms accumulated over the lifetime of the process, for
+// trending diff noise over time. Cloning shares the underlying state.
+#[derive(Clone)]
+pub struct Metrics {
+    state: Arc<Mutex<MetricsState>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Metrics {
+        Metrics::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            state: Arc::new(Mutex::new(MetricsState {
+                comparisons: 0,
+                failed_comparisons: 0,
+                pages: 0,
+                changed_pages: 0,
+                blank_pages: 0,
+                page_diff_ratio: Histogram::new(RATIO_BUCKETS),
+                comparison_duration: Histogram::new(DURATION_BUCKETS),
+            })),
+        }
+    }
+
+    pub fn record_comparison(&self, summary: &ComparisonSummary, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.comparisons += 1;
+        state.comparison_duration.observe(duration.as_secs_f64());
+
+        if summary.error.is_some() {
+            state.failed_comparisons += 1;
+            return;
+        }
+
+        state.pages += summary.total_pages as u64;
+        state.changed_pages += summary.changed_pages as u64;
+        state.blank_pages += summary.blank_pages.len() as u64;
+        for ratio in &summary.page_ratios {
+            state.page_diff_ratio.observe(*ratio);
+        }
+    }
+
+    // Everything recorded so far in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+
+        let counters = [
+            ("pdf_diff_comparisons_total", "Document pairs compared", state.comparisons),
+            ("pdf_diff_comparisons_failed_total", "Document pairs that could not be compared", state.failed_comparisons),
+            ("pdf_diff_pages_total", "Pages processed", state.pages),
+            ("pdf_diff_pages_changed_total", "Pages that differ", state.changed_pages),
+            ("pdf_diff_pages_blank_total", "Pages with a blank render", state.blank_pages),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}", name, help, name, name, value);
+        }
+
+        state.page_diff_ratio.render(&mut out, "pdf_diff_page_diff_ratio", "Fraction of changed pixels per page");
+        state.comparison_duration.render(
+            &mut out,
+            "pdf_diff_comparison_duration_seconds",
+            "Time taken to compare a document pair",
+        );
+        out
+    }
+
+    pub fn write_file(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = std::path::Path::new(path).parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.render())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::create_solid_color_image;
+    use image::Rgba;
+
+    #[test]
+    fn test_metrics_render() {
+        let red = create_solid_color_image(10, 10, Rgba([255, 0, 0, 255]));
+        let green = create_solid_color_image(10, 10, Rgba([0, 255, 0, 255]));
+        let summary = ComparisonSummary::new(
+            "a.pdf",
+            "b.pdf",
+            "out",
+            &[(Some(red.clone()), Some(red.clone())), (Some(red), Some(green))],
+            72.0,
+        );
+
+        let metrics = Metrics::new();
+        metrics.record_comparison(&summary, Duration::from_millis(1500));
+        metrics.record_comparison(&ComparisonSummary::failed("a.pdf", "c.pdf", "out", "broken"), Duration::ZERO);
+        let text = metrics.render();
+
+        assert!(text.contains("# TYPE pdf_diff_comparisons_total counter\npdf_diff_comparisons_total 2\n"));
+        assert!(text.contains("pdf_diff_comparisons_failed_total 1\n"));
+        assert!(text.contains("pdf_diff_pages_total 2\n"));
+        assert!(text.contains("pdf_diff_pages_changed_total 1\n"));
+        assert!(text.contains("pdf_diff_page_diff_ratio_bucket{le=\"0\"} 1\n"));
+        assert!(text.contains("pdf_diff_page_diff_ratio_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("pdf_diff_comparison_duration_seconds_bucket{le=\"1\"} 1\n"));
+        assert!(text.contains("pdf_diff_comparison_duration_seconds_bucket{le=\"2.5\"} 2\n"));
+        assert!(text.contains("pdf_diff_comparison_duration_seconds_sum 1.5\n"));
+    }
+}
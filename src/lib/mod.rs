@@ -1,2 +1,68 @@
 pub mod pdf;
-pub mod image_utils;
\ No newline at end of file
+pub mod raster;
+pub mod app_dirs;
+pub mod pdfium_pool;
+pub mod pdfium_search;
+pub mod info;
+pub mod text;
+pub mod pairing;
+pub mod image_utils;
+pub mod diff_core;
+pub mod benchmark;
+pub mod calibrate;
+pub mod regions;
+pub mod classify;
+pub mod severity;
+pub mod color;
+pub mod spot;
+pub mod page_diff;
+pub mod duplicates;
+pub mod reorder;
+pub mod orient;
+pub mod ignore;
+pub mod layers;
+pub mod preprocess;
+pub mod scan;
+pub mod profiles;
+pub mod sampling;
+pub mod contact_sheet;
+pub mod thumbnails;
+pub mod label;
+pub mod layout;
+pub mod report;
+pub mod diff_report;
+pub mod metadata;
+pub mod compare_reports;
+pub mod pdf_report;
+pub mod server;
+pub mod metrics;
+pub mod watch;
+pub mod batch;
+pub mod checksums;
+pub mod budget;
+pub mod cancel;
+pub mod exit_code;
+pub mod isolation;
+pub mod resume;
+pub mod output_dir;
+pub mod approvals;
+pub mod term_image;
+pub mod term_table;
+pub mod review;
+pub mod verdict;
+pub mod hooks;
+pub mod i18n;
+#[cfg(feature = "video")]
+pub mod video;
+#[cfg(feature = "gui")]
+pub mod gui;
+#[cfg(feature = "cloud")]
+pub mod cloud;
+#[cfg(feature = "tokio")]
+pub mod service;
+#[cfg(feature = "history")]
+pub mod history;
+#[cfg(feature = "sandbox")]
+pub mod sandbox;
+#[cfg(feature = "plugins")]
+pub mod plugin;
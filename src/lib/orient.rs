@@ -0,0 +1,119 @@
+use image::DynamicImage;
+
+use crate::regions::{change_ratio, PIXEL_TOLERANCE};
+use crate::report::PageWarning;
+
+// Share of a page still allowed to differ once it is turned back, for scanner
+// noise and antialiasing along edges
+pub const MAX_ROTATED_CHANGE: f64 = 0.005;
+
+// Largest relative size difference between the old page and a turned new page
+// still worth comparing
+const SIZE_TOLERANCE: f64 = 0.02;
+
+// The new page turned back by a clockwise rotation of `degrees`
+pub fn turn_back(image: &DynamicImage, degrees: u32) -> DynamicImage {
+    match degrees % 360 {
+        90 => image.rotate270(),
+        180 => image.rotate180(),
+        270 => image.rotate90(),
+        _ => image.clone(),
+    }
+}
+
+fn similar_size(old: &DynamicImage, new: &DynamicImage) -> bool {
+    let close = |a: u32, b: u32| (a as f64 - b as f64).abs() <= a.max(b) as f64 * SIZE_TOLERANCE;
+    close(old.width(), new.width()) && close(old.height(), new.height())
+}
+
+// Clockwise rotation of the new page relative to the old one, in degrees, when
+// turning it back makes the pages match and leaving it as it is doesn't
+pub fn detect_rotation(old: &DynamicImage, new: &DynamicImage) -> Option<u32> {
+    let unrotated = match similar_size(old, new) {
+        true => change_ratio(old, new, PIXEL_TOLERANCE),
+        false => 1.0,
+    };
+    if unrotated <= MAX_ROTATED_CHANGE {
+        return None;
+    }
+
+    [90, 180, 270]
+        .into_iter()
+        .filter_map(|degrees| {
+            let turned = turn_back(new, degrees);
+            if !similar_size(old, &turned) {
+                return None;
+            }
+            let ratio = change_ratio(old, &turned, PIXEL_TOLERANCE);
+            (ratio <= MAX_ROTATED_CHANGE && ratio < unrotated).then_some((degrees, ratio))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(degrees, _)| degrees)
+}
+
+// Turn back new pages that only differ from their old page by a rotation, so
+// they are compared as they were meant to be read. Each turned page gets a
+// warning naming the rotation instead of showing up as a full-page change.
+pub fn auto_orient(images: &mut [(Option<DynamicImage>, Option<DynamicImage>)]) -> Vec<PageWarning> {
+    let mut warnings = vec![];
+    for (index, (old_image, new_image)) in images.iter_mut().enumerate() {
+        let (Some(old), Some(new)) = (old_image.as_ref(), new_image.as_mut()) else {
+            continue;
+        };
+        if let Some(degrees) = detect_rotation(old, new) {
+            *new = turn_back(new, degrees);
+            warnings.push(PageWarning {
+                page: index + 1,
+                message: format!("New page is rotated {}° clockwise; compared after turning it back", degrees),
+            });
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    // A portrait page with a mark near its top-left corner
+    fn page() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(40, 60, |x, y| {
+            if x < 10 && y < 5 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) }
+        }))
+    }
+
+    #[test]
+    fn test_detect_rotation() {
+        let page = page();
+        assert_eq!(detect_rotation(&page, &page), None);
+        assert_eq!(detect_rotation(&page, &page.rotate90()), Some(90));
+        assert_eq!(detect_rotation(&page, &page.rotate180()), Some(180));
+        assert_eq!(detect_rotation(&page, &page.rotate270()), Some(270));
+
+        let mut edited = page.rotate90().to_rgba8();
+        for x in 20..25 {
+            edited.put_pixel(x, 30, Rgba([255, 0, 0, 255]));
+        }
+        assert_eq!(detect_rotation(&page, &DynamicImage::ImageRgba8(edited.clone())), Some(90), "Small edits are still compared");
+        for y in 0..40 {
+            for x in 30..60 {
+                edited.put_pixel(x, y, Rgba([0, 0, 255, 255]));
+            }
+        }
+        assert_eq!(detect_rotation(&page, &DynamicImage::ImageRgba8(edited)), None, "A changed page isn't a rotated one");
+    }
+
+    #[test]
+    fn test_auto_orient() {
+        let page = page();
+        let mut images = vec![(Some(page.clone()), Some(page.rotate180())), (Some(page.clone()), Some(page.clone())), (None, Some(page.rotate90()))];
+        let warnings = auto_orient(&mut images);
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].page, 1);
+        assert!(warnings[0].message.contains("180°"), "{}", warnings[0].message);
+        assert_eq!(images[0].1.as_ref().unwrap().to_rgba8(), page.to_rgba8());
+        assert_eq!(images[2].1.as_ref().unwrap().width(), 60, "Unpaired pages are left alone");
+    }
+}
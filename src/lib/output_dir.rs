@@ -0,0 +1,179 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Whether `dir` has any entries; a directory that doesn't exist yet has none
+pub fn has_entries(dir: &Path) -> io::Result<bool> {
+    match std::fs::read_dir(dir) {
+        Ok(mut entries) => Ok(entries.next().is_some()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+// Refuse to clean a directory that holds more than outputs: the filesystem
+// root, the home directory, the working directory or one of its parents, or
+// a directory containing one of `inputs`
+pub fn check_cleanable(dir: &Path, inputs: &[PathBuf]) -> Result<(), String> {
+    let Ok(dir) = dir.canonicalize() else {
+        // Nothing there yet, so nothing to delete
+        return Ok(());
+    };
+
+    if dir.parent().is_none() {
+        return Err(format!("Refusing to clean {}: it is the filesystem root", dir.display()));
+    }
+    if let Some(home) = std::env::home_dir().and_then(|home| home.canonicalize().ok())
+        && home == dir
+    {
+        return Err(format!("Refusing to clean {}: it is the home directory", dir.display()));
+    }
+    if let Ok(cwd) = std::env::current_dir().and_then(|cwd| cwd.canonicalize())
+        && cwd.starts_with(&dir)
+    {
+        return Err(format!("Refusing to clean {}: it contains the working directory", dir.display()));
+    }
+    for input in inputs {
+        if let Ok(input) = input.canonicalize()
+            && input.starts_with(&dir)
+        {
+            return Err(format!("Refusing to clean {}: it contains the input {}", dir.display(), input.display()));
+        }
+    }
+    Ok(())
+}
+
+// Remove everything inside `dir`, keeping `dir` itself, and return the
+// number of entries removed. Symbolic links are removed rather than
+// followed, so nothing outside `dir` is touched.
+pub fn clean_dir(dir: &Path) -> io::Result<usize> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let mut removed = 0;
+    for entry in entries {
+        let entry = entry?;
+        // `file_type` describes the entry itself, not what a link points to
+        if entry.file_type()?.is_dir() {
+            std::fs::remove_dir_all(entry.path())?;
+        } else {
+            std::fs::remove_file(entry.path())?;
+        }
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+// Sibling of `dir` a run writes its outputs to before `publish` moves them
+// into `dir`. Being on the same filesystem, the move is a rename. `None` for
+// a directory without a name of its own, such as `.`.
+pub fn staging_dir(dir: &Path) -> Option<PathBuf> {
+    let name = dir.file_name()?.to_string_lossy();
+    Some(dir.with_file_name(format!(".{}.partial", name)))
+}
+
+// Move the outputs staged in `staging` into `dir`. A missing or empty `dir`
+// is replaced by `staging` in a single rename, so it never shows part of a
+// run's outputs; into a non-empty one each entry is renamed over its
+// namesake, so every file is complete when it appears.
+pub fn publish(staging: &Path, dir: &Path) -> io::Result<()> {
+    if !staging.exists() {
+        return Ok(());
+    }
+
+    if !has_entries(dir)? {
+        if dir.exists() {
+            std::fs::remove_dir(dir)?;
+        }
+        return std::fs::rename(staging, dir);
+    }
+
+    for entry in std::fs::read_dir(staging)? {
+        let entry = entry?;
+        let target = dir.join(entry.file_name());
+        match std::fs::symlink_metadata(&target) {
+            Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(&target)?,
+            Ok(_) => std::fs::remove_file(&target)?,
+            Err(_) => {}
+        }
+        std::fs::rename(entry.path(), target)?;
+    }
+    std::fs::remove_dir(staging)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_dir() {
+        let root = std::env::temp_dir().join("diff_pdf_test_clean_dir");
+        std::fs::remove_dir_all(&root).ok();
+        let (dir, outside) = (root.join("output"), root.join("outside"));
+        std::fs::create_dir_all(dir.join("crops")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(dir.join("diff_page_7.png"), b"stale").unwrap();
+        std::fs::write(dir.join("crops").join("region.png"), b"stale").unwrap();
+        std::fs::write(outside.join("keep.pdf"), b"%PDF-").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&outside, dir.join("link")).unwrap();
+
+        assert!(has_entries(&dir).unwrap());
+        assert!(clean_dir(&dir).unwrap() >= 2);
+        assert!(dir.is_dir(), "The directory itself is kept");
+        assert!(!has_entries(&dir).unwrap());
+        assert!(outside.join("keep.pdf").is_file(), "Links are not followed");
+
+        assert!(!has_entries(&root.join("missing")).unwrap());
+        assert_eq!(clean_dir(&root.join("missing")).unwrap(), 0);
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_check_cleanable() {
+        let root = std::env::temp_dir().join("diff_pdf_test_check_cleanable");
+        std::fs::create_dir_all(root.join("output")).unwrap();
+        std::fs::write(root.join("output").join("old.pdf"), b"%PDF-").unwrap();
+
+        let inputs = [root.join("output").join("old.pdf")];
+        assert!(check_cleanable(&root.join("output"), &inputs).unwrap_err().contains("contains the input"));
+        assert!(check_cleanable(&root.join("output"), &[]).is_ok());
+        assert!(check_cleanable(Path::new("/"), &[]).unwrap_err().contains("filesystem root"));
+        assert!(check_cleanable(Path::new("."), &[]).unwrap_err().contains("working directory"));
+        assert!(check_cleanable(&root.join("missing"), &inputs).is_ok());
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_publish() {
+        let root = std::env::temp_dir().join("diff_pdf_test_publish");
+        std::fs::remove_dir_all(&root).ok();
+        let dir = root.join("output");
+        let staging = staging_dir(&dir).unwrap();
+        assert_eq!(staging, root.join(".output.partial"));
+        assert_eq!(staging_dir(Path::new(".")), None);
+
+        // Into a missing directory
+        std::fs::create_dir_all(staging.join("crops")).unwrap();
+        std::fs::write(staging.join("report.html"), b"first").unwrap();
+        publish(&staging, &dir).unwrap();
+        assert!(!staging.exists());
+        assert_eq!(std::fs::read(dir.join("report.html")).unwrap(), b"first");
+        assert!(dir.join("crops").is_dir());
+
+        // Over the outputs of an earlier run
+        std::fs::write(dir.join("notes.txt"), b"kept").unwrap();
+        std::fs::create_dir_all(&staging).unwrap();
+        std::fs::write(staging.join("report.html"), b"second").unwrap();
+        publish(&staging, &dir).unwrap();
+        assert!(!staging.exists());
+        assert_eq!(std::fs::read(dir.join("report.html")).unwrap(), b"second");
+        assert!(dir.join("notes.txt").is_file());
+
+        // Nothing staged
+        publish(&staging, &dir).unwrap();
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
@@ -0,0 +1,140 @@
+use std::error::Error;
+use std::io::Write;
+
+use image::{DynamicImage, GrayImage, Luma};
+
+use crate::image_utils::{diff_page, write_png, PngOptions};
+use crate::regions::{change_mask, find_changed_regions, Region, PIXEL_TOLERANCE};
+use crate::report::PageStatus;
+
+// Everything known about one compared page, held in memory for embedders
+// that don't want outputs written to a directory
+#[derive(Debug, Clone)]
+pub struct PageDiff {
+    // 1-based position of the page in the comparison
+    pub page: usize,
+    pub status: PageStatus,
+    // The diff where the renders differ, otherwise the page itself (the lone
+    // render for added and removed pages)
+    pub image: DynamicImage,
+    // White where pixels changed, over the union of both render sizes
+    pub mask: GrayImage,
+    // Fraction of pixels that changed; added and removed pages count as 1.0
+    pub ratio: f64,
+    pub regions: Vec<Region>,
+}
+
+impl PageDiff {
+    // Encode the page's image as PNG into `writer`
+    pub fn write_to<W: Write>(&self, writer: W, options: PngOptions) -> Result<(), Box<dyn Error>> {
+        write_png(&self.image, writer, options)
+    }
+
+    // Encode the change mask as PNG into `writer`
+    pub fn write_mask_to<W: Write>(&self, writer: W, options: PngOptions) -> Result<(), Box<dyn Error>> {
+        write_png(&DynamicImage::ImageLuma8(self.mask.clone()), writer, options)
+    }
+}
+
+fn page_mask(old_image: Option<&DynamicImage>, new_image: Option<&DynamicImage>) -> GrayImage {
+    match (old_image, new_image) {
+        (Some(old), Some(new)) => {
+            let (width, height, mask) = change_mask(old, new, PIXEL_TOLERANCE);
+            let pixels = mask.into_iter().map(|changed| if changed { 255 } else { 0 }).collect();
+            GrayImage::from_raw(width, height, pixels).expect("mask covers every pixel")
+        }
+        (Some(page), None) | (None, Some(page)) => GrayImage::from_pixel(page.width(), page.height(), Luma([255])),
+        (None, None) => GrayImage::new(0, 0),
+    }
+}
+
+// Diff the page at a 0-based position; `None` when neither side has a render
+pub fn diff_one_page(
+    index: usize,
+    old_image: Option<&DynamicImage>,
+    new_image: Option<&DynamicImage>,
+    sensitivity: f32,
+) -> Result<Option<PageDiff>, Box<dyn Error>> {
+    // An unchanged page shows itself
+    let Some(image) = diff_page(old_image, new_image, sensitivity)?.or_else(|| new_image.cloned()) else {
+        return Ok(None);
+    };
+
+    let mask = page_mask(old_image, new_image);
+    let changed = mask.pixels().filter(|pixel| pixel[0] > 0).count();
+    let total = mask.width() as f64 * mask.height() as f64;
+    let regions = match (old_image, new_image) {
+        (Some(old), Some(new)) => find_changed_regions(old, new, PIXEL_TOLERANCE),
+        _ => vec![],
+    };
+
+    Ok(Some(PageDiff {
+        page: index + 1,
+        status: PageStatus::of(old_image, new_image),
+        image,
+        ratio: if total > 0.0 { changed as f64 / total } else { 0.0 },
+        mask,
+        regions,
+    }))
+}
+
+// Diff every page without touching the filesystem. Pages left out of the
+// comparison (no render on either side) have no entry.
+pub fn diff_pages(
+    images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+    sensitivity: f32,
+) -> Result<Vec<PageDiff>, Box<dyn Error>> {
+    let mut pages = vec![];
+    for (index, (old_image, new_image)) in images.iter().enumerate() {
+        pages.extend(diff_one_page(index, old_image.as_ref(), new_image.as_ref(), sensitivity)?);
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::create_solid_color_image;
+    use image::Rgba;
+
+    #[test]
+    fn test_diff_pages_in_memory() {
+        let solid = |color| Some(create_solid_color_image(16, 16, Rgba(color)));
+        let red = solid([255, 0, 0, 255]);
+        let mut spotted = red.as_ref().unwrap().to_rgba8();
+        spotted.put_pixel(3, 4, Rgba([0, 0, 255, 255]));
+        let spotted = Some(DynamicImage::ImageRgba8(spotted));
+        let images = vec![(red.clone(), red.clone()), (None, None), (red.clone(), spotted), (None, red)];
+
+        let pages = diff_pages(&images, 0.12).unwrap();
+        let numbers: Vec<usize> = pages.iter().map(|page| page.page).collect();
+        assert_eq!(numbers, vec![1, 3, 4]);
+
+        assert_eq!(pages[0].status, PageStatus::Unchanged);
+        assert_eq!(pages[0].ratio, 0.0);
+        assert!(pages[0].regions.is_empty());
+
+        assert_eq!(pages[1].status, PageStatus::Changed);
+        assert_eq!(pages[1].ratio, 1.0 / 256.0);
+        assert_eq!(pages[1].mask.get_pixel(3, 4), &Luma([255]));
+        assert_eq!(pages[1].regions.len(), 1);
+
+        assert_eq!(pages[2].status, PageStatus::Added);
+        assert_eq!(pages[2].ratio, 1.0);
+    }
+
+    #[test]
+    fn test_write_to_buffer() {
+        let solid = |color| Some(create_solid_color_image(16, 16, Rgba(color)));
+        let images = vec![(solid([255, 0, 0, 255]), solid([0, 0, 255, 255]))];
+        let page = &diff_pages(&images, 0.12).unwrap()[0];
+
+        let mut png = vec![];
+        page.write_to(&mut png, PngOptions::default()).unwrap();
+        assert_eq!(image::load_from_memory(&png).unwrap().to_rgba8(), page.image.to_rgba8());
+
+        let mut mask = vec![];
+        page.write_mask_to(&mut mask, PngOptions::default()).unwrap();
+        assert_eq!(image::load_from_memory(&mask).unwrap().to_luma8(), page.mask);
+    }
+}
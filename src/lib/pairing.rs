@@ -0,0 +1,306 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+
+use pdfium_render::prelude::{PdfDocument, PdfPageIndex};
+
+use crate::pdf::{index_pairing, PagePairs};
+use crate::text::document_text;
+
+// Similarity below which two pages are never paired
+pub const MIN_PAGE_SIMILARITY: f64 = 0.5;
+
+// How pages of the old document are matched with pages of the new one, as
+// chosen with `--pairing`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PairingMode {
+    /// Pair by position, unless the page counts differ; then pair by text similarity
+    Auto,
+    /// Always pair pages by position
+    Index,
+    /// Pair pages with the same page label, such as "iv" or "A-3"
+    Label,
+    /// Always pair pages by text similarity
+    Similarity,
+}
+
+// Policy pairing the pages of two documents, for embedders choosing one
+// without going through `PairingMode`
+#[derive(Debug, Clone, PartialEq)]
+pub enum PagePairing {
+    // Same position; the longer document's extra pages are added or removed
+    ByIndex,
+    // Same page label, in document order. Documents without labels are
+    // paired by position.
+    ByLabel,
+    // Most similar text, in document order, never pairing pages less similar
+    // than `min_similarity`
+    BySimilarity { min_similarity: f64 },
+    // 0-based old page index to the new page it is paired with. Pages between
+    // two mapped pairs are paired by position; pages left over are added or
+    // removed.
+    Explicit(BTreeMap<usize, usize>),
+}
+
+impl PagePairing {
+    // Pair the pages of two documents
+    pub fn pair(&self, old_document: &PdfDocument, new_document: &PdfDocument) -> Result<PagePairs, Box<dyn Error>> {
+        let (old_count, new_count) = (old_document.pages().len() as usize, new_document.pages().len() as usize);
+        match self {
+            PagePairing::ByIndex => Ok(index_pairing(old_count, new_count)),
+            PagePairing::ByLabel => Ok(pair_by_label(&document_labels(old_document), &document_labels(new_document))),
+            PagePairing::BySimilarity { min_similarity } => pair_documents(old_document, new_document, *min_similarity),
+            PagePairing::Explicit(map) => Ok(explicit_pairing(map, old_count, new_count)?),
+        }
+    }
+
+    // What pages were paired by, for messages
+    pub fn describe(&self) -> &'static str {
+        match self {
+            PagePairing::ByIndex => "position",
+            PagePairing::ByLabel => "page label",
+            PagePairing::BySimilarity { .. } => "text similarity",
+            PagePairing::Explicit(_) => "the given page map",
+        }
+    }
+}
+
+// Set of hashed lowercase words on a page. Word order and layout are ignored,
+// which keeps it cheap and tolerant of reflowed text.
+pub type Fingerprint = HashSet<u64>;
+
+pub fn fingerprint(text: &str) -> Fingerprint {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut hasher = DefaultHasher::new();
+            word.to_lowercase().hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+// Jaccard similarity of two fingerprints; two pages without text count as equal
+pub fn similarity(a: &Fingerprint, b: &Fingerprint) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    let shared = a.intersection(b).count();
+    shared as f64 / (a.len() + b.len() - shared) as f64
+}
+
+// Fingerprints of every page of a document
+pub fn document_fingerprints(document: &PdfDocument) -> Result<Vec<Fingerprint>, Box<dyn Error>> {
+    let pages: Vec<usize> = (0..document.pages().len() as usize).collect();
+    Ok(document_text(document, &pages, false)?
+        .iter()
+        .map(|page| fingerprint(&page.text))
+        .collect())
+}
+
+// Pair pages in document order so that the summed similarity of the pairs is
+// as high as possible, never pairing pages less similar than `min_similarity`.
+// Pages left without a partner come back as removed `(Some, None)` or
+// inserted `(None, Some)`. Without any text on one side there is nothing to go
+// by, so pages are paired by position.
+pub fn pair_by_similarity(old: &[Fingerprint], new: &[Fingerprint], min_similarity: f64) -> PagePairs {
+    if old.iter().all(HashSet::is_empty) || new.iter().all(HashSet::is_empty) {
+        return index_pairing(old.len(), new.len());
+    }
+
+    align(old.len(), new.len(), |i, j| {
+        let similarity = similarity(&old[i], &new[j]);
+        (similarity >= min_similarity).then_some(similarity)
+    })
+}
+
+// Label of every page of a document; `None` for pages without one
+pub fn document_labels(document: &PdfDocument) -> Vec<Option<String>> {
+    (0..document.pages().len())
+        .map(|index| document.pages().get(index as PdfPageIndex).ok().and_then(|page| page.label().map(str::to_string)))
+        .collect()
+}
+
+// Pair pages with equal labels in document order, like `pair_by_similarity`
+// with labels for text. Without labels on one side, pages are paired by
+// position.
+pub fn pair_by_label(old: &[Option<String>], new: &[Option<String>]) -> PagePairs {
+    if old.iter().all(Option::is_none) || new.iter().all(Option::is_none) {
+        return index_pairing(old.len(), new.len());
+    }
+    align(old.len(), new.len(), |i, j| (old[i].is_some() && old[i] == new[j]).then_some(1.0))
+}
+
+// Pair `n` old pages with `m` new pages in document order so that the summed
+// score of the pairs is as high as possible. `score` is `None` for pages that
+// may not be paired.
+fn align(n: usize, m: usize, score: impl Fn(usize, usize) -> Option<f64>) -> PagePairs {
+    let scores: Vec<Vec<Option<f64>>> = (0..n).map(|i| (0..m).map(|j| score(i, j)).collect()).collect();
+    let pairable = |i: usize, j: usize| scores[i][j].is_some();
+    let pair_score = |i: usize, j: usize| scores[i][j].unwrap_or(0.0);
+
+    // best[i][j]: highest score aligning the first i old pages with the first j new pages
+    let mut best = vec![vec![0.0f64; m + 1]; n + 1];
+    for i in 1..=n {
+        for j in 1..=m {
+            let mut score = best[i - 1][j].max(best[i][j - 1]);
+            if pairable(i - 1, j - 1) {
+                score = score.max(best[i - 1][j - 1] + pair_score(i - 1, j - 1));
+            }
+            best[i][j] = score;
+        }
+    }
+
+    let mut pairs = vec![];
+    let (mut i, mut j) = (n, m);
+    while i > 0 || j > 0 {
+        if i > 0 && j > 0 && pairable(i - 1, j - 1) && best[i][j] == best[i - 1][j - 1] + pair_score(i - 1, j - 1) {
+            pairs.push((Some(i - 1), Some(j - 1)));
+            i -= 1;
+            j -= 1;
+        } else if j > 0 && (i == 0 || best[i][j] == best[i][j - 1]) {
+            pairs.push((None, Some(j - 1)));
+            j -= 1;
+        } else {
+            pairs.push((Some(i - 1), None));
+            i -= 1;
+        }
+    }
+
+    pairs.reverse();
+    pairs
+}
+
+// Pairing from `map`, with the pages between mapped pairs paired by position
+// like `index_pairing` does. The map has to keep both documents in order.
+pub fn explicit_pairing(map: &BTreeMap<usize, usize>, old_count: usize, new_count: usize) -> Result<PagePairs, String> {
+    let mut pairs = vec![];
+    let (mut old, mut new) = (0, 0);
+    for (&old_index, &new_index) in map {
+        if old_index >= old_count || new_index >= new_count {
+            return Err(format!(
+                "Page map pairs old page {} with new page {}, but the documents have {} and {} pages",
+                old_index + 1,
+                new_index + 1,
+                old_count,
+                new_count
+            ));
+        }
+        if new_index < new {
+            return Err(format!("Page map pairs old page {} with new page {}, out of order with the pages before it", old_index + 1, new_index + 1));
+        }
+        pair_gap(&mut pairs, old..old_index, new..new_index);
+        pairs.push((Some(old_index), Some(new_index)));
+        (old, new) = (old_index + 1, new_index + 1);
+    }
+    pair_gap(&mut pairs, old..old_count, new..new_count);
+    Ok(pairs)
+}
+
+// Pair two runs of pages by position, the longer run's extra pages alone
+fn pair_gap(pairs: &mut PagePairs, old: std::ops::Range<usize>, new: std::ops::Range<usize>) {
+    for offset in 0..old.len().max(new.len()) {
+        pairs.push(((offset < old.len()).then(|| old.start + offset), (offset < new.len()).then(|| new.start + offset)));
+    }
+}
+
+// Parse a 1-based page map such as `1=1,3=5` into 0-based page indices
+pub fn parse_page_map(value: &str) -> Result<BTreeMap<usize, usize>, String> {
+    let page = |number: &str| match number.trim().parse::<usize>() {
+        Ok(number) if number > 0 => Ok(number - 1),
+        _ => Err(format!("Invalid page '{}' in page map, expected a page number from 1", number.trim())),
+    };
+    let mut map = BTreeMap::new();
+    for entry in value.split(',').filter(|entry| !entry.trim().is_empty()) {
+        let Some((old, new)) = entry.split_once('=') else {
+            return Err(format!("Invalid page map entry '{}', expected OLD=NEW such as 3=5", entry.trim()));
+        };
+        if map.insert(page(old)?, page(new)?).is_some() {
+            return Err(format!("Old page {} is mapped twice", old.trim()));
+        }
+    }
+    Ok(map)
+}
+
+// Pair the pages of two documents by the similarity of their text
+pub fn pair_documents(
+    old_document: &PdfDocument,
+    new_document: &PdfDocument,
+    min_similarity: f64,
+) -> Result<PagePairs, Box<dyn Error>> {
+    let old = document_fingerprints(old_document)?;
+    let new = document_fingerprints(new_document)?;
+    Ok(pair_by_similarity(&old, &new, min_similarity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prints(pages: &[&str]) -> Vec<Fingerprint> {
+        pages.iter().map(|text| fingerprint(text)).collect()
+    }
+
+    #[test]
+    fn test_similarity() {
+        let a = fingerprint("The quick brown fox");
+        let b = fingerprint("the QUICK brown dog");
+        assert_eq!(similarity(&a, &a), 1.0);
+        assert!((similarity(&a, &b) - 0.6).abs() < 1e-9);
+        assert_eq!(similarity(&fingerprint(""), &fingerprint("  ")), 1.0);
+        assert_eq!(similarity(&a, &fingerprint("")), 0.0);
+    }
+
+    #[test]
+    fn test_pair_by_similarity_inserted_title_page() {
+        let old = prints(&["chapter one begins here", "chapter two continues", "the end"]);
+        let new = prints(&["cover title page", "chapter one begins here", "chapter two continues now", "the end"]);
+
+        let pairs = pair_by_similarity(&old, &new, MIN_PAGE_SIMILARITY);
+        assert_eq!(pairs, vec![(None, Some(0)), (Some(0), Some(1)), (Some(1), Some(2)), (Some(2), Some(3))]);
+    }
+
+    #[test]
+    fn test_pair_by_similarity_removed_page() {
+        let old = prints(&["alpha beta", "gamma delta", "epsilon zeta"]);
+        let new = prints(&["alpha beta", "epsilon zeta"]);
+
+        let pairs = pair_by_similarity(&old, &new, MIN_PAGE_SIMILARITY);
+        assert_eq!(pairs, vec![(Some(0), Some(0)), (Some(1), None), (Some(2), Some(1))]);
+    }
+
+    #[test]
+    fn test_pair_by_label() {
+        let labels = |labels: &[&str]| labels.iter().map(|label| Some(label.to_string())).collect::<Vec<_>>();
+        let old = labels(&["i", "ii", "1", "2"]);
+        let new = labels(&["i", "ii", "iii", "1", "2"]);
+        assert_eq!(pair_by_label(&old, &new), vec![(Some(0), Some(0)), (Some(1), Some(1)), (None, Some(2)), (Some(2), Some(3)), (Some(3), Some(4))]);
+        assert_eq!(pair_by_label(&[None, None], &new), index_pairing(2, 5));
+    }
+
+    #[test]
+    fn test_explicit_pairing() {
+        // New page 3 was inserted, old page 5 removed
+        let map = parse_page_map("3=4, 6=6").unwrap();
+        assert_eq!(map, BTreeMap::from([(2, 3), (5, 5)]));
+        assert_eq!(
+            explicit_pairing(&map, 6, 6).unwrap(),
+            vec![(Some(0), Some(0)), (Some(1), Some(1)), (None, Some(2)), (Some(2), Some(3)), (Some(3), Some(4)), (Some(4), None), (Some(5), Some(5))]
+        );
+        assert_eq!(explicit_pairing(&BTreeMap::new(), 2, 3).unwrap(), index_pairing(2, 3));
+
+        assert!(explicit_pairing(&map, 6, 5).is_err(), "New page 6 doesn't exist");
+        assert!(explicit_pairing(&parse_page_map("1=2,2=1").unwrap(), 2, 2).is_err(), "Crossed pairs");
+        assert!(parse_page_map("0=1").is_err());
+        assert!(parse_page_map("1-2").is_err());
+        assert!(parse_page_map("1=1,1=2").is_err());
+    }
+
+    #[test]
+    fn test_pair_by_similarity_without_text_pairs_by_position() {
+        let old = prints(&["", ""]);
+        let new = prints(&["", "", ""]);
+        assert_eq!(pair_by_similarity(&old, &new, MIN_PAGE_SIMILARITY), index_pairing(2, 3));
+    }
+}
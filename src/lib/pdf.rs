@@ -1,97 +1,810 @@
-use std::path::{Path};
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
 use std::fmt;
 use std::error::Error;
+use std::str::FromStr;
 #[allow(unused_imports)]
 use image::{DynamicImage, GenericImageView};
-use pdfium_render::prelude::{PdfDocument, PdfRenderConfig, Pdfium};
+use pdfium_render::prelude::{
+    PdfDocument, PdfPage, PdfPageIndex, PdfPageObject, PdfPageObjectsCommon, PdfPageObjectsIterator, PdfRenderConfig,
+    Pdfium, PdfiumError, PdfiumInternalError,
+};
+
+use crate::cancel::CancelToken;
+use crate::label::placeholder_page;
+use crate::pdfium_search;
+use crate::raster::fit_to;
 
  
+// Callers can tell bad input apart from other failures, e.g. to answer an
+// upload with a client error rather than a server error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdfErrorKind {
+    Other,
+    // Not a PDF, unparseable, or over the `InputLimits`
+    MalformedInput,
+}
+
 #[derive(Debug)]
 pub struct PdfError {
-    message: String
+    message: String,
+    kind: PdfErrorKind,
+}
+
+impl PdfError {
+    fn new(message: String) -> PdfError {
+        PdfError {
+            message,
+            kind: PdfErrorKind::Other,
+        }
+    }
+
+    fn malformed(message: String) -> PdfError {
+        PdfError {
+            message,
+            kind: PdfErrorKind::MalformedInput,
+        }
+    }
+
+    pub fn kind(&self) -> PdfErrorKind {
+        self.kind
+    }
+
+    // Prefix the message, keeping the kind
+    pub fn context(self, prefix: &str) -> PdfError {
+        PdfError {
+            message: format!("{}: {}", prefix, self.message),
+            ..self
+        }
+    }
 }
 
 impl fmt::Display for PdfError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.message)
+        match self.kind {
+            PdfErrorKind::Other => write!(f, "{}", self.message),
+            PdfErrorKind::MalformedInput => write!(f, "Malformed input: {}", self.message),
+        }
     }
 }
 
 impl Error for PdfError {}
 
+// Renders of the same page from the old and new document; `None` where a
+// document has no such page
+pub type PagePair = (Option<DynamicImage>, Option<DynamicImage>);
+
+// Which pages are compared with each other: 0-based (old, new) page indices
+// for every position of the comparison, `None` where a page has no counterpart
+pub type PagePairs = Vec<(Option<usize>, Option<usize>)>;
+
+// Pair pages by position, covering the pages of the new document. A new
+// document without pages covers the old one instead, so that its pages show
+// as removed rather than the comparison coming out empty.
+pub fn index_pairing(old_page_count: usize, new_page_count: usize) -> PagePairs {
+    if new_page_count == 0 {
+        return (0..old_page_count).map(|index| (Some(index), None)).collect();
+    }
+    (0..new_page_count)
+        .map(|index| ((index < old_page_count).then_some(index), Some(index)))
+        .collect()
+}
+
+// Pdfium rendering switches. Anti-aliasing differences between machines are a
+// common source of flaky diffs, so pinning these makes renders reproducible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RenderFlag {
+    /// Optimize text anti-aliasing for LCD displays
+    LcdText,
+    /// Render all text with pdfium's own rasterizer instead of the platform's
+    NoNativeText,
+    /// Render in grayscale, including grayscale anti-aliasing
+    Grayscale,
+    /// Disable text anti-aliasing
+    NoTextAa,
+    /// Disable image smoothing
+    NoImageAa,
+    /// Disable vector path anti-aliasing
+    NoPathAa,
+    /// Use the document's print quality settings
+    PrintQuality,
+    /// Leave out annotations and form field data
+    NoAnnotations,
+}
+
+fn apply_render_flags(config: PdfRenderConfig, flags: &[RenderFlag]) -> PdfRenderConfig {
+    flags.iter().fold(config, |config, flag| match flag {
+        RenderFlag::LcdText => config.use_lcd_text_rendering(true),
+        RenderFlag::NoNativeText => config.disable_native_text_rendering(true),
+        RenderFlag::Grayscale => config.use_grayscale_rendering(true),
+        RenderFlag::NoTextAa => config.set_text_smoothing(false),
+        RenderFlag::NoImageAa => config.set_image_smoothing(false),
+        RenderFlag::NoPathAa => config.set_path_smoothing(false),
+        RenderFlag::PrintQuality => config.use_print_quality(true),
+        RenderFlag::NoAnnotations => config.render_annotations(false).render_form_data(false),
+    })
+}
+
+// A physical length on the page, kept in points (1/72 inch)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Length {
+    pub points: f32,
+}
+
+impl FromStr for Length {
+    type Err = String;
+
+    // Accepts a non-negative number with a `pt`, `mm`, `cm` or `in` unit
+    fn from_str(value: &str) -> Result<Length, String> {
+        let invalid = || format!("Invalid length '{}', expected a number with a unit like '1mm', '0.5pt' or '0.01in'", value);
+        let split = value.find(|c: char| c.is_ascii_alphabetic()).ok_or_else(invalid)?;
+        let (number, unit) = value.split_at(split);
+        let number: f32 = number.trim().parse().map_err(|_| invalid())?;
+        let points_per_unit = match unit {
+            "pt" => 1.0,
+            "mm" => 72.0 / 25.4,
+            "cm" => 72.0 / 2.54,
+            "in" => 72.0,
+            _ => return Err(invalid()),
+        };
+        match number.is_finite() && number >= 0.0 {
+            true => Ok(Length { points: number * points_per_unit }),
+            false => Err(invalid()),
+        }
+    }
+}
+
+// Directory of the PDFium library bundled with the source tree
+pub const DEFAULT_PDFIUM_PATH: &str = "./pdfium-mac-arm64/lib/";
+
+// Directory PDFium is loaded from without `--pdfium-path`: the first
+// directory of the platform's search path that has the library (see
+// `pdfium_search::search_dirs`), else `pdfium/lib/` in the per-user data
+// directory, which works wherever the tool itself is installed
+pub fn default_pdfium_path() -> String {
+    let dirs = pdfium_search::default_search_dirs();
+    if let Some(dir) = pdfium_search::find_library(&dirs, pdfium_search::current_os()) {
+        return dir.display().to_string();
+    }
+    match crate::app_dirs::data_dir() {
+        Some(dir) => format!("{}/", dir.join("pdfium").join("lib").display()),
+        None => DEFAULT_PDFIUM_PATH.to_string(),
+    }
+}
+
+// Bind to PDFium wherever the platform's search path finds it
 pub fn create_pdfium() -> Result<Pdfium, PdfError> {
+    let dirs = pdfium_search::default_search_dirs();
+    match pdfium_search::find_library(&dirs, pdfium_search::current_os()) {
+        Some(dir) => create_pdfium_at(&dir.display().to_string()),
+        None => Err(PdfError::new(pdfium_search::not_found_message(&dirs, pdfium_search::current_os()))),
+    }
+}
+
+// Version of the PDFium build in `library_dir`, read from the `VERSION` file
+// that PDFium binary distributions ship next to (or one level above) `lib/`.
+// PDFium has no API to report its own version.
+pub fn pdfium_version(library_dir: &str) -> Option<String> {
+    let dir = Path::new(library_dir);
+    [dir.join("VERSION"), dir.join("../VERSION")]
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .and_then(|text| parse_version_file(&text))
+}
+
+// Turn `MAJOR=140\nMINOR=0\nBUILD=7350\nPATCH=0` into "140.0.7350.0"
+fn parse_version_file(text: &str) -> Option<String> {
+    let field = |name: &str| {
+        text.lines()
+            .filter_map(|line| line.split_once('='))
+            .find(|(key, _)| key.trim() == name)
+            .map(|(_, value)| value.trim().to_string())
+    };
+
+    Some(format!("{}.{}.{}.{}", field("MAJOR")?, field("MINOR")?, field("BUILD")?, field("PATCH")?))
+}
+
+// One-line description of the PDFium library in `library_dir`, recorded in
+// reports since renders (and so diffs) depend on the PDFium build
+pub fn describe_pdfium(library_dir: &str) -> String {
+    let library = Pdfium::pdfium_platform_library_name_at_path(library_dir);
+    match pdfium_version(library_dir) {
+        Some(version) => format!("PDFium {} ({})", version, library.display()),
+        None => format!("PDFium, unknown version ({})", library.display()),
+    }
+}
+
+// Bind to the PDFium library in `library_dir`. Without a library there, the
+// error lists where else it was looked for, so a wrong `--pdfium-path` can
+// be told apart from a missing install.
+pub fn create_pdfium_at(library_dir: &str) -> Result<Pdfium, PdfError> {
+    let library = Pdfium::pdfium_platform_library_name_at_path(library_dir);
+    if !library.is_file() {
+        let mut dirs = vec![PathBuf::from(library_dir)];
+        dirs.extend(pdfium_search::default_search_dirs().into_iter().filter(|dir| dir != Path::new(library_dir)));
+        return Err(PdfError::new(pdfium_search::not_found_message(&dirs, pdfium_search::current_os())));
+    }
+
     let pdfium = Pdfium::new(
-        Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("./pdfium-mac-arm64/lib/"))
-            .map_err(|e| PdfError {
-                message: format!("Failed to bind to PDFium library: {:?}", e),
-            })?
+        Pdfium::bind_to_library(&library)
+            .map_err(|e| PdfError::new(format!("Failed to bind to PDFium library {}: {:?}", library.display(), e)))?
     );
     Ok(pdfium)
 }
 
+// Guard rails for untrusted input. Documents over a limit are rejected as
+// malformed input before PDFium can spend unbounded time or memory on them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputLimits {
+    // Bytes
+    pub max_file_size: u64,
+    pub max_pages: usize,
+    // Text runs, paths, images and so on drawn on one page, including those
+    // inside form XObjects
+    pub max_page_objects: usize,
+    // Pixels of one page render at the requested DPI
+    pub max_page_pixels: u64,
+}
+
+impl Default for InputLimits {
+    fn default() -> InputLimits {
+        InputLimits {
+            max_file_size: 512 * 1024 * 1024,
+            max_pages: 10_000,
+            max_page_objects: 500_000,
+            max_page_pixels: 250_000_000,
+        }
+    }
+}
+
+// Deepest nesting of form XObjects followed on a page. Forms can draw other
+// forms, and a document nesting them without end would otherwise keep the
+// object count and font checks busy forever.
+pub const MAX_FORM_DEPTH: usize = 32;
+
+// Bytes at the start of a file searched for the `%PDF-` header; readers
+// accept leading junk up to this point
+const HEADER_SEARCH_LEN: u64 = 1024;
+
+// Check a file before PDFium parses it: it must exist, fit the size limit
+// and carry a PDF header
+fn check_input_file(path: &Path, limits: &InputLimits) -> Result<(), PdfError> {
+    if !path.is_file() {
+        return Err(PdfError::new(format!("PDF file does not exist: {:?}", path)));
+    }
+
+    let size = std::fs::metadata(path)
+        .map_err(|e| PdfError::new(format!("Failed to read {:?}: {}", path, e)))?
+        .len();
+    if size > limits.max_file_size {
+        return Err(PdfError::malformed(format!(
+            "{:?} is {} bytes, over the limit of {}",
+            path, size, limits.max_file_size
+        )));
+    }
+
+    let mut head = vec![];
+    std::fs::File::open(path)
+        .and_then(|file| file.take(HEADER_SEARCH_LEN).read_to_end(&mut head))
+        .map_err(|e| PdfError::new(format!("Failed to read {:?}: {}", path, e)))?;
+    if !head.windows(5).any(|window| window == b"%PDF-") {
+        return Err(PdfError::malformed(format!("{:?} is not a PDF file (no %PDF- header)", path)));
+    }
+    Ok(())
+}
+
+// Load a checked file; PDFium's parse failures count as malformed input
+fn load_checked<'a>(pdfium: &'a Pdfium, path: &Path, limits: &InputLimits) -> Result<PdfDocument<'a>, PdfError> {
+    check_input_file(path, limits)?;
+    let document = pdfium.load_pdf_from_file(path, None).map_err(|e| match e {
+        PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::FormatError) => {
+            PdfError::malformed(format!("{:?} could not be parsed as a PDF", path))
+        }
+        e => PdfError::new(format!("Failed to load PDF file: {:?}", e)),
+    })?;
+
+    let pages = document.pages().len() as usize;
+    if pages > limits.max_pages {
+        return Err(PdfError::malformed(format!(
+            "{:?} has {} pages, over the limit of {}",
+            path, pages, limits.max_pages
+        )));
+    }
+    Ok(document)
+}
+
 pub fn load_pdf_documents<'a>(
     pdfium: &'a Pdfium,
     old_pdf_path: &Path, 
     new_pdf_path: &Path
 ) -> Result<(PdfDocument<'a>, PdfDocument<'a>), PdfError> {
-    if !old_pdf_path.exists() || !old_pdf_path.is_file() {
-        return Err(PdfError {
-            message: format!("Old PDF file does not exist: {:?}", old_pdf_path),
-        });
+    load_pdf_documents_with_limits(pdfium, old_pdf_path, new_pdf_path, &InputLimits::default())
+}
+
+pub fn load_pdf_documents_with_limits<'a>(
+    pdfium: &'a Pdfium,
+    old_pdf_path: &Path,
+    new_pdf_path: &Path,
+    limits: &InputLimits,
+) -> Result<(PdfDocument<'a>, PdfDocument<'a>), PdfError> {
+    let old_document = load_checked(pdfium, old_pdf_path, limits).map_err(|e| e.context("Old PDF"))?;
+    let new_document = load_checked(pdfium, new_pdf_path, limits).map_err(|e| e.context("New PDF"))?;
+
+    Ok((old_document, new_document))
+}
+
+pub fn load_pdf_document<'a>(pdfium: &'a Pdfium, path: &Path) -> Result<PdfDocument<'a>, PdfError> {
+    load_pdf_document_with_limits(pdfium, path, &InputLimits::default())
+}
+
+pub fn load_pdf_document_with_limits<'a>(
+    pdfium: &'a Pdfium,
+    path: &Path,
+    limits: &InputLimits,
+) -> Result<PdfDocument<'a>, PdfError> {
+    load_checked(pdfium, path, limits)
+}
+
+// How the pages of a document pair are rasterized
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    pub dpi: f32,
+    pub flags: Vec<RenderFlag>,
+    // Sorted 0-based positions (in the pairing) to render. Positions left out
+    // come back as `(None, None)` so page numbers stay aligned.
+    pub pages: Option<Vec<usize>>,
+    // Pages to compare with each other; by position when not given
+    pub pairing: Option<PagePairs>,
+    // Checked before each page; once cancelled, the remaining positions come
+    // back as `(None, None)` like pages left out
+    pub cancel: Option<CancelToken>,
+    // Pages over the object or pixel limits fail to render as malformed input
+    pub limits: InputLimits,
+    // Points by which the width and height of paired pages may differ and
+    // still be rendered at the same size, the old page's. Different PDF
+    // generators round page sizes differently.
+    pub size_tolerance: f32,
+}
+
+impl RenderOptions {
+    pub fn new(dpi: f32) -> RenderOptions {
+        RenderOptions {
+            dpi,
+            flags: vec![],
+            pages: None,
+            pairing: None,
+            cancel: None,
+            limits: InputLimits::default(),
+            size_tolerance: 0.0,
+        }
     }
 
-    if !new_pdf_path.exists() || !new_pdf_path.is_file() {
-        return Err(PdfError {
-            message: format!("New PDF file does not exist: {:?}", new_pdf_path),
-        });
+    fn includes(&self, position: usize) -> bool {
+        let cancelled = self.cancel.as_ref().is_some_and(|cancel| cancel.is_cancelled());
+        !cancelled && self.pages.as_ref().is_none_or(|pages| pages.binary_search(&position).is_ok())
     }
 
-    let old_document = pdfium.load_pdf_from_file(old_pdf_path, None).map_err(|e| PdfError {
-        message: format!("Failed to load old PDF file: {:?}", e),
-    })?;
-    let new_document = pdfium.load_pdf_from_file(new_pdf_path, None).map_err(|e| PdfError {
-        message: format!("Failed to load new PDF file: {:?}", e),
-    })?;
+    fn pairing_for(&self, old_document: &PdfDocument, new_document: &PdfDocument) -> PagePairs {
+        match &self.pairing {
+            Some(pairing) => pairing.clone(),
+            None => index_pairing(old_document.pages().len() as usize, new_document.pages().len() as usize),
+        }
+    }
 
-    Ok((old_document, new_document))
+    // Pixel size to render both pages of a pair at, when their sizes differ
+    // by no more than the tolerance; `None` renders each at its own size
+    fn pair_size(&self, old_document: &PdfDocument, old_index: Option<usize>, new_document: &PdfDocument, new_index: Option<usize>) -> Option<(i32, i32)> {
+        let page_size = |document: &PdfDocument, index: usize| {
+            let page = document.pages().get(index as PdfPageIndex).ok()?;
+            Some((page.width().value, page.height().value))
+        };
+        let old_size = page_size(old_document, old_index?)?;
+        let new_size = page_size(new_document, new_index?)?;
+        normalized_size(old_size, new_size, self.dpi, self.size_tolerance)
+    }
+}
+
+// Positions whose pages are rendered at one size though their page sizes
+// differ, within `options.size_tolerance`, as (1-based position, message)
+pub fn stretched_pages(old_document: &PdfDocument, new_document: &PdfDocument, options: &RenderOptions) -> Vec<(usize, String)> {
+    let page_size = |document: &PdfDocument, index: usize| {
+        let page = document.pages().get(index as PdfPageIndex).ok()?;
+        Some((page.width().value, page.height().value))
+    };
+    let mut stretched = vec![];
+    for (position, (old_index, new_index)) in options.pairing_for(old_document, new_document).into_iter().enumerate() {
+        if !options.includes(position) || options.pair_size(old_document, old_index, new_document, new_index).is_none() {
+            continue;
+        }
+        let (Some(old), Some(new)) = (old_index.and_then(|index| page_size(old_document, index)), new_index.and_then(|index| page_size(new_document, index))) else {
+            continue;
+        };
+        stretched.push((
+            position + 1,
+            format!(
+                "Page sizes differ ({:.1} x {:.1} pt old, {:.1} x {:.1} pt new); the new page was scaled to the old page's size",
+                old.0, old.1, new.0, new.1
+            ),
+        ));
+    }
+    stretched
+}
+
+// Pixel size of the old page when the two page sizes, in points, are within
+// `tolerance` of each other but would render to different sizes at `dpi`
+fn normalized_size(old: (f32, f32), new: (f32, f32), dpi: f32, tolerance: f32) -> Option<(i32, i32)> {
+    let pixels = |(width, height): (f32, f32)| ((width * dpi / 72.0).round() as i32, (height * dpi / 72.0).round() as i32);
+    let within = (old.0 - new.0).abs() <= tolerance && (old.1 - new.1).abs() <= tolerance;
+    (within && pixels(old) != pixels(new)).then(|| pixels(old))
 }
 
 pub fn create_images_from_pdf(
     old_document: &PdfDocument,
     new_document: &PdfDocument,
     dpi: f32,
-) -> Result<Vec<(Option<DynamicImage>, Option<DynamicImage>)>, PdfError> {
-    let mut result = Vec::<(Option<DynamicImage>, Option<DynamicImage>)>::new();
-    
-    for index in 0..new_document.pages().len() {
-        let new_page = new_document.pages().get(index).map_err(|e| PdfError {
-            message: format!("Failed to get page {} from new PDF: {:?}", index, e),
-        })?;
-        let new_img = get_image_from_page(&new_page, dpi)?;
-
-        let old_page = old_document.pages().get(index).ok();
-
-        let old_image = match old_page {
-            Some(page) => Some(get_image_from_page(&page, dpi)?),
+) -> Result<Vec<PagePair>, PdfError> {
+    create_images_from_pdf_with_options(old_document, new_document, &RenderOptions::new(dpi))
+}
+
+pub fn create_images_from_pdf_with_options(
+    old_document: &PdfDocument,
+    new_document: &PdfDocument,
+    options: &RenderOptions,
+) -> Result<Vec<PagePair>, PdfError> {
+    let mut result = Vec::<PagePair>::new();
+
+    for (position, (old_index, new_index)) in options.pairing_for(old_document, new_document).into_iter().enumerate() {
+        if !options.includes(position) {
+            result.push((None, None));
+            continue;
+        }
+
+        let size = options.pair_size(old_document, old_index, new_document, new_index);
+        let new_image = match new_index {
+            Some(index) => Some(render_page_sized(new_document, index, options, size).map_err(|e| e.context("New PDF"))?),
             None => None,
         };
+        let old_image = match old_index {
+            Some(index) => Some(render_page_sized(old_document, index, options, size).map_err(|e| e.context("Old PDF"))?),
+            None => None,
+        };
+
+        result.push((old_image, new_image));
+    }
+
+    Ok(result)
+}
+
+// Render the page at a 0-based index of a single document
+pub fn render_page(document: &PdfDocument, index: usize, options: &RenderOptions) -> Result<DynamicImage, PdfError> {
+    render_page_sized(document, index, options, None)
+}
+
+// Render a page, stretched to `size` pixels when given
+fn render_page_sized(document: &PdfDocument, index: usize, options: &RenderOptions, size: Option<(i32, i32)>) -> Result<DynamicImage, PdfError> {
+    let page = document
+        .pages()
+        .get(index as PdfPageIndex)
+        .map_err(|e| PdfError::new(format!("Failed to get page {}: {:?}", index + 1, e)))?;
+    check_page(&page, options)?;
+    get_image_from_page(&page, options.dpi, &options.flags, size)
+}
+
+// Refuse pages that would take unbounded time or memory to render
+fn check_page(page: &PdfPage, options: &RenderOptions) -> Result<(), PdfError> {
+    let (width, height) = (page.width().value * options.dpi / 72.0, page.height().value * options.dpi / 72.0);
+    let pixels = width.round() as u64 * height.round() as u64;
+    if pixels > options.limits.max_page_pixels {
+        return Err(PdfError::malformed(format!(
+            "page renders to {} pixels at {} DPI, over the limit of {}",
+            pixels, options.dpi, options.limits.max_page_pixels
+        )));
+    }
+
+    let objects = count_objects(page.objects().iter(), 0, options.limits.max_page_objects)?;
+    if objects > options.limits.max_page_objects {
+        return Err(PdfError::malformed(format!(
+            "page has {} objects, over the limit of {}",
+            objects, options.limits.max_page_objects
+        )));
+    }
+    Ok(())
+}
+
+// Objects drawn by `objects`, counting those inside form XObjects, which
+// `depth` forms already enclose. Counting stops once past `limit`; forms
+// nested deeper than `MAX_FORM_DEPTH` are refused.
+fn count_objects(objects: PdfPageObjectsIterator, depth: usize, limit: usize) -> Result<usize, PdfError> {
+    if depth > MAX_FORM_DEPTH {
+        return Err(PdfError::malformed(format!("page has form XObjects nested over {} deep", MAX_FORM_DEPTH)));
+    }
+
+    let mut count = 0;
+    for object in objects {
+        count += 1;
+        if let PdfPageObject::XObjectForm(form) = &object {
+            count += count_objects(form.iter(), depth + 1, limit.saturating_sub(count))?;
+        }
+        if count > limit {
+            break;
+        }
+    }
+    Ok(count)
+}
+
+// Render one page of a document, falling back to a placeholder and recording
+// the error against the 0-based `position` if pdfium can't produce it
+fn render_or_placeholder(
+    document: &PdfDocument,
+    index: usize,
+    position: usize,
+    options: &RenderOptions,
+    size: Option<(i32, i32)>,
+    label: &str,
+    errors: &mut Vec<(usize, String)>,
+) -> DynamicImage {
+    let dpi = options.dpi;
+    let placeholder_text = format!("{} PAGE RENDER FAILED", label.to_uppercase());
+    let page = match document.pages().get(index as PdfPageIndex) {
+        Ok(page) => page,
+        Err(e) => {
+            errors.push((position + 1, format!("Failed to get page {} from the {} PDF: {:?}", index + 1, label, e)));
+            // Without the page its size is unknown, so assume US Letter
+            return placeholder_page((8.5 * dpi) as u32, (11.0 * dpi) as u32, &placeholder_text);
+        }
+    };
+
+    match check_page(&page, options).and_then(|_| get_image_from_page(&page, dpi, &options.flags, size)) {
+        Ok(image) => image,
+        Err(e) => {
+            errors.push((position + 1, format!("Failed to render page {} in the {} PDF: {}", index + 1, label, e)));
+            let width = (page.width().value * dpi / 72.0).round() as u32;
+            let height = (page.height().value * dpi / 72.0).round() as u32;
+            // A page too large to render gets a Letter-sized placeholder instead
+            if width as u64 * height as u64 > options.limits.max_page_pixels {
+                return placeholder_page((8.5 * dpi) as u32, (11.0 * dpi) as u32, &placeholder_text);
+            }
+            placeholder_page(width, height, &placeholder_text)
+        }
+    }
+}
+
+// Like `create_images_from_pdf_with_options`, but a page that fails to render is
+// replaced by a placeholder instead of aborting the whole document. Returns the
+// page pairs along with a (1-based page, message) entry for every failure.
+pub fn create_images_from_pdf_resilient(
+    old_document: &PdfDocument,
+    new_document: &PdfDocument,
+    options: &RenderOptions,
+) -> (Vec<PagePair>, Vec<(usize, String)>) {
+    let mut result = Vec::<PagePair>::new();
+    let mut errors = vec![];
+
+    for (position, (old_index, new_index)) in options.pairing_for(old_document, new_document).into_iter().enumerate() {
+        if !options.includes(position) {
+            result.push((None, None));
+            continue;
+        }
+
+        let size = options.pair_size(old_document, old_index, new_document, new_index);
+        let new_image = new_index.map(|index| render_or_placeholder(new_document, index, position, options, size, "new", &mut errors));
+        let old_image = old_index.map(|index| render_or_placeholder(old_document, index, position, options, size, "old", &mut errors));
+        result.push((old_image, new_image));
+    }
+
+    (result, errors)
+}
+
+// Pair a render with the reference image compared with it, scaled to the
+// render's size
+fn pair_with_reference(render: Option<DynamicImage>, reference: DynamicImage) -> PagePair {
+    let reference = match &render {
+        Some(render) => fit_to(reference, render.width(), render.height()),
+        None => reference,
+    };
+    (render, Some(reference))
+}
 
-        result.push((old_image, Some(new_img)));
+// Render a document's pages and pair them by position with reference images
+// produced by another tool, one per page. The references take the place of
+// the new document, so a page without one is left out like a removed page.
+pub fn create_images_against_references(
+    document: &PdfDocument,
+    references: Vec<DynamicImage>,
+    options: &RenderOptions,
+) -> Result<Vec<PagePair>, PdfError> {
+    let mut result = Vec::<PagePair>::new();
+    let pairing = index_pairing(document.pages().len() as usize, references.len());
+
+    for (position, ((old_index, _), reference)) in pairing.into_iter().zip(references).enumerate() {
+        if !options.includes(position) {
+            result.push((None, None));
+            continue;
+        }
+
+        let old_image = match old_index {
+            Some(index) => Some(render_page(document, index, options).map_err(|e| e.context("Old PDF"))?),
+            None => None,
+        };
+        result.push(pair_with_reference(old_image, reference));
     }
 
     Ok(result)
 }
 
+// Like `create_images_against_references`, with placeholders for pages that
+// fail to render as in `create_images_from_pdf_resilient`
+pub fn create_images_against_references_resilient(
+    document: &PdfDocument,
+    references: Vec<DynamicImage>,
+    options: &RenderOptions,
+) -> (Vec<PagePair>, Vec<(usize, String)>) {
+    let mut result = Vec::<PagePair>::new();
+    let mut errors = vec![];
+    let pairing = index_pairing(document.pages().len() as usize, references.len());
 
-fn get_image_from_page(page: &pdfium_render::prelude::PdfPage, dpi: f32) -> Result<DynamicImage, PdfError> {
-      let render_config = PdfRenderConfig::new()
-            .set_target_width((page.width().value * dpi / 72.0).round() as i32)
-            .set_maximum_height((page.height().value * dpi / 72.0).round() as i32);
+    for (position, ((old_index, _), reference)) in pairing.into_iter().zip(references).enumerate() {
+        if !options.includes(position) {
+            result.push((None, None));
+            continue;
+        }
 
-    Ok(page.render_with_config(&render_config).map_err(|e| PdfError {
-        message: format!("Failed to render page to image: {:?}", e),
-    })?.as_image())
+        let old_image = old_index.map(|index| render_or_placeholder(document, index, position, options, None, "old", &mut errors));
+        result.push(pair_with_reference(old_image, reference));
+    }
+
+    (result, errors)
+}
+
+// Render the pages of `images` a second time and report the ones that come out
+// different, as (1-based page, message) entries like render errors. PDFium
+// output should be identical every time; where it isn't (font fallback,
+// threading), diffs against a baseline show changes that aren't there.
+pub fn nondeterministic_pages<'a>(
+    old_document: &PdfDocument<'a>,
+    new_document: &PdfDocument<'a>,
+    options: &RenderOptions,
+    images: &[PagePair],
+) -> Vec<(usize, String)> {
+    let mut unstable = vec![];
+
+    for (position, (old_index, new_index)) in options.pairing_for(old_document, new_document).into_iter().enumerate() {
+        let Some((old_image, new_image)) = images.get(position) else {
+            break;
+        };
+        if !options.includes(position) {
+            continue;
+        }
+
+        let size = options.pair_size(old_document, old_index, new_document, new_index);
+        for (label, document, index, first) in [("old", old_document, old_index, old_image), ("new", new_document, new_index, new_image)] {
+            // Pages that fail to render are already reported
+            let (Some(index), Some(first)) = (index, first) else {
+                continue;
+            };
+            let Ok(second) = render_page_sized(document, index, options, size) else {
+                continue;
+            };
+
+            let differing = differing_pixels(first, &second);
+            if differing > 0 {
+                unstable.push((
+                    position + 1,
+                    format!(
+                        "Page {} of the {} PDF rendered differently a second time ({} pixels differ); rendering isn't deterministic, so its diffs may be flaky",
+                        index + 1, label, differing
+                    ),
+                ));
+            }
+        }
+    }
+
+    unstable
+}
+
+// Pixels that differ between two renders; all of them if the sizes differ
+fn differing_pixels(first: &DynamicImage, second: &DynamicImage) -> u64 {
+    if first.dimensions() != second.dimensions() {
+        return first.width().max(second.width()) as u64 * first.height().max(second.height()) as u64;
+    }
+    let (first, second) = (first.to_rgba8(), second.to_rgba8());
+    first.pixels().zip(second.pixels()).filter(|(a, b)| a != b).count() as u64
+}
+
+// Positions of a low-DPI prescan worth rendering again at full DPI: pages
+// whose renders differ at all, and pages found in only one document. This
+// trades accuracy for speed: a change too small to move a pixel at the
+// prescan DPI, such as a thin rule or a shifted hairline, goes unnoticed,
+// though it would show at full DPI.
+pub fn changed_positions(prescan: &[PagePair]) -> Vec<usize> {
+    prescan
+        .iter()
+        .enumerate()
+        .filter(|(_, pair)| match pair {
+            (Some(old), Some(new)) => differing_pixels(old, new) > 0,
+            (None, None) => false,
+            _ => true,
+        })
+        .map(|(position, _)| position)
+        .collect()
+}
+
+// Full-DPI renders of the positions in `rendered`, with the prescan renders
+// kept for the pages it left out as unchanged. Those are scaled by `scale`,
+// the full DPI over the prescan DPI, so every page has the size a full-DPI
+// render would and later stages can work in one resolution.
+pub fn merge_prescan(prescan: Vec<PagePair>, full: Vec<PagePair>, rendered: &[usize], scale: f32) -> Vec<PagePair> {
+    let upscale = |image: DynamicImage| {
+        let (width, height) = ((image.width() as f32 * scale).round() as u32, (image.height() as f32 * scale).round() as u32);
+        fit_to(image, width.max(1), height.max(1))
+    };
+    prescan
+        .into_iter()
+        .zip(full)
+        .enumerate()
+        .map(|(position, ((old, new), full))| match rendered.binary_search(&position) {
+            Ok(_) => full,
+            Err(_) => (old.map(upscale), new.map(upscale)),
+        })
+        .collect()
+}
+
+// Forms nested deeper than `MAX_FORM_DEPTH` are left out
+fn collect_substituted_fonts(objects: PdfPageObjectsIterator, depth: usize, fonts: &mut Vec<String>) {
+    for object in objects {
+        if let PdfPageObject::XObjectForm(form) = &object {
+            if depth < MAX_FORM_DEPTH {
+                collect_substituted_fonts(form.iter(), depth + 1, fonts);
+            }
+        } else if let Some(text) = object.as_text_object() {
+            let font = text.font();
+            // Pdfium ships the 14 standard fonts itself, so only other
+            // non-embedded fonts fall back to whatever the machine has installed
+            if !font.is_built_in() && matches!(font.is_embedded(), Ok(false)) {
+                fonts.push(font.family());
+            }
+        }
+    }
+}
+
+// Names of the fonts on a page that aren't embedded and will be substituted
+// with a system font when rendering
+pub fn substituted_fonts(page: &PdfPage) -> Vec<String> {
+    let mut fonts = vec![];
+    collect_substituted_fonts(page.objects().iter(), 0, &mut fonts);
+    fonts.sort();
+    fonts.dedup();
+    fonts
+}
+
+// Substituted fonts for every page of a document, as (1-based page, fonts)
+// for the pages that have any
+pub fn document_substituted_fonts(document: &PdfDocument) -> Vec<(usize, Vec<String>)> {
+    document
+        .pages()
+        .iter()
+        .enumerate()
+        .map(|(index, page)| (index + 1, substituted_fonts(&page)))
+        .filter(|(_, fonts)| !fonts.is_empty())
+        .collect()
+}
+
+fn get_image_from_page(
+    page: &PdfPage,
+    dpi: f32,
+    flags: &[RenderFlag],
+    size: Option<(i32, i32)>,
+) -> Result<DynamicImage, PdfError> {
+      let render_config = match size {
+            Some((width, height)) => PdfRenderConfig::new().set_target_size(width, height),
+            None => PdfRenderConfig::new()
+                .set_target_width((page.width().value * dpi / 72.0).round() as i32)
+                .set_maximum_height((page.height().value * dpi / 72.0).round() as i32),
+      };
+      let render_config = apply_render_flags(render_config, flags);
+
+    Ok(page.render_with_config(&render_config).map_err(|e| PdfError::new(format!("Failed to render page to image: {:?}", e)))?.as_image())
 
 }
 
@@ -100,6 +813,23 @@ mod tests {
     use super::*;
     use std::path::Path;
 
+    #[test]
+    fn test_parse_version_file() {
+        let text = "MAJOR=140\nMINOR=0\nBUILD=7350\nPATCH=0\n";
+        assert_eq!(parse_version_file(text).as_deref(), Some("140.0.7350.0"));
+        assert_eq!(parse_version_file("MAJOR=140\n"), None);
+        assert_eq!(pdfium_version("./no-such-pdfium/lib/"), None);
+    }
+
+    #[test]
+    fn test_index_pairing() {
+        assert_eq!(index_pairing(2, 3), vec![(Some(0), Some(0)), (Some(1), Some(1)), (None, Some(2))]);
+        assert_eq!(index_pairing(3, 1), vec![(Some(0), Some(0))]);
+        assert_eq!(index_pairing(0, 2), vec![(None, Some(0)), (None, Some(1))]);
+        assert_eq!(index_pairing(2, 0), vec![(Some(0), None), (Some(1), None)]);
+        assert!(index_pairing(0, 0).is_empty());
+    }
+
     #[test]
     fn test_create_pdfium() {
         let result = create_pdfium();
@@ -116,8 +846,8 @@ mod tests {
         assert!(result.is_ok(), "Failed to load PDF documents: {:?}", result.err());
 
         let (old_doc, new_doc) = result.unwrap();
-        assert!(old_doc.pages().len() > 0, "Old document should have pages");
-        assert!(new_doc.pages().len() > 0, "New document should have pages");
+        assert!(!old_doc.pages().is_empty(), "Old document should have pages");
+        assert!(!new_doc.pages().is_empty(), "New document should have pages");
     }
 
     #[test]
@@ -133,6 +863,54 @@ mod tests {
         assert!(error.to_string().contains("does not exist"));
     }
 
+    #[test]
+    fn test_check_input_file() {
+        let dir = std::env::temp_dir().join(format!("pdf_diff_input_check_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let limits = InputLimits::default();
+
+        let missing = check_input_file(&dir.join("missing.pdf"), &limits).unwrap_err();
+        assert_eq!(missing.kind(), PdfErrorKind::Other);
+
+        let html = dir.join("upload.pdf");
+        std::fs::write(&html, "<html>not a pdf</html>").unwrap();
+        let error = check_input_file(&html, &limits).unwrap_err();
+        assert_eq!(error.kind(), PdfErrorKind::MalformedInput);
+        assert!(error.to_string().starts_with("Malformed input: "));
+
+        // Junk before the header is tolerated, as by PDF readers
+        let pdf = dir.join("junk.pdf");
+        std::fs::write(&pdf, "\r\n\r\n%PDF-1.7\n%%EOF\n").unwrap();
+        assert!(check_input_file(&pdf, &limits).is_ok());
+
+        let small = InputLimits { max_file_size: 8, ..limits };
+        let error = check_input_file(&pdf, &small).unwrap_err();
+        assert_eq!(error.kind(), PdfErrorKind::MalformedInput);
+        assert_eq!(error.context("Old PDF").kind(), PdfErrorKind::MalformedInput);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_load_pdf_documents_over_limits() {
+        let pdfium = create_pdfium().expect("Failed to create Pdfium instance");
+        let path = Path::new("./samples/new.pdf");
+
+        let limits = InputLimits { max_pages: 0, ..InputLimits::default() };
+        let error = load_pdf_documents_with_limits(&pdfium, path, path, &limits).err().unwrap();
+        assert_eq!(error.kind(), PdfErrorKind::MalformedInput);
+
+        // Pages too complex to render become placeholders with a warning
+        let (old_doc, new_doc) = load_pdf_documents(&pdfium, path, path).expect("Failed to load PDF documents");
+        let options = RenderOptions {
+            limits: InputLimits { max_page_objects: 0, ..InputLimits::default() },
+            ..RenderOptions::new(72.0)
+        };
+        let (images, errors) = create_images_from_pdf_resilient(&old_doc, &new_doc, &options);
+        assert_eq!(errors.len(), images.len() * 2);
+        assert!(errors[0].1.contains("Malformed input: page has"));
+    }
+
     #[test]
     fn test_create_images_from_pdf() {
         let pdfium = create_pdfium().expect("Failed to create Pdfium instance");
@@ -146,7 +924,7 @@ mod tests {
         assert!(result.is_ok(), "Failed to create images from PDF: {:?}", result.err());
 
         let images = result.unwrap();
-        assert!(images.len() > 0, "Should generate at least one image pair");
+        assert!(!images.is_empty(), "Should generate at least one image pair");
 
         // Check that we have valid image data
         for (i, (_old_img, new_img)) in images.iter().enumerate() {
@@ -168,7 +946,7 @@ mod tests {
             .expect("Failed to load PDF document");
 
         let page = doc.pages().get(0).expect("Failed to get first page");
-        let result = get_image_from_page(&page, 300.0);
+        let result = get_image_from_page(&page, 300.0, &[], None);
         
         assert!(result.is_ok(), "Failed to render page to image: {:?}", result.err());
         
@@ -178,6 +956,18 @@ mod tests {
         // Remove the width/height constraints since they depend on DPI and page size
     }
 
+    #[test]
+    fn test_render_page_of_single_document() {
+        let pdfium = create_pdfium().expect("Failed to create Pdfium instance");
+        assert!(load_pdf_document(&pdfium, Path::new("./nonexistent.pdf")).is_err());
+
+        let doc = load_pdf_document(&pdfium, Path::new("./samples/new.pdf")).expect("Failed to load PDF document");
+        let image = render_page(&doc, 0, &RenderOptions::new(72.0)).expect("First page should render");
+        let page = doc.pages().get(0).unwrap();
+        assert_eq!(image.width(), page.width().value.round() as u32);
+        assert!(render_page(&doc, doc.pages().len() as usize, &RenderOptions::new(72.0)).is_err());
+    }
+
     #[test]
     fn test_different_page_counts() {
         let pdfium = create_pdfium().expect("Failed to create Pdfium instance");
@@ -194,4 +984,144 @@ mod tests {
         // Should process as many pages as the new document has
         assert_eq!(images.len(), new_doc.pages().len() as usize, "Should process all pages from new document");
     }
+
+    #[test]
+    fn test_create_images_with_grayscale_flag() {
+        let pdfium = create_pdfium().expect("Failed to create Pdfium instance");
+        let path = Path::new("./samples/new.pdf");
+
+        let (old_doc, new_doc) = load_pdf_documents(&pdfium, path, path)
+            .expect("Failed to load PDF documents");
+
+        let options = RenderOptions {
+            flags: vec![RenderFlag::Grayscale],
+            ..RenderOptions::new(72.0)
+        };
+        let images = create_images_from_pdf_with_options(&old_doc, &new_doc, &options)
+            .expect("Failed to render with flags");
+
+        let img = images[0].1.as_ref().expect("New page should render").to_rgba8();
+        assert!(img.pixels().all(|p| p[0] == p[1] && p[1] == p[2]), "Grayscale renders should have no colour");
+    }
+
+    #[test]
+    fn test_sample_fonts_are_embedded() {
+        let pdfium = create_pdfium().expect("Failed to create Pdfium instance");
+        let path = Path::new("./samples/old.pdf");
+
+        let (doc, _) = load_pdf_documents(&pdfium, path, path).expect("Failed to load PDF document");
+        assert!(document_substituted_fonts(&doc).is_empty(), "Sample fonts should be embedded");
+    }
+
+    #[test]
+    fn test_parse_length() {
+        assert_eq!("2pt".parse(), Ok(Length { points: 2.0 }));
+        assert_eq!("1in".parse(), Ok(Length { points: 72.0 }));
+        assert!(("25.4mm".parse::<Length>().unwrap().points - 72.0).abs() < 1e-4);
+        assert!(("0.5 cm".parse::<Length>().unwrap().points - 14.173).abs() < 1e-3);
+        assert!("1".parse::<Length>().is_err(), "A unit is required");
+        assert!("1ft".parse::<Length>().is_err());
+        assert!("-1mm".parse::<Length>().is_err());
+    }
+
+    #[test]
+    fn test_normalized_size() {
+        let letter = (612.0, 792.0);
+        // 1mm is 2.83pt
+        assert_eq!(normalized_size(letter, (613.4, 792.0), 72.0, 2.83), Some((612, 792)));
+        assert_eq!(normalized_size(letter, (613.4, 792.0), 72.0, 0.0), None);
+        assert_eq!(normalized_size(letter, (612.0, 789.0), 72.0, 2.83), None, "Over the tolerance");
+        assert_eq!(normalized_size(letter, (612.1, 792.0), 72.0, 2.83), None, "Renders the same size anyway");
+        assert_eq!(normalized_size(letter, (612.1, 792.0), 7200.0, 2.83), Some((61200, 79200)));
+    }
+
+    #[test]
+    fn test_differing_pixels() {
+        let first = DynamicImage::new_rgba8(4, 3);
+        let mut second = first.to_rgba8();
+        assert_eq!(differing_pixels(&first, &DynamicImage::ImageRgba8(second.clone())), 0);
+
+        second.put_pixel(1, 1, image::Rgba([255, 0, 0, 255]));
+        second.put_pixel(3, 2, image::Rgba([0, 0, 0, 255]));
+        assert_eq!(differing_pixels(&first, &DynamicImage::ImageRgba8(second)), 2);
+        assert_eq!(differing_pixels(&first, &DynamicImage::new_rgba8(4, 4)), 16, "Renders of different sizes differ everywhere");
+    }
+
+    #[test]
+    fn test_prescan() {
+        let page = DynamicImage::new_rgba8(4, 3);
+        let mut edited = page.to_rgba8();
+        edited.put_pixel(1, 1, image::Rgba([255, 0, 0, 255]));
+        let edited = DynamicImage::ImageRgba8(edited);
+        let prescan = vec![
+            (Some(page.clone()), Some(page.clone())),
+            (Some(page.clone()), Some(edited)),
+            (None, Some(page.clone())),
+            (None, None),
+        ];
+        let changed = changed_positions(&prescan);
+        assert_eq!(changed, vec![1, 2]);
+
+        let full_page = DynamicImage::new_rgba8(8, 6);
+        let full = vec![(None, None), (Some(full_page.clone()), Some(full_page.clone())), (None, Some(full_page)), (None, None)];
+        let merged = merge_prescan(prescan, full, &changed, 2.0);
+        assert_eq!(merged[0].0.as_ref().unwrap().dimensions(), (8, 6), "Unchanged pages are scaled to full DPI");
+        assert_eq!(merged[1].0.as_ref().unwrap().width(), 8);
+        assert_eq!(merged[2].1.as_ref().unwrap().width(), 8);
+        assert!(merged[3].0.is_none() && merged[3].1.is_none());
+    }
+
+    #[test]
+    fn test_sample_renders_are_deterministic() {
+        let pdfium = create_pdfium().expect("Failed to create Pdfium instance");
+        let (old_doc, new_doc) = load_pdf_documents(&pdfium, Path::new("./samples/old.pdf"), Path::new("./samples/new.pdf"))
+            .expect("Failed to load PDF documents");
+
+        let options = RenderOptions::new(72.0);
+        let images = create_images_from_pdf_with_options(&old_doc, &new_doc, &options).expect("Failed to render");
+        assert!(nondeterministic_pages(&old_doc, &new_doc, &options, &images).is_empty());
+    }
+
+    #[test]
+    fn test_create_images_from_pdf_resilient() {
+        let pdfium = create_pdfium().expect("Failed to create Pdfium instance");
+        let old_path = Path::new("./samples/old.pdf");
+        let new_path = Path::new("./samples/new.pdf");
+
+        let (old_doc, new_doc) = load_pdf_documents(&pdfium, old_path, new_path)
+            .expect("Failed to load PDF documents");
+
+        let (images, errors) = create_images_from_pdf_resilient(&old_doc, &new_doc, &RenderOptions::new(72.0));
+        assert!(errors.is_empty(), "Sample pages should render: {:?}", errors);
+        assert_eq!(images.len(), new_doc.pages().len() as usize);
+
+        // Pages outside the selection keep their slot but aren't rendered
+        let options = RenderOptions {
+            pages: Some(vec![0]),
+            ..RenderOptions::new(72.0)
+        };
+        let (images, _) = create_images_from_pdf_resilient(&old_doc, &new_doc, &options);
+        assert!(images[0].1.is_some());
+        assert!(images[1..].iter().all(|pair| pair.0.is_none() && pair.1.is_none()));
+
+        // An explicit pairing decides which pages are compared
+        let options = RenderOptions {
+            pairing: Some(vec![(None, Some(0)), (Some(0), None)]),
+            ..RenderOptions::new(72.0)
+        };
+        let (images, errors) = create_images_from_pdf_resilient(&old_doc, &new_doc, &options);
+        assert!(errors.is_empty());
+        assert!(matches!(images.as_slice(), [(None, Some(_)), (Some(_), None)]));
+
+        // Nothing more is rendered once the run is cancelled
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let options = RenderOptions {
+            cancel: Some(cancel),
+            ..RenderOptions::new(72.0)
+        };
+        let (images, _) = create_images_from_pdf_resilient(&old_doc, &new_doc, &options);
+        assert_eq!(images.len(), new_doc.pages().len() as usize);
+        assert!(images.iter().all(|pair| pair.0.is_none() && pair.1.is_none()));
+    }
 }
@@ -0,0 +1,278 @@
+use std::error::Error;
+use std::fmt::Write as _;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use image::codecs::jpeg::JpegEncoder;
+use image::DynamicImage;
+
+use crate::image_utils::scale_image;
+use crate::regions::{highlight_changes_with, OverlayStyle, PIXEL_TOLERANCE};
+use crate::report::{ComparisonSummary, PageStatus};
+
+pub const PDF_REPORT_FILE: &str = "report.pdf";
+
+const JPEG_QUALITY: u8 = 85;
+
+// One page of the combined PDF: a render or diff image and the bookmark
+// pointing at it, if the page changed
+struct ReportPage {
+    image: DynamicImage,
+    bookmark: Option<String>,
+}
+
+// Text string as UTF-16BE hex, which every viewer decodes regardless of the
+// characters in file names
+fn text_string(text: &str) -> String {
+    let mut hex = String::from("<FEFF");
+    for unit in text.encode_utf16() {
+        write!(hex, "{:04X}", unit).expect("writing to a String");
+    }
+    hex.push('>');
+    hex
+}
+
+fn bookmark_title(page: usize, status: PageStatus, ratio: f64) -> Option<String> {
+    match status {
+        PageStatus::Changed => Some(format!("Page {}: {:.2}% changed", page, ratio * 100.0)),
+        PageStatus::Added => Some(format!("Page {}: added", page)),
+        PageStatus::Removed => Some(format!("Page {}: removed", page)),
+        _ => None,
+    }
+}
+
+fn report_pages(images: &[(Option<DynamicImage>, Option<DynamicImage>)], summary: &ComparisonSummary, style: OverlayStyle) -> Vec<ReportPage> {
+    images
+        .iter()
+        .enumerate()
+        .filter_map(|(index, (old, new))| {
+            let status = summary.page_status(index, old.as_ref(), new.as_ref());
+            let image = match (old, new) {
+                _ if status == PageStatus::Skipped => return None,
+                (Some(old), Some(new)) if status != PageStatus::Unchanged => highlight_changes_with(old, new, PIXEL_TOLERANCE, style),
+                (_, Some(page)) | (Some(page), None) => page.clone(),
+                (None, None) => return None,
+            };
+            let ratio = summary.page_ratios.get(index).copied().unwrap_or(0.0);
+            Some(ReportPage { image, bookmark: bookmark_title(index + 1, status, ratio) })
+        })
+        .collect()
+}
+
+// Serializes numbered objects and records where each starts for the xref table
+struct PdfWriter {
+    bytes: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl PdfWriter {
+    fn new(object_count: usize) -> PdfWriter {
+        PdfWriter {
+            // The binary comment marks the file as binary for transfer tools
+            bytes: b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec(),
+            offsets: vec![0; object_count],
+        }
+    }
+
+    fn object(&mut self, id: usize, dictionary: &str) {
+        self.offsets[id - 1] = self.bytes.len();
+        self.bytes.extend(format!("{} 0 obj\n{}\nendobj\n", id, dictionary).bytes());
+    }
+
+    fn stream(&mut self, id: usize, dictionary: &str, data: &[u8]) {
+        self.offsets[id - 1] = self.bytes.len();
+        self.bytes.extend(format!("{} 0 obj\n<< {} /Length {} >>\nstream\n", id, dictionary, data.len()).bytes());
+        self.bytes.extend(data);
+        self.bytes.extend(b"\nendstream\nendobj\n");
+    }
+
+    fn finish(mut self, root: usize, info: usize) -> Vec<u8> {
+        let xref = self.bytes.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            writeln!(table, "{:010} 00000 n ", offset).expect("writing to a String");
+        }
+        write!(
+            table,
+            "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            root,
+            info,
+            xref
+        )
+        .expect("writing to a String");
+        self.bytes.extend(table.bytes());
+        self.bytes
+    }
+}
+
+// Build a PDF with one page per compared page (the highlighted diff where
+// the renders differ) and a bookmark for every changed page
+fn build_pdf_report(pages: &[ReportPage], title: &str, dpi: f32) -> Result<Vec<u8>, Box<dyn Error>> {
+    // Catalog, page tree, outline root and info first, then three objects per
+    // page (page, content, image), then one per bookmark
+    const CATALOG: usize = 1;
+    const PAGES: usize = 2;
+    const OUTLINES: usize = 3;
+    const INFO: usize = 4;
+    let page_id = |index: usize| 5 + index * 3;
+    let bookmarks: Vec<(usize, &str)> = pages
+        .iter()
+        .enumerate()
+        .filter_map(|(index, page)| page.bookmark.as_deref().map(|title| (index, title)))
+        .collect();
+    let bookmark_id = |index: usize| 5 + pages.len() * 3 + index;
+
+    let mut pdf = PdfWriter::new(4 + pages.len() * 3 + bookmarks.len());
+    let outlines = if bookmarks.is_empty() { String::new() } else { format!(" /Outlines {} 0 R /PageMode /UseOutlines", OUTLINES) };
+    pdf.object(CATALOG, &format!("<< /Type /Catalog /Pages {} 0 R{} >>", PAGES, outlines));
+
+    let kids: Vec<String> = (0..pages.len()).map(|index| format!("{} 0 R", page_id(index))).collect();
+    pdf.object(PAGES, &format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()));
+
+    match (bookmarks.first(), bookmarks.last()) {
+        (Some(_), Some(_)) => pdf.object(
+            OUTLINES,
+            &format!(
+                "<< /Type /Outlines /First {} 0 R /Last {} 0 R /Count {} >>",
+                bookmark_id(0),
+                bookmark_id(bookmarks.len() - 1),
+                bookmarks.len()
+            ),
+        ),
+        _ => pdf.object(OUTLINES, "<< /Type /Outlines /Count 0 >>"),
+    }
+    pdf.object(INFO, &format!("<< /Title {} /Producer (pdf_diff) >>", text_string(&format!("PDF diff: {}", title))));
+
+    let scale = 72.0 / dpi;
+    for (index, page) in pages.iter().enumerate() {
+        let rgb = page.image.to_rgb8();
+        let (width, height) = rgb.dimensions();
+        let (page_width, page_height) = (width as f32 * scale, height as f32 * scale);
+
+        let mut jpeg = vec![];
+        JpegEncoder::new_with_quality(&mut Cursor::new(&mut jpeg), JPEG_QUALITY).encode_image(&rgb)?;
+
+        let id = page_id(index);
+        pdf.object(
+            id,
+            &format!(
+                "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {:.2} {:.2}] /Contents {} 0 R /Resources << /XObject << /Im0 {} 0 R >> >> >>",
+                PAGES,
+                page_width,
+                page_height,
+                id + 1,
+                id + 2
+            ),
+        );
+        let content = format!("q {:.2} 0 0 {:.2} 0 0 cm /Im0 Do Q", page_width, page_height);
+        pdf.stream(id + 1, "", content.as_bytes());
+        pdf.stream(
+            id + 2,
+            &format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /DCTDecode",
+                width, height
+            ),
+            &jpeg,
+        );
+    }
+
+    for (i, &(index, title)) in bookmarks.iter().enumerate() {
+        let mut links = String::new();
+        if i > 0 {
+            write!(links, " /Prev {} 0 R", bookmark_id(i - 1))?;
+        }
+        if i + 1 < bookmarks.len() {
+            write!(links, " /Next {} 0 R", bookmark_id(i + 1))?;
+        }
+        pdf.object(
+            bookmark_id(i),
+            &format!(
+                "<< /Title {} /Parent {} 0 R{} /Dest [{} 0 R /Fit] >>",
+                text_string(title),
+                OUTLINES,
+                links,
+                page_id(index)
+            ),
+        );
+    }
+
+    Ok(pdf.finish(CATALOG, INFO))
+}
+
+// Write every compared page into one PDF, with a bookmark per changed page
+// showing how much of it changed. Highlights are composited with `style` and
+// page images resized by `scale`; pages keep their size. Returns the path of
+// the written file.
+pub fn write_pdf_report(
+    images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+    summary: &ComparisonSummary,
+    pdf_title: &str,
+    output_dir: &str,
+    dpi: f32,
+    scale: f32,
+    style: OverlayStyle,
+) -> Result<PathBuf, Box<dyn Error>> {
+    std::fs::create_dir_all(output_dir)?;
+    let mut pages = report_pages(images, summary, style);
+    if scale != 1.0 {
+        for page in &mut pages {
+            page.image = scale_image(&page.image, scale).into_owned();
+        }
+    }
+    let pdf = build_pdf_report(&pages, pdf_title, dpi * scale)?;
+    let path = Path::new(output_dir).join(PDF_REPORT_FILE);
+    std::fs::write(&path, pdf)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::create_solid_color_image;
+    use image::Rgba;
+
+    #[test]
+    fn test_text_string() {
+        assert_eq!(text_string("Pé"), "<FEFF005000E9>");
+    }
+
+    #[test]
+    fn test_report_pages_bookmarks() {
+        let solid = |color| Some(create_solid_color_image(72, 144, Rgba(color)));
+        let (blue, red) = (solid([0, 0, 255, 255]), solid([255, 0, 0, 255]));
+        let images = vec![(blue.clone(), blue.clone()), (blue.clone(), red.clone()), (None, None), (None, red)];
+        let summary = ComparisonSummary::new("a.pdf", "b.pdf", "out", &images, 72.0);
+
+        let pages = report_pages(&images, &summary, OverlayStyle::default());
+        let bookmarks: Vec<Option<&str>> = pages.iter().map(|page| page.bookmark.as_deref()).collect();
+        assert_eq!(bookmarks, vec![None, Some("Page 2: 100.00% changed"), Some("Page 4: added")]);
+    }
+
+    #[test]
+    fn test_build_pdf_report_structure() {
+        let solid = |color| Some(create_solid_color_image(72, 144, Rgba(color)));
+        let (blue, red) = (solid([0, 0, 255, 255]), solid([255, 0, 0, 255]));
+        let images = vec![(blue.clone(), blue.clone()), (blue, red)];
+        let summary = ComparisonSummary::new("a.pdf", "b.pdf", "out", &images, 72.0);
+
+        let pdf = build_pdf_report(&report_pages(&images, &summary, OverlayStyle::default()), "a_vs_b", 144.0).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.starts_with("%PDF-1.4"));
+        assert!(text.ends_with("%%EOF\n"));
+        assert_eq!(text.matches("/Type /Page ").count(), 2);
+        assert!(text.contains("/MediaBox [0 0 36.00 72.00]"), "Pages are sized from the render DPI");
+        assert!(text.contains("/Type /Outlines /First 11 0 R /Last 11 0 R /Count 1"));
+        assert!(text.contains(&format!("/Title {}", text_string("Page 2: 100.00% changed"))));
+
+        // Every xref entry points at the start of its object. Offsets are in
+        // bytes, so the table is read from the raw file rather than `text`.
+        let startxref = text.rfind("startxref\n").unwrap();
+        let xref: usize = text[startxref + 10..].lines().next().unwrap().parse().unwrap();
+        let table = String::from_utf8(pdf[xref..].to_vec()).unwrap();
+        for (id, entry) in table.lines().skip(3).take(11).enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj", id + 1).as_bytes()));
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::ops::Deref;
+use std::sync::{Mutex, MutexGuard};
+
+use pdfium_render::prelude::Pdfium;
+
+use crate::pdf::{create_pdfium_at, PdfError};
+
+// Shared access to PDFium for concurrent comparisons. PDFium keeps global,
+// thread-unsafe state, so binding the library more than once doesn't give
+// independent instances; instead the one binding is leased to a single caller
+// at a time. Loading and rendering happen under a lease, while diffing and
+// encoding the renders can run in parallel outside it.
+pub struct PdfiumPool {
+    pdfium: Pdfium,
+    lease: Mutex<()>,
+}
+
+// Exclusive use of the pool's PDFium until dropped. Documents loaded through
+// it borrow the lease, so they can't outlive it.
+pub struct PdfiumLease<'a> {
+    pdfium: &'a Pdfium,
+    _guard: MutexGuard<'a, ()>,
+}
+
+impl PdfiumPool {
+    // Bind to the PDFium library in `library_dir`
+    pub fn new(library_dir: &str) -> Result<PdfiumPool, PdfError> {
+        create_pdfium_at(library_dir).map(PdfiumPool::from_pdfium)
+    }
+
+    pub fn from_pdfium(pdfium: Pdfium) -> PdfiumPool {
+        PdfiumPool {
+            pdfium,
+            lease: Mutex::new(()),
+        }
+    }
+
+    // Wait until no one else is using PDFium and take it
+    pub fn lease(&self) -> PdfiumLease<'_> {
+        // A caller that panicked mid-render leaves nothing to repair; PDFium
+        // state is per document
+        let guard = self.lease.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        PdfiumLease {
+            pdfium: &self.pdfium,
+            _guard: guard,
+        }
+    }
+}
+
+impl Deref for PdfiumLease<'_> {
+    type Target = Pdfium;
+
+    fn deref(&self) -> &Pdfium {
+        self.pdfium
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::{load_pdf_document, DEFAULT_PDFIUM_PATH};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_leases_are_exclusive() {
+        let pool = PdfiumPool::new(DEFAULT_PDFIUM_PATH).expect("Failed to create Pdfium instance");
+        let (active, most_active) = (AtomicUsize::new(0), AtomicUsize::new(0));
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let pdfium = pool.lease();
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_active.fetch_max(now, Ordering::SeqCst);
+                    let document = load_pdf_document(&pdfium, Path::new("./samples/old.pdf")).expect("Failed to load PDF");
+                    assert!(!document.pages().is_empty());
+                    active.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        assert_eq!(most_active.load(Ordering::SeqCst), 1);
+    }
+}
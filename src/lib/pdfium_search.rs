@@ -0,0 +1,186 @@
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Os {
+    Linux,
+    MacOs,
+    Windows,
+}
+
+pub fn current_os() -> Os {
+    if cfg!(target_os = "macos") {
+        Os::MacOs
+    } else if cfg!(windows) {
+        Os::Windows
+    } else {
+        Os::Linux
+    }
+}
+
+// File name of the PDFium shared library
+pub fn library_name(os: Os) -> &'static str {
+    match os {
+        Os::Linux => "libpdfium.so",
+        Os::MacOs => "libpdfium.dylib",
+        Os::Windows => "pdfium.dll",
+    }
+}
+
+// PDFium binary release unpacked into the source tree, relative to the
+// working directory. Windows releases keep the DLL in `bin/`.
+fn bundled_dirs(os: Os) -> &'static [&'static str] {
+    match os {
+        Os::Linux => &["./pdfium-linux-x64/lib/", "./pdfium-linux-arm64/lib/"],
+        Os::MacOs => &["./pdfium-mac-arm64/lib/", "./pdfium-mac-x64/lib/"],
+        Os::Windows => &["./pdfium-win-x64/bin/", "./pdfium-win-arm64/bin/"],
+    }
+}
+
+// Variable listing directories the dynamic loader searches
+fn loader_path_var(os: Os) -> &'static str {
+    match os {
+        Os::Linux => "LD_LIBRARY_PATH",
+        Os::MacOs => "DYLD_LIBRARY_PATH",
+        Os::Windows => "PATH",
+    }
+}
+
+// Where distributions and package managers install shared libraries
+fn system_dirs(os: Os) -> &'static [&'static str] {
+    match os {
+        Os::Linux => &[
+            "/usr/local/lib",
+            "/usr/lib",
+            "/usr/lib64",
+            "/usr/lib/x86_64-linux-gnu",
+            "/usr/lib/aarch64-linux-gnu",
+        ],
+        Os::MacOs => &["/opt/homebrew/lib", "/usr/local/lib"],
+        Os::Windows => &[],
+    }
+}
+
+// Directories searched for PDFium, in order: the release bundled with the
+// source tree, next to the executable (and its `lib/`), `pdfium/lib/` in the
+// per-user data directory, the loader's search path variable, then the
+// system library directories. Each directory is listed once.
+pub fn search_dirs(
+    os: Os,
+    exe_dir: Option<&Path>,
+    data_dir: Option<&Path>,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = bundled_dirs(os).iter().map(PathBuf::from).collect();
+    if let Some(exe_dir) = exe_dir {
+        dirs.push(exe_dir.to_path_buf());
+        dirs.push(exe_dir.join("lib"));
+    }
+    if let Some(data_dir) = data_dir {
+        dirs.push(data_dir.join("pdfium").join("lib"));
+    }
+
+    let separator = if os == Os::Windows { ';' } else { ':' };
+    if let Some(paths) = env(loader_path_var(os)) {
+        dirs.extend(paths.split(separator).filter(|dir| !dir.is_empty()).map(PathBuf::from));
+    }
+    dirs.extend(system_dirs(os).iter().map(PathBuf::from));
+
+    let mut unique: Vec<PathBuf> = vec![];
+    for dir in dirs {
+        if !unique.contains(&dir) {
+            unique.push(dir);
+        }
+    }
+    unique
+}
+
+// `search_dirs` for this process
+pub fn default_search_dirs() -> Vec<PathBuf> {
+    let exe = std::env::current_exe().ok();
+    let exe_dir = exe.as_deref().and_then(Path::parent);
+    let data_dir = crate::app_dirs::data_dir();
+    search_dirs(current_os(), exe_dir, data_dir.as_deref(), &|name| std::env::var(name).ok())
+}
+
+// First of `dirs` holding the PDFium library
+pub fn find_library(dirs: &[PathBuf], os: Os) -> Option<&PathBuf> {
+    dirs.iter().find(|dir| dir.join(library_name(os)).is_file())
+}
+
+// Error for a PDFium library found in none of `dirs`
+pub fn not_found_message(dirs: &[PathBuf], os: Os) -> String {
+    let mut message = format!("PDFium library {} not found. Looked in:", library_name(os));
+    for dir in dirs {
+        message.push_str(&format!("\n  {}", dir.display()));
+    }
+    message.push_str("\nInstall PDFium in one of these directories or pass --pdfium-path (DIFF_PDF_PDFIUM_PATH)");
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        move |name| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string())
+    }
+
+    #[test]
+    fn test_search_dirs_linux() {
+        let dirs = search_dirs(
+            Os::Linux,
+            Some(Path::new("/opt/diff-pdf/bin")),
+            Some(Path::new("/home/ada/.local/share/diff-pdf")),
+            &env(&[("LD_LIBRARY_PATH", "/opt/pdfium/lib::/usr/lib")]),
+        );
+        let dirs: Vec<String> = dirs.iter().map(|dir| dir.display().to_string()).collect();
+        assert_eq!(
+            dirs,
+            vec![
+                "./pdfium-linux-x64/lib/",
+                "./pdfium-linux-arm64/lib/",
+                "/opt/diff-pdf/bin",
+                "/opt/diff-pdf/bin/lib",
+                "/home/ada/.local/share/diff-pdf/pdfium/lib",
+                "/opt/pdfium/lib",
+                "/usr/lib",
+                "/usr/local/lib",
+                "/usr/lib64",
+                "/usr/lib/x86_64-linux-gnu",
+                "/usr/lib/aarch64-linux-gnu",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search_dirs_windows() {
+        let dirs = search_dirs(Os::Windows, None, None, &env(&[("PATH", "C:\\pdfium\\bin;C:\\Windows"), ("LD_LIBRARY_PATH", "/ignored")]));
+        assert_eq!(
+            dirs,
+            vec![
+                PathBuf::from("./pdfium-win-x64/bin/"),
+                PathBuf::from("./pdfium-win-arm64/bin/"),
+                PathBuf::from("C:\\pdfium\\bin"),
+                PathBuf::from("C:\\Windows"),
+            ]
+        );
+        assert_eq!(library_name(Os::Windows), "pdfium.dll");
+    }
+
+    #[test]
+    fn test_find_library() {
+        let dir = std::env::temp_dir().join("diff_pdf_test_find_pdfium");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(library_name(Os::Linux)), b"").unwrap();
+
+        let dirs = vec![PathBuf::from("./no-such-pdfium/lib"), dir.clone()];
+        assert_eq!(find_library(&dirs, Os::Linux), Some(&dir));
+        assert_eq!(find_library(&dirs, Os::MacOs), None);
+
+        let message = not_found_message(&dirs, Os::MacOs);
+        assert!(message.starts_with("PDFium library libpdfium.dylib not found. Looked in:\n  ./no-such-pdfium/lib\n"));
+        assert!(message.contains("--pdfium-path"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
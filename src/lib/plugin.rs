@@ -0,0 +1,214 @@
+use std::ffi::{c_char, CString};
+
+use image::DynamicImage;
+use libloading::Library;
+
+use crate::report::ComparisonSummary;
+
+// Version of the plugin interface below; plugins built for another version
+// are refused rather than called with the wrong arguments
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+// `uint32_t diff_pdf_plugin_abi_version(void)`, required
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+
+// `int32_t hook(uint32_t page, uint32_t side, uint32_t width, uint32_t height, uint8_t *rgba)`
+// for the mask and preprocess stages. `page` is 1-based, `side` is 0 for the
+// old render and 1 for the new one, and `rgba` holds `width * height * 4`
+// bytes the hook may change in place. Non-zero fails the comparison.
+type ImageHook = unsafe extern "C" fn(u32, u32, u32, u32, *mut u8) -> i32;
+
+// `int32_t diff_pdf_report(const char *summary_json, const char *output_dir)`,
+// called once the comparison's outputs are written. Non-zero fails the
+// comparison, e.g. when a check the plugin runs doesn't pass.
+type ReportHook = unsafe extern "C" fn(*const c_char, *const c_char) -> i32;
+
+// Pipeline stages a plugin can take part in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    // After ignore rules are masked, on the renders as PDFium produced them
+    Mask,
+    // After the built-in preprocessing, on the renders as they are diffed
+    Preprocess,
+    Report,
+}
+
+impl Stage {
+    fn symbol(self) -> &'static [u8] {
+        match self {
+            Stage::Mask => b"diff_pdf_mask\0",
+            Stage::Preprocess => b"diff_pdf_preprocess\0",
+            Stage::Report => b"diff_pdf_report\0",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Stage::Mask => "mask",
+            Stage::Preprocess => "preprocess",
+            Stage::Report => "report",
+        }
+    }
+}
+
+// A shared library extending the comparison pipeline. Plugins run in-process
+// with the tool's own privileges, so only load trusted ones. Comparisons of a
+// batch may call a plugin from several threads at once.
+pub struct Plugin {
+    path: String,
+    mask: Option<ImageHook>,
+    preprocess: Option<ImageHook>,
+    report: Option<ReportHook>,
+    // Keeps the hooks above loaded
+    _library: Library,
+}
+
+impl Plugin {
+    pub fn load(path: &str) -> Result<Plugin, String> {
+        // Loading runs the library's initializers, which is why plugins must be trusted
+        let library = unsafe { Library::new(path) }.map_err(|e| format!("Failed to load plugin {}: {}", path, e))?;
+
+        let version = unsafe { library.get::<AbiVersionFn>(b"diff_pdf_plugin_abi_version\0") }
+            .map_err(|_| format!("{} is not a diff-pdf plugin: diff_pdf_plugin_abi_version is missing", path))?;
+        let version = unsafe { version() };
+        if version != PLUGIN_ABI_VERSION {
+            return Err(format!(
+                "Plugin {} was built for plugin interface version {}, this pdf_diff supports version {}",
+                path, version, PLUGIN_ABI_VERSION
+            ));
+        }
+
+        let image_hook = |stage: Stage| unsafe { library.get::<ImageHook>(stage.symbol()).ok().map(|hook| *hook) };
+        let mask = image_hook(Stage::Mask);
+        let preprocess = image_hook(Stage::Preprocess);
+        let report = unsafe { library.get::<ReportHook>(Stage::Report.symbol()).ok().map(|hook| *hook) };
+
+        Ok(Plugin {
+            path: path.to_string(),
+            mask,
+            preprocess,
+            report,
+            _library: library,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // Stages the plugin has hooks for
+    pub fn stages(&self) -> Vec<Stage> {
+        [
+            (Stage::Mask, self.mask.is_some()),
+            (Stage::Preprocess, self.preprocess.is_some()),
+            (Stage::Report, self.report.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(stage, present)| present.then_some(stage))
+        .collect()
+    }
+
+    // Run the plugin's mask or preprocess hook over both renders of every page
+    pub fn process_pages(&self, stage: Stage, images: &mut [(Option<DynamicImage>, Option<DynamicImage>)]) -> Result<(), String> {
+        let hook = match stage {
+            Stage::Mask => self.mask,
+            Stage::Preprocess => self.preprocess,
+            Stage::Report => None,
+        };
+        match hook {
+            Some(hook) => run_image_hook(hook, &self.path, stage, images),
+            None => Ok(()),
+        }
+    }
+
+    // Hand the finished comparison to the plugin's report hook
+    pub fn report(&self, summary: &ComparisonSummary) -> Result<(), String> {
+        let Some(hook) = self.report else {
+            return Ok(());
+        };
+
+        let json = serde_json::to_string(summary).map_err(|e| format!("Failed to describe the comparison for plugin {}: {}", self.path, e))?;
+        let json = CString::new(json).map_err(|e| e.to_string())?;
+        let output_dir = CString::new(summary.output_dir.as_str()).map_err(|e| e.to_string())?;
+
+        match unsafe { hook(json.as_ptr(), output_dir.as_ptr()) } {
+            0 => Ok(()),
+            status => Err(format!("Plugin {} failed the report stage (status {})", self.path, status)),
+        }
+    }
+}
+
+fn run_image_hook(
+    hook: ImageHook,
+    path: &str,
+    stage: Stage,
+    images: &mut [(Option<DynamicImage>, Option<DynamicImage>)],
+) -> Result<(), String> {
+    for (index, (old_image, new_image)) in images.iter_mut().enumerate() {
+        for (side, image) in [(0, old_image), (1, new_image)] {
+            let Some(image) = image else {
+                continue;
+            };
+
+            let mut rgba = image.to_rgba8();
+            let (width, height) = rgba.dimensions();
+            let status = unsafe { hook(index as u32 + 1, side, width, height, rgba.as_mut_ptr()) };
+            if status != 0 {
+                return Err(format!(
+                    "Plugin {} failed the {} stage on page {} (status {})",
+                    path,
+                    stage.label(),
+                    index + 1,
+                    status
+                ));
+            }
+            *image = DynamicImage::ImageRgba8(rgba);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::create_solid_color_image;
+    use image::{GenericImageView, Rgba};
+
+    // Paints the new render of page 1 black and rejects page 2
+    unsafe extern "C" fn blacken_new(page: u32, side: u32, width: u32, height: u32, rgba: *mut u8) -> i32 {
+        if page == 2 {
+            return 7;
+        }
+        if side == 1 {
+            let pixels = unsafe { std::slice::from_raw_parts_mut(rgba, (width * height * 4) as usize) };
+            for pixel in pixels.chunks_mut(4) {
+                pixel.copy_from_slice(&[0, 0, 0, 255]);
+            }
+        }
+        0
+    }
+
+    #[test]
+    fn test_run_image_hook() {
+        let white = Some(create_solid_color_image(3, 2, Rgba([255, 255, 255, 255])));
+        let mut images = vec![(white.clone(), white.clone())];
+        run_image_hook(blacken_new, "test.so", Stage::Mask, &mut images).unwrap();
+        assert_eq!(images[0].0.as_ref().unwrap().get_pixel(1, 1), Rgba([255, 255, 255, 255]));
+        assert_eq!(images[0].1.as_ref().unwrap().get_pixel(1, 1), Rgba([0, 0, 0, 255]));
+
+        let mut images = vec![(None, None), (None, white)];
+        let error = run_image_hook(blacken_new, "test.so", Stage::Preprocess, &mut images).unwrap_err();
+        assert_eq!(error, "Plugin test.so failed the preprocess stage on page 2 (status 7)");
+    }
+
+    #[test]
+    fn test_load_rejects_other_libraries() {
+        assert!(Plugin::load("./no-such-plugin.so").err().unwrap().starts_with("Failed to load plugin"));
+
+        #[cfg(target_os = "linux")]
+        {
+            let error = Plugin::load("libc.so.6").err().unwrap();
+            assert!(error.contains("is not a diff-pdf plugin"), "{}", error);
+        }
+    }
+}
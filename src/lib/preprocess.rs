@@ -0,0 +1,369 @@
+use std::fmt;
+use std::str::FromStr;
+
+use image::{imageops, DynamicImage, GenericImageView, GrayImage, Luma, Rgba};
+
+// Fraction of pixels clipped at each end of the histogram when stretching
+// levels, so a few stray specks don't pin the black or white point
+const LEVELS_CLIP: f64 = 0.005;
+
+// Blur applied before edge detection so scanner grain doesn't produce edges
+const EDGE_BLUR_SIGMA: f32 = 1.0;
+
+// Sobel gradient magnitude above which a pixel can be an edge; well below
+// the boundary of printed content, well above scanner grain
+const EDGE_THRESHOLD: f32 = 160.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Normalization {
+    /// Stretch each page's luminance levels to the full range
+    Luminance,
+}
+
+// How page renders are reduced to pure black and white
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binarization {
+    // Pick a threshold per page with Otsu's method
+    Otsu,
+    // Pixels with luminance above the given level become white
+    Threshold(u8),
+}
+
+impl FromStr for Binarization {
+    type Err = String;
+
+    // Accepts `otsu` or `threshold:N` with N in 0-255
+    fn from_str(value: &str) -> Result<Binarization, String> {
+        match value.split_once(':') {
+            None if value == "otsu" => Ok(Binarization::Otsu),
+            Some(("threshold", level)) => level
+                .parse()
+                .map(Binarization::Threshold)
+                .map_err(|_| format!("Invalid threshold level '{}', expected 0-255", level)),
+            _ => Err(format!("Invalid binarization '{}', expected 'otsu' or 'threshold:N'", value)),
+        }
+    }
+}
+
+impl fmt::Display for Binarization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binarization::Otsu => write!(f, "otsu"),
+            Binarization::Threshold(level) => write!(f, "threshold:{}", level),
+        }
+    }
+}
+
+// What is compared between the two renders of a page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Algorithm {
+    /// Compare the rendered pixels
+    #[default]
+    Pixel,
+    /// Compare edge maps of the renders, ignoring noise and tonal shifts
+    Edges,
+}
+
+// Preprocessing steps applied to both renders of every page before comparing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Preprocessing {
+    pub normalization: Option<Normalization>,
+    pub binarization: Option<Binarization>,
+    pub algorithm: Algorithm,
+}
+
+fn luminance(pixel: &Rgba<u8>) -> u8 {
+    ((pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114) / 1000) as u8
+}
+
+fn luminance_histogram(img: &DynamicImage) -> [u64; 256] {
+    let mut histogram = [0u64; 256];
+    for (_, _, pixel) in img.pixels() {
+        histogram[luminance(&pixel) as usize] += 1;
+    }
+    histogram
+}
+
+// Darkest and brightest luminance levels after clipping `LEVELS_CLIP` of the
+// pixels at each end
+fn luminance_levels(img: &DynamicImage) -> (u8, u8) {
+    let histogram = luminance_histogram(img);
+
+    let (width, height) = img.dimensions();
+    let clip = ((width as u64 * height as u64) as f64 * LEVELS_CLIP) as u64;
+
+    let mut seen = 0;
+    let low = (0..=255u8)
+        .find(|level| {
+            seen += histogram[*level as usize];
+            seen > clip
+        })
+        .unwrap_or(0);
+
+    seen = 0;
+    let high = (0..=255u8)
+        .rev()
+        .find(|level| {
+            seen += histogram[*level as usize];
+            seen > clip
+        })
+        .unwrap_or(255);
+
+    (low, high)
+}
+
+// Stretch the page's levels so its darkest content is black and its paper is
+// white. The same mapping is applied to every channel, which keeps hues intact.
+pub fn normalize_luminance(img: &DynamicImage) -> DynamicImage {
+    let (low, high) = luminance_levels(img);
+    if high <= low || (low == 0 && high == 255) {
+        return img.clone();
+    }
+
+    let range = (high - low) as f32;
+    let mut rgba = img.to_rgba8();
+    for pixel in rgba.pixels_mut() {
+        for channel in pixel.0.iter_mut().take(3) {
+            let stretched = (*channel as f32 - low as f32) * 255.0 / range;
+            *channel = stretched.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    DynamicImage::ImageRgba8(rgba)
+}
+
+// Luminance level separating ink from paper that maximizes the variance
+// between the two classes (Otsu's method)
+pub fn otsu_threshold(img: &DynamicImage) -> u8 {
+    let histogram = luminance_histogram(img);
+    let total: u64 = histogram.iter().sum();
+    let weighted_total: f64 = histogram.iter().enumerate().map(|(level, count)| level as f64 * *count as f64).sum();
+
+    let mut best = (0u8, 0.0f64);
+    let mut background = 0u64;
+    let mut weighted_background = 0.0;
+
+    for level in 0..=255u8 {
+        background += histogram[level as usize];
+        if background == 0 {
+            continue;
+        }
+        let foreground = total - background;
+        if foreground == 0 {
+            break;
+        }
+
+        weighted_background += level as f64 * histogram[level as usize] as f64;
+        let mean_background = weighted_background / background as f64;
+        let mean_foreground = (weighted_total - weighted_background) / foreground as f64;
+        let variance = background as f64 * foreground as f64 * (mean_background - mean_foreground).powi(2);
+
+        if variance > best.1 {
+            best = (level, variance);
+        }
+    }
+
+    best.0
+}
+
+// Turn the page into pure black ink on white paper, keeping transparency
+pub fn binarize(img: &DynamicImage, binarization: Binarization) -> DynamicImage {
+    let threshold = match binarization {
+        Binarization::Otsu => otsu_threshold(img),
+        Binarization::Threshold(level) => level,
+    };
+
+    let mut rgba = img.to_rgba8();
+    for pixel in rgba.pixels_mut() {
+        let level = if luminance(pixel) > threshold { 255 } else { 0 };
+        *pixel = Rgba([level, level, level, pixel[3]]);
+    }
+
+    DynamicImage::ImageRgba8(rgba)
+}
+
+// Map of the page's edges: black along the ridges of the Sobel gradient of
+// the (slightly blurred) luminance, white elsewhere, keeping transparency.
+// Thinning edges to their ridge keeps them one pixel wide whatever the
+// contrast, so layout changes move edges while noise and tonal shifts don't.
+pub fn edge_map(img: &DynamicImage) -> DynamicImage {
+    let (width, height) = img.dimensions();
+    let rgba = img.to_rgba8();
+    let gray = GrayImage::from_fn(width, height, |x, y| Luma([luminance(rgba.get_pixel(x, y))]));
+    let gray = imageops::blur(&gray, EDGE_BLUR_SIGMA);
+
+    // Clamp to the border so the page edge itself isn't an edge
+    let clamp = |x: i64, y: i64| (x.clamp(0, width as i64 - 1) as usize, y.clamp(0, height as i64 - 1) as usize);
+    let at = |x: i64, y: i64| {
+        let (x, y) = clamp(x, y);
+        gray.get_pixel(x as u32, y as u32)[0] as f32
+    };
+
+    let mut gradients = vec![(0.0f32, 0.0f32); width as usize * height as usize];
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let gx = at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1) - at(x - 1, y - 1) - 2.0 * at(x - 1, y) - at(x - 1, y + 1);
+            let gy = at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1) - at(x - 1, y - 1) - 2.0 * at(x, y - 1) - at(x + 1, y - 1);
+            gradients[y as usize * width as usize + x as usize] = (gx, gy);
+        }
+    }
+    let magnitude = |x: i64, y: i64| {
+        let (x, y) = clamp(x, y);
+        let (gx, gy) = gradients[y * width as usize + x];
+        gx.hypot(gy)
+    };
+
+    let mut out = rgba;
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        let (x, y) = (x as i64, y as i64);
+        let (gx, gy) = gradients[y as usize * width as usize + x as usize];
+        let strength = gx.hypot(gy);
+
+        // Neighbours across the edge, with the gradient direction rounded to
+        // the nearest 45 degrees
+        let (dx, dy) = match gy.atan2(gx).to_degrees().rem_euclid(180.0) {
+            angle if !(22.5..157.5).contains(&angle) => (1, 0),
+            angle if angle < 67.5 => (1, 1),
+            angle if angle < 112.5 => (0, 1),
+            _ => (-1, 1),
+        };
+        let ridge = strength >= magnitude(x + dx, y + dy) && strength > magnitude(x - dx, y - dy);
+
+        let level = if strength > EDGE_THRESHOLD && ridge { 0 } else { 255 };
+        *pixel = Rgba([level, level, level, pixel[3]]);
+    }
+
+    DynamicImage::ImageRgba8(out)
+}
+
+fn apply(img: &DynamicImage, preprocessing: &Preprocessing) -> DynamicImage {
+    let img = match preprocessing.normalization {
+        Some(Normalization::Luminance) => normalize_luminance(img),
+        None => img.clone(),
+    };
+    let img = match preprocessing.binarization {
+        Some(binarization) => binarize(&img, binarization),
+        None => img,
+    };
+    match preprocessing.algorithm {
+        Algorithm::Pixel => img,
+        Algorithm::Edges => edge_map(&img),
+    }
+}
+
+// Run the configured preprocessing over both renders of every page
+pub fn preprocess_pages(
+    images: Vec<(Option<DynamicImage>, Option<DynamicImage>)>,
+    preprocessing: &Preprocessing,
+) -> Vec<(Option<DynamicImage>, Option<DynamicImage>)> {
+    if *preprocessing == Preprocessing::default() {
+        return images;
+    }
+
+    images
+        .into_iter()
+        .map(|(old_image, new_image)| {
+            (
+                old_image.map(|img| apply(&img, preprocessing)),
+                new_image.map(|img| apply(&img, preprocessing)),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, RgbaImage};
+
+    // A page with `paper` background and a block of `ink`
+    fn page(paper: u8, ink: u8) -> DynamicImage {
+        let mut img: RgbaImage = ImageBuffer::from_pixel(40, 40, Rgba([paper, paper, paper, 255]));
+        for y in 10..20 {
+            for x in 10..30 {
+                img.put_pixel(x, y, Rgba([ink, ink, ink, 255]));
+            }
+        }
+        DynamicImage::ImageRgba8(img)
+    }
+
+    #[test]
+    fn test_normalize_luminance_matches_exposures() {
+        let bright = normalize_luminance(&page(250, 30));
+        let dark = normalize_luminance(&page(200, 10));
+
+        assert_eq!(bright.to_rgba8(), dark.to_rgba8(), "Both exposures should map to the same levels");
+        assert_eq!(bright.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(bright.get_pixel(15, 15), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_normalize_luminance_leaves_flat_page() {
+        let blank = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(10, 10, Rgba([230, 230, 230, 255])));
+        assert_eq!(normalize_luminance(&blank).to_rgba8(), blank.to_rgba8());
+    }
+
+    #[test]
+    fn test_preprocess_pages_keeps_missing_pages() {
+        let images = vec![(None, Some(page(240, 20)))];
+        let preprocessing = Preprocessing {
+            normalization: Some(Normalization::Luminance),
+            ..Preprocessing::default()
+        };
+        let processed = preprocess_pages(images, &preprocessing);
+        assert!(processed[0].0.is_none());
+        assert_eq!(processed[0].1.as_ref().unwrap().get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_parse_binarization() {
+        assert_eq!("otsu".parse(), Ok(Binarization::Otsu));
+        assert_eq!("threshold:128".parse(), Ok(Binarization::Threshold(128)));
+        assert!("threshold:300".parse::<Binarization>().is_err());
+        assert!("median".parse::<Binarization>().is_err());
+        assert_eq!(Binarization::Threshold(90).to_string(), "threshold:90");
+    }
+
+    #[test]
+    fn test_binarize_otsu_removes_grayscale_noise() {
+        // Same page scanned twice with slightly different paper and ink noise
+        let first = binarize(&page(235, 40), Binarization::Otsu);
+        let second = binarize(&page(222, 55), Binarization::Otsu);
+
+        assert_eq!(first.to_rgba8(), second.to_rgba8());
+        assert_eq!(first.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(first.get_pixel(15, 15), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_binarize_fixed_threshold() {
+        let img = binarize(&page(150, 100), Binarization::Threshold(120));
+        assert_eq!(img.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(img.get_pixel(15, 15), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_edge_map_ignores_tonal_shifts() {
+        // Same layout printed lighter and with a different paper tone
+        let first = edge_map(&page(250, 20));
+        let second = edge_map(&page(225, 90));
+
+        assert_eq!(first.to_rgba8(), second.to_rgba8());
+        assert_eq!(first.get_pixel(0, 0), Rgba([255, 255, 255, 255]), "The page border isn't an edge");
+        assert_eq!(first.get_pixel(20, 15), Rgba([255, 255, 255, 255]), "Inside the block is flat");
+        assert!((8..12).any(|x| first.get_pixel(x, 15) == Rgba([0, 0, 0, 255])), "The block's outline is an edge");
+    }
+
+    #[test]
+    fn test_edge_map_catches_layout_changes() {
+        let mut moved: RgbaImage = ImageBuffer::from_pixel(40, 40, Rgba([250, 250, 250, 255]));
+        for y in 22..32 {
+            for x in 10..30 {
+                moved.put_pixel(x, y, Rgba([20, 20, 20, 255]));
+            }
+        }
+        let original = edge_map(&page(250, 20));
+        let moved = edge_map(&DynamicImage::ImageRgba8(moved));
+        assert_ne!(original.to_rgba8(), moved.to_rgba8());
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+// Configuration file with user-defined profiles, looked up in the working
+// directory and its parents
+pub const CONFIG_FILE: &str = ".diffpdf.json";
+
+// Long option names and values; flags have no value
+type BuiltinOptions = &'static [(&'static str, Option<&'static str>)];
+
+// Options of the built-in profiles
+const BUILTIN_PROFILES: &[(&str, BuiltinOptions)] = &[
+    // Catch any change, with anti-aliasing pinned so renders are reproducible
+    (
+        "strict",
+        &[
+            ("dpi", Some("300")),
+            ("sensitivity", Some("0.02")),
+            ("render-flags", Some("no-text-aa,no-image-aa,no-path-aa")),
+            ("blank-pages", Some("change")),
+        ],
+    ),
+    // Only report obvious changes, ignoring exposure and margin shifts
+    (
+        "lenient",
+        &[
+            ("dpi", Some("100")),
+            ("sensitivity", Some("0.3")),
+            ("normalize", Some("luminance")),
+            ("crop-to-content", None),
+            ("skip-duplicate-pages", None),
+        ],
+    ),
+    // Scans of paper documents: noise, tonal drift and slightly different page sizes
+    (
+        "scanned",
+        &[
+            ("dpi", Some("200")),
+            ("sensitivity", Some("0.2")),
+            ("normalize", Some("luminance")),
+            ("binarize", Some("otsu")),
+            ("algorithm", Some("edges")),
+            ("crop-to-content", None),
+            ("size-tolerance", Some("2mm")),
+        ],
+    ),
+    // Pages as they would be printed: print quality, no annotations or form data
+    (
+        "print",
+        &[
+            ("dpi", Some("300")),
+            ("sensitivity", Some("0.05")),
+            ("render-flags", Some("print-quality,no-annotations")),
+            ("size-tolerance", Some("1mm")),
+        ],
+    ),
+];
+
+// One option a profile sets: its long name and value, none for a flag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileOption {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    profiles: BTreeMap<String, BTreeMap<String, Value>>,
+}
+
+// Names of the built-in profiles, for help and error messages
+pub fn builtin_profile_names() -> Vec<&'static str> {
+    BUILTIN_PROFILES.iter().map(|(name, _)| *name).collect()
+}
+
+fn builtin_profile(name: &str) -> Option<Vec<ProfileOption>> {
+    let (_, options) = BUILTIN_PROFILES.iter().find(|(builtin, _)| *builtin == name)?;
+    Some(
+        options
+            .iter()
+            .map(|(name, value)| ProfileOption {
+                name: name.to_string(),
+                value: value.map(str::to_string),
+            })
+            .collect(),
+    )
+}
+
+// Options of a user profile. Keys are long option names; `true` turns a flag
+// on and `false` leaves it off, arrays give a repeatable option several values.
+fn profile_options(profile: &str, settings: &BTreeMap<String, Value>) -> Result<Vec<ProfileOption>, String> {
+    let mut options = vec![];
+    for (name, value) in settings {
+        let invalid = || format!("Invalid value for '{}' in profile '{}': expected a string, number, boolean or list", name, profile);
+        let option = |value: Option<String>| ProfileOption { name: name.clone(), value };
+        let scalar = |value: &Value| match value {
+            Value::String(text) => Ok(text.clone()),
+            Value::Number(number) => Ok(number.to_string()),
+            _ => Err(invalid()),
+        };
+
+        match value {
+            Value::Bool(true) => options.push(option(None)),
+            Value::Bool(false) => {}
+            Value::Array(values) => {
+                for value in values {
+                    options.push(option(Some(scalar(value)?)));
+                }
+            }
+            value => options.push(option(Some(scalar(value)?))),
+        }
+    }
+    Ok(options)
+}
+
+// User profiles defined in a configuration file's text
+pub fn parse_config(text: &str) -> Result<BTreeMap<String, Vec<ProfileOption>>, String> {
+    let config: ConfigFile = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    config
+        .profiles
+        .iter()
+        .map(|(name, settings)| Ok((name.clone(), profile_options(name, settings)?)))
+        .collect()
+}
+
+pub fn load_config(path: &Path) -> Result<BTreeMap<String, Vec<ProfileOption>>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)?;
+    parse_config(&text).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+// The nearest configuration file in `dir` or one of its parents
+pub fn find_config_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors().map(|dir| dir.join(CONFIG_FILE)).find(|path| path.is_file())
+}
+
+// Options of the named profile. A user profile takes precedence over a
+// built-in one of the same name.
+pub fn resolve_profile(name: &str, user_profiles: &BTreeMap<String, Vec<ProfileOption>>) -> Result<Vec<ProfileOption>, String> {
+    if let Some(options) = user_profiles.get(name) {
+        return Ok(options.clone());
+    }
+    builtin_profile(name).ok_or_else(|| {
+        let mut known = builtin_profile_names();
+        known.extend(user_profiles.keys().map(String::as_str));
+        format!("Unknown profile '{}', expected one of: {}", name, known.join(", "))
+    })
+}
+
+// Command line arguments setting the options
+pub fn profile_arguments(options: &[ProfileOption]) -> Vec<String> {
+    let mut arguments = vec![];
+    for option in options {
+        arguments.push(format!("--{}", option.name));
+        arguments.extend(option.value.clone());
+    }
+    arguments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let text = r#"{
+            "profiles": {
+                "invoices": {
+                    "dpi": 200,
+                    "sensitivity": "0.05",
+                    "crop-to-content": true,
+                    "verbose": false,
+                    "ignore-region": ["0,0,100,20", "400,36,150,20"]
+                }
+            }
+        }"#;
+        let profiles = parse_config(text).unwrap();
+        assert_eq!(
+            profile_arguments(&profiles["invoices"]),
+            vec![
+                "--crop-to-content",
+                "--dpi",
+                "200",
+                "--ignore-region",
+                "0,0,100,20",
+                "--ignore-region",
+                "400,36,150,20",
+                "--sensitivity",
+                "0.05",
+            ]
+        );
+
+        assert!(parse_config(r#"{"profiles": {"a": {"dpi": {"x": 1}}}}"#).unwrap_err().contains("'dpi' in profile 'a'"));
+        assert!(parse_config("{}").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_profile() {
+        let mut user_profiles = BTreeMap::new();
+        assert!(resolve_profile("scanned", &user_profiles).unwrap().iter().any(|option| option.name == "binarize"));
+
+        let error = resolve_profile("invoices", &user_profiles).unwrap_err();
+        assert!(error.contains("strict, lenient, scanned, print"), "{}", error);
+
+        // User profiles shadow built-in ones
+        let mine = vec![ProfileOption { name: "dpi".to_string(), value: Some("50".to_string()) }];
+        user_profiles.insert("strict".to_string(), mine.clone());
+        assert_eq!(resolve_profile("strict", &user_profiles).unwrap(), mine);
+    }
+}
@@ -0,0 +1,185 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use image::imageops::FilterType;
+use image::DynamicImage;
+
+// Formats of reference images that can stand in for a PDF
+pub const IMAGE_EXTENSIONS: &[&str] = &["png", "tif", "tiff", "jpg", "jpeg", "bmp"];
+
+pub fn is_image_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| IMAGE_EXTENSIONS.iter().any(|known| e.eq_ignore_ascii_case(known)))
+}
+
+// Compare file names with runs of digits compared by value, so `page2` sorts
+// before `page10`
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let a_end = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+            let b_end = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+            let (a_number, b_number) = (a[..a_end].trim_start_matches('0'), b[..b_end].trim_start_matches('0'));
+            let ordering = a_number.len().cmp(&b_number.len()).then_with(|| a_number.cmp(b_number));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            (a, b) = (&a[a_end..], &b[b_end..]);
+        } else {
+            if x != y {
+                return x.cmp(&y);
+            }
+            (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+        }
+    }
+}
+
+// Image files in a directory, in natural name order
+pub fn list_images(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut images = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && is_image_path(&path) {
+            images.push(path);
+        }
+    }
+    images.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+    Ok(images)
+}
+
+// A file name with its image in the old and new directory, if any
+pub type NamedPair = (String, Option<PathBuf>, Option<PathBuf>);
+
+// Images of two directories matched by file name, in natural name order. A
+// name found in only one directory is paired with `None` on the other side.
+pub fn pair_by_name(old_dir: &Path, new_dir: &Path) -> std::io::Result<Vec<NamedPair>> {
+    let name = |path: &PathBuf| path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let (old_images, new_images) = (list_images(old_dir)?, list_images(new_dir)?);
+
+    let mut names: Vec<String> = old_images.iter().chain(&new_images).map(name).collect();
+    names.sort_by(|a, b| natural_cmp(a, b));
+    names.dedup();
+
+    let find = |images: &[PathBuf], wanted: &str| images.iter().find(|path| name(path) == wanted).cloned();
+    Ok(names
+        .into_iter()
+        .map(|wanted| {
+            let (old, new) = (find(&old_images, &wanted), find(&new_images, &wanted));
+            (wanted, old, new)
+        })
+        .collect())
+}
+
+// Reference images a `--new` argument names in place of a PDF, one per page:
+// a directory of images, or one or more comma-separated image files. `None`
+// when the argument is a PDF.
+pub fn reference_images(argument: &str) -> std::io::Result<Option<Vec<PathBuf>>> {
+    let path = Path::new(argument);
+    if path.is_dir() {
+        return list_images(path).map(Some);
+    }
+
+    let paths: Vec<PathBuf> = argument.split(',').map(PathBuf::from).collect();
+    Ok(paths.iter().all(|path| is_image_path(path)).then_some(paths))
+}
+
+// Decode reference images. Multi-page files such as TIFFs contribute their
+// first page, so pages are given as one file each.
+pub fn load_images(paths: &[PathBuf]) -> Result<Vec<DynamicImage>, Box<dyn Error>> {
+    if paths.is_empty() {
+        return Err("no reference images given".into());
+    }
+    paths
+        .iter()
+        .map(|path| image::open(path).map_err(|e| format!("{}: {}", path.display(), e).into()))
+        .collect()
+}
+
+// Scale a reference image to the size of the render it is compared with, so
+// references produced at another resolution line up
+pub fn fit_to(image: DynamicImage, width: u32, height: u32) -> DynamicImage {
+    if (image.width(), image.height()) == (width, height) {
+        return image;
+    }
+    image.resize_exact(width, height, FilterType::Triangle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+    use std::fs;
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = vec!["page10.png", "page2.png", "page1.png", "page02b.png", "cover.png"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, vec!["cover.png", "page1.png", "page2.png", "page02b.png", "page10.png"]);
+        assert_eq!(natural_cmp("a", "a1"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_reference_images() {
+        assert_eq!(reference_images("new.pdf").unwrap(), None);
+        assert_eq!(reference_images("report,final.pdf").unwrap(), None);
+        assert_eq!(
+            reference_images("p1.png,p2.TIFF").unwrap(),
+            Some(vec![PathBuf::from("p1.png"), PathBuf::from("p2.TIFF")])
+        );
+
+        let dir = "test_output_reference_images";
+        fs::create_dir_all(dir).unwrap();
+        let page = RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255]));
+        for name in ["page10.png", "page9.png"] {
+            page.save(format!("{}/{}", dir, name)).unwrap();
+        }
+        fs::write(format!("{}/notes.txt", dir), "not a page").unwrap();
+
+        let images = reference_images(dir).unwrap().unwrap();
+        let names: Vec<_> = images.iter().map(|path| path.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, vec!["page9.png", "page10.png"]);
+        assert_eq!(load_images(&images).unwrap().len(), 2);
+
+        let error = load_images(&[PathBuf::from(format!("{}/missing.png", dir))]).unwrap_err();
+        assert!(error.to_string().contains("missing.png"), "{}", error);
+
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_pair_by_name() {
+        let (old_dir, new_dir) = ("test_output_pair_old", "test_output_pair_new");
+        let page = RgbaImage::from_pixel(2, 2, Rgba([255, 255, 255, 255]));
+        for (dir, names) in [(old_dir, ["home.png", "login10.png", "login2.png"]), (new_dir, ["home.png", "login2.png", "signup.png"])] {
+            fs::create_dir_all(dir).unwrap();
+            for name in names {
+                page.save(format!("{}/{}", dir, name)).unwrap();
+            }
+        }
+
+        let pairs = pair_by_name(Path::new(old_dir), Path::new(new_dir)).unwrap();
+        let sides: Vec<_> = pairs.iter().map(|(name, old, new)| (name.as_str(), old.is_some(), new.is_some())).collect();
+        assert_eq!(
+            sides,
+            vec![("home.png", true, true), ("login2.png", true, true), ("login10.png", true, false), ("signup.png", false, true)]
+        );
+        assert_eq!(pairs[0].1.as_deref(), Some(Path::new("test_output_pair_old/home.png")));
+
+        fs::remove_dir_all(old_dir).ok();
+        fs::remove_dir_all(new_dir).ok();
+    }
+
+    #[test]
+    fn test_fit_to() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(40, 20, Rgba([0, 0, 0, 255])));
+        let fitted = fit_to(image, 20, 10);
+        assert_eq!((fitted.width(), fitted.height()), (20, 10));
+    }
+}
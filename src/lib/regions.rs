@@ -0,0 +1,457 @@
+use std::error::Error;
+use std::path::Path;
+
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+use crate::diff_core::{self, Pixels};
+use crate::image_utils::{save_png_with, PngOptions};
+
+// Size of the grid cells used to group changed pixels into regions
+const CELL_SIZE: u32 = 8;
+
+// Extra pixels kept around a region when cropping it out of a page
+pub const CROP_PADDING: u32 = 16;
+
+// Per-channel difference below which two pixels are considered equal
+pub const PIXEL_TOLERANCE: u8 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    // Grow the region by `padding` on every side, clamped to the given bounds
+    pub fn padded(&self, padding: u32, max_width: u32, max_height: u32) -> Region {
+        let x = self.x.saturating_sub(padding);
+        let y = self.y.saturating_sub(padding);
+        let right = (self.x + self.width + padding).min(max_width);
+        let bottom = (self.y + self.height + padding).min(max_height);
+        Region {
+            x,
+            y,
+            width: right.saturating_sub(x),
+            height: bottom.saturating_sub(y),
+        }
+    }
+
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+}
+
+// Pixels outside an image are treated as white paper
+fn pixel_or_white(img: &RgbaImage, x: u32, y: u32) -> Rgba<u8> {
+    if x < img.width() && y < img.height() {
+        *img.get_pixel(x, y)
+    } else {
+        Rgba([255, 255, 255, 255])
+    }
+}
+
+fn pixels_differ(a: Rgba<u8>, b: Rgba<u8>, tolerance: u8) -> bool {
+    diff_core::pixels_differ(&a.0, &b.0, tolerance)
+}
+
+// Build a per-pixel change mask covering the union of both image sizes
+pub fn change_mask(old: &DynamicImage, new: &DynamicImage, tolerance: u8) -> (u32, u32, Vec<bool>) {
+    let (old, new) = (diff_core::rgba(old), diff_core::rgba(new));
+    let mut mask = vec![];
+    let (width, height) = diff_core::fill_change_mask(Pixels::of(&old), Pixels::of(&new), tolerance, &mut mask);
+    (width, height, mask)
+}
+
+// Fraction of pixels (over the union of both sizes) that differ
+pub fn change_ratio(old: &DynamicImage, new: &DynamicImage, tolerance: u8) -> f64 {
+    let (old, new) = (diff_core::rgba(old), diff_core::rgba(new));
+    let (old, new) = (Pixels::of(&old), Pixels::of(&new));
+    let (width, height) = diff_core::union_size(old, new);
+    if width == 0 || height == 0 {
+        return 0.0;
+    }
+    diff_core::count_changed(old, new, tolerance) as f64 / (width as f64 * height as f64)
+}
+
+// Find the bounding boxes of changed areas between two page renders.
+// Changed pixels are bucketed into a coarse grid and neighbouring cells are
+// merged, so nearby edits (e.g. the letters of one word) form a single region.
+pub fn find_changed_regions(old: &DynamicImage, new: &DynamicImage, tolerance: u8) -> Vec<Region> {
+    let (width, height, mask) = change_mask(old, new, tolerance);
+    if width == 0 || height == 0 {
+        return vec![];
+    }
+
+    let cols = width.div_ceil(CELL_SIZE);
+    let rows = height.div_ceil(CELL_SIZE);
+    let mut cells = vec![false; (cols * rows) as usize];
+
+    for y in 0..height {
+        for x in 0..width {
+            if mask[(y * width + x) as usize] {
+                cells[((y / CELL_SIZE) * cols + x / CELL_SIZE) as usize] = true;
+            }
+        }
+    }
+
+    let mut visited = vec![false; cells.len()];
+    let mut regions = vec![];
+
+    for start in 0..cells.len() {
+        if !cells[start] || visited[start] {
+            continue;
+        }
+
+        let (mut min_c, mut min_r) = (cols, rows);
+        let (mut max_c, mut max_r) = (0, 0);
+        let mut stack = vec![start];
+        visited[start] = true;
+
+        while let Some(index) = stack.pop() {
+            let c = index as u32 % cols;
+            let r = index as u32 / cols;
+            min_c = min_c.min(c);
+            min_r = min_r.min(r);
+            max_c = max_c.max(c);
+            max_r = max_r.max(r);
+
+            for dr in -1i64..=1 {
+                for dc in -1i64..=1 {
+                    let nc = c as i64 + dc;
+                    let nr = r as i64 + dr;
+                    if nc < 0 || nr < 0 || nc >= cols as i64 || nr >= rows as i64 {
+                        continue;
+                    }
+                    let neighbour = (nr as u32 * cols + nc as u32) as usize;
+                    if cells[neighbour] && !visited[neighbour] {
+                        visited[neighbour] = true;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+        }
+
+        let x = min_c * CELL_SIZE;
+        let y = min_r * CELL_SIZE;
+        let right = ((max_c + 1) * CELL_SIZE).min(width);
+        let bottom = ((max_r + 1) * CELL_SIZE).min(height);
+        regions.push(Region {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        });
+    }
+
+    regions
+}
+
+// Crop a region out of an image, clamping it to the image bounds
+pub fn crop_region(img: &DynamicImage, region: &Region) -> DynamicImage {
+    let (width, height) = img.dimensions();
+    let x = region.x.min(width);
+    let y = region.y.min(height);
+    let w = region.width.min(width - x);
+    let h = region.height.min(height - y);
+    img.crop_imm(x, y, w, h)
+}
+
+// Highlights for changed pixels, like a text diff: content only in the old
+// render, content only in the new one, and content recolored in place
+pub const REMOVED_COLOR: Rgba<u8> = Rgba([255, 0, 0, 255]);
+pub const ADDED_COLOR: Rgba<u8> = Rgba([0, 170, 0, 255]);
+pub const RECOLORED_COLOR: Rgba<u8> = Rgba([255, 165, 0, 255]);
+
+// How much ink a pixel puts on white paper, 0-255
+fn ink(pixel: Rgba<u8>) -> i32 {
+    let [r, g, b, a] = pixel.0.map(i32::from);
+    let luma = (299 * r + 587 * g + 114 * b) / 1000;
+    (255 - luma) * a / 255
+}
+
+// Highlight for a changed pixel: darker in the new render means content was
+// added there, lighter means it was removed
+fn change_color(old: Rgba<u8>, new: Rgba<u8>, tolerance: u8) -> Rgba<u8> {
+    let (old_ink, new_ink) = (ink(old), ink(new));
+    let tolerance = i32::from(tolerance);
+    if new_ink > old_ink + tolerance {
+        ADDED_COLOR
+    } else if old_ink > new_ink + tolerance {
+        REMOVED_COLOR
+    } else {
+        RECOLORED_COLOR
+    }
+}
+
+// How highlights are combined with the page below them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum BlendMode {
+    /// Paint the highlight over the page
+    #[default]
+    Normal,
+    /// Absolute difference of page and highlight
+    Difference,
+    /// Darken the page with the highlight, keeping its linework visible
+    Multiply,
+    /// Lighten the page with the highlight
+    Screen,
+}
+
+impl BlendMode {
+    fn blend(self, page: u8, highlight: u8) -> f32 {
+        let (page, highlight) = (page as f32 / 255.0, highlight as f32 / 255.0);
+        match self {
+            BlendMode::Normal => highlight,
+            BlendMode::Difference => (page - highlight).abs(),
+            BlendMode::Multiply => page * highlight,
+            BlendMode::Screen => 1.0 - (1.0 - page) * (1.0 - highlight),
+        }
+    }
+}
+
+// Compositing of change highlights onto the faded page, so they can be toned
+// down on dense pages such as engineering drawings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverlayStyle {
+    // 0.0 leaves the page as is, 1.0 applies the blended highlight fully
+    pub opacity: f32,
+    pub blend: BlendMode,
+}
+
+impl Default for OverlayStyle {
+    fn default() -> OverlayStyle {
+        OverlayStyle {
+            opacity: 1.0,
+            blend: BlendMode::Normal,
+        }
+    }
+}
+
+impl OverlayStyle {
+    fn apply(&self, page: Rgba<u8>, highlight: Rgba<u8>) -> Rgba<u8> {
+        let opacity = self.opacity.clamp(0.0, 1.0);
+        let channel = |i: usize| {
+            let blended = self.blend.blend(page[i], highlight[i]) * 255.0;
+            (page[i] as f32 * (1.0 - opacity) + blended * opacity).round() as u8
+        };
+        Rgba([channel(0), channel(1), channel(2), 255])
+    }
+}
+
+// Render a region of the new page with changed pixels colored by whether
+// content was removed, added or recolored there
+pub fn diff_crop(old: &DynamicImage, new: &DynamicImage, region: &Region, tolerance: u8) -> DynamicImage {
+    diff_crop_with(old, new, region, tolerance, OverlayStyle::default())
+}
+
+// Like `diff_crop`, compositing highlights onto the faded page with `style`
+pub fn diff_crop_with(old: &DynamicImage, new: &DynamicImage, region: &Region, tolerance: u8, style: OverlayStyle) -> DynamicImage {
+    let old = old.to_rgba8();
+    let new = new.to_rgba8();
+    let mut out: RgbaImage = RgbaImage::new(region.width, region.height);
+
+    for dy in 0..region.height {
+        for dx in 0..region.width {
+            let a = pixel_or_white(&old, region.x + dx, region.y + dy);
+            let b = pixel_or_white(&new, region.x + dx, region.y + dy);
+            // Fade the page so highlights stand out
+            let faded = Rgba([
+                255 - (255 - b[0]) / 3,
+                255 - (255 - b[1]) / 3,
+                255 - (255 - b[2]) / 3,
+                255,
+            ]);
+            let pixel = if pixels_differ(a, b, tolerance) {
+                style.apply(faded, change_color(a, b, tolerance))
+            } else {
+                faded
+            };
+            out.put_pixel(dx, dy, pixel);
+        }
+    }
+
+    DynamicImage::ImageRgba8(out)
+}
+
+// Whole page with changed pixels colored as in `diff_crop` on the faded new
+// render
+pub fn highlight_changes(old: &DynamicImage, new: &DynamicImage, tolerance: u8) -> DynamicImage {
+    highlight_changes_with(old, new, tolerance, OverlayStyle::default())
+}
+
+pub fn highlight_changes_with(old: &DynamicImage, new: &DynamicImage, tolerance: u8, style: OverlayStyle) -> DynamicImage {
+    let page = Region {
+        x: 0,
+        y: 0,
+        width: old.width().max(new.width()),
+        height: old.height().max(new.height()),
+    };
+    diff_crop_with(old, new, &page, tolerance, style)
+}
+
+// Save old/new/diff crops for every changed region of every paired page.
+// Diff crops are composited with `style`. Returns the number of regions
+// exported.
+pub fn export_region_crops(
+    images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+    pdf_title: &str,
+    output_dir: &Path,
+    png: PngOptions,
+    style: OverlayStyle,
+) -> Result<usize, Box<dyn Error>> {
+    std::fs::create_dir_all(output_dir)?;
+    let mut exported = 0;
+
+    for (page, (old_image, new_image)) in images.iter().enumerate() {
+        let (Some(old), Some(new)) = (old_image, new_image) else {
+            continue;
+        };
+
+        let width = old.width().max(new.width());
+        let height = old.height().max(new.height());
+
+        for (index, region) in find_changed_regions(old, new, PIXEL_TOLERANCE).iter().enumerate() {
+            let padded = region.padded(CROP_PADDING, width, height);
+            let prefix = format!("{}_page{}_region{}", pdf_title, page + 1, index + 1);
+            let path = |kind: &str| output_dir.join(format!("{}_{}.png", prefix, kind));
+
+            save_png_with(&crop_region(old, &padded), path("old"), png)?;
+            save_png_with(&crop_region(new, &padded), path("new"), png)?;
+            save_png_with(&diff_crop_with(old, new, &padded, PIXEL_TOLERANCE, style), path("diff"), png)?;
+            exported += 1;
+        }
+    }
+
+    Ok(exported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageBuffer;
+    use std::fs;
+
+    fn white_image(width: u32, height: u32) -> RgbaImage {
+        ImageBuffer::from_pixel(width, height, Rgba([255, 255, 255, 255]))
+    }
+
+    fn fill(img: &mut RgbaImage, x: u32, y: u32, w: u32, h: u32) {
+        for py in y..y + h {
+            for px in x..x + w {
+                img.put_pixel(px, py, Rgba([0, 0, 0, 255]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_highlight_changes_colors_added_and_removed() {
+        let mut old = white_image(40, 20);
+        let mut new = white_image(40, 20);
+        fill(&mut old, 0, 0, 10, 10);
+        fill(&mut new, 20, 0, 10, 10);
+        // Black turned blue: the same ink, recolored
+        old.put_pixel(35, 15, Rgba([0, 0, 0, 255]));
+        new.put_pixel(35, 15, Rgba([0, 0, 40, 255]));
+
+        let diff = highlight_changes(&DynamicImage::ImageRgba8(old), &DynamicImage::ImageRgba8(new), PIXEL_TOLERANCE).to_rgba8();
+        assert_eq!(*diff.get_pixel(5, 5), REMOVED_COLOR);
+        assert_eq!(*diff.get_pixel(25, 5), ADDED_COLOR);
+        assert_eq!(*diff.get_pixel(35, 15), RECOLORED_COLOR);
+        assert_eq!(*diff.get_pixel(15, 15), Rgba([255, 255, 255, 255]), "Unchanged paper stays white");
+    }
+
+    #[test]
+    fn test_overlay_style() {
+        let (page, highlight) = (Rgba([200, 100, 255, 255]), REMOVED_COLOR);
+        assert_eq!(OverlayStyle::default().apply(page, highlight), highlight);
+
+        let blend = |blend, opacity| OverlayStyle { opacity, blend }.apply(page, highlight);
+        assert_eq!(blend(BlendMode::Normal, 0.0), page, "A transparent highlight leaves the page");
+        assert_eq!(blend(BlendMode::Normal, 0.5), Rgba([228, 50, 128, 255]));
+        assert_eq!(blend(BlendMode::Multiply, 1.0), Rgba([200, 0, 0, 255]));
+        assert_eq!(blend(BlendMode::Screen, 1.0), Rgba([255, 100, 255, 255]));
+        assert_eq!(blend(BlendMode::Difference, 1.0), Rgba([55, 100, 255, 255]));
+    }
+
+    #[test]
+    fn test_find_changed_regions_identical() {
+        let img = DynamicImage::ImageRgba8(white_image(64, 64));
+        assert!(find_changed_regions(&img, &img, PIXEL_TOLERANCE).is_empty());
+    }
+
+    #[test]
+    fn test_find_changed_regions_separate_blocks() {
+        let old = white_image(200, 200);
+        let mut new = white_image(200, 200);
+        fill(&mut new, 10, 10, 20, 20);
+        fill(&mut new, 150, 150, 10, 10);
+
+        let regions = find_changed_regions(
+            &DynamicImage::ImageRgba8(old),
+            &DynamicImage::ImageRgba8(new),
+            PIXEL_TOLERANCE,
+        );
+
+        assert_eq!(regions.len(), 2, "Distant changes should form separate regions");
+        for region in &regions {
+            assert!(region.width >= 10 && region.height >= 10);
+        }
+    }
+
+    #[test]
+    fn test_find_changed_regions_size_mismatch() {
+        let old = DynamicImage::ImageRgba8(white_image(50, 50));
+        let mut new = white_image(50, 80);
+        fill(&mut new, 0, 60, 50, 10);
+
+        let regions = find_changed_regions(&old, &DynamicImage::ImageRgba8(new), PIXEL_TOLERANCE);
+        assert_eq!(regions.len(), 1);
+        assert!(regions[0].y >= 50);
+    }
+
+    #[test]
+    fn test_change_ratio() {
+        let old = white_image(10, 10);
+        let mut new = white_image(10, 10);
+        fill(&mut new, 0, 0, 5, 2);
+
+        let old = DynamicImage::ImageRgba8(old);
+        let new = DynamicImage::ImageRgba8(new);
+        assert_eq!(change_ratio(&old, &old, PIXEL_TOLERANCE), 0.0);
+        assert!((change_ratio(&old, &new, PIXEL_TOLERANCE) - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_region_padded_clamps_to_bounds() {
+        let region = Region { x: 5, y: 5, width: 10, height: 10 };
+        let padded = region.padded(16, 20, 100);
+        assert_eq!(padded, Region { x: 0, y: 0, width: 20, height: 31 });
+    }
+
+    #[test]
+    fn test_export_region_crops() {
+        let test_dir = "test_output_crops";
+        let old = white_image(100, 100);
+        let mut new = white_image(100, 100);
+        fill(&mut new, 40, 40, 10, 10);
+
+        if Path::new(test_dir).exists() {
+            fs::remove_dir_all(test_dir).ok();
+        }
+
+        let images = vec![(
+            Some(DynamicImage::ImageRgba8(old)),
+            Some(DynamicImage::ImageRgba8(new)),
+        )];
+        let exported = export_region_crops(&images, "doc", Path::new(test_dir), PngOptions::default(), OverlayStyle::default()).expect("export should succeed");
+
+        assert_eq!(exported, 1);
+        for kind in ["old", "new", "diff"] {
+            assert!(Path::new(&format!("{}/doc_page1_region1_{}.png", test_dir, kind)).exists());
+        }
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use image::DynamicImage;
+
+use crate::duplicates::render_hash;
+use crate::pdf::PagePairs;
+use crate::tr;
+
+// 0-based old page shown at each position of the new document, when the new
+// document has exactly the pages of the old one in another order. Every page
+// must be rendered on both sides and match an old page pixel for pixel, so a
+// reordering that also changed a page is left to the page-by-page diff.
+// `None` when the pages differ or are in the same order.
+pub fn detect_reordering(images: &[(Option<DynamicImage>, Option<DynamicImage>)]) -> Option<Vec<usize>> {
+    // Old positions per hash, last first, so repeated pages keep their order
+    let mut old_positions: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (index, (old_image, _)) in images.iter().enumerate().rev() {
+        old_positions.entry(render_hash(old_image.as_ref()?)).or_default().push(index);
+    }
+
+    let order = images
+        .iter()
+        .map(|(_, new_image)| old_positions.get_mut(&render_hash(new_image.as_ref()?))?.pop())
+        .collect::<Option<Vec<usize>>>()?;
+
+    let moved = order.iter().enumerate().any(|(position, &old_index)| position != old_index);
+    moved.then_some(order)
+}
+
+// Pairing that compares each new page with the old page it was moved from
+pub fn reordered_pairing(order: &[usize]) -> PagePairs {
+    order.iter().enumerate().map(|(position, &old_index)| (Some(old_index), Some(position))).collect()
+}
+
+// Put each old render next to the new render of the same page
+pub fn reorder_old_pages(images: &mut [(Option<DynamicImage>, Option<DynamicImage>)], order: &[usize]) {
+    let mut old_images: Vec<Option<DynamicImage>> = images.iter_mut().map(|(old_image, _)| old_image.take()).collect();
+    for ((old_image, _), &old_index) in images.iter_mut().zip(order) {
+        *old_image = old_images[old_index].take();
+    }
+}
+
+// The moved pages of a reordering, e.g. `new page 1 is old page 3, new page 3 is old page 1`
+pub fn describe_reordering(order: &[usize]) -> String {
+    order
+        .iter()
+        .enumerate()
+        .filter(|&(position, &old_index)| position != old_index)
+        .map(|(position, &old_index)| tr!("cli-page-moved", new = position + 1, old = old_index + 1))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::create_solid_color_image;
+    use image::Rgba;
+
+    #[test]
+    fn test_detect_reordering() {
+        let solid = |color| Some(create_solid_color_image(4, 4, Rgba(color)));
+        let (red, green, blue) = ([255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]);
+        let mut images = vec![(solid(red), solid(blue)), (solid(green), solid(green)), (solid(blue), solid(red))];
+
+        let order = detect_reordering(&images).expect("pages were swapped");
+        assert_eq!(order, vec![2, 1, 0]);
+        assert_eq!(reordered_pairing(&order), vec![(Some(2), Some(0)), (Some(1), Some(1)), (Some(0), Some(2))]);
+        assert_eq!(describe_reordering(&order), "new page 1 is old page 3, new page 3 is old page 1");
+
+        reorder_old_pages(&mut images, &order);
+        assert!(images.iter().all(|(old, new)| old == new));
+    }
+
+    #[test]
+    fn test_detect_reordering_needs_the_same_pages() {
+        let solid = |color| Some(create_solid_color_image(4, 4, Rgba(color)));
+        let (red, green, blue) = ([255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]);
+        assert_eq!(detect_reordering(&[(solid(red), solid(red)), (solid(green), solid(green))]), None, "Same order");
+        assert_eq!(detect_reordering(&[(solid(red), solid(green)), (solid(green), solid(blue))]), None, "Changed page");
+        assert_eq!(detect_reordering(&[(solid(red), solid(red)), (solid(red), solid(green))]), None, "Page used twice");
+        assert_eq!(detect_reordering(&[(solid(red), solid(green)), (None, solid(red))]), None, "Missing page");
+    }
+}
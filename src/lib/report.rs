@@ -0,0 +1,1460 @@
+use std::error::Error;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+use crate::classify::RegionKind;
+use crate::diff_report::{DiffReport, PageResult};
+use crate::i18n::language;
+use crate::tr;
+use crate::budget::{save_png_within, OutputBudget};
+use crate::image_utils::{is_blank, page_changed, scaled_size, PngOptions};
+use crate::regions::{change_ratio, PIXEL_TOLERANCE};
+
+// Directory (relative to the report) holding the page renders it references
+pub const REPORT_ASSETS_DIR: &str = "report";
+pub const REPORT_FILE: &str = "report.html";
+pub const CSV_REPORT_FILE: &str = "pages.csv";
+pub const JSON_REPORT_FILE: &str = "report.json";
+pub const MARKDOWN_REPORT_FILE: &str = "report.md";
+
+// Report formats selectable with `--report`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// report.html with before/after sliders (same as --html-report)
+    Html,
+    /// report.pdf with bookmarks to changed pages (same as --pdf-report)
+    Pdf,
+    /// pages.csv with one row of metrics per page
+    Csv,
+    /// report.json with the document's stats, every page's result, warnings and timings
+    Json,
+    /// report.md with a summary and a table of the pages that differ
+    Markdown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageStatus {
+    Unchanged,
+    Changed,
+    Added,
+    Removed,
+    // At least one side rendered without any content, so the comparison
+    // can't be trusted either way
+    Blank,
+    // Left out by page sampling
+    Skipped,
+    // Changed, but the difference was approved in an earlier run
+    Approved,
+}
+
+// What to do with pages that render blank, such as filler pages inserted for
+// duplex printing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlankPolicy {
+    /// Compare blank pages like any other page
+    Change,
+    /// Leave out pages that are blank on every side they exist on
+    Skip,
+    /// Give pages with a blank side their own status instead of comparing them
+    #[default]
+    Report,
+}
+
+impl BlankPolicy {
+    // A page that is blank on every side it exists on
+    pub fn is_blank_only(old_image: Option<&DynamicImage>, new_image: Option<&DynamicImage>) -> bool {
+        let mut sides = old_image.into_iter().chain(new_image).peekable();
+        sides.peek().is_some() && sides.all(is_blank)
+    }
+}
+
+impl PageStatus {
+    pub fn of(old_image: Option<&DynamicImage>, new_image: Option<&DynamicImage>) -> PageStatus {
+        PageStatus::with_policy(old_image, new_image, BlankPolicy::Report)
+    }
+
+    pub fn with_policy(old_image: Option<&DynamicImage>, new_image: Option<&DynamicImage>, policy: BlankPolicy) -> PageStatus {
+        if policy == BlankPolicy::Skip && BlankPolicy::is_blank_only(old_image, new_image) {
+            return PageStatus::Skipped;
+        }
+        if policy != BlankPolicy::Change && old_image.into_iter().chain(new_image).any(is_blank) {
+            return PageStatus::Blank;
+        }
+
+        match (old_image, new_image) {
+            (None, None) => PageStatus::Skipped,
+            (None, Some(_)) => PageStatus::Added,
+            (Some(_), None) => PageStatus::Removed,
+            _ if page_changed(old_image, new_image) => PageStatus::Changed,
+            _ => PageStatus::Unchanged,
+        }
+    }
+
+    pub const ALL: [PageStatus; 7] = [
+        PageStatus::Unchanged,
+        PageStatus::Changed,
+        PageStatus::Added,
+        PageStatus::Removed,
+        PageStatus::Blank,
+        PageStatus::Skipped,
+        PageStatus::Approved,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PageStatus::Unchanged => "unchanged",
+            PageStatus::Changed => "changed",
+            PageStatus::Added => "added",
+            PageStatus::Removed => "removed",
+            PageStatus::Blank => "blank",
+            PageStatus::Skipped => "skipped",
+            PageStatus::Approved => "approved",
+        }
+    }
+
+    // Status as shown to people, in the chosen language; `label` stays the
+    // same for file formats and CSS classes
+    pub fn display_label(&self) -> String {
+        tr!(format!("status-{}", self.label()))
+    }
+}
+
+// Something about a page that makes its comparison less trustworthy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageWarning {
+    // 1-based page number
+    pub page: usize,
+    pub message: String,
+}
+
+// Warnings for every side of a page that rendered blank, for the pages the
+// policy reports as blank
+pub fn blank_page_warnings(images: &[(Option<DynamicImage>, Option<DynamicImage>)], policy: BlankPolicy) -> Vec<PageWarning> {
+    let mut warnings = vec![];
+    for (index, (old_image, new_image)) in images.iter().enumerate() {
+        if PageStatus::with_policy(old_image.as_ref(), new_image.as_ref(), policy) != PageStatus::Blank {
+            continue;
+        }
+        for (label, image) in [("old", old_image), ("new", new_image)] {
+            if image.as_ref().is_some_and(is_blank) {
+                warnings.push(PageWarning {
+                    page: index + 1,
+                    message: format!("Page renders blank in the {} PDF (rendering failure or empty page)", label),
+                });
+            }
+        }
+    }
+    warnings
+}
+
+// Outcome of comparing one pair of documents, used for consolidated reports
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComparisonSummary {
+    pub old_pdf: String,
+    pub new_pdf: String,
+    // Directory the comparison's outputs were written to
+    pub output_dir: String,
+    pub total_pages: usize,
+    pub changed_pages: usize,
+    // 1-based numbers of pages with a blank render; not counted as changed
+    #[serde(default)]
+    pub blank_pages: Vec<usize>,
+    // Fraction of changed pixels per page; unpaired pages count as 1.0
+    pub page_ratios: Vec<f64>,
+    // Severity of each page's change from 0 to 1, see `severity::Signals`;
+    // empty when not scored
+    #[serde(default)]
+    pub page_severities: Vec<f64>,
+    // Changed area summed over all pages, in square inches
+    pub changed_area: f64,
+    // Set when the comparison could not be completed
+    pub error: Option<String>,
+    #[serde(default)]
+    pub warnings: Vec<PageWarning>,
+    // Set when only a subset of pages was compared, describing how it was picked
+    #[serde(default)]
+    pub sampling: Option<String>,
+    // PDFium library and version the pages were rendered with
+    #[serde(default)]
+    pub pdfium: Option<String>,
+    // 0-based (old, new) page indices compared at each position, when pages
+    // were paired by text similarity or reordering rather than by position
+    #[serde(default)]
+    pub page_pairs: Vec<(Option<usize>, Option<usize>)>,
+    // Set when the new document has the old pages in another order; the
+    // order is in `page_pairs`
+    #[serde(default)]
+    pub reordered: bool,
+    #[serde(default)]
+    pub blank_policy: BlankPolicy,
+    // 1-based numbers of changed pages whose difference was approved; not
+    // counted as changed
+    #[serde(default)]
+    pub approved_pages: Vec<usize>,
+    // Set when the run was cancelled part way; pages it didn't reach are left
+    // out like unsampled ones
+    #[serde(default)]
+    pub interrupted: bool,
+}
+
+impl ComparisonSummary {
+    pub fn new(
+        old_pdf: &str,
+        new_pdf: &str,
+        output_dir: &str,
+        images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+        dpi: f32,
+    ) -> ComparisonSummary {
+        ComparisonSummary::with_blank_policy(old_pdf, new_pdf, output_dir, images, dpi, BlankPolicy::default())
+    }
+
+    pub fn with_blank_policy(
+        old_pdf: &str,
+        new_pdf: &str,
+        output_dir: &str,
+        images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+        dpi: f32,
+        blank_policy: BlankPolicy,
+    ) -> ComparisonSummary {
+        let mut changed_pages = 0;
+        let mut blank_pages = vec![];
+        let mut page_ratios = vec![];
+        let mut changed_pixels = 0.0;
+
+        for (index, (old_image, new_image)) in images.iter().enumerate() {
+            let status = PageStatus::with_policy(old_image.as_ref(), new_image.as_ref(), blank_policy);
+            match status {
+                PageStatus::Unchanged | PageStatus::Skipped => {}
+                PageStatus::Blank => blank_pages.push(index + 1),
+                _ => changed_pages += 1,
+            }
+
+            let (ratio, pixels) = match (old_image, new_image) {
+                _ if status == PageStatus::Skipped => (0.0, 0.0),
+                (Some(old), Some(new)) => {
+                    let (width, height) = (old.width().max(new.width()), old.height().max(new.height()));
+                    let ratio = change_ratio(old, new, PIXEL_TOLERANCE);
+                    (ratio, ratio * width as f64 * height as f64)
+                }
+                (Some(page), None) | (None, Some(page)) => (1.0, page.width() as f64 * page.height() as f64),
+                (None, None) => (0.0, 0.0),
+            };
+            page_ratios.push(ratio);
+            changed_pixels += pixels;
+        }
+
+        ComparisonSummary {
+            old_pdf: old_pdf.to_string(),
+            new_pdf: new_pdf.to_string(),
+            output_dir: output_dir.to_string(),
+            total_pages: images.len(),
+            changed_pages,
+            blank_pages,
+            page_ratios,
+            page_severities: vec![],
+            changed_area: changed_pixels / (dpi as f64 * dpi as f64),
+            error: None,
+            warnings: vec![],
+            sampling: None,
+            pdfium: None,
+            page_pairs: vec![],
+            reordered: false,
+            blank_policy,
+            approved_pages: vec![],
+            interrupted: false,
+        }
+    }
+
+    // Status of the page at a 0-based position, telling approved differences apart
+    pub fn page_status(&self, index: usize, old_image: Option<&DynamicImage>, new_image: Option<&DynamicImage>) -> PageStatus {
+        match PageStatus::with_policy(old_image, new_image, self.blank_policy) {
+            PageStatus::Changed | PageStatus::Added | PageStatus::Removed if self.approved_pages.contains(&(index + 1)) => {
+                PageStatus::Approved
+            }
+            status => status,
+        }
+    }
+
+    // 0-based positions of the `count` pages that changed the most, most
+    // changed first, by severity when the pages were scored and by changed
+    // pixels otherwise. Approved and blank pages don't count as changes.
+    pub fn top_changes(&self, images: &[(Option<DynamicImage>, Option<DynamicImage>)], count: usize) -> Vec<usize> {
+        let mut changed: Vec<usize> = images
+            .iter()
+            .enumerate()
+            .filter(|(index, (old, new))| {
+                matches!(
+                    self.page_status(*index, old.as_ref(), new.as_ref()),
+                    PageStatus::Changed | PageStatus::Added | PageStatus::Removed
+                )
+            })
+            .map(|(index, _)| index)
+            .collect();
+        let scores = if self.page_severities.is_empty() { &self.page_ratios } else { &self.page_severities };
+        let score = |index: usize| scores.get(index).copied().unwrap_or(0.0);
+        // Stable, so equally changed pages stay in page order
+        changed.sort_by(|&a, &b| score(b).total_cmp(&score(a)));
+        changed.truncate(count);
+        changed
+    }
+
+    // 1-based pages whose severity is above `threshold`. Approved and blank
+    // pages don't count as changes.
+    pub fn pages_above_severity(&self, threshold: f64) -> Vec<usize> {
+        self.page_severities
+            .iter()
+            .enumerate()
+            .map(|(index, &severity)| (index + 1, severity))
+            .filter(|(page, severity)| *severity > threshold && !self.approved_pages.contains(page) && !self.blank_pages.contains(page))
+            .map(|(page, _)| page)
+            .collect()
+    }
+
+    // Stop counting the given changed pages as changes
+    pub fn approve_pages(&mut self, pages: &[usize]) {
+        for &page in pages {
+            if !self.approved_pages.contains(&page) && !self.blank_pages.contains(&page) {
+                self.approved_pages.push(page);
+                self.changed_pages = self.changed_pages.saturating_sub(1);
+            }
+        }
+        self.approved_pages.sort_unstable();
+    }
+
+    pub fn failed(old_pdf: &str, new_pdf: &str, output_dir: &str, error: &str) -> ComparisonSummary {
+        ComparisonSummary {
+            old_pdf: old_pdf.to_string(),
+            new_pdf: new_pdf.to_string(),
+            output_dir: output_dir.to_string(),
+            total_pages: 0,
+            changed_pages: 0,
+            blank_pages: vec![],
+            page_ratios: vec![],
+            page_severities: vec![],
+            changed_area: 0.0,
+            error: Some(error.to_string()),
+            warnings: vec![],
+            sampling: None,
+            pdfium: None,
+            page_pairs: vec![],
+            reordered: false,
+            blank_policy: BlankPolicy::default(),
+            approved_pages: vec![],
+            interrupted: false,
+        }
+    }
+}
+
+// Number of most-changed pages listed in the aggregate summary
+pub const WORST_PAGE_COUNT: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WorstPage {
+    pub old_pdf: String,
+    pub new_pdf: String,
+    pub page: usize,
+    pub ratio: f64,
+}
+
+// Roll-up of a multi-document run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AggregateSummary {
+    pub total_documents: usize,
+    pub documents_with_differences: usize,
+    pub failed_documents: usize,
+    pub total_pages: usize,
+    pub changed_pages: usize,
+    pub blank_pages: usize,
+    // Square inches, summed over every comparison
+    pub total_changed_area: f64,
+    pub worst_pages: Vec<WorstPage>,
+}
+
+impl AggregateSummary {
+    pub fn from_summaries(summaries: &[ComparisonSummary]) -> AggregateSummary {
+        let completed = || summaries.iter().filter(|s| s.error.is_none());
+
+        let mut worst_pages: Vec<WorstPage> = completed()
+            .flat_map(|summary| {
+                summary.page_ratios.iter().enumerate().filter(|(_, ratio)| **ratio > 0.0).map(|(index, ratio)| WorstPage {
+                    old_pdf: summary.old_pdf.clone(),
+                    new_pdf: summary.new_pdf.clone(),
+                    page: index + 1,
+                    ratio: *ratio,
+                })
+            })
+            .collect();
+        worst_pages.sort_by(|a, b| b.ratio.total_cmp(&a.ratio));
+        worst_pages.truncate(WORST_PAGE_COUNT);
+
+        AggregateSummary {
+            total_documents: summaries.len(),
+            documents_with_differences: completed().filter(|s| s.changed_pages > 0).count(),
+            failed_documents: summaries.len() - completed().count(),
+            total_pages: completed().map(|s| s.total_pages).sum(),
+            changed_pages: completed().map(|s| s.changed_pages).sum(),
+            blank_pages: completed().map(|s| s.blank_pages.len()).sum(),
+            total_changed_area: completed().map(|s| s.changed_area).sum(),
+            worst_pages,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SummaryFile<'a> {
+    summary: &'a AggregateSummary,
+    comparisons: &'a [ComparisonSummary],
+}
+
+pub const SUMMARY_FILE: &str = "summary.json";
+
+// The aggregate summary and per-comparison details as JSON
+pub fn summary_json(aggregate: &AggregateSummary, summaries: &[ComparisonSummary]) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(&SummaryFile {
+        summary: aggregate,
+        comparisons: summaries,
+    })
+}
+
+// Write the aggregate summary and per-comparison details as JSON
+pub fn write_summary_json(
+    aggregate: &AggregateSummary,
+    summaries: &[ComparisonSummary],
+    output_dir: &str,
+) -> Result<PathBuf, Box<dyn Error>> {
+    std::fs::create_dir_all(output_dir)?;
+    let json = summary_json(aggregate, summaries)?;
+
+    let path = Path::new(output_dir).join(SUMMARY_FILE);
+    std::fs::write(&path, json)?;
+    Ok(path)
+}
+
+// Quote a CSV field when it contains a separator, quote or line break
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+// One row per page with its pairing, status, diff ratio, severity, number of
+// changed regions of each kind and the diff image saved for it
+pub fn csv_report(report: &DiffReport) -> Result<String, std::fmt::Error> {
+    let mut csv = String::from("page,old_page,new_page,status,diff_ratio,severity,changed_regions,text_changes,image_changes,graphic_changes,output_path\n");
+    for page in &report.pages {
+        let field = |value: Option<usize>| value.map(|value| value.to_string()).unwrap_or_default();
+        let kind = |kind: RegionKind| field(page.region_kinds.map(|kinds| kinds.count(kind)));
+        writeln!(
+            csv,
+            "{},{},{},{},{:.6},{},{},{},{},{},{}",
+            page.page,
+            field(page.old_page),
+            field(page.new_page),
+            page.status.label(),
+            page.diff_ratio,
+            page.severity.map(|severity| format!("{:.3}", severity)).unwrap_or_default(),
+            field(page.changed_regions),
+            kind(RegionKind::Text),
+            kind(RegionKind::Image),
+            kind(RegionKind::Graphic),
+            csv_field(page.output_path.as_deref().unwrap_or_default())
+        )?;
+    }
+    Ok(csv)
+}
+
+// Write the CSV report. Returns the path written.
+pub fn write_csv_report(report: &DiffReport, output_dir: &str) -> Result<PathBuf, Box<dyn Error>> {
+    std::fs::create_dir_all(output_dir)?;
+    let path = Path::new(output_dir).join(CSV_REPORT_FILE);
+    std::fs::write(&path, csv_report(report)?)?;
+    Ok(path)
+}
+
+// Write the report as JSON. Returns the path written.
+pub fn write_json_report(report: &DiffReport, output_dir: &str) -> Result<PathBuf, Box<dyn Error>> {
+    std::fs::create_dir_all(output_dir)?;
+    let path = Path::new(output_dir).join(JSON_REPORT_FILE);
+    std::fs::write(&path, serde_json::to_string_pretty(report)?)?;
+    Ok(path)
+}
+
+// Escape the characters Markdown would read as table cell boundaries or markup
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '|' | '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' | '\r' => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// A Markdown summary of the comparison, e.g. for a pull request comment: the
+// document's stats and a table of every page that isn't unchanged
+pub fn markdown_report(report: &DiffReport) -> Result<String, std::fmt::Error> {
+    let mut md = String::new();
+    writeln!(md, "# {}\n", tr!("report-title", title = escape_markdown(&report.title)))?;
+    let (old, new) = (format!("`{}`", report.old_pdf.replace('`', "'")), format!("`{}`", report.new_pdf.replace('`', "'")));
+    writeln!(md, "{}\n", tr!("report-comparing", old = old, new = new))?;
+
+    if let Some(sampling) = &report.sampling {
+        writeln!(md, "> {}\n", tr!("report-sampled", sampling = escape_markdown(sampling)))?;
+    }
+    if report.interrupted {
+        writeln!(md, "> {}\n", tr!("report-interrupted"))?;
+    }
+    if report.reordered {
+        writeln!(md, "> {}\n", tr!("report-reordered"))?;
+    }
+
+    let stats = &report.stats;
+    let changed = tr!("report-changed-pages-count", changed = stats.changed_pages, total = stats.total_pages);
+    let area = tr!("report-square-inches", area = format!("{:.2}", stats.changed_area));
+    writeln!(md, "| | |\n|---|---|")?;
+    writeln!(md, "| {} | {} |", tr!("report-changed-pages"), changed)?;
+    writeln!(md, "| {} | {} |", tr!("report-blank-pages"), stats.blank_pages.len())?;
+    writeln!(md, "| {} | {} |", tr!("report-approved-pages"), stats.approved_pages.len())?;
+    writeln!(md, "| {} | {} |", tr!("report-changed-area"), area)?;
+    writeln!(md, "| {} | {} |\n", tr!("report-column-warnings"), report.warnings.len())?;
+
+    let listed: Vec<_> = report
+        .pages
+        .iter()
+        .filter(|page| !matches!(page.status, PageStatus::Unchanged | PageStatus::Skipped))
+        .collect();
+    if listed.is_empty() {
+        writeln!(md, "{}", tr!("report-no-differences"))?;
+    } else {
+        let columns = [
+            "report-column-page",
+            "report-column-compared",
+            "report-column-status",
+            "report-column-changed",
+            "report-column-severity",
+            "report-column-kinds",
+            "report-column-warnings",
+        ];
+        let header: Vec<String> = columns.iter().map(|id| tr!(id)).collect();
+        writeln!(md, "| {} |\n|---|---|---|---|---|---|---|", header.join(" | "))?;
+        for page in listed {
+            let warnings: Vec<String> = report.page_warnings(page.page).map(escape_markdown).collect();
+            writeln!(
+                md,
+                "| {} | {} | {} | {:.2}% | {} | {} | {} |",
+                page.page,
+                pair_label(page.old_page.map(|p| p - 1), page.new_page.map(|p| p - 1)),
+                page.status.display_label(),
+                page.diff_ratio * 100.0,
+                severity_label(page),
+                kinds_summary(page).unwrap_or_default(),
+                warnings.join("<br>")
+            )?;
+        }
+    }
+
+    if let Some(pdfium) = &report.pdfium {
+        writeln!(md, "\n{}", tr!("report-rendered-with", pdfium = escape_markdown(pdfium)))?;
+    }
+    Ok(md)
+}
+
+// Write the Markdown report. Returns the path written.
+pub fn write_markdown_report(report: &DiffReport, output_dir: &str) -> Result<PathBuf, Box<dyn Error>> {
+    std::fs::create_dir_all(output_dir)?;
+    let path = Path::new(output_dir).join(MARKDOWN_REPORT_FILE);
+    std::fs::write(&path, markdown_report(report)?)?;
+    Ok(path)
+}
+
+// Percent-encode a relative path for a link, keeping its separators, so names
+// with spaces, `#` or non-ASCII characters still resolve
+pub fn url_path(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => encoded.push(byte as char),
+            _ => write!(encoded, "%{:02X}", byte).expect("writing to a String"),
+        }
+    }
+    encoded
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const STYLE: &str = r#"
+:root { color-scheme: light; --bg: #fff; --fg: #000; --border: #ccc; --changed: #b00; --warning: #a60; --approved: #070; --muted: #888; --link: #0645ad; --focus: #06c; }
+:root[data-theme="dark"] { color-scheme: dark; --bg: #1c1c1f; --fg: #e4e4e4; --border: #555; --changed: #ff7070; --warning: #f0b050; --approved: #6c6; --muted: #999; --link: #8ab4f8; --focus: #6af; }
+@media (prefers-color-scheme: dark) {
+  :root:not([data-theme="light"]) { color-scheme: dark; --bg: #1c1c1f; --fg: #e4e4e4; --border: #555; --changed: #ff7070; --warning: #f0b050; --approved: #6c6; --muted: #999; --link: #8ab4f8; --focus: #6af; }
+}
+body { font-family: sans-serif; margin: 2em; background: var(--bg); color: var(--fg); }
+a { color: var(--link); }
+table { border-collapse: collapse; margin-bottom: 2em; }
+td, th { border: 1px solid var(--border); padding: 4px 10px; }
+.changed, .added, .removed { color: var(--changed); font-weight: bold; }
+.warning, .blank { color: var(--warning); }
+.blank { font-weight: bold; }
+.approved { color: var(--approved); }
+.footer { color: var(--muted); font-size: 0.85em; margin-top: 2em; }
+.theme-toggle { float: right; }
+.print-select { float: right; color: var(--muted); font-size: 0.85em; }
+.page { margin-bottom: 3em; }
+.slider { position: relative; display: inline-block; max-width: 100%; --pos: 50%; }
+.slider img { display: block; max-width: 100%; height: auto; }
+.slider .before { position: absolute; inset: 0; clip-path: inset(0 calc(100% - var(--pos)) 0 0); }
+.slider .before img { width: 100%; height: 100%; }
+.slider .divider { position: absolute; top: 0; bottom: 0; left: var(--pos); width: 2px; background: #e00; pointer-events: none; }
+.slider input { position: absolute; inset: 0; width: 100%; height: 100%; margin: 0; opacity: 0; cursor: ew-resize; }
+.single img { max-width: 100%; height: auto; border: 1px solid var(--border); }
+.parts ol { list-style: none; padding: 0; display: flex; flex-wrap: wrap; gap: 0.5em 1em; }
+.parts [aria-current] { font-weight: bold; }
+a:focus-visible, .page:focus-visible, .slider:focus-within { outline: 3px solid var(--focus); outline-offset: 2px; }
+@media print {
+  :root, :root[data-theme="dark"] { color-scheme: light; --bg: #fff; --fg: #000; --border: #999; --changed: #b00; --warning: #a60; --approved: #070; --muted: #555; --link: #000; }
+  body { margin: 0; }
+  a { text-decoration: none; }
+  .theme-toggle, .print-select, nav, .slider input, .slider .divider { display: none; }
+  .page:has(.print-select input:not(:checked)) { display: none; }
+  .page { break-before: page; margin: 0; }
+  table, tr { break-inside: avoid; }
+  .slider { display: flex; gap: 1em; align-items: flex-start; }
+  .slider > img, .slider .before { flex: 1; min-width: 0; }
+  .slider .before { position: static; clip-path: none; order: -1; }
+  .slider .before img { height: auto; }
+  .slider img, .single img { max-height: 80vh; width: auto; max-width: 100%; border: 1px solid var(--border); }
+}
+"#;
+
+// Restores the theme picked last time before the page is drawn, and switches
+// between light and dark for the toggle. Without a choice, the system's
+// preference applies.
+const THEME_SCRIPT: &str = r#"
+(function () { try { var theme = localStorage.getItem("pdf-diff-theme"); if (theme) document.documentElement.dataset.theme = theme; } catch (e) {} })();
+function toggleTheme() {
+  var root = document.documentElement;
+  var dark = root.dataset.theme ? root.dataset.theme === "dark" : matchMedia("(prefers-color-scheme: dark)").matches;
+  root.dataset.theme = dark ? "light" : "dark";
+  try { localStorage.setItem("pdf-diff-theme", root.dataset.theme); } catch (e) {}
+}
+"#;
+
+// Button switching the report between a light and a dark theme
+fn theme_toggle_html() -> String {
+    format!("<button type=\"button\" class=\"theme-toggle\" onclick=\"toggleTheme()\">{}</button>", tr!("report-theme-toggle"))
+}
+
+// A render in the report. Images are only fetched as they scroll into view;
+// their size is given up front so the page doesn't jump as they arrive, and
+// so images further down aren't all taken to be in view before they load.
+fn img_html(src: &str, alt: &str, (width, height): (u32, u32)) -> String {
+    format!(
+        "<img src=\"{}\" alt=\"{}\" width=\"{}\" height=\"{}\" loading=\"lazy\" decoding=\"async\">",
+        src,
+        escape_html(alt),
+        width,
+        height
+    )
+}
+
+// Old render clipped over the new one; dragging the range input, or moving
+// it with the arrow keys, moves the split
+fn slider_html(old_img: &str, new_img: &str, page: &PageResult) -> String {
+    format!(
+        concat!(
+            "<div class=\"slider\" role=\"group\" aria-label=\"{label}\">",
+            "{new}",
+            "<div class=\"before\">{old}</div>",
+            "<div class=\"divider\" aria-hidden=\"true\"></div>",
+            "<input type=\"range\" min=\"0\" max=\"100\" value=\"50\" aria-label=\"{position}\" ",
+            "oninput=\"this.parentNode.style.setProperty('--pos', this.value + '%')\">",
+            "</div>"
+        ),
+        old = old_img,
+        new = new_img,
+        label = escape_html(&tr!("report-slider", page = page.page)),
+        position = escape_html(&tr!("report-slider-position", page = page.page))
+    )
+}
+
+// Severity of a page's change with two decimals, blank when not scored
+fn severity_label(page: &PageResult) -> String {
+    page.severity.map(|severity| format!("{:.2}", severity)).unwrap_or_default()
+}
+
+// What the changed regions of a page are made of, e.g. `2 text, 1 graphic`
+fn kinds_summary(page: &PageResult) -> Option<String> {
+    let kinds = page.region_kinds?;
+    let counts: Vec<String> = RegionKind::ALL
+        .iter()
+        .filter(|&&kind| kinds.count(kind) > 0)
+        .map(|kind| tr!("report-kind-count", count = kinds.count(*kind), kind = kind.display_label()))
+        .collect();
+    (!counts.is_empty()).then(|| counts.join(", "))
+}
+
+// What happened to a page, in words, e.g. `changed, 12.50% of pixels in 3
+// regions (2 text, 1 graphic)`
+fn change_summary(page: &PageResult) -> String {
+    let status = page.status.display_label();
+    match page.changed_regions {
+        Some(regions) if page.status == PageStatus::Changed => {
+            let id = if regions == 1 { "report-change-regions-one" } else { "report-change-regions-other" };
+            let percent = format!("{:.2}", page.diff_ratio * 100.0);
+            let summary = tr!(id, status = status, percent = percent, regions = regions);
+            match kinds_summary(page) {
+                Some(kinds) => format!("{} ({})", summary, kinds),
+                None => summary,
+            }
+        }
+        _ => status,
+    }
+}
+
+// Alt text of one side's render of a page, naming the source page and the change
+fn image_alt(page: &PageResult, side: &str) -> String {
+    let (version, source, number) = match side {
+        "old" => ("report-image-old", "report-image-source-old", page.old_page),
+        _ => ("report-image-new", "report-image-source-new", page.new_page),
+    };
+    let source = number.map_or(String::new(), |number| format!(" ({})", tr!(source, number = number)));
+    format!("{}{}: {}", tr!(version, page = page.page), source, change_summary(page))
+}
+
+// Which pages were compared at a position, from 0-based page indices
+pub fn pair_label(old: Option<usize>, new: Option<usize>) -> String {
+    match (old, new) {
+        (Some(old), Some(new)) => tr!("pair-compared", old = old + 1, new = new + 1),
+        (None, Some(new)) => tr!("pair-inserted", new = new + 1),
+        (Some(old), None) => tr!("pair-removed", old = old + 1),
+        (None, None) => String::new(),
+    }
+}
+
+// Page numbers of a result as `pair_label` takes them
+fn result_pair_label(page: &PageResult) -> String {
+    pair_label(page.old_page.map(|p| p - 1), page.new_page.map(|p| p - 1))
+}
+
+// Changed pages shown per HTML report file by default. The rest go to
+// numbered files linked from each, so a report with hundreds of changed pages
+// doesn't make the browser hold all their images at once.
+pub const PAGES_PER_FILE: usize = 50;
+
+// File of part `index` (from 0) of a paginated HTML report
+fn report_part_file(index: usize) -> String {
+    match index {
+        0 => REPORT_FILE.to_string(),
+        _ => format!("report-{}.html", index + 1),
+    }
+}
+
+// Links to every part of a paginated report, each named by the first and last
+// page it shows
+fn pagination_html(parts: &[(usize, usize)], current: usize) -> String {
+    let label = tr!("report-parts");
+    let mut nav = format!("<nav class=\"parts\" aria-label=\"{}\">\n<ol>", escape_html(&label));
+    for (index, &(first, last)) in parts.iter().enumerate() {
+        let name = match first == last {
+            true => tr!("report-part-one", page = first),
+            false => tr!("report-part", first = first, last = last),
+        };
+        let current = if index == current { " aria-current=\"page\"" } else { "" };
+        nav.push_str(&format!("<li><a href=\"{}\"{}>{}</a></li>", report_part_file(index), current, name));
+    }
+    nav.push_str("</ol>\n</nav>");
+    nav
+}
+
+// Start of every file of the HTML report, up to its heading
+fn html_head(html: &mut String, title: &str) -> std::fmt::Result {
+    writeln!(html, "<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">", language())?;
+    writeln!(html, "<title>{}</title>", title)?;
+    writeln!(html, "<style>{}</style>\n<script>{}</script>\n</head>\n<body>", STYLE, THEME_SCRIPT)?;
+    writeln!(html, "<main>\n{}\n<h1>{}</h1>", theme_toggle_html(), title)
+}
+
+// Write an HTML report with a summary table and, for every page that differs,
+// a before/after swipe slider (or the lone render for added/removed pages).
+// `images` are the renders the report's pages were compared from; they are
+// saved within `budget` if there is one. Past `pages_per_file` changed pages
+// (0 for no limit), the sliders continue in `report-2.html`, `report-3.html`
+// and so on. Returns the path of the written report.
+pub fn write_html_report(
+    images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+    report: &DiffReport,
+    output_dir: &str,
+    png: PngOptions,
+    budget: Option<&OutputBudget>,
+    pages_per_file: usize,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let assets_dir = Path::new(output_dir).join(REPORT_ASSETS_DIR);
+    std::fs::create_dir_all(&assets_dir)?;
+
+    let mut html = String::new();
+    let title = tr!("report-title", title = escape_html(&report.title));
+    html_head(&mut html, &title)?;
+
+    if let Some(sampling) = &report.sampling {
+        writeln!(html, "<p class=\"warning\">{}</p>", tr!("report-sampled", sampling = escape_html(sampling)))?;
+    }
+    if report.interrupted {
+        writeln!(html, "<p class=\"warning\">{}</p>", tr!("report-interrupted"))?;
+    }
+    if report.reordered {
+        writeln!(html, "<p class=\"warning\">{}</p>", tr!("report-reordered"))?;
+    }
+
+    let page_warnings = |page: usize| -> Vec<String> { report.page_warnings(page).map(escape_html).collect() };
+
+    // Pages paired out of position get their old/new page numbers spelled out
+    let paired = |page: &PageResult| match report.paired_by_text {
+        true => format!("<td>{}</td>", result_pair_label(page)),
+        false => String::new(),
+    };
+    let pairs_header = match report.paired_by_text {
+        true => format!("<th scope=\"col\">{}</th>", tr!("report-column-compared")),
+        false => String::new(),
+    };
+    // Only pages that differ get a section to jump to
+    let has_section = |page: &PageResult| !matches!(page.status, PageStatus::Unchanged | PageStatus::Skipped);
+    let listed: Vec<&PageResult> = report.pages.iter().filter(|page| has_section(page)).collect();
+
+    // First and last page of the sections in each file
+    let per_file = if pages_per_file == 0 { listed.len().max(1) } else { pages_per_file };
+    let parts: Vec<(usize, usize)> = listed.chunks(per_file).map(|chunk| (chunk[0].page, chunk[chunk.len() - 1].page)).collect();
+    let part_of = |page: usize| parts.iter().position(|&(_, last)| page <= last).unwrap_or(0);
+    let section_href = |page: usize| match part_of(page) {
+        0 => format!("#page-{}", page),
+        part => format!("{}#page-{}", report_part_file(part), page),
+    };
+
+    // Page list first, so keyboard and screen reader users can jump straight
+    // to each difference
+    if !listed.is_empty() {
+        let heading = tr!("report-page-list");
+        writeln!(html, "<nav aria-label=\"{}\">\n<h2>{}</h2>\n<ol>", escape_html(&heading), heading)?;
+        for page in listed {
+            writeln!(
+                html,
+                "<li><a href=\"{}\">{} {}: {}</a></li>",
+                section_href(page.page),
+                tr!("report-column-page"),
+                page.page,
+                change_summary(page)
+            )?;
+        }
+        writeln!(html, "</ol>\n</nav>")?;
+    }
+
+    writeln!(
+        html,
+        "<table>\n<caption>{}</caption>\n<tr><th scope=\"col\">{}</th>{}<th scope=\"col\">{}</th><th scope=\"col\">{}</th><th scope=\"col\">{}</th></tr>",
+        tr!("report-table-caption"),
+        tr!("report-column-page"),
+        pairs_header,
+        tr!("report-column-status"),
+        tr!("report-column-severity"),
+        tr!("report-column-warnings")
+    )?;
+    for page in &report.pages {
+        if page.status == PageStatus::Skipped {
+            continue;
+        }
+        let number = match has_section(page) {
+            true => format!("<a href=\"{}\">{}</a>", section_href(page.page), page.page),
+            false => page.page.to_string(),
+        };
+        writeln!(
+            html,
+            "<tr><th scope=\"row\">{number}</th>{pair}<td class=\"{class}\">{label}</td><td>{severity}</td><td class=\"warning\">{warnings}</td></tr>",
+            number = number,
+            pair = paired(page),
+            class = page.status.label(),
+            label = page.status.display_label(),
+            severity = severity_label(page),
+            warnings = page_warnings(page.page).join("<br>")
+        )?;
+    }
+    writeln!(html, "</table>")?;
+
+    let mut sections = vec![String::new(); parts.len().max(1)];
+    for ((old_image, new_image), result) in images.iter().zip(&report.pages) {
+        let status = result.status;
+        if matches!(status, PageStatus::Unchanged | PageStatus::Skipped) {
+            continue;
+        }
+
+        let page = result.page;
+        let section = &mut sections[part_of(page)];
+        let heading = tr!("report-page-heading", page = page, status = status.display_label());
+        // Focusable, so following a page list link moves keyboard focus here
+        writeln!(
+            section,
+            "<section class=\"page\" id=\"page-{}\" tabindex=\"-1\" aria-label=\"{}\">",
+            page,
+            escape_html(&format!("{} {}, {}", tr!("report-column-page"), page, change_summary(result)))
+        )?;
+        // Unticked pages are left out when the report is printed
+        writeln!(section, "<label class=\"print-select\"><input type=\"checkbox\" checked> {}</label>", tr!("report-print-page"))?;
+        match report.paired_by_text {
+            true => writeln!(section, "<h2>{}: {}</h2>", heading, result_pair_label(result))?,
+            false => writeln!(section, "<h2>{}</h2>", heading)?,
+        }
+        for warning in page_warnings(page) {
+            writeln!(section, "<p class=\"warning\">{}</p>", tr!("report-warning", message = warning))?;
+        }
+
+        let old_src = format!("{}/page{}_old.png", REPORT_ASSETS_DIR, page);
+        let new_src = format!("{}/page{}_new.png", REPORT_ASSETS_DIR, page);
+        let mut reduced = false;
+        if let Some(old) = old_image {
+            reduced |= save_png_within(budget, old, &Path::new(output_dir).join(&old_src), png)?;
+        }
+        if let Some(new) = new_image {
+            reduced |= save_png_within(budget, new, &Path::new(output_dir).join(&new_src), png)?;
+        }
+        if let Some(budget) = budget.filter(|_| reduced) {
+            let message = format!("Report images {} to stay within the output size limit", budget.mode().describe());
+            writeln!(section, "<p class=\"warning\">{}</p>", tr!("report-warning", message = message))?;
+        }
+
+        // Shown at the size they were meant to be saved at, even when the
+        // budget saved them smaller
+        let img = |image: &DynamicImage, src: &str, side: &str| img_html(src, &image_alt(result, side), scaled_size(image.width(), image.height(), png.scale));
+        match (old_image, new_image) {
+            (Some(old), Some(new)) => writeln!(section, "{}", slider_html(&img(old, &old_src, "old"), &img(new, &new_src, "new"), result))?,
+            (None, Some(new)) => writeln!(section, "<div class=\"single\">{}</div>", img(new, &new_src, "new"))?,
+            (Some(old), None) => writeln!(section, "<div class=\"single\">{}</div>", img(old, &old_src, "old"))?,
+            (None, None) => {}
+        }
+        writeln!(section, "</section>")?;
+    }
+
+    let pagination = (parts.len() > 1).then(|| pagination_html(&parts, 0));
+    html.push_str(&sections[0]);
+    if let Some(pagination) = &pagination {
+        writeln!(html, "{}", pagination)?;
+    }
+
+    if let Some(pdfium) = &report.pdfium {
+        writeln!(html, "<p class=\"footer\">{}</p>", tr!("report-rendered-with", pdfium = escape_html(pdfium)))?;
+    }
+    if let Some(metadata) = &report.metadata {
+        let run = tr!("report-run", started = &metadata.started, finished = &metadata.finished, version = escape_html(&metadata.version));
+        writeln!(html, "<p class=\"footer\">{}</p>", run)?;
+        if let Some(host) = &metadata.host {
+            writeln!(html, "<p class=\"footer\">{}</p>", tr!("report-run-host", host = escape_html(host)))?;
+        }
+        let arguments = format!("<code>{}</code>", escape_html(&metadata.command_line()));
+        writeln!(html, "<p class=\"footer\">{}</p>", tr!("report-run-arguments", arguments = arguments))?;
+    }
+    writeln!(html, "</main>\n</body>\n</html>")?;
+
+    let report_path = Path::new(output_dir).join(REPORT_FILE);
+    std::fs::write(&report_path, html)?;
+
+    for (index, part) in sections.iter().enumerate().skip(1) {
+        let mut html = String::new();
+        html_head(&mut html, &title)?;
+        let pagination = pagination_html(&parts, index);
+        writeln!(html, "{}\n{}{}\n</main>\n</body>\n</html>", pagination, part, pagination)?;
+        std::fs::write(Path::new(output_dir).join(report_part_file(index)), html)?;
+    }
+    // Parts left over from an earlier report that had more of them
+    let mut stale = sections.len();
+    while std::fs::remove_file(Path::new(output_dir).join(report_part_file(stale))).is_ok() {
+        stale += 1;
+    }
+    Ok(report_path)
+}
+
+// Write a consolidated report linking to each comparison's own report.
+// `relative_dirs` are the comparison directories relative to `output_dir`.
+pub fn write_index_report(
+    summaries: &[ComparisonSummary],
+    relative_dirs: &[String],
+    output_dir: &str,
+) -> Result<PathBuf, Box<dyn Error>> {
+    std::fs::create_dir_all(output_dir)?;
+    let aggregate = AggregateSummary::from_summaries(summaries);
+
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(html, "<title>PDF diff: {} comparisons</title>", summaries.len())?;
+    writeln!(html, "<style>{}</style>\n<script>{}</script>\n</head>\n<body>", STYLE, THEME_SCRIPT)?;
+    writeln!(html, "{}\n<h1>PDF diff: {} comparisons</h1>", theme_toggle_html(), summaries.len())?;
+
+    writeln!(html, "<table>")?;
+    writeln!(html, "<tr><th>Documents</th><td>{}</td></tr>", aggregate.total_documents)?;
+    writeln!(html, "<tr><th>With differences</th><td>{}</td></tr>", aggregate.documents_with_differences)?;
+    writeln!(html, "<tr><th>Failed</th><td>{}</td></tr>", aggregate.failed_documents)?;
+    writeln!(html, "<tr><th>Changed pages</th><td>{} of {}</td></tr>", aggregate.changed_pages, aggregate.total_pages)?;
+    writeln!(html, "<tr><th>Blank pages</th><td>{}</td></tr>", aggregate.blank_pages)?;
+    writeln!(html, "<tr><th>Changed area</th><td>{:.2} sq in</td></tr>", aggregate.total_changed_area)?;
+    writeln!(html, "</table>")?;
+
+    if !aggregate.worst_pages.is_empty() {
+        writeln!(html, "<h2>Most changed pages</h2>\n<table>\n<tr><th>Old</th><th>New</th><th>Page</th><th>Changed</th></tr>")?;
+        for worst in &aggregate.worst_pages {
+            writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.2}%</td></tr>",
+                escape_html(&worst.old_pdf),
+                escape_html(&worst.new_pdf),
+                worst.page,
+                worst.ratio * 100.0
+            )?;
+        }
+        writeln!(html, "</table>")?;
+    }
+
+    writeln!(html, "<h2>Comparisons</h2>")?;
+    writeln!(html, "<table>\n<tr><th>Old</th><th>New</th><th>Pages</th><th>Changed</th><th>Warnings</th><th>Report</th></tr>")?;
+
+    for (summary, dir) in summaries.iter().zip(relative_dirs) {
+        if let Some(error) = &summary.error {
+            writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td></td><td class=\"changed\" colspan=\"3\">error: {}</td></tr>",
+                escape_html(&summary.old_pdf),
+                escape_html(&summary.new_pdf),
+                escape_html(error)
+            )?;
+            continue;
+        }
+
+        let class = if summary.changed_pages > 0 { "changed" } else { "unchanged" };
+        writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"{}\">{}</td><td class=\"warning\">{}</td><td><a href=\"{}/{}\">{}</a></td></tr>",
+            escape_html(&summary.old_pdf),
+            escape_html(&summary.new_pdf),
+            summary.total_pages,
+            class,
+            summary.changed_pages,
+            summary.warnings.len(),
+            url_path(dir),
+            REPORT_FILE,
+            escape_html(dir)
+        )?;
+    }
+
+    writeln!(html, "</table>")?;
+
+    // Comparisons in one run share a PDFium build, so list each distinct one once
+    let mut backends: Vec<&str> = summaries.iter().filter_map(|s| s.pdfium.as_deref()).collect();
+    backends.sort_unstable();
+    backends.dedup();
+    for pdfium in backends {
+        writeln!(html, "<p class=\"footer\">Rendered with {}</p>", escape_html(pdfium))?;
+    }
+    writeln!(html, "</body>\n</html>")?;
+
+    let report_path = Path::new(output_dir).join(REPORT_FILE);
+    std::fs::write(&report_path, html)?;
+    Ok(report_path)
+}
+
+// A comparison of `old_pdf` with `new_pdf` whose pages differ by
+// `page_ratios`, shared by the tests of every module
+#[cfg(test)]
+pub(crate) fn summary_with_ratios(old_pdf: &str, new_pdf: &str, page_ratios: Vec<f64>) -> ComparisonSummary {
+    let mut summary = ComparisonSummary::new(old_pdf, new_pdf, "out", &[], 72.0);
+    summary.changed_pages = page_ratios.iter().filter(|&&ratio| ratio > 0.0).count();
+    summary.total_pages = page_ratios.len();
+    summary.page_ratios = page_ratios;
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classify::PageContent;
+    use crate::diff_report::Timings;
+    use crate::image_utils::{create_solid_color_image, DiffOutput};
+    use crate::layout::Layout;
+    use crate::metadata::RunMetadata;
+    use image::Rgba;
+    use std::fs;
+    use std::path::Path;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn report(images: &[(Option<DynamicImage>, Option<DynamicImage>)], summary: &ComparisonSummary, title: &str) -> DiffReport {
+        DiffReport::new(images, summary, title, None, Timings::default())
+    }
+
+    #[test]
+    fn test_page_status() {
+        let red = create_solid_color_image(20, 20, Rgba([255, 0, 0, 255]));
+        let green = create_solid_color_image(20, 20, Rgba([0, 255, 0, 255]));
+
+        assert_eq!(PageStatus::of(Some(&red), Some(&red)), PageStatus::Unchanged);
+        assert_eq!(PageStatus::of(Some(&red), Some(&green)), PageStatus::Changed);
+        assert_eq!(PageStatus::of(None, Some(&green)), PageStatus::Added);
+        assert_eq!(PageStatus::of(Some(&red), None), PageStatus::Removed);
+    }
+
+    #[test]
+    fn test_blank_pages_reported_separately() {
+        let red = create_solid_color_image(20, 20, Rgba([255, 0, 0, 255]));
+        let white = create_solid_color_image(20, 20, Rgba([255, 255, 255, 255]));
+
+        assert_eq!(PageStatus::of(Some(&white), Some(&white)), PageStatus::Blank);
+        assert_eq!(PageStatus::of(Some(&red), Some(&white)), PageStatus::Blank);
+
+        let images = vec![(Some(red.clone()), Some(white)), (Some(red.clone()), Some(red))];
+        let summary = ComparisonSummary::new("a.pdf", "b.pdf", "out", &images, 72.0);
+        assert_eq!(summary.blank_pages, vec![1]);
+        assert_eq!(summary.changed_pages, 0);
+
+        let warnings = blank_page_warnings(&images, BlankPolicy::Report);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].page, 1);
+        assert!(warnings[0].message.contains("new PDF"));
+    }
+
+    #[test]
+    fn test_blank_policy() {
+        let red = create_solid_color_image(20, 20, Rgba([255, 0, 0, 255]));
+        let white = create_solid_color_image(20, 20, Rgba([255, 255, 255, 255]));
+
+        // Duplex filler page inserted in the new document
+        let filler = PageStatus::with_policy(None, Some(&white), BlankPolicy::Skip);
+        assert_eq!(filler, PageStatus::Skipped);
+        assert_eq!(PageStatus::with_policy(Some(&red), Some(&white), BlankPolicy::Skip), PageStatus::Blank);
+        assert_eq!(PageStatus::with_policy(Some(&red), Some(&white), BlankPolicy::Change), PageStatus::Changed);
+        assert_eq!(PageStatus::with_policy(None, Some(&white), BlankPolicy::Change), PageStatus::Added);
+
+        let images = vec![(None, Some(white.clone())), (Some(red), Some(white))];
+        let summary = ComparisonSummary::with_blank_policy("a.pdf", "b.pdf", "out", &images, 72.0, BlankPolicy::Skip);
+        assert_eq!(summary.blank_pages, vec![2]);
+        assert_eq!(summary.page_ratios[0], 0.0);
+        assert_eq!(blank_page_warnings(&images, BlankPolicy::Skip).len(), 1);
+        assert!(blank_page_warnings(&images, BlankPolicy::Change).is_empty());
+    }
+
+    #[test]
+    fn test_approve_pages() {
+        let red = create_solid_color_image(20, 20, Rgba([255, 0, 0, 255]));
+        let green = create_solid_color_image(20, 20, Rgba([0, 255, 0, 255]));
+
+        let images = vec![(Some(red.clone()), Some(green.clone())), (Some(red), Some(green))];
+        let mut summary = ComparisonSummary::new("a.pdf", "b.pdf", "out", &images, 72.0);
+        summary.approve_pages(&[2]);
+        summary.approve_pages(&[2]);
+        assert_eq!(summary.changed_pages, 1);
+        assert_eq!(summary.approved_pages, vec![2]);
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(escape_html("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+    }
+
+    #[test]
+    fn test_write_html_report() {
+        let test_dir = "test_output_report";
+        if Path::new(test_dir).exists() {
+            fs::remove_dir_all(test_dir).ok();
+        }
+
+        let red = create_solid_color_image(20, 20, Rgba([255, 0, 0, 255]));
+        let green = create_solid_color_image(20, 20, Rgba([0, 255, 0, 255]));
+        let images = vec![
+            (Some(red.clone()), Some(red.clone())),
+            (Some(red), Some(green.clone())),
+            (None, Some(green)),
+        ];
+
+        let mut summary = ComparisonSummary::new("a.pdf", "b.pdf", test_dir, &images, 72.0);
+        summary.warnings = vec![PageWarning {
+            page: 2,
+            message: "Font 'Arial' is not embedded".to_string(),
+        }];
+        summary.page_pairs = vec![(Some(0), Some(0)), (Some(2), Some(1)), (None, Some(2))];
+        summary.approve_pages(&[3]);
+
+        let path = write_html_report(&images, &report(&images, &summary, "doc"), test_dir, PngOptions::default(), None, PAGES_PER_FILE).expect("report should be written");
+        let html = fs::read_to_string(&path).expect("report should be readable");
+
+        assert_eq!(html.matches("class=\"slider\"").count(), 1, "Only the changed page gets a slider");
+        assert!(html.contains("alt=\"Page 3, new version (page 3 of the new PDF): approved\""));
+        assert!(html.contains("alt=\"Page 2, old version (page 3 of the old PDF): changed, 100.00% of pixels in 1 region\""));
+        assert!(html.contains("<li><a href=\"#page-2\">Page 2: changed, 100.00% of pixels in 1 region</a></li>"));
+        assert!(html.contains("<tr><th scope=\"row\">1</th>"), "Unchanged pages have no section to link to");
+        assert!(html.contains("Warning: Font 'Arial' is not embedded"));
+        assert!(html.contains("<h2>Page 2 (changed): old page 3 vs new page 2</h2>"));
+        assert!(html.contains("<td>new page 3 (inserted)</td>"));
+        assert!(html.contains("<h2>Page 3 (approved): new page 3 (inserted)</h2>"));
+        assert!(!Path::new(&format!("{}/report/page1_old.png", test_dir)).exists());
+        assert!(Path::new(&format!("{}/report/page2_old.png", test_dir)).exists());
+        assert!(Path::new(&format!("{}/report/page2_new.png", test_dir)).exists());
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_top_changes() {
+        let red = create_solid_color_image(20, 20, Rgba([255, 0, 0, 255]));
+        let mut spotted = red.to_rgba8();
+        spotted.put_pixel(0, 0, Rgba([0, 0, 255, 255]));
+        let spotted = DynamicImage::ImageRgba8(spotted);
+        let green = create_solid_color_image(20, 20, Rgba([0, 255, 0, 255]));
+        let images = vec![
+            (Some(red.clone()), Some(spotted)),
+            (Some(red.clone()), Some(red.clone())),
+            (Some(red.clone()), Some(green.clone())),
+            (None, Some(green)),
+        ];
+        let mut summary = ComparisonSummary::new("a.pdf", "b.pdf", "out", &images, 72.0);
+
+        assert_eq!(summary.top_changes(&images, 10), vec![2, 3, 0]);
+        assert_eq!(summary.top_changes(&images, 2), vec![2, 3]);
+        summary.approve_pages(&[3]);
+        assert_eq!(summary.top_changes(&images, 2), vec![3, 0]);
+
+        // Scored pages are ranked by severity
+        summary.page_severities = vec![0.9, 0.0, 0.4, 0.6];
+        assert_eq!(summary.top_changes(&images, 10), vec![0, 3]);
+        assert_eq!(summary.pages_above_severity(0.5), vec![1, 4]);
+        assert_eq!(summary.pages_above_severity(0.0), vec![1, 4], "Approved pages don't count");
+    }
+
+    #[test]
+    fn test_write_csv_report() {
+        let test_dir = "test_output_report_csv";
+        let red = create_solid_color_image(20, 20, Rgba([255, 0, 0, 255]));
+        let green = create_solid_color_image(20, 20, Rgba([0, 255, 0, 255]));
+        let images = vec![(Some(red.clone()), Some(red.clone())), (Some(red), Some(green.clone())), (None, Some(green))];
+        let summary = ComparisonSummary::new("a.pdf", "b.pdf", test_dir, &images, 72.0);
+
+        let saved = |output| Some((output, Layout::Separate));
+        let report = DiffReport::new(&images, &summary, "doc,v2", saved(DiffOutput::DiffAndPage), Timings::default());
+        let path = write_csv_report(&report, test_dir).expect("CSV should be written");
+        let csv = fs::read_to_string(&path).unwrap();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            rows[0],
+            "page,old_page,new_page,status,diff_ratio,severity,changed_regions,text_changes,image_changes,graphic_changes,output_path"
+        );
+        // The title's comma gets the paths quoted
+        assert_eq!(rows[1], format!("1,1,1,unchanged,0.000000,,0,,,,\"{}/doc,v2_1.png\"", test_dir));
+        assert_eq!(rows[2], format!("2,2,2,changed,1.000000,,1,,,,\"{}/doc,v2_2.png\"", test_dir));
+        assert_eq!(rows[3], format!("3,,3,added,1.000000,,,,,,\"{}/doc,v2_4.png\"", test_dir));
+
+        // Classified regions are counted by kind
+        let mut classified = report.clone();
+        classified.pages[1].severity = Some(0.4567);
+        classified.classify_regions(&images, &[PageContent::default(), PageContent::default(), PageContent::default()], 72.0);
+        let csv = csv_report(&classified).unwrap();
+        assert_eq!(csv.lines().nth(2).unwrap(), format!("2,2,2,changed,1.000000,0.457,1,0,0,1,\"{}/doc,v2_2.png\"", test_dir));
+
+        // Unchanged pages have no image when only diffs are saved
+        let report = DiffReport::new(&images, &summary, "doc", saved(DiffOutput::DiffOnly), Timings::default());
+        write_csv_report(&report, test_dir).unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+        let paths: Vec<&str> = csv.lines().skip(1).map(|row| row.rsplit(',').next().unwrap()).collect();
+        assert_eq!(paths, vec!["".to_string(), format!("{}/doc_1.png", test_dir), format!("{}/doc_2.png", test_dir)]);
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_markdown_report() {
+        let red = create_solid_color_image(20, 20, Rgba([255, 0, 0, 255]));
+        let green = create_solid_color_image(20, 20, Rgba([0, 255, 0, 255]));
+        let images = vec![(Some(red.clone()), Some(red.clone())), (Some(red.clone()), Some(green))];
+        let mut summary = ComparisonSummary::new("a.pdf", "b.pdf", "out", &images, 72.0);
+        summary.warnings = vec![PageWarning {
+            page: 2,
+            message: "Font 'Arial|Bold' is not embedded".to_string(),
+        }];
+
+        let markdown = markdown_report(&report(&images, &summary, "doc_v2")).unwrap();
+        assert!(markdown.starts_with("# PDF diff: doc\\_v2\n"));
+        assert!(markdown.contains("| Changed pages | 1 of 2 |"));
+        assert!(markdown.contains("| 2 | old page 2 vs new page 2 | changed | 100.00% |  |  | Font 'Arial\\|Bold' is not embedded |"));
+        assert!(!markdown.contains("\n| 1 | old page 1"), "Unchanged pages aren't listed");
+
+        let mut classified = report(&images, &summary, "doc");
+        classified.classify_regions(&images, &[PageContent::default(), PageContent::default()], 72.0);
+        assert!(markdown_report(&classified).unwrap().contains("| changed | 100.00% |  | 1 graphic |"));
+        assert_eq!(change_summary(&classified.pages[1]), "changed, 100.00% of pixels in 1 region (1 graphic)");
+
+        let unchanged = ComparisonSummary::new("a.pdf", "b.pdf", "out", &images[..1], 72.0);
+        assert!(markdown_report(&report(&images[..1], &unchanged, "doc")).unwrap().contains("No differences."));
+    }
+
+    #[test]
+    fn test_write_html_report_skips_unsampled_pages() {
+        let test_dir = "test_output_report_sampled";
+        let red = create_solid_color_image(20, 20, Rgba([255, 0, 0, 255]));
+        let green = create_solid_color_image(20, 20, Rgba([0, 255, 0, 255]));
+        let images = vec![(Some(red), Some(green)), (None, None)];
+
+        let mut summary = ComparisonSummary::new("a.pdf", "b.pdf", test_dir, &images, 72.0);
+        summary.sampling = Some("--max-pages 1 (1 of 2 pages compared)".to_string());
+        summary.pdfium = Some("PDFium 140.0.7350.0 (lib/libpdfium.so)".to_string());
+        assert_eq!(summary.changed_pages, 1);
+        assert_eq!(PageStatus::of(None, None), PageStatus::Skipped);
+
+        let path = write_html_report(&images, &report(&images, &summary, "doc"), test_dir, PngOptions::default(), None, PAGES_PER_FILE).expect("report should be written");
+        let html = fs::read_to_string(path).unwrap();
+        assert!(html.contains("Sampled run: --max-pages 1 (1 of 2 pages compared)"));
+        assert!(!html.contains("Interrupted run"));
+        assert!(!html.contains("href=\"#page-2\""));
+        assert!(html.contains("Rendered with PDFium 140.0.7350.0"));
+        assert!(html.contains("onclick=\"toggleTheme()\""));
+        assert!(html.contains("<label class=\"print-select\"><input type=\"checkbox\" checked> Print</label>"));
+        assert!(!html.contains(">Compared</th>"), "Pages paired by position need no pairing column");
+
+        summary.interrupted = true;
+        let path = write_html_report(&images, &report(&images, &summary, "doc"), test_dir, PngOptions::default(), None, PAGES_PER_FILE).expect("report should be written");
+        assert!(fs::read_to_string(path).unwrap().contains("Interrupted run"));
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_write_html_report_paginates() {
+        let test_dir = "test_output_report_paginated";
+        let red = create_solid_color_image(20, 20, Rgba([255, 0, 0, 255]));
+        let green = create_solid_color_image(20, 20, Rgba([0, 255, 0, 255]));
+        let images = vec![(Some(red.clone()), Some(green.clone())), (Some(red.clone()), Some(red.clone())), (Some(red.clone()), Some(green.clone())), (Some(red), Some(green))];
+        let summary = ComparisonSummary::new("a.pdf", "b.pdf", test_dir, &images, 72.0);
+        let report = report(&images, &summary, "doc");
+
+        let path = write_html_report(&images, &report, test_dir, PngOptions::default(), None, 2).expect("report should be written");
+        let html = fs::read_to_string(path).unwrap();
+        assert!(html.contains("<a href=\"#page-3\">"));
+        assert!(html.contains("<a href=\"report-2.html#page-4\">"));
+        assert!(html.contains("id=\"page-3\"") && !html.contains("id=\"page-4\""));
+        assert!(html.contains("<a href=\"report.html\" aria-current=\"page\">Pages 1–3</a>"));
+        assert!(html.contains("width=\"20\" height=\"20\" loading=\"lazy\""));
+
+        let second = fs::read_to_string(Path::new(test_dir).join("report-2.html")).unwrap();
+        assert!(second.contains("id=\"page-4\""));
+        assert!(second.contains("<a href=\"report-2.html\" aria-current=\"page\">Page 4</a>"));
+
+        // One file for every page leaves no stale parts behind
+        let html = fs::read_to_string(write_html_report(&images, &report, test_dir, PngOptions::default(), None, 0).unwrap()).unwrap();
+        assert!(html.contains("id=\"page-4\"") && !html.contains("class=\"parts\""));
+        assert!(!Path::new(test_dir).join("report-2.html").exists());
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_write_html_report_metadata() {
+        let test_dir = "test_output_report_metadata";
+        let images = vec![(Some(create_solid_color_image(20, 20, Rgba([255, 0, 0, 255]))), Some(create_solid_color_image(20, 20, Rgba([0, 255, 0, 255]))))];
+        let summary = ComparisonSummary::new("a.pdf", "b.pdf", test_dir, &images, 72.0);
+
+        let mut with_metadata = report(&images, &summary, "doc");
+        let arguments = ["--old", "a.pdf", "--new", "b.pdf"].map(String::from).to_vec();
+        with_metadata.metadata = Some(RunMetadata::new(UNIX_EPOCH, UNIX_EPOCH + Duration::from_secs(90), arguments));
+        let path = write_html_report(&images, &with_metadata, test_dir, PngOptions::default(), None, PAGES_PER_FILE).expect("report should be written");
+        let html = fs::read_to_string(path).unwrap();
+        assert!(html.contains("Compared from 1970-01-01T00:00:00Z to 1970-01-01T00:01:30Z with pdf_diff"));
+        assert!(html.contains("<code>--old a.pdf --new b.pdf</code>"));
+
+        // Without metadata, the same comparison gives the same bytes
+        let mut without_metadata = report(&images, &summary, "doc");
+        without_metadata.timings = None;
+        let first = fs::read(write_html_report(&images, &without_metadata, test_dir, PngOptions::default(), None, PAGES_PER_FILE).unwrap()).unwrap();
+        let second = fs::read(write_html_report(&images, &without_metadata, test_dir, PngOptions::default(), None, PAGES_PER_FILE).unwrap()).unwrap();
+        assert_eq!(first, second);
+        assert!(!String::from_utf8(first).unwrap().contains("Compared from"));
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_write_index_report() {
+        let test_dir = "test_output_index_report";
+        let red = create_solid_color_image(20, 20, Rgba([255, 0, 0, 255]));
+        let green = create_solid_color_image(20, 20, Rgba([0, 255, 0, 255]));
+
+        let summaries = vec![
+            ComparisonSummary::new("a.pdf", "b.pdf", "out/a_vs_b", &[(Some(red.clone()), Some(green))], 72.0),
+            ComparisonSummary::new("a.pdf", "契約書.pdf", "out/a_vs_契約書", &[(Some(red.clone()), Some(red))], 72.0),
+            ComparisonSummary::failed("a.pdf", "d.pdf", "out/a_vs_d", "broken <xref>"),
+        ];
+        assert_eq!(summaries[0].changed_pages, 1);
+        assert_eq!(summaries[1].changed_pages, 0);
+
+        let dirs = vec!["a_vs_b".to_string(), "a_vs_契約書".to_string(), "a_vs_d".to_string()];
+        let path = write_index_report(&summaries, &dirs, test_dir).expect("index should be written");
+        let html = fs::read_to_string(path).unwrap();
+
+        assert!(html.contains("href=\"a_vs_b/report.html\""));
+        assert!(html.contains("href=\"a_vs_%E5%A5%91%E7%B4%84%E6%9B%B8/report.html\">a_vs_契約書</a>"));
+        assert!(html.contains("error: broken &lt;xref&gt;"));
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_aggregate_summary() {
+        let red = create_solid_color_image(20, 20, Rgba([255, 0, 0, 255]));
+        let green = create_solid_color_image(20, 20, Rgba([0, 255, 0, 255]));
+
+        let summaries = vec![
+            ComparisonSummary::new(
+                "a.pdf",
+                "b.pdf",
+                "out/a_vs_b",
+                &[(Some(red.clone()), Some(red.clone())), (Some(red.clone()), Some(green.clone()))],
+                20.0,
+            ),
+            ComparisonSummary::new("a.pdf", "c.pdf", "out/a_vs_c", &[(Some(red.clone()), Some(red))], 20.0),
+            ComparisonSummary::failed("a.pdf", "d.pdf", "out/a_vs_d", "broken"),
+        ];
+
+        // 20x20 pixel pages at 20 DPI are one square inch each
+        assert_eq!(summaries[0].page_ratios, vec![0.0, 1.0]);
+        assert_eq!(summaries[0].changed_area, 1.0);
+
+        let aggregate = AggregateSummary::from_summaries(&summaries);
+        assert_eq!(aggregate.total_documents, 3);
+        assert_eq!(aggregate.documents_with_differences, 1);
+        assert_eq!(aggregate.failed_documents, 1);
+        assert_eq!(aggregate.total_pages, 3);
+        assert_eq!(aggregate.changed_pages, 1);
+        assert_eq!(aggregate.worst_pages.len(), 1);
+        assert_eq!(aggregate.worst_pages[0].page, 2);
+    }
+
+    #[test]
+    fn test_write_summary_json() {
+        let test_dir = "test_output_summary_json";
+        let summaries = vec![ComparisonSummary::failed("a.pdf", "b.pdf", "out", "broken")];
+        let aggregate = AggregateSummary::from_summaries(&summaries);
+
+        let path = write_summary_json(&aggregate, &summaries, test_dir).expect("summary should be written");
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+
+        assert_eq!(json["summary"]["failed_documents"], 1);
+        assert_eq!(json["comparisons"][0]["error"], "broken");
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+}
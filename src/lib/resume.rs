@@ -0,0 +1,110 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+use crate::batch::Comparison;
+use crate::report::ComparisonSummary;
+
+// Written into a comparison's output directory once all of its outputs exist
+pub const COMPLETION_FILE: &str = ".pdf_diff_complete.json";
+
+#[derive(Serialize, Deserialize)]
+struct CompletionState {
+    fingerprint: String,
+    summary: ComparisonSummary,
+}
+
+fn file_stamp(path: &str) -> std::io::Result<String> {
+    let metadata = std::fs::metadata(path)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok(format!("{}:{}:{}", path, metadata.len(), modified.as_nanos()))
+}
+
+// Identify a comparison's inputs and the settings that shape its outputs.
+// Touching either PDF or changing a setting invalidates earlier results.
+pub fn fingerprint(comparison: &Comparison, settings: &str) -> std::io::Result<String> {
+    Ok(format!(
+        "{}|{}|{:?}|{:?}|{}",
+        file_stamp(&comparison.old_pdf)?,
+        file_stamp(&comparison.new_pdf)?,
+        comparison.dpi,
+        comparison.sensitivity,
+        settings
+    ))
+}
+
+fn completion_path(output_dir: &str) -> PathBuf {
+    Path::new(output_dir).join(COMPLETION_FILE)
+}
+
+// Summary of an earlier run of this comparison, if it finished with the same fingerprint
+pub fn load_completed(output_dir: &str, fingerprint: &str) -> Option<ComparisonSummary> {
+    let text = std::fs::read_to_string(completion_path(output_dir)).ok()?;
+    let state: CompletionState = serde_json::from_str(&text).ok()?;
+    (state.fingerprint == fingerprint).then_some(state.summary)
+}
+
+pub fn mark_completed(output_dir: &str, fingerprint: &str, summary: &ComparisonSummary) -> Result<(), Box<dyn Error>> {
+    let state = CompletionState {
+        fingerprint: fingerprint.to_string(),
+        summary: summary.clone(),
+    };
+    std::fs::create_dir_all(output_dir)?;
+    std::fs::write(completion_path(output_dir), serde_json::to_string_pretty(&state)?)?;
+    Ok(())
+}
+
+// Forget a completed run before its outputs are rewritten, so an interrupted
+// rerun isn't mistaken for a finished one
+pub fn clear_completed(output_dir: &str) {
+    let path = completion_path(output_dir);
+    if Path::new(&path).exists() {
+        std::fs::remove_file(path).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_completion_round_trip() {
+        let test_dir = "test_output_resume";
+        fs::create_dir_all(test_dir).unwrap();
+        let old_pdf = format!("{}/old.pdf", test_dir);
+        let new_pdf = format!("{}/new.pdf", test_dir);
+        fs::write(&old_pdf, "old").unwrap();
+        fs::write(&new_pdf, "new").unwrap();
+
+        let comparison = Comparison {
+            old_pdf: old_pdf.clone(),
+            new_pdf: new_pdf.clone(),
+            name: "old_vs_new".to_string(),
+            dpi: None,
+            sensitivity: None,
+        };
+        let output_dir = format!("{}/old_vs_new", test_dir);
+        let summary = ComparisonSummary::failed(&old_pdf, &new_pdf, &output_dir, "boom");
+
+        let print = fingerprint(&comparison, "dpi=150").unwrap();
+        assert!(load_completed(&output_dir, &print).is_none());
+
+        mark_completed(&output_dir, &print, &summary).unwrap();
+        assert_eq!(load_completed(&output_dir, &print), Some(summary));
+
+        let other = fingerprint(&comparison, "dpi=300").unwrap();
+        assert!(load_completed(&output_dir, &other).is_none(), "Changed settings should invalidate");
+
+        fs::write(&new_pdf, "newer").unwrap();
+        let touched = fingerprint(&comparison, "dpi=150").unwrap();
+        assert!(load_completed(&output_dir, &touched).is_none(), "Changed input should invalidate");
+
+        clear_completed(&output_dir);
+        assert!(!Path::new(&completion_path(&output_dir)).exists());
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+}
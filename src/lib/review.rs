@@ -0,0 +1,401 @@
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::Path;
+
+use image::DynamicImage;
+use ratatui::crossterm::cursor::MoveTo;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use serde::{Deserialize, Serialize};
+
+use crate::regions::{find_changed_regions, highlight_changes_with, OverlayStyle, PIXEL_TOLERANCE};
+use crate::report::{pair_label, ComparisonSummary, PageStatus};
+use crate::term_image::{clear_images, draw_image, ImageProtocol};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    Approved,
+    Rejected,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageDecision {
+    // 1-based page number
+    pub page: usize,
+    pub decision: Decision,
+}
+
+// What `review` writes when the user is done
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReviewDecisions {
+    pub old_pdf: String,
+    pub new_pdf: String,
+    pub decisions: Vec<PageDecision>,
+}
+
+pub fn write_decisions(path: &Path, decisions: &ReviewDecisions) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(decisions)?)?;
+    Ok(())
+}
+
+// Which render of the selected page the preview shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewView {
+    Diff,
+    Old,
+    New,
+}
+
+impl PreviewView {
+    fn next(self) -> PreviewView {
+        match self {
+            PreviewView::Diff => PreviewView::Old,
+            PreviewView::Old => PreviewView::New,
+            PreviewView::New => PreviewView::Diff,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            PreviewView::Diff => "diff",
+            PreviewView::Old => "old",
+            PreviewView::New => "new",
+        }
+    }
+}
+
+// A page that needs a decision
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReviewPage {
+    // 1-based page number
+    pub page: usize,
+    pub status: PageStatus,
+    pub ratio: f64,
+    pub regions: usize,
+    // Which old and new pages were compared, when pages were paired by text
+    pub compared: Option<String>,
+    pub warnings: Vec<String>,
+    pub decision: Option<Decision>,
+}
+
+// State of a review session: the pages to decide on and what is selected
+pub struct Review<'a> {
+    pub pages: Vec<ReviewPage>,
+    images: &'a [(Option<DynamicImage>, Option<DynamicImage>)],
+    pub selected: usize,
+    pub view: PreviewView,
+    // Compositing of highlights in the diff view
+    pub overlay: OverlayStyle,
+}
+
+impl<'a> Review<'a> {
+    // Every page that isn't unchanged or skipped is up for review. Pages
+    // approved in an earlier run start out approved.
+    pub fn new(images: &'a [(Option<DynamicImage>, Option<DynamicImage>)], summary: &ComparisonSummary) -> Review<'a> {
+        let mut pages = vec![];
+        for (index, (old_image, new_image)) in images.iter().enumerate() {
+            let page = index + 1;
+            let status = summary.page_status(index, old_image.as_ref(), new_image.as_ref());
+            if matches!(status, PageStatus::Unchanged | PageStatus::Skipped) {
+                continue;
+            }
+
+            let regions = match (old_image, new_image) {
+                (Some(old), Some(new)) => find_changed_regions(old, new, PIXEL_TOLERANCE).len(),
+                _ => 0,
+            };
+            pages.push(ReviewPage {
+                page,
+                status,
+                ratio: summary.page_ratios.get(index).copied().unwrap_or(0.0),
+                regions,
+                compared: summary.page_pairs.get(index).map(|&(old, new)| pair_label(old, new)),
+                warnings: summary
+                    .warnings
+                    .iter()
+                    .filter(|warning| warning.page == page)
+                    .map(|warning| warning.message.clone())
+                    .collect(),
+                decision: (status == PageStatus::Approved).then_some(Decision::Approved),
+            });
+        }
+
+        Review {
+            pages,
+            images,
+            selected: 0,
+            view: PreviewView::Diff,
+            overlay: OverlayStyle::default(),
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.pages.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    // Record a decision for the selected page and move on to the next page
+    // still waiting for one
+    pub fn decide(&mut self, decision: Option<Decision>) {
+        let Some(page) = self.pages.get_mut(self.selected) else {
+            return;
+        };
+        page.decision = decision;
+
+        if decision.is_some()
+            && let Some(next) = (self.selected + 1..self.pages.len()).find(|&i| self.pages[i].decision.is_none())
+        {
+            self.selected = next;
+        }
+    }
+
+    pub fn decisions(&self) -> Vec<PageDecision> {
+        self.pages
+            .iter()
+            .filter_map(|page| page.decision.map(|decision| PageDecision { page: page.page, decision }))
+            .collect()
+    }
+
+    pub fn approved_pages(&self) -> Vec<usize> {
+        self.pages
+            .iter()
+            .filter(|page| page.decision == Some(Decision::Approved))
+            .map(|page| page.page)
+            .collect()
+    }
+
+    // Image shown for the selected page. The diff view highlights changed
+    // pixels on a faded new page; a page on one side only is shown as is.
+    pub fn preview(&self) -> Option<DynamicImage> {
+        let page = self.pages.get(self.selected)?;
+        let (old_image, new_image) = self.images.get(page.page - 1)?;
+
+        match (self.view, old_image, new_image) {
+            (PreviewView::Diff, Some(old), Some(new)) => Some(highlight_changes_with(old, new, PIXEL_TOLERANCE, self.overlay)),
+            (PreviewView::Old, Some(old), _) => Some(old.clone()),
+            (PreviewView::New, _, Some(new)) => Some(new.clone()),
+            (_, Some(only), None) | (_, None, Some(only)) => Some(only.clone()),
+            _ => None,
+        }
+    }
+}
+
+fn decision_mark(decision: Option<Decision>) -> Span<'static> {
+    match decision {
+        Some(Decision::Approved) => Span::styled("[✓]", Style::default().fg(Color::Green)),
+        Some(Decision::Rejected) => Span::styled("[✗]", Style::default().fg(Color::Red)),
+        None => Span::raw("[ ]"),
+    }
+}
+
+// Draw the page list, the selected page's stats and an empty preview pane.
+// Returns the area left for the preview image.
+fn draw(frame: &mut Frame, review: &Review, protocol: ImageProtocol) -> Rect {
+    let [main, footer] = Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+    let [list_area, detail_area] = Layout::horizontal([Constraint::Length(36), Constraint::Min(20)]).areas(main);
+
+    let decided = review.pages.iter().filter(|page| page.decision.is_some()).count();
+    let items: Vec<ListItem> = review
+        .pages
+        .iter()
+        .map(|page| {
+            ListItem::new(Line::from(vec![
+                decision_mark(page.decision),
+                Span::raw(format!(" Page {:<4} {:<9} {:>6.2}%", page.page, page.status.label(), page.ratio * 100.0)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::bordered().title(format!(" Pages ({}/{} decided) ", decided, review.pages.len())))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(review.selected));
+    frame.render_stateful_widget(list, list_area, &mut state);
+
+    let Some(page) = review.pages.get(review.selected) else {
+        return Rect::default();
+    };
+
+    let mut lines = vec![
+        Line::from(format!("Status: {}", page.status.label())),
+        Line::from(format!("Changed pixels: {:.2}%", page.ratio * 100.0)),
+        Line::from(format!("Changed regions: {}", page.regions)),
+    ];
+    if let Some(compared) = &page.compared {
+        lines.push(Line::from(format!("Compared: {}", compared)));
+    }
+    for warning in &page.warnings {
+        lines.push(Line::styled(format!("Warning: {}", warning), Style::default().fg(Color::Yellow)));
+    }
+
+    let [stats_area, preview_area] =
+        Layout::vertical([Constraint::Length(lines.len() as u16 + 2), Constraint::Min(3)]).areas(detail_area);
+    let stats = Paragraph::new(lines)
+        .wrap(Wrap { trim: true })
+        .block(Block::bordered().title(format!(" Page {} ", page.page)));
+    frame.render_widget(stats, stats_area);
+
+    let preview_block = Block::bordered().title(format!(" Preview: {} ", review.view.label()));
+    let inner = preview_block.inner(preview_area);
+    frame.render_widget(preview_block, preview_area);
+    if protocol == ImageProtocol::None {
+        let hint = Paragraph::new("This terminal can't show images; try --image-protocol kitty or sixel")
+            .wrap(Wrap { trim: true });
+        frame.render_widget(hint, inner);
+    }
+
+    let keys = "↑/↓ move  a approve  r reject  u undo  v diff/old/new  q save and quit  Esc quit without saving";
+    frame.render_widget(Paragraph::new(keys).style(Style::default().add_modifier(Modifier::DIM)), footer);
+    inner
+}
+
+// Run the review until the user quits. Returns whether the decisions should
+// be saved.
+pub fn run_review(review: &mut Review, protocol: ImageProtocol) -> io::Result<bool> {
+    let mut terminal = ratatui::try_init()?;
+    let result = review_loop(&mut terminal, review, protocol);
+    let cleared = clear_images(&mut io::stdout(), protocol);
+    ratatui::try_restore()?;
+    cleared?;
+    result
+}
+
+fn review_loop(terminal: &mut DefaultTerminal, review: &mut Review, protocol: ImageProtocol) -> io::Result<bool> {
+    // What the preview currently shows, so images are only sent when it changes
+    let mut shown: Option<(usize, PreviewView, Rect)> = None;
+
+    loop {
+        let mut preview_area = Rect::default();
+        terminal.draw(|frame| preview_area = draw(frame, review, protocol))?;
+
+        let wanted = (review.selected, review.view, preview_area);
+        if protocol != ImageProtocol::None && shown != Some(wanted) {
+            clear_images(&mut io::stdout(), protocol)?;
+            if protocol == ImageProtocol::Sixel && shown.is_some() {
+                // The old sixel image is only overwritten by a full redraw
+                terminal.clear()?;
+                terminal.draw(|frame| preview_area = draw(frame, review, protocol))?;
+            }
+            if let Some(image) = review.preview() {
+                let mut stdout = io::stdout();
+                execute!(stdout, MoveTo(preview_area.x, preview_area.y))?;
+                draw_image(&mut stdout, &image, protocol, preview_area.width, preview_area.height)?;
+                stdout.flush()?;
+            }
+            shown = Some(wanted);
+        }
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(false),
+            KeyCode::Esc => return Ok(false),
+            KeyCode::Char('q') => return Ok(true),
+            KeyCode::Down | KeyCode::Char('j') => review.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => review.select_previous(),
+            KeyCode::Char('a') => review.decide(Some(Decision::Approved)),
+            KeyCode::Char('r') => review.decide(Some(Decision::Rejected)),
+            KeyCode::Char('u') => review.decide(None),
+            KeyCode::Char('v') => review.view = review.view.next(),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::create_solid_color_image;
+    use image::Rgba;
+    use std::fs;
+
+    #[test]
+    fn test_review_decisions() {
+        let solid = |color| Some(create_solid_color_image(8, 8, Rgba(color)));
+        let (red, green) = (solid([255, 0, 0, 255]), solid([0, 255, 0, 255]));
+        let images = vec![
+            (red.clone(), green.clone()),
+            (red.clone(), red.clone()),
+            (None, green.clone()),
+            (red.clone(), green),
+        ];
+        let mut summary = ComparisonSummary::new("a.pdf", "b.pdf", "out", &images, 72.0);
+        summary.approve_pages(&[4]);
+
+        let mut review = Review::new(&images, &summary);
+        let pages: Vec<usize> = review.pages.iter().map(|page| page.page).collect();
+        assert_eq!(pages, vec![1, 3, 4], "Unchanged pages aren't up for review");
+        assert_eq!(review.pages[2].status, PageStatus::Approved);
+        assert_eq!(review.pages[0].regions, 1);
+
+        review.decide(Some(Decision::Rejected));
+        assert_eq!(review.selected, 1, "Deciding moves to the next undecided page");
+        review.decide(Some(Decision::Approved));
+        assert_eq!(review.selected, 1, "No undecided page is left after it");
+
+        assert_eq!(review.approved_pages(), vec![3, 4]);
+        assert_eq!(
+            review.decisions(),
+            vec![
+                PageDecision { page: 1, decision: Decision::Rejected },
+                PageDecision { page: 3, decision: Decision::Approved },
+                PageDecision { page: 4, decision: Decision::Approved },
+            ]
+        );
+
+        review.decide(None);
+        assert_eq!(review.approved_pages(), vec![4]);
+    }
+
+    #[test]
+    fn test_review_preview() {
+        let solid = |color| Some(create_solid_color_image(8, 8, Rgba(color)));
+        let images = vec![(solid([255, 0, 0, 255]), solid([0, 255, 0, 255])), (None, solid([0, 0, 255, 255]))];
+        let summary = ComparisonSummary::new("a.pdf", "b.pdf", "out", &images, 72.0);
+        let mut review = Review::new(&images, &summary);
+
+        let diff = review.preview().unwrap().to_rgba8();
+        assert_eq!(*diff.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+        review.view = PreviewView::Old;
+        assert_eq!(review.preview(), images[0].0);
+
+        // An added page has only the new render, whatever the view
+        review.select_next();
+        assert_eq!(review.preview(), images[1].1);
+    }
+
+    #[test]
+    fn test_write_decisions() {
+        let test_dir = "test_output_review";
+        let path = Path::new(test_dir).join("review.json");
+        let decisions = ReviewDecisions {
+            old_pdf: "a.pdf".to_string(),
+            new_pdf: "b.pdf".to_string(),
+            decisions: vec![PageDecision { page: 2, decision: Decision::Approved }],
+        };
+
+        write_decisions(&path, &decisions).expect("decisions should be written");
+        let json = fs::read_to_string(&path).unwrap();
+        assert!(json.contains("\"decision\": \"approved\""));
+        assert_eq!(serde_json::from_str::<ReviewDecisions>(&json).unwrap(), decisions);
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Which pages of a large document to compare for a quick smoke check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sampling {
+    // Every Nth page, starting with the first
+    Every(usize),
+    // `count` pages picked at random; the same seed always picks the same pages
+    Random { count: usize, seed: u64 },
+}
+
+impl FromStr for Sampling {
+    type Err = String;
+
+    // Accepts `every:N` or `random:N` with an optional `@SEED` (default 0)
+    fn from_str(value: &str) -> Result<Sampling, String> {
+        let invalid = || format!("Invalid sampling '{}', expected 'every:N' or 'random:N[@SEED]'", value);
+
+        match value.split_once(':') {
+            Some(("every", step)) => match step.parse() {
+                Ok(step) if step > 0 => Ok(Sampling::Every(step)),
+                _ => Err(invalid()),
+            },
+            Some(("random", spec)) => {
+                let (count, seed) = spec.split_once('@').unwrap_or((spec, "0"));
+                match (count.parse(), seed.parse()) {
+                    (Ok(count), Ok(seed)) => Ok(Sampling::Random { count, seed }),
+                    _ => Err(invalid()),
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Sampling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sampling::Every(step) => write!(f, "every:{}", step),
+            Sampling::Random { count, seed } => write!(f, "random:{}@{}", count, seed),
+        }
+    }
+}
+
+// SplitMix64; good enough for picking pages and stable across platforms and
+// releases, which a reproducible sample needs
+fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE5_E9B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// 0-based indices of the pages to compare, in page order. Sampling is applied
+// first and `max_pages` then caps the result.
+pub fn select_pages(page_count: usize, sampling: Option<Sampling>, max_pages: Option<usize>) -> Vec<usize> {
+    let mut pages: Vec<usize> = match sampling {
+        None => (0..page_count).collect(),
+        Some(Sampling::Every(step)) => (0..page_count).step_by(step.max(1)).collect(),
+        Some(Sampling::Random { count, seed }) => {
+            // Partial Fisher-Yates shuffle
+            let mut all: Vec<usize> = (0..page_count).collect();
+            let mut state = seed;
+            let count = count.min(page_count);
+            for i in 0..count {
+                let j = i + (next_random(&mut state) % (page_count - i) as u64) as usize;
+                all.swap(i, j);
+            }
+            all.truncate(count);
+            all.sort_unstable();
+            all
+        }
+    };
+
+    if let Some(max_pages) = max_pages {
+        pages.truncate(max_pages);
+    }
+    pages
+}
+
+// Explicit page list such as `1-10,12,20-`, with 1-based inclusive ranges.
+// An open-ended range runs to the last page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageRanges(Vec<(usize, Option<usize>)>);
+
+impl PageRanges {
+    // Sorted, distinct 0-based indices of the listed pages that exist
+    pub fn indices(&self, page_count: usize) -> Vec<usize> {
+        let mut pages: Vec<usize> = self
+            .0
+            .iter()
+            .flat_map(|&(first, last)| first - 1..last.unwrap_or(page_count).min(page_count))
+            .collect();
+        pages.sort_unstable();
+        pages.dedup();
+        pages
+    }
+
+    // Whether a 1-based page number is listed
+    pub fn contains(&self, page: usize) -> bool {
+        self.0.iter().any(|&(first, last)| page >= first && last.is_none_or(|last| page <= last))
+    }
+}
+
+impl FromStr for PageRanges {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<PageRanges, String> {
+        let invalid = || format!("Invalid page range '{}', expected e.g. '1-10,12,20-'", value);
+        let page = |text: &str| match text.trim().parse() {
+            Ok(page) if page > 0 => Ok(page),
+            _ => Err(invalid()),
+        };
+
+        let mut ranges = vec![];
+        for part in value.split(',') {
+            let range = match part.split_once('-') {
+                Some((first, "")) => (page(first)?, None),
+                Some((first, last)) => (page(first)?, Some(page(last)?)),
+                None => (page(part)?, Some(page(part)?)),
+            };
+            if range.1.is_some_and(|last| last < range.0) {
+                return Err(invalid());
+            }
+            ranges.push(range);
+        }
+        Ok(PageRanges(ranges))
+    }
+}
+
+// Human readable record of the page selection, kept in reports so a sampled
+// run can be reproduced
+pub fn describe_selection(
+    sampling: Option<Sampling>,
+    max_pages: Option<usize>,
+    selected: usize,
+    page_count: usize,
+) -> Option<String> {
+    let mut parts = vec![];
+    if let Some(sampling) = sampling {
+        parts.push(format!("--sample {}", sampling));
+    }
+    if let Some(max_pages) = max_pages {
+        parts.push(format!("--max-pages {}", max_pages));
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    Some(format!("{} ({} of {} pages compared)", parts.join(" "), selected, page_count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sampling() {
+        assert_eq!("every:10".parse(), Ok(Sampling::Every(10)));
+        assert_eq!("random:25@7".parse(), Ok(Sampling::Random { count: 25, seed: 7 }));
+        assert_eq!("random:25".parse(), Ok(Sampling::Random { count: 25, seed: 0 }));
+        assert!("every:0".parse::<Sampling>().is_err());
+        assert!("first:3".parse::<Sampling>().is_err());
+        assert_eq!(Sampling::Random { count: 3, seed: 9 }.to_string(), "random:3@9");
+    }
+
+    #[test]
+    fn test_select_every_and_max_pages() {
+        assert_eq!(select_pages(25, Some(Sampling::Every(10)), None), vec![0, 10, 20]);
+        assert_eq!(select_pages(25, Some(Sampling::Every(10)), Some(2)), vec![0, 10]);
+        assert_eq!(select_pages(5, None, Some(3)), vec![0, 1, 2]);
+        assert_eq!(select_pages(2, None, Some(3)), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_select_random_is_reproducible() {
+        let sampling = Some(Sampling::Random { count: 10, seed: 42 });
+        let first = select_pages(500, sampling, None);
+        assert_eq!(first, select_pages(500, sampling, None));
+        assert_eq!(first.len(), 10);
+        assert!(first.windows(2).all(|w| w[0] < w[1]), "Pages should be sorted and distinct");
+        assert_ne!(first, select_pages(500, Some(Sampling::Random { count: 10, seed: 43 }), None));
+        assert_eq!(select_pages(3, Some(Sampling::Random { count: 10, seed: 1 }), None), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_describe_selection() {
+        assert_eq!(describe_selection(None, None, 10, 10), None);
+        assert_eq!(
+            describe_selection(Some(Sampling::Every(10)), Some(2), 2, 25).as_deref(),
+            Some("--sample every:10 --max-pages 2 (2 of 25 pages compared)")
+        );
+    }
+
+    #[test]
+    fn test_page_ranges() {
+        let ranges: PageRanges = "1-3,5,9-".parse().unwrap();
+        assert_eq!(ranges.indices(10), vec![0, 1, 2, 4, 8, 9]);
+        assert_eq!(ranges.indices(2), vec![0, 1]);
+        assert_eq!("2-4,3".parse::<PageRanges>().unwrap().indices(10), vec![1, 2, 3]);
+        assert!(ranges.contains(2) && ranges.contains(5) && ranges.contains(100));
+        assert!(!ranges.contains(4) && !ranges.contains(8));
+
+        for invalid in ["", "0", "3-1", "a-b", "1,,2"] {
+            assert!(invalid.parse::<PageRanges>().is_err(), "'{}' should be rejected", invalid);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::process::Command;
+
+// OS resource limits for worker processes handling untrusted documents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SandboxLimits {
+    // Address space ceiling, in bytes; allocations beyond it fail
+    pub memory: u64,
+    // CPU time, in seconds; the worker is killed once it is used up
+    pub cpu_time: u64,
+}
+
+// Confine the process `command` starts: memory and CPU time limits, no core
+// dumps, and a network namespace of its own holding only a loopback device
+// that is down, so a hostile PDF can neither exhaust the host nor reach the
+// network. The namespace needs unprivileged user namespaces; where they are
+// disabled the worker fails to start rather than running unconfined.
+#[cfg(target_os = "linux")]
+pub fn confine(command: &mut Command, limits: SandboxLimits) -> Result<(), String> {
+    use std::os::unix::process::CommandExt;
+
+    // The child runs between fork and exec, where only plain system calls are
+    // safe, so everything it writes is prepared here
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let uid_map = format!("{0} {0} 1", uid);
+    let gid_map = format!("{0} {0} 1", gid);
+
+    unsafe {
+        command.pre_exec(move || {
+            let set_limit = |resource, value: u64| {
+                let limit = libc::rlimit {
+                    rlim_cur: value as libc::rlim_t,
+                    rlim_max: value as libc::rlim_t,
+                };
+                match libc::setrlimit(resource, &limit) {
+                    0 => Ok(()),
+                    _ => Err(std::io::Error::last_os_error()),
+                }
+            };
+            set_limit(libc::RLIMIT_AS, limits.memory)?;
+            set_limit(libc::RLIMIT_CPU, limits.cpu_time)?;
+            set_limit(libc::RLIMIT_CORE, 0)?;
+
+            if libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            // Keep the worker's own user and group inside the namespace so
+            // it can still write its outputs
+            write_file(c"/proc/self/setgroups", b"deny")?;
+            write_file(c"/proc/self/uid_map", uid_map.as_bytes())?;
+            write_file(c"/proc/self/gid_map", gid_map.as_bytes())?;
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn confine(_command: &mut Command, _limits: SandboxLimits) -> Result<(), String> {
+    Err("--sandbox is only supported on Linux".to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn write_file(path: &std::ffi::CStr, contents: &[u8]) -> std::io::Result<()> {
+    unsafe {
+        let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let written = libc::write(fd, contents.as_ptr().cast(), contents.len());
+        let error = std::io::Error::last_os_error();
+        libc::close(fd);
+        match written == contents.len() as isize {
+            true => Ok(()),
+            false => Err(error),
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confine() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "ulimit -v; ulimit -t; ulimit -c; id -u; cat /proc/net/dev"]);
+        confine(&mut command, SandboxLimits { memory: 512 * 1024 * 1024, cpu_time: 30 }).unwrap();
+
+        let output = command.output().expect("Failed to start confined process");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines[..4], ["524288", "30", "0", &unsafe { libc::getuid() }.to_string()]);
+
+        // Only the loopback device exists in the worker's network namespace
+        let devices: Vec<&str> = lines[6..].iter().filter_map(|line| line.split(':').next()).map(str::trim).collect();
+        assert_eq!(devices, vec!["lo"]);
+    }
+}
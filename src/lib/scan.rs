@@ -0,0 +1,265 @@
+use std::error::Error;
+
+use image::{DynamicImage, GrayImage, Rgba, RgbaImage};
+use pdfium_render::prelude::{PdfDocument, PdfPage, PdfPageIndex, PdfPageObject, PdfPageObjectCommon, PdfPageObjectsCommon, PdfPageTextRenderMode};
+
+use crate::pdf::PagePair;
+use crate::preprocess::{binarize, preprocess_pages, Binarization, Preprocessing};
+
+// Share of the page an image must cover for the page to be a scan
+const FULL_PAGE_COVERAGE: f32 = 0.9;
+
+// Share of the page enough for a bilevel JBIG2 or CCITT image, which only
+// scanners produce and which are often cropped to the scanned content
+const BILEVEL_COVERAGE: f32 = 0.5;
+
+// Image filters of bilevel scans
+const BILEVEL_FILTERS: [&str; 2] = ["JBIG2Decode", "CCITTFaxDecode"];
+
+// Largest offset searched for when registering scans, as a share of the page
+// width or height
+const MAX_OFFSET_SHARE: f64 = 0.02;
+
+// Which pages are compared with the scan pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ScanMode {
+    /// Pages that are a single page-sized scanned image
+    #[default]
+    Auto,
+    /// Every page
+    Always,
+    /// No page
+    Never,
+}
+
+// A page is a scan when one image covers it and everything else on it is
+// invisible, like the text layer OCR adds
+pub fn is_scanned_page(page: &PdfPage) -> bool {
+    let page_area = page.width().value * page.height().value;
+    if page_area <= 0.0 {
+        return false;
+    }
+
+    let mut scan = false;
+    for object in page.objects().iter() {
+        match &object {
+            PdfPageObject::Image(image) if !scan => {
+                let Ok(bounds) = object.bounds() else {
+                    return false;
+                };
+                let bilevel = image.filters().iter().any(|filter| BILEVEL_FILTERS.contains(&filter.name()));
+                let coverage = bounds.width().value * bounds.height().value / page_area;
+                if coverage < if bilevel { BILEVEL_COVERAGE } else { FULL_PAGE_COVERAGE } {
+                    return false;
+                }
+                scan = true;
+            }
+            PdfPageObject::Text(text) if text.render_mode() == PdfPageTextRenderMode::Invisible => {}
+            _ => return false,
+        }
+    }
+    scan
+}
+
+// Whether each position of `pairing` is compared as a scan: when either of
+// its pages is one. Positions without renders are left out.
+pub fn pair_scans<'a>(
+    old_document: &PdfDocument<'a>,
+    new_document: Option<&PdfDocument<'a>>,
+    pairing: &[(Option<usize>, Option<usize>)],
+    images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+) -> Result<Vec<bool>, Box<dyn Error>> {
+    let mut scans = vec![];
+    for ((old_image, new_image), &(old_index, new_index)) in images.iter().zip(pairing) {
+        let mut scan = false;
+        if old_image.is_some() && new_image.is_some() {
+            for (document, index) in [(Some(old_document), old_index), (new_document, new_index)] {
+                let (Some(document), Some(index)) = (document, index) else {
+                    continue;
+                };
+                scan |= is_scanned_page(&document.pages().get(index as PdfPageIndex)?);
+            }
+        }
+        scans.push(scan);
+    }
+    Ok(scans)
+}
+
+fn is_ink(pixel: &Rgba<u8>) -> bool {
+    pixel[0] == 0
+}
+
+// Remove specks from a binarized page: ink pixels with at most one inked
+// neighbour, which scanner dust and paper grain leave behind
+pub fn despeckle(image: &DynamicImage) -> DynamicImage {
+    let source = image.to_rgba8();
+    let (width, height) = source.dimensions();
+    let inked = |x: i64, y: i64| x >= 0 && y >= 0 && x < width as i64 && y < height as i64 && is_ink(source.get_pixel(x as u32, y as u32));
+
+    let mut cleaned = source.clone();
+    for (x, y, pixel) in cleaned.enumerate_pixels_mut() {
+        if !is_ink(pixel) {
+            continue;
+        }
+        let (x, y) = (x as i64, y as i64);
+        let neighbours = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)]
+            .iter()
+            .filter(|(dx, dy)| inked(x + dx, y + dy))
+            .count();
+        if neighbours <= 1 {
+            *pixel = Rgba([255, 255, 255, pixel[3]]);
+        }
+    }
+    DynamicImage::ImageRgba8(cleaned)
+}
+
+// Offset of `new` against `old` along one axis: the `d` within `max` for which
+// `new[i + d]` best matches `old[i]`, the smaller one on a tie
+fn profile_offset(old: &[u64], new: &[u64], max: i64) -> i64 {
+    let at = |profile: &[u64], i: i64| if i < 0 { 0 } else { profile.get(i as usize).copied().unwrap_or(0) };
+    let length = old.len().max(new.len()) as i64;
+    let cost = |d: i64| (-max..length + max).map(|i| at(old, i).abs_diff(at(new, i + d))).sum::<u64>();
+
+    let mut best = (0, cost(0));
+    for step in 1..=max {
+        for d in [-step, step] {
+            let cost = cost(d);
+            if cost < best.1 {
+                best = (d, cost);
+            }
+        }
+    }
+    best.0
+}
+
+// Ink per row and per column of a binarized page
+fn ink_profiles(image: &GrayImage) -> (Vec<u64>, Vec<u64>) {
+    let mut rows = vec![0; image.height() as usize];
+    let mut columns = vec![0; image.width() as usize];
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel[0] == 0 {
+            rows[y as usize] += 1;
+            columns[x as usize] += 1;
+        }
+    }
+    (rows, columns)
+}
+
+// Offset (`dx`, `dy`) in pixels at which the binarized `new` page lines up
+// with `old`, found by matching how ink is spread over rows and columns
+pub fn registration_offset(old: &DynamicImage, new: &DynamicImage) -> (i32, i32) {
+    let (old_rows, old_columns) = ink_profiles(&old.to_luma8());
+    let (new_rows, new_columns) = ink_profiles(&new.to_luma8());
+    let max = |length: usize| (length as f64 * MAX_OFFSET_SHARE).round() as i64;
+    let dx = profile_offset(&old_columns, &new_columns, max(old_columns.len().max(new_columns.len())));
+    let dy = profile_offset(&old_rows, &new_rows, max(old_rows.len().max(new_rows.len())));
+    (dx as i32, dy as i32)
+}
+
+// `image` moved so what was at (`x` + `dx`, `y` + `dy`) is at (`x`, `y`),
+// with white paper coming in at the edges
+pub fn shift(image: &DynamicImage, dx: i32, dy: i32) -> DynamicImage {
+    let source = image.to_rgba8();
+    let (width, height) = source.dimensions();
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+        let (from_x, from_y) = (x as i64 + dx as i64, y as i64 + dy as i64);
+        if from_x < 0 || from_y < 0 || from_x >= width as i64 || from_y >= height as i64 {
+            Rgba([255, 255, 255, 255])
+        } else {
+            *source.get_pixel(from_x as u32, from_y as u32)
+        }
+    }))
+}
+
+// Scan pipeline of a page: both renders binarized and despeckled, and the new
+// one moved to line up with the old one. Returns the offset it was moved by.
+pub fn tune_scan(old: &DynamicImage, new: &DynamicImage) -> (DynamicImage, DynamicImage, (i32, i32)) {
+    let old = despeckle(&binarize(old, Binarization::Otsu));
+    let new = despeckle(&binarize(new, Binarization::Otsu));
+    let (dx, dy) = registration_offset(&old, &new);
+    let new = if (dx, dy) == (0, 0) { new } else { shift(&new, dx, dy) };
+    (old, new, (dx, dy))
+}
+
+// A position compared as a scan, with the offset (`dx`, `dy`) in pixels its
+// new page was moved by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScannedPage {
+    pub position: usize,
+    pub offset: (i32, i32),
+}
+
+// Scan pipeline over the positions `scans` marks and the configured
+// preprocessing over the rest
+pub fn preprocess_with_scans(
+    mut images: Vec<PagePair>,
+    preprocessing: &Preprocessing,
+    scans: &[bool],
+) -> (Vec<PagePair>, Vec<ScannedPage>) {
+    let mut tuned = vec![];
+    for (position, pair) in images.iter_mut().enumerate() {
+        if !scans.get(position).copied().unwrap_or(false) {
+            continue;
+        }
+        if let (Some(old), Some(new)) = pair {
+            let (old, new, offset) = tune_scan(old, new);
+            tuned.push((position, offset, (Some(old), Some(new))));
+            *pair = (None, None);
+        }
+    }
+
+    let mut images = preprocess_pages(images, preprocessing);
+    let mut scanned = vec![];
+    for (position, offset, pair) in tuned {
+        images[position] = pair;
+        scanned.push(ScannedPage { position, offset });
+    }
+    (images, scanned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    // A gray scanned page with a dark block at (`x`, `y`) and dust specks
+    fn scan(x: u32, y: u32, paper: u8) -> DynamicImage {
+        let mut image = RgbaImage::from_pixel(100, 100, Rgba([paper, paper, paper, 255]));
+        for dy in 0..20 {
+            for dx in 0..30 {
+                image.put_pixel(x + dx, y + dy, Rgba([30, 30, 30, 255]));
+            }
+        }
+        for (x, y) in [(5, 90), (80, 7), (60, 60)] {
+            image.put_pixel(x, y, Rgba([20, 20, 20, 255]));
+        }
+        DynamicImage::ImageRgba8(image)
+    }
+
+    #[test]
+    fn test_despeckle() {
+        let cleaned = despeckle(&binarize(&scan(10, 10, 230), Binarization::Otsu));
+        assert_eq!(cleaned.get_pixel(60, 60), Rgba([255, 255, 255, 255]), "Specks are removed");
+        assert_eq!(cleaned.get_pixel(10, 10), Rgba([0, 0, 0, 255]), "Corners of content stay");
+    }
+
+    #[test]
+    fn test_tune_scan() {
+        // Fed in slightly lower and to the right, on other paper
+        let (old, new, offset) = tune_scan(&scan(20, 30, 230), &scan(22, 31, 210));
+        assert_eq!(offset, (2, 1));
+        assert_eq!(old.to_rgba8(), new.to_rgba8());
+        assert_eq!(shift(&new, 0, 0).to_rgba8(), new.to_rgba8());
+    }
+
+    #[test]
+    fn test_preprocess_with_scans() {
+        let born_digital = DynamicImage::ImageRgba8(RgbaImage::from_pixel(10, 10, Rgba([200, 200, 200, 255])));
+        let images = vec![(Some(born_digital.clone()), Some(born_digital)), (Some(scan(20, 30, 230)), Some(scan(21, 30, 230)))];
+
+        let (images, scanned) = preprocess_with_scans(images, &Preprocessing::default(), &[false, true]);
+        assert_eq!(scanned, vec![ScannedPage { position: 1, offset: (1, 0) }]);
+        assert_eq!(images[0].0.as_ref().unwrap().get_pixel(0, 0), Rgba([200, 200, 200, 255]), "Other pages are left alone");
+        assert_eq!(images[1].0.as_ref().unwrap().get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+    }
+}
@@ -0,0 +1,331 @@
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::metrics::{Metrics, METRICS_CONTENT_TYPE};
+
+// Path of the server-sent events stream used for live reload
+const EVENTS_PATH: &str = "/events";
+
+// Path of the Prometheus metrics endpoint
+const METRICS_PATH: &str = "/metrics";
+
+// Appended to HTML pages when live reload is active
+const RELOAD_SCRIPT: &str = "<script>new EventSource('/events').onmessage = () => location.reload();</script>";
+
+// Connected browsers waiting for a reload event. Cloning shares the client list.
+#[derive(Clone, Default)]
+pub struct LiveReload {
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl LiveReload {
+    pub fn new() -> LiveReload {
+        LiveReload::default()
+    }
+
+    fn register(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n"
+        )?;
+        stream.flush()?;
+        self.clients.lock().unwrap().push(stream);
+        Ok(())
+    }
+
+    // Tell every connected browser to reload, forgetting ones that went away
+    pub fn notify(&self) {
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain_mut(|stream| stream.write_all(b"data: reload\n\n").and_then(|_| stream.flush()).is_ok());
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("png") => "image/png",
+        Some("json") => "application/json",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("csv") => "text/csv",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+// Decode the `%XX` escapes browsers put in request paths, e.g. for non-ASCII
+// file names. `None` if the result isn't UTF-8.
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = path.get(i + 1..i + 3).filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()));
+        match (bytes[i], hex) {
+            (b'%', Some(hex)) => {
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+// Map a request path onto a file below `root`, rejecting anything that would
+// escape it, escaped or not. Directory requests fall back to the report page.
+pub fn resolve_request_path(root: &Path, url_path: &str) -> Option<PathBuf> {
+    let path = percent_decode(url_path.split(['?', '#']).next().unwrap_or(""))?;
+    let mut resolved = root.to_path_buf();
+
+    for component in Path::new(path.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    if resolved.is_dir() {
+        resolved.push(crate::report::REPORT_FILE);
+    }
+
+    Some(resolved)
+}
+
+fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)
+}
+
+fn inject_reload_script(body: Vec<u8>) -> Vec<u8> {
+    let html = String::from_utf8_lossy(&body);
+    match html.rfind("</body>") {
+        Some(index) => format!("{}{}{}", &html[..index], RELOAD_SCRIPT, &html[index..]).into_bytes(),
+        None => format!("{}{}", html, RELOAD_SCRIPT).into_bytes(),
+    }
+}
+
+// Serve a single GET request for a file below `root`. With live reload enabled,
+// HTML pages get a reload script and `/events` streams reload notifications.
+// With metrics, `/metrics` serves them for Prometheus to scrape.
+pub fn handle_connection(
+    mut stream: TcpStream,
+    root: &Path,
+    live_reload: Option<&LiveReload>,
+    metrics: Option<&Metrics>,
+) -> std::io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let url_path = parts.next().unwrap_or("/");
+
+    if method != "GET" {
+        return write_response(&mut stream, "405 Method Not Allowed", "text/plain", b"Method not allowed");
+    }
+
+    if let Some(live_reload) = live_reload
+        && url_path == EVENTS_PATH
+    {
+        return live_reload.register(stream);
+    }
+
+    if let Some(metrics) = metrics
+        && url_path == METRICS_PATH
+    {
+        return write_response(&mut stream, "200 OK", METRICS_CONTENT_TYPE, metrics.render().as_bytes());
+    }
+
+    match resolve_request_path(root, url_path) {
+        Some(path) => match std::fs::read(&path) {
+            Ok(body) => {
+                let content_type = content_type(&path);
+                let body = match live_reload {
+                    Some(_) if content_type.starts_with("text/html") => inject_reload_script(body),
+                    _ => body,
+                };
+                write_response(&mut stream, "200 OK", content_type, &body)
+            }
+            Err(_) => write_response(&mut stream, "404 Not Found", "text/plain", b"Not found"),
+        },
+        None => write_response(&mut stream, "400 Bad Request", "text/plain", b"Bad request"),
+    }
+}
+
+pub fn bind(port: u16) -> Result<TcpListener, Box<dyn Error>> {
+    TcpListener::bind(("127.0.0.1", port)).map_err(|e| format!("Failed to bind to port {}: {}", port, e).into())
+}
+
+// Serve files from `root` on an already bound listener until the process is stopped
+pub fn serve(
+    listener: TcpListener,
+    root: &str,
+    live_reload: Option<LiveReload>,
+    metrics: Option<Metrics>,
+) -> Result<(), Box<dyn Error>> {
+    let root = PathBuf::from(root);
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let root = root.clone();
+        let live_reload = live_reload.clone();
+        let metrics = metrics.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, &root, live_reload.as_ref(), metrics.as_ref()) {
+                eprintln!("Error serving request: {}", e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+// Serve files from `root` on localhost until the process is stopped
+pub fn serve_directory(root: &str, port: u16, metrics: Option<Metrics>) -> Result<(), Box<dyn Error>> {
+    serve(bind(port)?, root, None, metrics)
+}
+
+// Open a URL in the platform's default browser
+pub fn open_in_browser(url: &str) -> Result<(), Box<dyn Error>> {
+    let status = if cfg!(target_os = "macos") {
+        Command::new("open").arg(url).status()?
+    } else if cfg!(target_os = "windows") {
+        Command::new("cmd").args(["/C", "start", "", url]).status()?
+    } else {
+        Command::new("xdg-open").arg(url).status()?
+    };
+
+    if !status.success() {
+        return Err(format!("Failed to open browser for {}", url).into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Read;
+
+    #[test]
+    fn test_resolve_request_path_rejects_traversal() {
+        let root = Path::new("some_root");
+        assert!(resolve_request_path(root, "/../secret").is_none());
+        assert_eq!(
+            resolve_request_path(root, "/report/page1_old.png?v=1"),
+            Some(PathBuf::from("some_root/report/page1_old.png"))
+        );
+        assert_eq!(
+            resolve_request_path(root, "/a_vs_%E5%A5%91%E7%B4%84%E6%9B%B8/report%20old.png"),
+            Some(PathBuf::from("some_root/a_vs_契約書/report old.png"))
+        );
+        assert!(resolve_request_path(root, "/%2E%2E/secret").is_none());
+    }
+
+    #[test]
+    fn test_handle_connection_serves_file() {
+        let test_dir = "test_output_server";
+        fs::create_dir_all(test_dir).unwrap();
+        fs::write(format!("{}/report.html", test_dir), "<html>ok</html>").unwrap();
+
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handle_connection(stream, Path::new(test_dir), None, None).unwrap();
+        });
+
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        client.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        server.join().unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("text/html"));
+        assert!(response.ends_with("<html>ok</html>"));
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+
+    #[test]
+    fn test_handle_connection_serves_metrics() {
+        let metrics = Metrics::new();
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server_metrics = metrics.clone();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handle_connection(stream, Path::new("."), None, Some(&server_metrics)).unwrap();
+        });
+
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        client.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        server.join().unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("pdf_diff_comparisons_total 0"));
+    }
+
+    #[test]
+    fn test_inject_reload_script() {
+        let html = inject_reload_script(b"<html><body>x</body></html>".to_vec());
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.ends_with(&format!("x{}</body></html>", RELOAD_SCRIPT)));
+    }
+
+    #[test]
+    fn test_live_reload_notifies_clients() {
+        let live_reload = LiveReload::new();
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server_reload = live_reload.clone();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handle_connection(stream, Path::new("."), Some(&server_reload), None).unwrap();
+        });
+
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        client.write_all(b"GET /events HTTP/1.1\r\n\r\n").unwrap();
+        server.join().unwrap();
+        assert_eq!(live_reload.client_count(), 1);
+
+        live_reload.notify();
+        let mut reader = BufReader::new(client);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            if line.starts_with("data:") {
+                break;
+            }
+        }
+        assert_eq!(line.trim_end(), "data: reload");
+    }
+}
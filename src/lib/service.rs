@@ -0,0 +1,107 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use tokio::task::spawn_blocking;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+
+use crate::page_diff::{diff_one_page, diff_pages, PageDiff};
+use crate::pdf::{create_images_from_pdf_with_options, load_pdf_documents, PagePair, RenderOptions};
+use crate::pdfium_pool::PdfiumPool;
+
+// Pages buffered ahead of a slow consumer of `diff_page_stream`
+const STREAM_BUFFER: usize = 4;
+
+// Rendering and diffing are CPU-bound, so everything here runs on tokio's
+// blocking pool and the runtime's worker threads stay free for I/O. Errors
+// are strings because the library's boxed errors can't cross threads.
+
+// Render both documents' pages with the settings a diff would use. Renders
+// for concurrent requests take turns with the pool's PDFium.
+pub async fn render_pages_async(
+    pool: Arc<PdfiumPool>,
+    old_pdf: PathBuf,
+    new_pdf: PathBuf,
+    options: RenderOptions,
+) -> Result<Vec<PagePair>, String> {
+    spawn_blocking(move || {
+        let pdfium = pool.lease();
+        let (old_document, new_document) = load_pdf_documents(&pdfium, &old_pdf, &new_pdf).map_err(|e| e.to_string())?;
+        create_images_from_pdf_with_options(&old_document, &new_document, &options).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("Render task failed: {}", e))?
+}
+
+// Diff every page, like `page_diff::diff_pages`
+pub async fn diff_pages_async(
+    images: Vec<PagePair>,
+    sensitivity: f32,
+) -> Result<Vec<PageDiff>, String> {
+    spawn_blocking(move || diff_pages(&images, sensitivity).map_err(|e| e.to_string()))
+        .await
+        .map_err(|e| format!("Diff task failed: {}", e))?
+}
+
+// Diff pages one at a time, yielding each result as soon as it is ready so a
+// service can respond page by page. The stream ends after the first error;
+// dropping it stops the remaining work.
+pub fn diff_page_stream(
+    images: Vec<PagePair>,
+    sensitivity: f32,
+) -> impl Stream<Item = Result<PageDiff, String>> {
+    let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+
+    spawn_blocking(move || {
+        for (index, (old_image, new_image)) in images.iter().enumerate() {
+            let result = match diff_one_page(index, old_image.as_ref(), new_image.as_ref(), sensitivity) {
+                Ok(Some(page)) => Ok(page),
+                Ok(None) => continue,
+                Err(e) => Err(format!("Failed to diff page {}: {}", index + 1, e)),
+            };
+            let failed = result.is_err();
+            if sender.blocking_send(result).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    ReceiverStream::new(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::create_solid_color_image;
+    use image::Rgba;
+    use tokio_stream::StreamExt;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread().build().unwrap()
+    }
+
+    #[test]
+    fn test_diff_page_stream() {
+        let solid = |color| Some(create_solid_color_image(8, 8, Rgba(color)));
+        let (red, green) = (solid([255, 0, 0, 255]), solid([0, 255, 0, 255]));
+        let images = vec![(red.clone(), red.clone()), (None, None), (red.clone(), green.clone()), (None, green)];
+
+        let pages: Vec<usize> = runtime().block_on(async {
+            diff_page_stream(images, 0.12).map(|result| result.unwrap().page).collect().await
+        });
+        assert_eq!(pages, vec![1, 3, 4]);
+    }
+
+    #[test]
+    fn test_diff_pages_async_matches_sync() {
+        let solid = |color| Some(create_solid_color_image(8, 8, Rgba(color)));
+        let images = vec![(solid([255, 0, 0, 255]), solid([0, 0, 255, 255]))];
+        let pages = runtime().block_on(diff_pages_async(images.clone(), 0.12)).unwrap();
+        let expected = diff_pages(&images, 0.12).unwrap();
+
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].ratio, expected[0].ratio);
+        assert_eq!(pages[0].image, expected[0].image);
+    }
+}
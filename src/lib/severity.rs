@@ -0,0 +1,279 @@
+use std::fmt;
+use std::str::FromStr;
+
+use image::{DynamicImage, GrayImage};
+
+use crate::regions::{change_ratio, find_changed_regions, PIXEL_TOLERANCE};
+
+// Changed-pixel ratio at which the pixel signal is at its highest
+const RATIO_SATURATION: f64 = 0.1;
+
+// Number of changed regions at which the region signal is at its highest
+const REGION_SATURATION: f64 = 10.0;
+
+// Side of the square windows SSIM is computed over
+const SSIM_WINDOW: u32 = 8;
+
+// Largest offset, in pixels, a misregistered render is searched for at
+const MAX_SHIFT: i32 = 2;
+
+// How much each signal counts towards a page's severity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeverityWeights {
+    pub pixels: f64,
+    pub ssim: f64,
+    pub regions: f64,
+    pub registration: f64,
+}
+
+impl Default for SeverityWeights {
+    // Misregistration is the most common false alarm, so whether a shift
+    // explains the change counts double
+    fn default() -> SeverityWeights {
+        SeverityWeights {
+            pixels: 1.0,
+            ssim: 1.0,
+            regions: 1.0,
+            registration: 2.0,
+        }
+    }
+}
+
+impl FromStr for SeverityWeights {
+    type Err = String;
+
+    // Accepts comma-separated `signal=weight` pairs, e.g. `ssim=2,regions=0`.
+    // Signals left out keep their default weight.
+    fn from_str(value: &str) -> Result<SeverityWeights, String> {
+        let mut weights = SeverityWeights::default();
+        for pair in value.split(',').filter(|pair| !pair.trim().is_empty()) {
+            let Some((name, weight)) = pair.split_once('=') else {
+                return Err(format!("Invalid severity weight '{}', expected SIGNAL=WEIGHT", pair));
+            };
+            let weight: f64 = match weight.trim().parse() {
+                Ok(weight) if weight >= 0.0 && f64::is_finite(weight) => weight,
+                _ => return Err(format!("Invalid weight '{}' for {}, expected a number of at least 0", weight, name)),
+            };
+            match name.trim() {
+                "pixels" => weights.pixels = weight,
+                "ssim" => weights.ssim = weight,
+                "regions" => weights.regions = weight,
+                "registration" => weights.registration = weight,
+                name => {
+                    return Err(format!("Unknown signal '{}', expected pixels, ssim, regions or registration", name));
+                }
+            }
+        }
+        if weights.total() == 0.0 {
+            return Err("At least one severity weight must be above 0".to_string());
+        }
+        Ok(weights)
+    }
+}
+
+impl fmt::Display for SeverityWeights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pixels={},ssim={},regions={},registration={}",
+            self.pixels, self.ssim, self.regions, self.registration
+        )
+    }
+}
+
+impl SeverityWeights {
+    fn total(&self) -> f64 {
+        self.pixels + self.ssim + self.regions + self.registration
+    }
+}
+
+// What a page's change looks like, each signal from 0 (nothing) to 1 (as
+// bad as it gets)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Signals {
+    // Share of changed pixels, saturating at `RATIO_SATURATION`
+    pub pixels: f64,
+    // Structural dissimilarity, 1 - SSIM
+    pub ssim: f64,
+    // Number of changed regions, saturating at `REGION_SATURATION`
+    pub regions: f64,
+    // Share of the changed pixels still changed after moving the new render
+    // by the offset of up to `MAX_SHIFT` pixels that matches best. Low when
+    // the pages only differ by being misregistered.
+    pub registration: f64,
+}
+
+impl Signals {
+    // Signals of a compared page; all 0 when no pixel changed
+    pub fn of(old: &DynamicImage, new: &DynamicImage) -> Signals {
+        let ratio = change_ratio(old, new, PIXEL_TOLERANCE);
+        if ratio == 0.0 {
+            return Signals { pixels: 0.0, ssim: 0.0, regions: 0.0, registration: 0.0 };
+        }
+        let (old_luma, new_luma) = (old.to_luma8(), new.to_luma8());
+        Signals {
+            pixels: (ratio / RATIO_SATURATION).min(1.0),
+            ssim: (1.0 - ssim(&old_luma, &new_luma)).clamp(0.0, 1.0),
+            regions: (find_changed_regions(old, new, PIXEL_TOLERANCE).len() as f64 / REGION_SATURATION).min(1.0),
+            registration: unexplained_by_shift(&old_luma, &new_luma),
+        }
+    }
+
+    // Weighted mean of the signals, from 0 to 1
+    pub fn severity(&self, weights: &SeverityWeights) -> f64 {
+        let total = weights.total();
+        if total == 0.0 {
+            return 0.0;
+        }
+        (self.pixels * weights.pixels + self.ssim * weights.ssim + self.regions * weights.regions + self.registration * weights.registration)
+            / total
+    }
+}
+
+// Luma at a pixel, white outside the image so pages of different sizes are
+// compared over both their areas
+fn luma_at(image: &GrayImage, x: i64, y: i64) -> f64 {
+    if x < 0 || y < 0 || x >= image.width() as i64 || y >= image.height() as i64 {
+        return 255.0;
+    }
+    image.get_pixel(x as u32, y as u32).0[0] as f64
+}
+
+// Mean structural similarity of two grayscale renders over square windows,
+// from 1 for identical renders down to 0 or below
+pub fn ssim(old: &GrayImage, new: &GrayImage) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+    let (width, height) = (old.width().max(new.width()), old.height().max(new.height()));
+
+    let mut total = 0.0;
+    let mut windows = 0;
+    for top in (0..height).step_by(SSIM_WINDOW as usize) {
+        for left in (0..width).step_by(SSIM_WINDOW as usize) {
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            let mut count = 0.0;
+            for y in top..(top + SSIM_WINDOW).min(height) {
+                for x in left..(left + SSIM_WINDOW).min(width) {
+                    let (a, b) = (luma_at(old, x as i64, y as i64), luma_at(new, x as i64, y as i64));
+                    sum_a += a;
+                    sum_b += b;
+                    sum_aa += a * a;
+                    sum_bb += b * b;
+                    sum_ab += a * b;
+                    count += 1.0;
+                }
+            }
+            let (mean_a, mean_b) = (sum_a / count, sum_b / count);
+            let variance_a = sum_aa / count - mean_a * mean_a;
+            let variance_b = sum_bb / count - mean_b * mean_b;
+            let covariance = sum_ab / count - mean_a * mean_b;
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (variance_a + variance_b + C2));
+            windows += 1;
+        }
+    }
+    if windows == 0 { 1.0 } else { total / windows as f64 }
+}
+
+// Pixels that differ with the new render moved by (`dx`, `dy`)
+fn changed_at_shift(old: &GrayImage, new: &GrayImage, dx: i64, dy: i64) -> u64 {
+    let (width, height) = (old.width().max(new.width()), old.height().max(new.height()));
+    let mut changed = 0;
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            if (luma_at(old, x, y) - luma_at(new, x + dx, y + dy)).abs() > PIXEL_TOLERANCE as f64 {
+                changed += 1;
+            }
+        }
+    }
+    changed
+}
+
+// Share of the changed pixels that moving the new render by up to
+// `MAX_SHIFT` pixels doesn't explain. No shift explains a change of hue
+// that leaves brightness alone.
+fn unexplained_by_shift(old: &GrayImage, new: &GrayImage) -> f64 {
+    let unshifted = changed_at_shift(old, new, 0, 0);
+    if unshifted == 0 {
+        return 1.0;
+    }
+    let mut best = unshifted;
+    for dy in -MAX_SHIFT..=MAX_SHIFT {
+        for dx in -MAX_SHIFT..=MAX_SHIFT {
+            if (dx, dy) != (0, 0) {
+                best = best.min(changed_at_shift(old, new, dx as i64, dy as i64));
+            }
+        }
+    }
+    best as f64 / unshifted as f64
+}
+
+// Severity of every page, from 0 (unchanged) to 1. A page found in only one
+// document is as severe as it gets; positions that weren't compared are 0.
+pub fn page_severities(images: &[(Option<DynamicImage>, Option<DynamicImage>)], weights: &SeverityWeights) -> Vec<f64> {
+    images
+        .iter()
+        .map(|pair| match pair {
+            (Some(old), Some(new)) => Signals::of(old, new).severity(weights),
+            (Some(_), None) | (None, Some(_)) => 1.0,
+            (None, None) => 0.0,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    // White page with a black bar at (`x`, `y`)
+    fn page(bars: &[(u32, u32)]) -> DynamicImage {
+        let mut image = RgbaImage::from_pixel(64, 64, Rgba([255, 255, 255, 255]));
+        for &(x, y) in bars {
+            for dy in 0..4 {
+                for dx in 0..20 {
+                    image.put_pixel(x + dx, y + dy, Rgba([0, 0, 0, 255]));
+                }
+            }
+        }
+        DynamicImage::ImageRgba8(image)
+    }
+
+    #[test]
+    fn test_signals() {
+        let old = page(&[(10, 10), (10, 30)]);
+        assert_eq!(Signals::of(&old, &old).severity(&SeverityWeights::default()), 0.0);
+        assert!((1.0 - ssim(&old.to_luma8(), &old.to_luma8())).abs() < 1e-9);
+
+        // Moved by a pixel: many changed pixels, all explained by the shift
+        let shifted = Signals::of(&old, &page(&[(11, 11), (11, 31)]));
+        assert_eq!(shifted.registration, 0.0);
+        assert!(shifted.pixels > 0.0);
+
+        // A new bar: fewer changed pixels, none explained by a shift
+        let edited = Signals::of(&old, &page(&[(10, 10), (10, 30), (30, 50)]));
+        assert_eq!(edited.registration, 1.0);
+        assert!(edited.ssim > 0.0);
+        assert!(edited.severity(&SeverityWeights::default()) > shifted.severity(&SeverityWeights::default()));
+
+        // Ranking by pixels alone gets it the other way round
+        let pixels_only: SeverityWeights = "pixels=1,ssim=0,regions=0,registration=0".parse().unwrap();
+        assert!(edited.severity(&pixels_only) < shifted.severity(&pixels_only));
+
+        let severities = page_severities(&[(Some(old.clone()), None), (None, None), (Some(old.clone()), Some(old))], &SeverityWeights::default());
+        assert_eq!(severities, vec![1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_severity_weights_from_str() {
+        let weights: SeverityWeights = "ssim=2, regions=0".parse().unwrap();
+        assert_eq!(weights, SeverityWeights { ssim: 2.0, regions: 0.0, ..SeverityWeights::default() });
+        assert_eq!(weights.to_string(), "pixels=1,ssim=2,regions=0,registration=2");
+        assert_eq!("".parse(), Ok(SeverityWeights::default()));
+
+        assert!("color=1".parse::<SeverityWeights>().unwrap_err().contains("Unknown signal 'color'"));
+        assert!("ssim".parse::<SeverityWeights>().is_err());
+        assert!("ssim=-1".parse::<SeverityWeights>().is_err());
+        assert!("pixels=0,ssim=0,regions=0,registration=0".parse::<SeverityWeights>().unwrap_err().contains("above 0"));
+    }
+}
@@ -0,0 +1,556 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::path::Path;
+
+use crate::report::PageWarning;
+
+// Colorants of every press that aren't spot inks
+const PROCESS_COLORANTS: [&str; 6] = ["Cyan", "Magenta", "Yellow", "Black", "All", "None"];
+
+// How deep resources may nest (forms in forms, patterns in forms) before the
+// rest is ignored, in case a malformed file nests them forever
+const MAX_DEPTH: usize = 16;
+
+// A PDF object, as far as spot colors need one
+#[derive(Debug, Clone, PartialEq)]
+enum Object {
+    Null,
+    Number(f64),
+    Name(String),
+    String,
+    Array(Vec<Object>),
+    Dict(BTreeMap<String, Object>),
+    Stream(BTreeMap<String, Object>, Vec<u8>),
+    Ref(u32),
+}
+
+impl Object {
+    fn dict(&self) -> Option<&BTreeMap<String, Object>> {
+        match self {
+            Object::Dict(dict) | Object::Stream(dict, _) => Some(dict),
+            _ => None,
+        }
+    }
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\r' | b'\n' | b'\x0c' | b'\0')
+}
+
+fn is_delimiter(byte: u8) -> bool {
+    matches!(byte, b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%')
+}
+
+// Reads objects out of PDF syntax
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Parser<'a> {
+        Parser { data, pos }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&byte) = self.data.get(self.pos) {
+            if byte == b'%' {
+                while self.data.get(self.pos).is_some_and(|&byte| byte != b'\n' && byte != b'\r') {
+                    self.pos += 1;
+                }
+            } else if is_whitespace(byte) {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn token(&mut self) -> &'a [u8] {
+        let start = self.pos;
+        while self.data.get(self.pos).is_some_and(|&byte| !is_whitespace(byte) && !is_delimiter(byte)) {
+            self.pos += 1;
+        }
+        &self.data[start..self.pos]
+    }
+
+    fn eat(&mut self, keyword: &[u8]) -> bool {
+        self.skip_whitespace();
+        let end = self.pos + keyword.len();
+        let bounded = self.data.get(end).is_none_or(|&byte| is_whitespace(byte) || is_delimiter(byte));
+        if self.data.get(self.pos..end) == Some(keyword) && bounded {
+            self.pos = end;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Name after its `/`, with `#xx` escapes decoded
+    fn name(&mut self) -> String {
+        let raw = self.token();
+        let mut bytes = vec![];
+        let mut i = 0;
+        while i < raw.len() {
+            let escaped = (raw[i] == b'#')
+                .then(|| raw.get(i + 1..i + 3))
+                .flatten()
+                .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+            match escaped {
+                Some(byte) => {
+                    bytes.push(byte);
+                    i += 3;
+                }
+                None => {
+                    bytes.push(raw[i]);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    fn skip_literal_string(&mut self) {
+        let mut depth = 0;
+        while let Some(&byte) = self.data.get(self.pos) {
+            self.pos += 1;
+            match byte {
+                b'\\' => self.pos += 1,
+                b'(' => depth += 1,
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn object(&mut self) -> Option<Object> {
+        self.skip_whitespace();
+        let byte = *self.data.get(self.pos)?;
+        match byte {
+            b'/' => {
+                self.pos += 1;
+                Some(Object::Name(self.name()))
+            }
+            b'(' => {
+                self.skip_literal_string();
+                Some(Object::String)
+            }
+            b'<' if self.data.get(self.pos + 1) == Some(&b'<') => {
+                self.pos += 2;
+                let mut dict = BTreeMap::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.data.get(self.pos)? {
+                        b'>' => {
+                            self.pos += 2;
+                            break;
+                        }
+                        b'/' => {
+                            self.pos += 1;
+                            let key = self.name();
+                            let value = self.object()?;
+                            dict.insert(key, value);
+                        }
+                        _ => return None,
+                    }
+                }
+                if self.eat(b"stream") {
+                    return Some(self.stream(dict));
+                }
+                Some(Object::Dict(dict))
+            }
+            b'<' => {
+                self.pos += self.data[self.pos..].iter().position(|&byte| byte == b'>')? + 1;
+                Some(Object::String)
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut items = vec![];
+                loop {
+                    self.skip_whitespace();
+                    if *self.data.get(self.pos)? == b']' {
+                        self.pos += 1;
+                        return Some(Object::Array(items));
+                    }
+                    items.push(self.object()?);
+                }
+            }
+            b'+' | b'-' | b'.' | b'0'..=b'9' => {
+                let number: f64 = std::str::from_utf8(self.token()).ok()?.parse().ok()?;
+                // `12 0 R` is a reference
+                let after_number = self.pos;
+                if number.fract() == 0.0 && number >= 0.0 {
+                    self.skip_whitespace();
+                    let generation = self.token();
+                    if !generation.is_empty() && generation.iter().all(u8::is_ascii_digit) && self.eat(b"R") {
+                        return Some(Object::Ref(number as u32));
+                    }
+                }
+                self.pos = after_number;
+                Some(Object::Number(number))
+            }
+            _ => {
+                let keyword = self.token();
+                match keyword {
+                    b"true" | b"false" | b"null" => Some(Object::Null),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    // Stream data following its dictionary and the `stream` keyword
+    fn stream(&mut self, dict: BTreeMap<String, Object>) -> Object {
+        if self.data.get(self.pos) == Some(&b'\r') {
+            self.pos += 1;
+        }
+        if self.data.get(self.pos) == Some(&b'\n') {
+            self.pos += 1;
+        }
+        let start = self.pos;
+        let declared = match dict.get("Length") {
+            Some(Object::Number(length)) => Some(start + *length as usize),
+            _ => None,
+        };
+        let end = match declared {
+            Some(end) if self.data.get(end..).is_some_and(|rest| rest.trim_ascii_start().starts_with(b"endstream")) => end,
+            _ => find(self.data, b"endstream", start).unwrap_or(self.data.len()),
+        };
+        self.pos = end;
+        self.eat(b"endstream");
+        Object::Stream(dict, self.data[start..end].to_vec())
+    }
+}
+
+fn find(data: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?.windows(needle.len()).position(|window| window == needle).map(|at| from + at)
+}
+
+// Start of the `N G obj` header ending just before `at`, if there is one
+fn object_number_before(data: &[u8], at: usize) -> Option<(u32, usize)> {
+    let mut i = at;
+    let skip_back = |i: &mut usize, test: fn(u8) -> bool| {
+        let end = *i;
+        while *i > 0 && test(data[*i - 1]) {
+            *i -= 1;
+        }
+        end - *i
+    };
+    if skip_back(&mut i, is_whitespace) == 0 || skip_back(&mut i, |byte| byte.is_ascii_digit()) == 0 {
+        return None;
+    }
+    if skip_back(&mut i, is_whitespace) == 0 {
+        return None;
+    }
+    let number_end = i;
+    if skip_back(&mut i, |byte| byte.is_ascii_digit()) == 0 || (i > 0 && !is_whitespace(data[i - 1]) && !is_delimiter(data[i - 1])) {
+        return None;
+    }
+    let number = std::str::from_utf8(&data[i..number_end]).ok()?.parse().ok()?;
+    Some((number, i))
+}
+
+fn decoded(dict: &BTreeMap<String, Object>, data: &[u8]) -> Option<Vec<u8>> {
+    match dict.get("Filter") {
+        None => Some(data.to_vec()),
+        Some(Object::Name(filter)) if filter == "FlateDecode" => miniz_oxide::inflate::decompress_to_vec_zlib(data).ok(),
+        Some(Object::Array(filters)) if filters == &[Object::Name("FlateDecode".to_string())] => {
+            miniz_oxide::inflate::decompress_to_vec_zlib(data).ok()
+        }
+        _ => None,
+    }
+}
+
+// Every object of a PDF by number. Objects are read in file order, so
+// incremental updates replace what they change.
+fn read_objects(data: &[u8]) -> HashMap<u32, Object> {
+    let mut objects = HashMap::new();
+    let mut pos = 0;
+    while let Some(at) = find(data, b"obj", pos) {
+        pos = at + 3;
+        let bounded = data.get(at + 3).is_none_or(|&byte| is_whitespace(byte) || is_delimiter(byte));
+        let Some((number, _)) = object_number_before(data, at).filter(|_| bounded) else {
+            continue;
+        };
+        let mut parser = Parser::new(data, at + 3);
+        let Some(object) = parser.object() else {
+            continue;
+        };
+        // Skipping past the object keeps stream data from being read as objects
+        pos = parser.pos;
+
+        if let Object::Stream(dict, stream) = &object
+            && dict.get("Type") == Some(&Object::Name("ObjStm".to_string()))
+        {
+            objects.extend(object_stream(dict, stream));
+        }
+        objects.insert(number, object);
+    }
+    objects
+}
+
+// Objects packed into an object stream
+fn object_stream(dict: &BTreeMap<String, Object>, stream: &[u8]) -> Vec<(u32, Object)> {
+    let (Some(Object::Number(count)), Some(Object::Number(first)), Some(data)) = (dict.get("N"), dict.get("First"), decoded(dict, stream))
+    else {
+        return vec![];
+    };
+    let mut header = Parser::new(&data, 0);
+    let mut objects = vec![];
+    for _ in 0..*count as usize {
+        let (Some(Object::Number(number)), Some(Object::Number(offset))) = (header.object(), header.object()) else {
+            break;
+        };
+        if let Some(object) = Parser::new(&data, *first as usize + offset as usize).object() {
+            objects.push((number as u32, object));
+        }
+    }
+    objects
+}
+
+// Spot color names of the pages of a PDF
+struct Inks<'a> {
+    objects: &'a HashMap<u32, Object>,
+}
+
+impl<'a> Inks<'a> {
+    fn resolve(&self, object: &'a Object) -> &'a Object {
+        let mut object = object;
+        // References to references are legal, if odd
+        for _ in 0..MAX_DEPTH {
+            match object {
+                Object::Ref(number) => object = self.objects.get(number).unwrap_or(&Object::Null),
+                _ => break,
+            }
+        }
+        object
+    }
+
+    fn get(&self, dict: &'a BTreeMap<String, Object>, key: &str) -> &'a Object {
+        dict.get(key).map_or(&Object::Null, |object| self.resolve(object))
+    }
+
+    // Spot inks of a color space: `[/Separation /Name alt tint]`,
+    // `[/DeviceN [names] alt tint ...]`, or one of those as the base of an
+    // indexed or pattern color space
+    fn color_space(&self, space: &'a Object, inks: &mut BTreeSet<String>, depth: usize) {
+        let Object::Array(items) = self.resolve(space) else {
+            return;
+        };
+        match items.first().map(|family| self.resolve(family)) {
+            Some(Object::Name(family)) if family == "Separation" => {
+                if let Some(Object::Name(name)) = items.get(1).map(|name| self.resolve(name)) {
+                    inks.insert(name.clone());
+                }
+            }
+            Some(Object::Name(family)) if family == "DeviceN" => {
+                if let Some(Object::Array(names)) = items.get(1).map(|names| self.resolve(names)) {
+                    for name in names {
+                        if let Object::Name(name) = self.resolve(name) {
+                            inks.insert(name.clone());
+                        }
+                    }
+                }
+            }
+            Some(Object::Name(family)) if (family == "Indexed" || family == "Pattern") && depth < MAX_DEPTH => {
+                if let Some(base) = items.get(1) {
+                    self.color_space(base, inks, depth + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Spot inks of a resource dictionary: its color spaces, the color spaces
+    // of its images and shadings, and the resources of its forms and patterns
+    fn resources(&self, resources: &'a Object, inks: &mut BTreeSet<String>, seen: &mut HashSet<*const BTreeMap<String, Object>>, depth: usize) {
+        let Some(resources) = self.resolve(resources).dict() else {
+            return;
+        };
+        if depth > MAX_DEPTH || !seen.insert(resources) {
+            return;
+        }
+
+        if let Some(spaces) = self.get(resources, "ColorSpace").dict() {
+            for space in spaces.values() {
+                self.color_space(space, inks, depth);
+            }
+        }
+        for category in ["XObject", "Pattern", "Shading"] {
+            let Some(entries) = self.get(resources, category).dict() else {
+                continue;
+            };
+            for entry in entries.values() {
+                let Some(dict) = self.resolve(entry).dict() else {
+                    continue;
+                };
+                self.color_space(self.get(dict, "ColorSpace"), inks, depth);
+                if let Some(shading) = self.get(dict, "Shading").dict() {
+                    self.color_space(self.get(shading, "ColorSpace"), inks, depth);
+                }
+                if let Some(resources) = dict.get("Resources") {
+                    self.resources(resources, inks, seen, depth + 1);
+                }
+            }
+        }
+    }
+
+    // Pages of a page tree node in order, each with the resources it has or
+    // inherits
+    fn pages(&self, node: &'a Object, inherited: Option<&'a Object>, pages: &mut Vec<Option<&'a Object>>, depth: usize) {
+        let Some(dict) = self.resolve(node).dict() else {
+            return;
+        };
+        let resources = dict.get("Resources").or(inherited);
+        match self.get(dict, "Kids") {
+            Object::Array(kids) if depth < MAX_DEPTH => {
+                for kid in kids {
+                    self.pages(kid, resources, pages, depth + 1);
+                }
+            }
+            _ => pages.push(resources),
+        }
+    }
+}
+
+// Spot inks each page of a PDF file uses, in page order, read from the color
+// spaces its resources define. Process colorants (cyan, magenta, yellow,
+// black) and the `All` and `None` pseudo-inks aren't spot inks.
+pub fn spot_colors_of_bytes(data: &[u8]) -> Result<Vec<BTreeSet<String>>, String> {
+    let objects = read_objects(data);
+    let inks = Inks { objects: &objects };
+    // The last catalog is the current one
+    let catalog = find_catalog(data, &objects).ok_or("No document catalog found")?;
+    let root = catalog.dict().map(|dict| inks.get(dict, "Pages")).ok_or("No page tree found")?;
+
+    let mut pages = vec![];
+    inks.pages(root, None, &mut pages, 0);
+    Ok(pages
+        .into_iter()
+        .map(|resources| {
+            let mut names = BTreeSet::new();
+            if let Some(resources) = resources {
+                inks.resources(resources, &mut names, &mut HashSet::new(), 0);
+            }
+            names.retain(|name| !PROCESS_COLORANTS.contains(&name.as_str()));
+            names
+        })
+        .collect())
+}
+
+// The catalog the trailer (or cross-reference stream) names as the root,
+// or else any catalog
+fn find_catalog<'a>(data: &[u8], objects: &'a HashMap<u32, Object>) -> Option<&'a Object> {
+    let mut root = None;
+    let mut pos = 0;
+    while let Some(at) = find(data, b"/Root", pos) {
+        pos = at + 5;
+        if let Some(Object::Ref(number)) = Parser::new(data, pos).object() {
+            root = Some(number);
+        }
+    }
+    let is_catalog = |object: &&Object| object.dict().and_then(|dict| dict.get("Type")) == Some(&Object::Name("Catalog".to_string()));
+    root.and_then(|number| objects.get(&number))
+        .filter(is_catalog)
+        .or_else(|| objects.values().find(is_catalog))
+}
+
+pub fn spot_colors(path: &Path) -> Result<Vec<BTreeSet<String>>, Box<dyn Error>> {
+    let data = std::fs::read(path)?;
+    Ok(spot_colors_of_bytes(&data)?)
+}
+
+// Spot inks only one of two sets has, as (added, removed)
+pub fn ink_changes(old: &BTreeSet<String>, new: &BTreeSet<String>) -> (Vec<String>, Vec<String>) {
+    (new.difference(old).cloned().collect(), old.difference(new).cloned().collect())
+}
+
+// A warning per compared position whose pages use different spot inks.
+// `pairing` gives the 0-based pages compared at each position; positions
+// `compared` leaves out get none.
+pub fn spot_color_warnings(
+    old: &[BTreeSet<String>],
+    new: &[BTreeSet<String>],
+    pairing: &[(Option<usize>, Option<usize>)],
+    compared: impl Fn(usize) -> bool,
+) -> Vec<PageWarning> {
+    let empty = BTreeSet::new();
+    let mut warnings = vec![];
+    for (position, &(old_index, new_index)) in pairing.iter().enumerate() {
+        if !compared(position) {
+            continue;
+        }
+        let old_inks = old_index.and_then(|index| old.get(index)).unwrap_or(&empty);
+        let new_inks = new_index.and_then(|index| new.get(index)).unwrap_or(&empty);
+        let (added, removed) = ink_changes(old_inks, new_inks);
+        for (inks, change) in [(added, "added"), (removed, "removed")] {
+            for ink in inks {
+                warnings.push(PageWarning {
+                    page: position + 1,
+                    message: format!("Spot color '{}' {}", ink, change),
+                });
+            }
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PDF: &str = "%PDF-1.7
+1 0 obj << /Type /Catalog /Pages 2 0 R >> endobj
+2 0 obj << /Type /Pages /Kids [3 0 R 4 0 R 5 0 R] /Count 3 /Resources << /ColorSpace << /CS0 6 0 R >> >> >> endobj
+3 0 obj << /Type /Page /Parent 2 0 R >> endobj
+4 0 obj << /Type /Page /Parent 2 0 R /Resources << /ColorSpace << /CS1 [/DeviceN [/Cyan /Spot#20Varnish] /DeviceCMYK 7 0 R] >>
+  /XObject << /Fm0 8 0 R >> >> >> endobj
+5 0 obj << /Type /Page /Parent 2 0 R /Resources << /Pattern << /P0 << /PatternType 2 /Shading << /ColorSpace [/Separation /All /DeviceGray 7 0 R] >> >> >> >> >> endobj
+6 0 obj [/Separation /PANTONE#20185#20C /DeviceCMYK 7 0 R] endobj
+7 0 obj << /FunctionType 2 /Domain [0 1] /C0 [0 0 0 0] /C1 [0 1 1 0] /N 1 >> endobj
+8 0 obj << /Type /XObject /Subtype /Form /Length 8 /Resources << /ColorSpace << /CS2 [/Indexed [/Separation /Gold /DeviceRGB 7 0 R] 1 <00ff>] >> >> >>
+stream
+0 0 m S
+endstream
+endobj
+trailer << /Root 1 0 R /Size 9 >>
+%%EOF
+";
+
+    #[test]
+    fn test_spot_colors() {
+        let pages = spot_colors_of_bytes(PDF.as_bytes()).unwrap();
+        let names = |page: &BTreeSet<String>| page.iter().cloned().collect::<Vec<_>>();
+        // Resources are inherited from the page tree unless a page has its own
+        assert_eq!(names(&pages[0]), vec!["PANTONE 185 C"]);
+        // Inks of forms a page draws count as the page's
+        assert_eq!(names(&pages[1]), vec!["Gold", "Spot Varnish"]);
+        assert!(pages[2].is_empty(), "`All` isn't a spot ink");
+        assert!(spot_colors_of_bytes(b"%PDF-1.7\n").is_err());
+    }
+
+    #[test]
+    fn test_spot_color_warnings() {
+        let inks = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<BTreeSet<String>>();
+        let old = vec![inks(&["PANTONE 185 C"]), inks(&["Gold"])];
+        let new = vec![inks(&["PANTONE 186 C"]), inks(&["Gold"]), inks(&["Silver"])];
+        let pairing = [(Some(0), Some(0)), (Some(1), Some(1)), (None, Some(2))];
+
+        let warnings = spot_color_warnings(&old, &new, &pairing, |_| true);
+        let messages: Vec<(usize, &str)> = warnings.iter().map(|warning| (warning.page, warning.message.as_str())).collect();
+        assert_eq!(
+            messages,
+            vec![
+                (1, "Spot color 'PANTONE 186 C' added"),
+                (1, "Spot color 'PANTONE 185 C' removed"),
+                (3, "Spot color 'Silver' added"),
+            ]
+        );
+        assert!(spot_color_warnings(&old, &new, &pairing, |position| position == 1).is_empty());
+    }
+}
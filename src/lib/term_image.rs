@@ -0,0 +1,236 @@
+use std::io::{self, Cursor, Write};
+
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+
+// Cell size assumed when the terminal doesn't report its size in pixels
+const FALLBACK_CELL_SIZE: (u32, u32) = (8, 16);
+
+// Largest payload of a single kitty graphics escape
+const KITTY_CHUNK: usize = 4096;
+
+// How images are drawn in the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ImageProtocol {
+    /// Pick one from the terminal's environment variables
+    Auto,
+    /// Kitty graphics protocol (kitty, WezTerm, Ghostty)
+    Kitty,
+    /// Sixel graphics (foot, mlterm, xterm with sixel support)
+    Sixel,
+    /// Don't draw images
+    None,
+}
+
+impl ImageProtocol {
+    // Resolve `Auto` for the terminal we are running in
+    pub fn detect(self) -> ImageProtocol {
+        match self {
+            ImageProtocol::Auto => protocol_from_env(|name| std::env::var(name).ok()),
+            protocol => protocol,
+        }
+    }
+}
+
+// Terminals don't answer capability queries reliably through a TUI's input
+// handling, so support is guessed from the variables well-known terminals set
+fn protocol_from_env(var: impl Fn(&str) -> Option<String>) -> ImageProtocol {
+    let term = var("TERM").unwrap_or_default();
+    let program = var("TERM_PROGRAM").unwrap_or_default();
+
+    if var("KITTY_WINDOW_ID").is_some()
+        || term.contains("kitty")
+        || term.contains("ghostty")
+        || program == "WezTerm"
+        || program == "ghostty"
+    {
+        ImageProtocol::Kitty
+    } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") {
+        ImageProtocol::Sixel
+    } else {
+        ImageProtocol::None
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (i, &b)| value | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(value >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+// Kitty graphics escapes displaying a PNG over `cols` x `rows` cells at the cursor
+fn kitty_escape(png: &[u8], cols: u32, rows: u32) -> String {
+    let data = base64(png);
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).expect("base64 is ASCII");
+        if i == 0 {
+            out.push_str(&format!("\x1b_Gf=100,a=T,q=2,c={},r={},m={};{}\x1b\\", cols, rows, more, chunk));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+        }
+    }
+    out
+}
+
+// Sixel escape for an image, with colors reduced to a 6x6x6 cube. Pages are
+// mostly paper and ink, so a fixed palette looks fine and keeps this simple.
+fn sixel_escape(img: &DynamicImage) -> String {
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+
+    // Blend onto white paper, then pick the nearest level per channel
+    let level = |channel: u8, alpha: u8| {
+        let blended = (channel as u32 * alpha as u32 + 255 * (255 - alpha as u32)) / 255;
+        ((blended * 5 + 127) / 255) as usize
+    };
+    let color = |x: u32, y: u32| {
+        let pixel = rgba.get_pixel(x, y);
+        level(pixel[0], pixel[3]) * 36 + level(pixel[1], pixel[3]) * 6 + level(pixel[2], pixel[3])
+    };
+
+    let mut out = format!("\x1bPq\"1;1;{};{}", width, height);
+    for index in 0..216 {
+        out.push_str(&format!("#{};2;{};{};{}", index, index / 36 * 20, index / 6 % 6 * 20, index % 6 * 20));
+    }
+
+    for top in (0..height).step_by(6) {
+        let rows = top..(top + 6).min(height);
+        let band: Vec<Vec<usize>> = (0..width).map(|x| rows.clone().map(|y| color(x, y)).collect()).collect();
+        let mut colors: Vec<usize> = band.iter().flatten().copied().collect();
+        colors.sort_unstable();
+        colors.dedup();
+
+        for (i, &index) in colors.iter().enumerate() {
+            if i > 0 {
+                out.push('$');
+            }
+            out.push_str(&format!("#{}", index));
+
+            let sixels: Vec<char> = band
+                .iter()
+                .map(|column| {
+                    let bits = column.iter().enumerate().filter(|(_, c)| **c == index).fold(0u8, |bits, (row, _)| bits | 1 << row);
+                    (63 + bits) as char
+                })
+                .collect();
+            for run in sixels.chunk_by(|a, b| a == b) {
+                match run.len() {
+                    1..=3 => run.iter().for_each(|&c| out.push(c)),
+                    len => out.push_str(&format!("!{}{}", len, run[0])),
+                }
+            }
+        }
+        out.push('-');
+    }
+
+    out.push_str("\x1b\\");
+    out
+}
+
+// Terminal cell size in pixels
+fn cell_size() -> (u32, u32) {
+    match ratatui::crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => (
+            (size.width / size.columns) as u32,
+            (size.height / size.rows) as u32,
+        ),
+        _ => FALLBACK_CELL_SIZE,
+    }
+}
+
+// Draw an image scaled to fit `cols` x `rows` cells, with its top-left corner
+// at the cursor
+pub fn draw_image(out: &mut impl Write, img: &DynamicImage, protocol: ImageProtocol, cols: u16, rows: u16) -> io::Result<()> {
+    let (cell_width, cell_height) = cell_size();
+    let (max_width, max_height) = (cols as u32 * cell_width, rows as u32 * cell_height);
+    if max_width == 0 || max_height == 0 {
+        return Ok(());
+    }
+    let fitted = img.resize(max_width, max_height, FilterType::Triangle);
+
+    match protocol {
+        ImageProtocol::Kitty => {
+            let mut png = vec![];
+            fitted
+                .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+                .map_err(io::Error::other)?;
+            let (cols, rows) = (fitted.width().div_ceil(cell_width), fitted.height().div_ceil(cell_height));
+            out.write_all(kitty_escape(&png, cols, rows).as_bytes())?;
+        }
+        ImageProtocol::Sixel => out.write_all(sixel_escape(&fitted).as_bytes())?,
+        ImageProtocol::Auto | ImageProtocol::None => {}
+    }
+    out.flush()
+}
+
+// Remove images drawn earlier. Sixel images are ordinary cell contents, so
+// redrawing the screen removes those.
+pub fn clear_images(out: &mut impl Write, protocol: ImageProtocol) -> io::Result<()> {
+    if protocol == ImageProtocol::Kitty {
+        out.write_all(b"\x1b_Ga=d,q=2\x1b\\")?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+        assert_eq!(base64(b""), "");
+    }
+
+    #[test]
+    fn test_kitty_escape_is_chunked() {
+        let escape = kitty_escape(&vec![0u8; KITTY_CHUNK], 10, 5);
+        assert!(escape.starts_with("\x1b_Gf=100,a=T,q=2,c=10,r=5,m=1;"));
+        assert!(escape.contains("\x1b_Gm=0;"));
+        assert_eq!(escape.matches("\x1b\\").count(), 2);
+    }
+
+    #[test]
+    fn test_sixel_escape() {
+        let mut img = RgbaImage::from_pixel(5, 7, Rgba([255, 0, 0, 255]));
+        img.put_pixel(0, 6, Rgba([0, 0, 0, 0]));
+        let escape = sixel_escape(&DynamicImage::ImageRgba8(img));
+
+        assert!(escape.starts_with("\x1bPq\"1;1;5;7"));
+        // All six rows of the first band are red, the second band has one row
+        assert!(escape.contains("#180!5~-"));
+        // Transparent pixels are drawn as paper
+        assert!(escape.contains("#180?!4@$#215@!4?-"));
+        assert!(escape.ends_with("-\x1b\\"));
+    }
+
+    #[test]
+    fn test_protocol_from_env() {
+        let env = |vars: &[(&str, &str)]| {
+            let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            protocol_from_env(move |name| vars.get(name).cloned())
+        };
+        assert_eq!(env(&[("TERM", "xterm-kitty")]), ImageProtocol::Kitty);
+        assert_eq!(env(&[("TERM", "xterm-256color"), ("TERM_PROGRAM", "WezTerm")]), ImageProtocol::Kitty);
+        assert_eq!(env(&[("TERM", "foot")]), ImageProtocol::Sixel);
+        assert_eq!(env(&[("TERM", "xterm-256color")]), ImageProtocol::None);
+    }
+}
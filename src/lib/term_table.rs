@@ -0,0 +1,121 @@
+use std::fmt::Write as _;
+use std::io::IsTerminal;
+
+use image::DynamicImage;
+
+use crate::report::{ComparisonSummary, PageStatus};
+use crate::tr;
+
+const RESET: &str = "\x1b[0m";
+
+// Whether to color terminal output: only on a terminal, and neither
+// `--no-color` nor a non-empty `NO_COLOR` (https://no-color.org) turns it off
+pub fn use_color(no_color: bool) -> bool {
+    let disabled = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    !no_color && !disabled && std::io::stdout().is_terminal()
+}
+
+// ANSI color of a status in the table
+fn status_color(status: PageStatus) -> &'static str {
+    match status {
+        PageStatus::Unchanged => "\x1b[32m",
+        PageStatus::Changed => "\x1b[33m",
+        PageStatus::Added => "\x1b[36m",
+        PageStatus::Removed => "\x1b[31m",
+        PageStatus::Approved => "\x1b[35m",
+        PageStatus::Blank | PageStatus::Skipped => "\x1b[2m",
+    }
+}
+
+// Table of every compared page with its status and share of changed pixels,
+// followed by a count per status. Pages left out of the comparison aren't
+// listed.
+pub fn page_table(images: &[(Option<DynamicImage>, Option<DynamicImage>)], summary: &ComparisonSummary, color: bool) -> String {
+    let rows: Vec<(usize, PageStatus, f64)> = images
+        .iter()
+        .enumerate()
+        .map(|(index, (old, new))| {
+            let status = summary.page_status(index, old.as_ref(), new.as_ref());
+            (index + 1, status, summary.page_ratios.get(index).copied().unwrap_or(0.0))
+        })
+        .filter(|(_, status, _)| *status != PageStatus::Skipped)
+        .collect();
+
+    let (page_header, status_header) = (tr!("table-page"), tr!("table-status"));
+    let page_width = rows.last().map_or(0, |(page, _, _)| page.to_string().len()).max(page_header.chars().count());
+    // Wide enough for any status, so the column doesn't depend on which pages changed
+    let status_width = PageStatus::ALL.iter().map(|status| status.display_label().chars().count()).max().unwrap_or(0);
+    let mut table = String::new();
+    writeln!(table, "{:>page_width$}  {:<status_width$}  {:>7}", page_header, status_header, tr!("table-diff")).expect("writing to a String");
+
+    let mut counts: Vec<(PageStatus, usize)> = vec![];
+    for (page, status, ratio) in rows {
+        let label = format!("{:<status_width$}", status.display_label());
+        let label = match color {
+            true => format!("{}{}{}", status_color(status), label, RESET),
+            false => label,
+        };
+        writeln!(table, "{:>page_width$}  {}  {:>6.2}%", page, label, ratio * 100.0).expect("writing to a String");
+
+        match counts.iter_mut().find(|(counted, _)| *counted == status) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((status, 1)),
+        }
+    }
+
+    let totals: Vec<String> = counts.iter().map(|(status, count)| format!("{} {}", count, status.display_label())).collect();
+    if totals.is_empty() {
+        table.push_str(&tr!("table-no-pages"));
+        table.push('\n');
+    } else {
+        table.push_str(&totals.join(", "));
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn page(color: [u8; 4]) -> Option<DynamicImage> {
+        let mut image = image::RgbaImage::from_pixel(10, 10, Rgba([255, 255, 255, 255]));
+        image.put_pixel(5, 5, Rgba(color));
+        Some(DynamicImage::ImageRgba8(image))
+    }
+
+    #[test]
+    fn test_page_table() {
+        let black = [0, 0, 0, 255];
+        let images = vec![
+            (page(black), page(black)),
+            (page(black), page([255, 0, 0, 255])),
+            (None, None),
+            (None, page(black)),
+        ];
+        let summary = ComparisonSummary::new("old.pdf", "new.pdf", "output", &images, 72.0);
+
+        let table = page_table(&images, &summary, false);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "Page  Status      Diff %",
+                "   1  unchanged    0.00%",
+                "   2  changed      1.00%",
+                "   4  added      100.00%",
+                "1 unchanged, 1 changed, 1 added",
+            ]
+        );
+
+        let colored = page_table(&images, &summary, true);
+        assert!(colored.contains("\x1b[33mchanged  \x1b[0m"));
+    }
+
+    #[test]
+    fn test_page_table_without_pages() {
+        let summary = ComparisonSummary::new("old.pdf", "new.pdf", "output", &[], 72.0);
+        assert_eq!(page_table(&[], &summary, false), "Page  Status      Diff %\nNo pages compared\n");
+    }
+}
@@ -0,0 +1,107 @@
+use std::error::Error;
+
+use pdfium_render::prelude::{PdfDocument, PdfPage, PdfPageIndex};
+use serde::Serialize;
+
+// A run of text pdfium laid out on one line with the same style. Coordinates
+// are in points from the top-left corner of the page, like page renders, so
+// multiplying by `dpi / 72` maps them onto a render.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TextSegment {
+    pub text: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageText {
+    // 1-based page number
+    pub page: usize,
+    pub text: String,
+    // Only filled in when positions were asked for
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<TextSegment>,
+}
+
+pub fn page_text(page: &PdfPage, number: usize, positions: bool) -> Result<PageText, Box<dyn Error>> {
+    let text = page.text().map_err(|e| format!("Failed to extract text from page {}: {:?}", number, e))?;
+    let page_height = page.height().value;
+
+    let segments = if positions {
+        text.segments()
+            .iter()
+            .map(|segment| {
+                let bounds = segment.bounds();
+                TextSegment {
+                    text: segment.text(),
+                    x: bounds.left().value,
+                    y: page_height - bounds.top().value,
+                    width: bounds.width().value,
+                    height: bounds.height().value,
+                }
+            })
+            .collect()
+    } else {
+        vec![]
+    };
+
+    Ok(PageText {
+        page: number,
+        text: text.all(),
+        segments,
+    })
+}
+
+// Text of the pages at the given 0-based indices
+pub fn document_text(document: &PdfDocument, pages: &[usize], positions: bool) -> Result<Vec<PageText>, Box<dyn Error>> {
+    pages
+        .iter()
+        .map(|&index| {
+            let page = document
+                .pages()
+                .get(index as PdfPageIndex)
+                .map_err(|e| format!("Failed to get page {}: {:?}", index + 1, e))?;
+            page_text(&page, index + 1, positions)
+        })
+        .collect()
+}
+
+// Plain text output, with pages separated by form feeds as pdftotext does
+pub fn plain_text(pages: &[PageText]) -> String {
+    pages.iter().map(|page| format!("{}\n\x0c", page.text.trim_end())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::{create_pdfium, load_pdf_document};
+    use std::path::Path;
+
+    #[test]
+    fn test_plain_text_separates_pages() {
+        let pages = vec![
+            PageText { page: 1, text: "First page\r\n".to_string(), segments: vec![] },
+            PageText { page: 2, text: "Second page".to_string(), segments: vec![] },
+        ];
+        assert_eq!(plain_text(&pages), "First page\n\x0cSecond page\n\x0c");
+    }
+
+    #[test]
+    fn test_document_text_positions() {
+        let pdfium = create_pdfium().expect("Failed to create Pdfium instance");
+        let doc = load_pdf_document(&pdfium, Path::new("./samples/new.pdf")).expect("Failed to load PDF document");
+        let page_height = doc.pages().get(0).unwrap().height().value;
+
+        let pages = document_text(&doc, &[0], true).expect("Text should extract");
+        assert_eq!(pages.len(), 1);
+        assert!(!pages[0].text.trim().is_empty(), "Sample page should have text");
+        for segment in &pages[0].segments {
+            assert!(segment.y >= 0.0 && segment.y + segment.height <= page_height + 1.0);
+        }
+
+        let plain = document_text(&doc, &[0], false).unwrap();
+        assert!(plain[0].segments.is_empty());
+    }
+}
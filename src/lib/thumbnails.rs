@@ -0,0 +1,92 @@
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use pdfium_render::prelude::{PdfDocument, PdfPageIndex};
+
+use crate::pdf::{render_page, PdfError, RenderOptions};
+
+// Pages are rendered at this many times the resolution that fits the box and
+// scaled down, so small text comes out smooth instead of blocky
+const OVERSAMPLE: f32 = 2.0;
+
+// Size of thumbnails in pixels: every thumbnail is exactly this size, with the
+// page fitted inside
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThumbnailSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ThumbnailSize {
+    pub fn new(width: u32, height: u32) -> ThumbnailSize {
+        ThumbnailSize { width, height }
+    }
+
+    // DPI that fits a page of `page_width` by `page_height` points in the box
+    fn fitting_dpi(self, page_width: f32, page_height: f32) -> Option<f32> {
+        if page_width <= 0.0 || page_height <= 0.0 {
+            return None;
+        }
+        Some((self.width as f32 / page_width).min(self.height as f32 / page_height) * 72.0)
+    }
+}
+
+// `page` scaled to fit within `size`, keeping its aspect ratio
+pub fn fit_thumbnail(page: &DynamicImage, size: ThumbnailSize) -> DynamicImage {
+    page.thumbnail(size.width, size.height)
+}
+
+// `page` fitted within `size` and centred on white, so thumbnails of pages of
+// any shape line up in a grid
+pub fn uniform_thumbnail(page: &DynamicImage, size: ThumbnailSize) -> DynamicImage {
+    let thumb = fit_thumbnail(page, size).to_rgba8();
+    let mut canvas = RgbaImage::from_pixel(size.width, size.height, Rgba([255, 255, 255, 255]));
+    let x = (size.width - thumb.width()) / 2;
+    let y = (size.height - thumb.height()) / 2;
+    imageops::overlay(&mut canvas, &thumb, x as i64, y as i64);
+    DynamicImage::ImageRgba8(canvas)
+}
+
+// Uniform thumbnails of every page of a single document. Each page is rendered
+// with `options` at a resolution just high enough for its thumbnail, so this
+// is much cheaper than rendering the pages for comparison; `options.dpi` is
+// only used for pages without a size.
+pub fn render_thumbnails(document: &PdfDocument, options: &RenderOptions, size: ThumbnailSize) -> Result<Vec<DynamicImage>, PdfError> {
+    let mut thumbnails = vec![];
+    for index in 0..document.pages().len() as usize {
+        // A page that can't be read fails in `render_page` with its error
+        let dpi = document
+            .pages()
+            .get(index as PdfPageIndex)
+            .ok()
+            .and_then(|page| size.fitting_dpi(page.width().value, page.height().value))
+            .map_or(options.dpi, |dpi| dpi * OVERSAMPLE);
+        let render = render_page(document, index, &RenderOptions { dpi, ..options.clone() })?;
+        thumbnails.push(uniform_thumbnail(&render, size));
+    }
+    Ok(thumbnails)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    #[test]
+    fn test_uniform_thumbnail() {
+        let size = ThumbnailSize::new(100, 100);
+        // A landscape page fills the width and is centred vertically
+        let page = DynamicImage::ImageRgba8(RgbaImage::from_pixel(400, 200, Rgba([0, 0, 0, 255])));
+        let thumb = uniform_thumbnail(&page, size);
+        assert_eq!(thumb.dimensions(), (100, 100));
+        assert_eq!(thumb.get_pixel(50, 10), Rgba([255, 255, 255, 255]));
+        assert_eq!(thumb.get_pixel(50, 50), Rgba([0, 0, 0, 255]));
+        assert_eq!(fit_thumbnail(&page, size).dimensions(), (100, 50));
+    }
+
+    #[test]
+    fn test_fitting_dpi() {
+        // US Letter is limited by its height in a square box
+        let size = ThumbnailSize::new(160, 160);
+        assert_eq!(size.fitting_dpi(612.0, 792.0), Some(160.0 / 792.0 * 72.0));
+        assert_eq!(size.fitting_dpi(0.0, 792.0), None);
+    }
+}
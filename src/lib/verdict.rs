@@ -0,0 +1,54 @@
+use std::io::Write;
+use std::process::Stdio;
+
+use crate::exit_code::Failure;
+use crate::hooks::shell;
+use crate::report::{summary_json, AggregateSummary, ComparisonSummary};
+
+// Let a user command decide whether a run passes. The command gets the run's
+// summary, as written to `summary.json`, on standard input and passes the run
+// by exiting with status 0; its output goes to the terminal so it can explain
+// itself. Acceptance rules differ from team to team, so they live in a script
+// instead of in more thresholds here. A rejection fails the run as having
+// differences.
+pub fn run_verdict_command(command_line: &str, summaries: &[ComparisonSummary]) -> Result<(), Failure> {
+    let aggregate = AggregateSummary::from_summaries(summaries);
+    let json = summary_json(&aggregate, summaries).map_err(|e| format!("Failed to describe the run for the verdict command: {}", e))?;
+
+    let mut child = shell(command_line)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start verdict command '{}': {}", command_line, e))?;
+
+    // A command that decides without reading its input closes the pipe early
+    if let Some(mut stdin) = child.stdin.take()
+        && let Err(e) = stdin.write_all(json.as_bytes())
+        && e.kind() != std::io::ErrorKind::BrokenPipe
+    {
+        return Err(format!("Failed to send the summary to the verdict command: {}", e).into());
+    }
+
+    let status = child.wait().map_err(|e| format!("Failed to run verdict command '{}': {}", command_line, e))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(Failure::differences(format!("Rejected by verdict command '{}' ({})", command_line, status))),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_verdict_command() {
+        let summary = ComparisonSummary::new("old.pdf", "new.pdf", "output", &[], 72.0);
+        let summaries = [summary];
+
+        assert!(run_verdict_command("grep -q '\"total_documents\": 1'", &summaries).is_ok());
+        assert!(run_verdict_command("true", &summaries).is_ok(), "The input doesn't have to be read");
+
+        let error = run_verdict_command("cat > /dev/null; exit 3", &summaries).unwrap_err();
+        assert!(error.message.contains("exit status: 3"), "{}", error);
+        assert_eq!(error.code, crate::exit_code::ExitCode::Differences);
+    }
+}
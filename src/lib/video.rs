@@ -0,0 +1,188 @@
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use image::{imageops, DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+use crate::image_utils::page_changed;
+use crate::label::with_caption;
+
+// How many times each page flips between old and new
+const FLIP_CYCLES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum VideoMode {
+    /// One video per changed page
+    PerPage,
+    /// A single video covering every changed page
+    Document,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum VideoFormat {
+    Mp4,
+    Webm,
+}
+
+impl VideoFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            VideoFormat::Mp4 => "mp4",
+            VideoFormat::Webm => "webm",
+        }
+    }
+
+    fn codec_args(&self) -> &'static [&'static str] {
+        match self {
+            VideoFormat::Mp4 => &["-c:v", "libx264", "-pix_fmt", "yuv420p"],
+            VideoFormat::Webm => &["-c:v", "libvpx-vp9", "-pix_fmt", "yuv420p"],
+        }
+    }
+}
+
+// Captioned old/new frames for one page, repeated so the flip is easy to follow.
+// A page missing on one side is shown as a blank sheet of the other's size.
+pub fn flip_frames(old_image: Option<&DynamicImage>, new_image: Option<&DynamicImage>, page: usize) -> Vec<DynamicImage> {
+    let (width, height) = match (old_image, new_image) {
+        (_, Some(img)) | (Some(img), None) => img.dimensions(),
+        (None, None) => return vec![],
+    };
+    let blank = DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255])));
+
+    let old_frame = match old_image {
+        Some(img) => with_caption(img, &format!("PAGE {} - OLD", page), Rgba([180, 0, 0, 255])),
+        None => with_caption(&blank, &format!("PAGE {} - OLD (MISSING)", page), Rgba([180, 0, 0, 255])),
+    };
+    let new_frame = match new_image {
+        Some(img) => with_caption(img, &format!("PAGE {} - NEW", page), Rgba([0, 130, 0, 255])),
+        None => with_caption(&blank, &format!("PAGE {} - NEW (MISSING)", page), Rgba([0, 130, 0, 255])),
+    };
+
+    let mut frames = vec![];
+    for _ in 0..FLIP_CYCLES {
+        frames.push(old_frame.clone());
+        frames.push(new_frame.clone());
+    }
+    frames
+}
+
+// Centre every frame on a white canvas of a common, even-sized resolution
+// (yuv420p encoders reject odd dimensions)
+fn normalize_frames(frames: &[DynamicImage]) -> (u32, u32, Vec<RgbaImage>) {
+    let width = frames.iter().map(|f| f.width()).max().unwrap_or(2).next_multiple_of(2);
+    let height = frames.iter().map(|f| f.height()).max().unwrap_or(2).next_multiple_of(2);
+
+    let canvases = frames
+        .iter()
+        .map(|frame| {
+            let mut canvas = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+            let x = (width - frame.width()) / 2;
+            let y = (height - frame.height()) / 2;
+            imageops::overlay(&mut canvas, &frame.to_rgba8(), x as i64, y as i64);
+            canvas
+        })
+        .collect();
+
+    (width, height, canvases)
+}
+
+// Encode frames (one second each) by piping raw RGBA data into ffmpeg
+pub fn encode_video(frames: &[DynamicImage], output_path: &Path, format: VideoFormat) -> Result<(), Box<dyn Error>> {
+    let (width, height, canvases) = normalize_frames(frames);
+
+    let mut child = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgba"])
+        .args(["-s", &format!("{}x{}", width, height), "-framerate", "1", "-i", "-"])
+        .args(format.codec_args())
+        .args(["-r", "25"])
+        .arg(output_path)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run ffmpeg (is it installed and on PATH?): {}", e))?;
+
+    {
+        let stdin = child.stdin.as_mut().ok_or("Failed to open ffmpeg stdin")?;
+        for canvas in &canvases {
+            stdin.write_all(canvas.as_raw())?;
+        }
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format!("ffmpeg exited with {} while writing {}", status, output_path.display()).into());
+    }
+
+    Ok(())
+}
+
+// Write flip videos for all changed pages, returning the paths written
+pub fn write_flip_videos(
+    images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+    pdf_title: &str,
+    output_dir: &Path,
+    mode: VideoMode,
+    format: VideoFormat,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    std::fs::create_dir_all(output_dir)?;
+    let mut written = vec![];
+    let mut document_frames = vec![];
+
+    for (index, (old_image, new_image)) in images.iter().enumerate() {
+        if !page_changed(old_image.as_ref(), new_image.as_ref()) {
+            continue;
+        }
+
+        let frames = flip_frames(old_image.as_ref(), new_image.as_ref(), index + 1);
+        match mode {
+            VideoMode::PerPage => {
+                let path = output_dir.join(format!("{}_page{}.{}", pdf_title, index + 1, format.extension()));
+                encode_video(&frames, &path, format)?;
+                written.push(path);
+            }
+            VideoMode::Document => document_frames.extend(frames),
+        }
+    }
+
+    if mode == VideoMode::Document && !document_frames.is_empty() {
+        let path = output_dir.join(format!("{}.{}", pdf_title, format.extension()));
+        encode_video(&document_frames, &path, format)?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::create_solid_color_image;
+
+    #[test]
+    fn test_flip_frames_alternate() {
+        let old = create_solid_color_image(40, 60, Rgba([255, 0, 0, 255]));
+        let new = create_solid_color_image(40, 60, Rgba([0, 255, 0, 255]));
+
+        let frames = flip_frames(Some(&old), Some(&new), 1);
+        assert_eq!(frames.len(), FLIP_CYCLES * 2);
+        assert_eq!(frames[0].width(), 40);
+        assert!(frames[0].height() > 60, "Frames should include a caption bar");
+    }
+
+    #[test]
+    fn test_flip_frames_missing_page() {
+        let new = create_solid_color_image(40, 60, Rgba([0, 255, 0, 255]));
+        let frames = flip_frames(None, Some(&new), 2);
+        assert_eq!(frames.len(), FLIP_CYCLES * 2);
+        assert!(flip_frames(None, None, 3).is_empty());
+    }
+
+    #[test]
+    fn test_normalize_frames_even_dimensions() {
+        let frames = vec![create_solid_color_image(41, 61, Rgba([0, 0, 0, 255])), create_solid_color_image(30, 70, Rgba([0, 0, 0, 255]))];
+        let (width, height, canvases) = normalize_frames(&frames);
+
+        assert_eq!((width, height), (42, 70));
+        assert!(canvases.iter().all(|c| c.dimensions() == (42, 70)));
+    }
+}
@@ -0,0 +1,76 @@
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// How often watched files are polled for modifications
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Polls a set of files for modification time changes. Polling keeps us free
+// of platform-specific notification APIs and copes with editors that replace
+// files instead of writing them in place.
+pub struct FileWatcher {
+    paths: Vec<PathBuf>,
+    last_modified: Vec<Option<SystemTime>>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl FileWatcher {
+    pub fn new(paths: &[&Path]) -> FileWatcher {
+        let paths: Vec<PathBuf> = paths.iter().map(|p| p.to_path_buf()).collect();
+        let last_modified = paths.iter().map(|p| modified(p)).collect();
+        FileWatcher { paths, last_modified }
+    }
+
+    // Returns true (and records the new state) if any file changed since the last check
+    pub fn poll(&mut self) -> bool {
+        let current: Vec<Option<SystemTime>> = self.paths.iter().map(|p| modified(p)).collect();
+        if current == self.last_modified {
+            return false;
+        }
+        self.last_modified = current;
+        true
+    }
+
+    // Block until a change is seen and the files have stopped changing, so a
+    // half-written PDF isn't picked up mid-save
+    pub fn wait_for_change(&mut self) {
+        while !self.poll() {
+            thread::sleep(POLL_INTERVAL);
+        }
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            if !self.poll() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_file_watcher_detects_change() {
+        let test_dir = "test_output_watch";
+        fs::create_dir_all(test_dir).unwrap();
+        let path = PathBuf::from(format!("{}/watched.pdf", test_dir));
+        fs::write(&path, "v1").unwrap();
+
+        let mut watcher = FileWatcher::new(&[&path]);
+        assert!(!watcher.poll(), "Nothing changed yet");
+
+        // Removing the file is a change, as is it coming back
+        fs::remove_file(&path).unwrap();
+        assert!(watcher.poll());
+        assert!(!watcher.poll(), "Change should only be reported once");
+        fs::write(&path, "v2").unwrap();
+        assert!(watcher.poll());
+
+        fs::remove_dir_all(test_dir).ok();
+    }
+}
@@ -1,151 +1,3267 @@
 
-use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Instant, SystemTime};
 
-use clap::Parser;
-use lib::{image_utils::save_images, pdf::create_pdfium};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use lib::batch::{
+    document_distance, effective_jobs, list_pdfs, load_manifest, manifest_comparisons, plan_comparisons,
+    run_parallel, Comparison, ComparisonMode, MATCH_DPI,
+};
+use lib::isolation::{run_worker, worker_command, write_worker_result, WorkerJob};
+use lib::cancel::{cancel_on_interrupt, CancelToken, INTERRUPTED_EXIT_CODE};
+use lib::exit_code::{ExitCode, Failure};
+use lib::approvals::{approve_changes, approve_pages, approved_pages, load_approvals};
+use lib::layout::Layout;
+use lib::regions::{BlendMode, OverlayStyle};
+use lib::layers::{isolate_layer, text_boxes, ContentLayer};
+use lib::ignore::{find_ignore_file, load_ignore_file, mask_regions, text_regions, IgnoreRegion, IgnoreRule, IgnoreSection};
+use lib::metrics::Metrics;
+use lib::review::{Review, ReviewDecisions};
+use lib::benchmark::Throughput;
+use lib::budget::{dir_size, ByteSize, OutputBudget, OverBudget};
+use lib::calibrate::{Calibration, CalibrationMode};
+use lib::classify::PageContent;
+use lib::severity::SeverityWeights;
+use lib::color::{ColorProof, Intent};
+use lib::spot::spot_colors;
+use lib::scan::ScanMode;
+use lib::diff_report::{DiffReport, Timings};
+use lib::metadata::RunMetadata;
+use lib::report::{AggregateSummary, BlankPolicy, ComparisonSummary, PageStatus, PageWarning, ReportFormat};
+use lib::image_utils::{crop_pages_to_content, ContentDetection, DiffOutput, PngCompression, PngFilter, PngOptions, SaveOptions};
+use lib::i18n::Language;
+use lib::tr;
+use lib::pairing::{PagePairing, PairingMode};
+use lib::profiles::{find_config_file, load_config, profile_arguments, resolve_profile};
+use lib::pdfium_pool::PdfiumPool;
+use lib::pdf::{create_pdfium_at, InputLimits, describe_pdfium, document_substituted_fonts, index_pairing, Length, PagePairs, RenderFlag, RenderOptions};
+use lib::term_image::ImageProtocol;
+use lib::sampling::{describe_selection, select_pages, PageRanges, Sampling};
+use image::DynamicImage;
+use pdfium_render::prelude::{PdfDocument, PdfPageIndex};
+use lib::preprocess::{Algorithm, Binarization, Normalization, Preprocessing};
+
+// Set for `--json`, whose stdout is for the summary alone
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+// `println!` for the progress and results of a diff run, left out with `--json`
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !JSON_OUTPUT.load(Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
 
 #[derive(Parser)]
 #[command(name = "pdf_diff")]
 #[command(about = "A tool for comparing PDF documents by generating visual diffs")]
 #[command(version = "0.1.0")]
-struct Args {
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    // Without a subcommand the flags are those of `diff`, as before subcommands existed
+    #[command(flatten)]
+    diff: DiffArgs,
+
+    /// Language of messages and report labels; by default that of the locale (`LC_ALL`, `LC_MESSAGES` or `LANG`), else English
+    #[arg(long = "lang", value_name = "LANG", env = "DIFF_PDF_LANG", global = true, help = "Language of messages and reports (en, de, fr)")]
+    lang: Option<Language>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compare PDF documents and write visual diffs (the default)
+    Diff(DiffArgs),
+
+    /// Render the pages of a single PDF to PNG images
+    Render(RenderArgs),
+
+    /// Print page sizes, rotation, encryption, metadata and fonts of a PDF
+    Info(InfoArgs),
+
+    /// Print the text of a PDF, optionally with positions as JSON
+    #[command(alias = "extract-text")]
+    Text(TextArgs),
+
+    /// Compare, then re-compare whenever an input file changes
+    Watch(DiffArgs),
+
+    /// Serve a previously written output directory over HTTP
+    Serve(ServeArgs),
+
+    /// Accept the new PDF as the baseline for future comparisons
+    Approve(ApproveArgs),
+
+    /// Compare, then step through the changed pages to approve or reject each one
+    Review(ReviewArgs),
+
+    /// Check an output directory against the checksums written by `--checksums`
+    Verify(VerifyArgs),
+
+    /// Compare two directories of images matched by file name, without PDFium
+    ImagesDiff(ImagesDiffArgs),
+
+    /// Show which pages got better or worse between two runs' summary.json files
+    CompareReports(CompareReportsArgs),
+
+    /// Show trends of the runs recorded with `--history`
+    #[cfg(feature = "history")]
+    History(HistoryArgs),
+}
+
+#[derive(Args)]
+struct RenderArgs {
+    /// The PDF to render
+    #[arg(help = "Path to the PDF file to render")]
+    input: String,
+
+    /// Output directory for page images
+    #[arg(short = 'd', long = "output-dir", env = "DIFF_PDF_OUTPUT_DIR", default_value = "output", help = "Directory to save page images")]
+    output_dir: String,
+
+    /// DPI for rendering
+    #[arg(long = "dpi", env = "DIFF_PDF_DPI", default_value = "300", help = "DPI for PDF rendering")]
+    dpi: f32,
+
+    /// Pages to render, e.g. `1-10,12,20-`; all pages by default
+    #[arg(long = "pages", value_name = "RANGES", help = "Pages to render (e.g. 1-10,12)")]
+    pages: Option<PageRanges>,
+
+    #[command(flatten)]
+    png: PngArgs,
+
+    #[command(flatten)]
+    limits: LimitArgs,
+
+    /// Pdfium rendering flags
+    #[arg(long = "render-flags", env = "DIFF_PDF_RENDER_FLAGS", value_enum, value_delimiter = ',', help = "Comma separated pdfium render flags")]
+    render_flags: Vec<RenderFlag>,
+
+    /// Directory containing the PDFium shared library
+    #[arg(long = "pdfium-path", env = "DIFF_PDF_PDFIUM_PATH", default_value_t = lib::pdf::default_pdfium_path(), help = "Directory containing the PDFium library")]
+    pdfium_path: String,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose", env = "DIFF_PDF_VERBOSE", help = "Enable verbose output")]
+    verbose: bool,
+}
+
+#[derive(Args)]
+struct InfoArgs {
+    /// The PDF to inspect
+    #[arg(help = "Path to the PDF file to inspect")]
+    input: String,
+
+    /// Print JSON instead of text
+    #[arg(long = "json", help = "Print the information as JSON")]
+    json: bool,
+
+    /// Directory containing the PDFium shared library
+    #[arg(long = "pdfium-path", env = "DIFF_PDF_PDFIUM_PATH", default_value_t = lib::pdf::default_pdfium_path(), help = "Directory containing the PDFium library")]
+    pdfium_path: String,
+}
+
+#[derive(Args)]
+struct TextArgs {
+    /// The PDF to extract text from
+    #[arg(help = "Path to the PDF file")]
+    input: String,
+
+    /// Pages to extract, e.g. `1-10,12,20-`; all pages by default
+    #[arg(long = "pages", value_name = "RANGES", help = "Pages to extract (e.g. 1-10,12)")]
+    pages: Option<PageRanges>,
+
+    /// Print JSON with the position of every text segment instead of plain text
+    #[arg(long = "json", help = "Print JSON with text positions")]
+    json: bool,
+
+    /// Directory containing the PDFium shared library
+    #[arg(long = "pdfium-path", env = "DIFF_PDF_PDFIUM_PATH", default_value_t = lib::pdf::default_pdfium_path(), help = "Directory containing the PDFium library")]
+    pdfium_path: String,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    /// Output directory of an earlier comparison
+    #[arg(env = "DIFF_PDF_OUTPUT_DIR", default_value = "output", help = "Directory to serve")]
+    dir: String,
+
+    /// Port to listen on
+    #[arg(short = 'p', long = "port", default_value = "8000", help = "Port to serve on")]
+    port: u16,
+
+    /// Open the served report in the default browser
+    #[arg(long = "open", help = "Open the served report in a browser")]
+    open: bool,
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    /// Output directory of an earlier comparison
+    #[arg(env = "DIFF_PDF_OUTPUT_DIR", default_value = "output", help = "Directory to verify")]
+    dir: String,
+}
+
+#[derive(Args)]
+struct CompareReportsArgs {
+    /// summary.json of the earlier run
+    #[arg(help = "Summary of the earlier run")]
+    old_summary: String,
+
+    /// summary.json of the later run, over the same documents
+    #[arg(help = "Summary of the later run")]
+    new_summary: String,
+
+    /// Smallest change of a page's diff ratio (0.0-1.0) counted as better or worse
+    #[arg(long = "min-change", value_name = "RATIO", default_value_t = lib::compare_reports::MIN_RATIO_CHANGE, help = "Smallest diff ratio change to report")]
+    min_change: f64,
+
+    /// Print JSON instead of text
+    #[arg(long = "json", help = "Print the page trends as JSON")]
+    json: bool,
+
+    /// Exit with status 1 when any page got worse
+    #[arg(long = "fail-on-worse", help = "Fail when any page got worse")]
+    fail_on_worse: bool,
+}
+
+#[cfg(feature = "history")]
+#[derive(Args)]
+struct HistoryArgs {
+    /// Database written by `--history`
+    #[arg(env = "DIFF_PDF_HISTORY", help = "History database")]
+    database: String,
+
+    /// Only show comparisons whose old or new PDF path contains this
+    #[arg(long = "document", value_name = "TEXT", help = "Only show documents whose path contains TEXT")]
+    document: Option<String>,
+
+    /// Number of most frequently changed pages listed
+    #[arg(long = "top", value_name = "N", default_value = "10", help = "How many of the most frequently changed pages to list")]
+    top: usize,
+
+    /// Print JSON instead of text
+    #[arg(long = "json", help = "Print the trends as JSON")]
+    json: bool,
+}
+
+#[derive(Args)]
+struct ImagesDiffArgs {
+    /// Directory of baseline images
+    #[arg(help = "Directory of old images")]
+    old_dir: String,
+
+    /// Directory of new images, compared with the old images of the same name
+    #[arg(help = "Directory of new images")]
+    new_dir: String,
+
+    /// Output directory for diff images
+    #[arg(short = 'd', long = "output-dir", env = "DIFF_PDF_OUTPUT_DIR", default_value = "output", help = "Directory to save diff images")]
+    output_dir: String,
+
+    /// Diff sensitivity (0.0-1.0, lower = more sensitive)
+    #[arg(long = "sensitivity", env = "DIFF_PDF_SENSITIVITY", default_value = "0.12", help = "Diff sensitivity threshold")]
+    sensitivity: f32,
+
+    #[command(flatten)]
+    png: PngArgs,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose", env = "DIFF_PDF_VERBOSE", help = "Enable verbose output")]
+    verbose: bool,
+}
+
+#[derive(Args)]
+struct ApproveArgs {
+    /// The PDF to accept
+    #[arg(help = "Path to the new PDF to approve")]
+    new_pdf: String,
+
+    /// Baseline PDF to replace
+    #[arg(short = 'o', long = "old", required_unless_present = "baseline_dir", help = "Baseline PDF to overwrite with the new PDF")]
+    old_pdf: Option<String>,
+
+    /// Directory of baseline PDFs to copy the new PDF into
+    #[arg(long = "baseline-dir", conflicts_with = "old_pdf", help = "Copy the new PDF into this baseline directory")]
+    baseline_dir: Option<String>,
+}
+
+#[derive(Args)]
+struct ReviewArgs {
+    #[command(flatten)]
+    diff: DiffArgs,
+
+    /// File the approve/reject decisions are written to
+    #[arg(long = "decisions", value_name = "PATH", default_value = "review.json", help = "File to write review decisions to")]
+    decisions: String,
+
+    /// Terminal graphics protocol for page previews
+    #[arg(long = "image-protocol", value_enum, default_value = "auto", help = "Terminal graphics protocol for page previews")]
+    image_protocol: ImageProtocol,
+}
+
+// PNG encoder settings for commands that save images
+#[derive(Args)]
+struct PngArgs {
+    /// PNG compression effort; better compression means smaller files but slower runs
+    #[arg(long = "png-compression", env = "DIFF_PDF_PNG_COMPRESSION", value_enum, default_value = "fast", help = "PNG compression level")]
+    png_compression: PngCompression,
+
+    /// PNG row filter applied before compression
+    #[arg(long = "png-filter", value_enum, default_value = "adaptive", help = "PNG row filter")]
+    png_filter: PngFilter,
+}
+
+// Limits on untrusted input, for commands that render documents
+#[derive(Args)]
+struct LimitArgs {
+    /// Largest PDF accepted, in MiB
+    #[arg(long = "max-file-size", value_name = "MIB", env = "DIFF_PDF_MAX_FILE_SIZE", default_value_t = InputLimits::default().max_file_size / MIB, help = "Reject PDFs larger than this (MiB)")]
+    max_file_size: u64,
+
+    /// Most pages accepted in one document
+    #[arg(long = "max-page-count", value_name = "N", env = "DIFF_PDF_MAX_PAGE_COUNT", default_value_t = InputLimits::default().max_pages, help = "Reject PDFs with more pages than this")]
+    max_page_count: usize,
+
+    /// Most objects (text runs, paths, images) rendered on one page
+    #[arg(long = "max-page-objects", value_name = "N", env = "DIFF_PDF_MAX_PAGE_OBJECTS", default_value_t = InputLimits::default().max_page_objects, help = "Refuse to render pages with more objects than this")]
+    max_page_objects: usize,
+
+    /// Largest page render, in megapixels at the chosen DPI
+    #[arg(long = "max-page-megapixels", value_name = "MP", env = "DIFF_PDF_MAX_PAGE_MEGAPIXELS", default_value_t = InputLimits::default().max_page_pixels / 1_000_000, help = "Refuse to render pages larger than this (megapixels)")]
+    max_page_megapixels: u64,
+}
+
+const MIB: u64 = 1024 * 1024;
+
+impl LimitArgs {
+    fn limits(&self) -> InputLimits {
+        InputLimits {
+            max_file_size: self.max_file_size.saturating_mul(MIB),
+            max_pages: self.max_page_count,
+            max_page_objects: self.max_page_objects,
+            max_page_pixels: self.max_page_megapixels.saturating_mul(1_000_000),
+        }
+    }
+}
+
+impl PngArgs {
+    fn options(&self) -> PngOptions {
+        PngOptions {
+            compression: self.png_compression,
+            filter: self.png_filter,
+            ..PngOptions::default()
+        }
+    }
+}
+
+#[derive(Args)]
+struct DiffArgs {
     /// Path to the old PDF file
-    #[arg(short = 'o', long = "old", help = "Path to the old PDF file",)]
-    old_pdf: String,
+    #[arg(short = 'o', long = "old", required_unless_present_any = ["baseline_dir", "manifest"], help = "Path to the old PDF file",)]
+    old_pdf: Option<String>,
+
+    /// Directory of baseline PDFs; the closest one is used as the old PDF
+    #[arg(long = "baseline-dir", conflicts_with = "old_pdf", help = "Compare the new PDF against the closest PDF in this directory")]
+    baseline_dir: Option<String>,
+
+    /// Path to the new PDF file (repeat to compare several documents), or reference images standing in for it:
+    /// comma-separated image files, one per page, or a directory of them
+    #[arg(short = 'n', long = "new", required_unless_present = "manifest", help = "Path to the new PDF file or reference images (repeat for N-way comparison)")]
+    new_pdfs: Vec<String>,
+
+    /// CSV or JSON manifest listing document pairs to compare in one run
+    #[arg(long = "manifest", conflicts_with_all = ["old_pdf", "new_pdfs", "baseline_dir"], help = "Compare every (old, new) pair listed in a CSV/JSON manifest")]
+    manifest: Option<String>,
+
+    /// Number of document pairs processed concurrently
+    #[arg(short = 'j', long = "jobs", env = "DIFF_PDF_JOBS", default_value = "1", help = "Document pairs to process in parallel (0 = one per CPU)")]
+    jobs: usize,
+
+    /// How documents are paired when more than one new PDF is given
+    #[arg(long = "n-way", value_enum, default_value = "against-first", help = "Pairing of documents for N-way comparison")]
+    n_way: ComparisonMode,
+
+    /// Output directory for diff images; `s3://bucket/prefix` uploads outputs with the `cloud` feature
+    #[arg(short = 'd', long = "output-dir", env = "DIFF_PDF_OUTPUT_DIR", default_value = "output", help = "Directory (or s3://bucket/prefix) to save diff images")]
+    output_dir: String,
+
+    /// Named set of comparison settings; options given explicitly take precedence
+    #[arg(long = "profile", value_name = "NAME", env = "DIFF_PDF_PROFILE", help = "Apply a settings profile (strict, lenient, scanned, print or one from the config file)")]
+    profile: Option<String>,
+
+    /// Configuration file with user-defined profiles, instead of the nearest `.diffpdf.json` or the per-user config file
+    #[arg(long = "config", value_name = "PATH", env = "DIFF_PDF_CONFIG", help = "Configuration file with user-defined profiles")]
+    config: Option<String>,
+
+    /// DPI for rendering (higher = better quality, slower processing)
+    #[arg(long = "dpi", env = "DIFF_PDF_DPI", default_value = "300", help = "DPI for PDF rendering")]
+    dpi: f32,
+
+    /// Render every page at this lower DPI first and only the pages that differ at --dpi, so unchanged pages don't pay for full resolution
+    #[arg(long = "prescan-dpi", value_name = "DPI", env = "DIFF_PDF_PRESCAN_DPI", help = "Find changed pages at DPI before rendering them at --dpi")]
+    prescan_dpi: Option<f32>,
+
+    /// Pdfium rendering flags, pinned to make renders reproducible across machines
+    #[arg(long = "render-flags", env = "DIFF_PDF_RENDER_FLAGS", value_enum, value_delimiter = ',', help = "Comma separated pdfium render flags")]
+    render_flags: Vec<RenderFlag>,
+
+    /// Directory containing the PDFium shared library
+    #[arg(long = "pdfium-path", env = "DIFF_PDF_PDFIUM_PATH", default_value_t = lib::pdf::default_pdfium_path(), help = "Directory containing the PDFium library")]
+    pdfium_path: String,
+
+    /// How pages of the old PDF are matched with pages of the new one
+    #[arg(long = "pairing", value_enum, default_value = "auto", help = "Page pairing (auto pairs by text similarity when page counts differ)")]
+    pairing: PairingMode,
+
+    /// Minimum text similarity (0.0-1.0) for two pages to be paired
+    #[arg(long = "min-page-similarity", default_value_t = lib::pairing::MIN_PAGE_SIMILARITY, help = "Minimum text similarity for pairing pages")]
+    min_page_similarity: f64,
+
+    /// Pairs of 1-based old and new pages to compare; pages between them are paired by position
+    #[arg(long = "pair-pages", value_name = "MAP", value_parser = lib::pairing::parse_page_map, conflicts_with = "pairing", help = "Pair pages explicitly, e.g. 3=4,6=6 (OLD=NEW)")]
+    pair_pages: Option<BTreeMap<usize, usize>>,
+
+    /// Paired pages whose sizes differ by up to this much are rendered at the same size
+    #[arg(long = "size-tolerance", value_name = "LENGTH", env = "DIFF_PDF_SIZE_TOLERANCE", help = "Render paired pages within this size difference (e.g. 1mm) at the same size")]
+    size_tolerance: Option<Length>,
+
+    /// Compare at most this many pages, for quick smoke checks of large documents
+    #[arg(long = "max-pages", value_name = "N", help = "Compare at most N pages")]
+    max_pages: Option<usize>,
+
+    /// Only save and list the N pages that changed the most, for triaging big documents
+    #[arg(long = "top-changes", value_name = "N", help = "Only save and list the N most changed pages")]
+    top_changes: Option<usize>,
+
+    /// Compare only a sample of the pages
+    #[arg(long = "sample", value_name = "every:N|random:N[@SEED]", help = "Compare a sample of pages")]
+    sample: Option<Sampling>,
+
+    /// Diff sensitivity (0.0-1.0, lower = more sensitive)
+    #[arg(long = "sensitivity", env = "DIFF_PDF_SENSITIVITY", default_value = "0.12", help = "Diff sensitivity threshold")]
+    sensitivity: f32,
+
+    /// Measure rendering noise by comparing the old PDF against re-renders of itself, then print a sensitivity just above it (suggest) or compare with it in place of --sensitivity (apply)
+    #[arg(
+        long = "calibrate",
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        default_missing_value = "suggest",
+        requires = "old_pdf",
+        help = "Suggest or apply a sensitivity measured from rendering noise"
+    )]
+    calibrate: Option<CalibrationMode>,
+
+    /// Normalize page renders before comparing to cancel out exposure differences
+    #[arg(long = "normalize", value_enum, help = "Normalize page renders before comparing")]
+    normalize: Option<Normalization>,
+
+    /// ICC output profile (RGB, CMYK or gray) both documents' renders are proofed through, so colors are compared in one color space
+    #[arg(long = "icc-profile", value_name = "FILE", env = "DIFF_PDF_ICC_PROFILE", help = "Compare colors as proofed through this ICC profile")]
+    icc_profile: Option<String>,
+
+    /// How colors outside the ICC profile's gamut are mapped into it
+    #[arg(long = "rendering-intent", value_enum, default_value_t = Intent::default(), requires = "icc_profile", help = "Rendering intent of --icc-profile")]
+    rendering_intent: Intent,
+
+    /// Report spot colors (separation and DeviceN inks such as Pantone colors) that pages add or remove, read from the PDFs rather than the renders
+    #[arg(long = "spot-colors", env = "DIFF_PDF_SPOT_COLORS", help = "Report spot colors added or removed")]
+    spot_colors: bool,
+
+    /// Pages compared with the scan pipeline (registration, binarization and despeckling) in place of the other preprocessing; auto picks pages that are a single page-sized scanned image
+    #[arg(long = "scan-pipeline", value_enum, env = "DIFF_PDF_SCAN_PIPELINE", default_value_t = ScanMode::default(), help = "Which pages are compared as scans")]
+    scan_pipeline: ScanMode,
+
+    /// Reduce page renders to black and white before comparing, for scanned documents
+    #[arg(long = "binarize", value_name = "otsu|threshold:N", help = "Binarize page renders before comparing")]
+    binarize: Option<Binarization>,
+
+    /// Compare the rendered pixels, or edge maps of the renders for scans with noise and tonal drift
+    #[arg(long = "algorithm", value_enum, env = "DIFF_PDF_ALGORITHM", default_value_t = Algorithm::Pixel, help = "How page renders are compared")]
+    algorithm: Algorithm,
+
+    /// Compare only the text or only the graphics of the pages, by masking text boxes
+    #[arg(long = "compare", value_enum, env = "DIFF_PDF_COMPARE", default_value_t = ContentLayer::All, help = "Which content of the pages to compare")]
+    compare: ContentLayer,
+
+    /// Turn new pages back when they only differ from the old page by a rotation of 90, 180 or 270 degrees
+    #[arg(long = "auto-orient", env = "DIFF_PDF_AUTO_ORIENT", help = "Undo page rotations before comparing and report them")]
+    auto_orient: bool,
+
+    /// Trim the page margins both renders of a page share before comparing
+    #[arg(long = "crop-to-content", help = "Crop page renders to their content before comparing")]
+    crop_to_content: bool,
+
+    /// Per-channel distance from white still treated as background when cropping
+    #[arg(long = "white-tolerance", default_value_t = lib::image_utils::WHITE_TOLERANCE, requires = "crop_to_content", help = "Distance from white treated as background (raise for light watermarks)")]
+    white_tolerance: u8,
+
+    /// Pixels at or below this alpha are background when cropping
+    #[arg(long = "alpha-threshold", default_value = "0", requires = "crop_to_content", help = "Alpha at or below which pixels are treated as background")]
+    alpha_threshold: u8,
+
+    /// Verbose output
+    #[arg(short = 'v', long = "verbose", env = "DIFF_PDF_VERBOSE", help = "Enable verbose output")]
+    verbose: bool,
+
+    /// Print the page table without colors; setting `NO_COLOR` does the same
+    #[arg(long = "no-color", help = "Don't color terminal output")]
+    no_color: bool,
+
+    /// Print nothing to stdout but the run's summary, as written to `summary.json`, for piping into tools like jq
+    #[arg(long = "json", help = "Print only the JSON summary to stdout")]
+    json: bool,
+
+    /// Save old/new/diff crops around each changed region
+    #[arg(long = "export-crops", help = "Save cropped images of each changed region")]
+    export_crops: bool,
+
+    /// Save a thumbnail grid of all pages with changed pages outlined
+    #[arg(long = "contact-sheet", help = "Save a thumbnail overview of all pages")]
+    contact_sheet: bool,
+
+    /// Write an HTML report with before/after sliders for changed pages
+    #[arg(long = "html-report", help = "Write an HTML report with before/after sliders")]
+    html_report: bool,
+
+    /// Changed pages shown per HTML report file; the rest continue in report-2.html, report-3.html, ... linked from each. 0 puts every page in report.html
+    #[arg(long = "report-pages-per-file", value_name = "N", env = "DIFF_PDF_REPORT_PAGES_PER_FILE", default_value_t = lib::report::PAGES_PER_FILE, help = "Changed pages per HTML report file (0 for one file)")]
+    report_pages_per_file: usize,
+
+    /// Write all compared pages to one PDF with a bookmark per changed page
+    #[arg(long = "pdf-report", help = "Write a PDF of all pages with bookmarks to changed pages")]
+    pdf_report: bool,
+
+    /// Reports to write; may be repeated
+    #[arg(long = "report", value_enum, value_name = "FORMAT", help = "Write a report (html, pdf or csv); may be repeated")]
+    reports: Vec<ReportFormat>,
+
+    /// Leave the run's start and end time, host, version, arguments and timings out of the JSON and HTML reports, so the same inputs give byte-identical reports
+    #[arg(long = "no-metadata", env = "DIFF_PDF_NO_METADATA", help = "Leave run metadata out of reports, for reproducible output")]
+    no_metadata: bool,
+
+    /// Images saved per page: the diff then the new page for changed pages and the page alone for others, or only the diff
+    #[arg(long = "diff-output", value_enum, value_name = "MODE", env = "DIFF_PDF_DIFF_OUTPUT", default_value = "diff-and-page", help = "Images saved per page")]
+    diff_output: DiffOutput,
+
+    /// Arrangement of saved images; `triptych` saves old | diff | new as one captioned image per page
+    #[arg(long = "layout", value_enum, env = "DIFF_PDF_LAYOUT", default_value = "separate", help = "Layout of saved images")]
+    layout: Layout,
+
+    /// Opacity of change highlights over the faded page, from 0 (invisible) to 1
+    #[arg(long = "overlay-opacity", value_name = "OPACITY", env = "DIFF_PDF_OVERLAY_OPACITY", default_value_t = 1.0, help = "Opacity of change highlights (0-1)")]
+    overlay_opacity: f32,
+
+    /// How change highlights combine with the page below them
+    #[arg(long = "blend-mode", value_enum, env = "DIFF_PDF_BLEND_MODE", default_value = "normal", help = "Blend mode of change highlights")]
+    blend_mode: BlendMode,
+
+    /// Save exactly one image per changed page and nothing for unchanged pages (same as --diff-output diff-only)
+    #[arg(long = "diff-only", conflicts_with = "diff_output", help = "Save only one diff image per changed page")]
+    diff_only: bool,
+
+    #[command(flatten)]
+    png: PngArgs,
+
+    /// Resize saved images by this factor (0-1], so pages are compared at full DPI but saved smaller
+    #[arg(long = "output-scale", value_name = "FACTOR", help = "Scale saved images by FACTOR (e.g. 0.5)")]
+    output_scale: Option<f32>,
+
+    /// Save images at this DPI instead of the comparison DPI
+    #[arg(long = "output-dpi", value_name = "DPI", conflicts_with = "output_scale", help = "DPI of saved images")]
+    output_dpi: Option<f32>,
+
+    /// Bytes the run's diff images and HTML report images may take (e.g. 500MB or 2GiB); once the rest would go over, they are saved reduced as --over-budget says
+    #[arg(long = "max-output-size", value_name = "SIZE", env = "DIFF_PDF_MAX_OUTPUT_SIZE", help = "Reduce images saved once outputs near SIZE (e.g. 500MB)")]
+    max_output_size: Option<ByteSize>,
+
+    /// How images are reduced once --max-output-size is running out
+    #[arg(long = "over-budget", value_enum, default_value = "downscale", requires = "max_output_size", help = "How to reduce images over --max-output-size")]
+    over_budget: OverBudget,
+
+    /// Serve the HTML report on localhost after the comparison
+    #[arg(long = "serve", value_name = "PORT", num_args = 0..=1, default_missing_value = "8000", help = "Serve the HTML report over HTTP (default port 8000)")]
+    serve: Option<u16>,
+
+    /// Open the served report in the default browser
+    #[arg(long = "open", requires = "serve", help = "Open the served report in a browser")]
+    open: bool,
+
+    /// Skip document pairs finished by an earlier, interrupted batch run
+    #[arg(long = "resume", help = "Skip document pairs whose outputs are already up to date")]
+    resume: bool,
+
+    /// Write into an output directory that already has files in it
+    #[arg(long = "force", env = "DIFF_PDF_FORCE", help = "Allow writing into a non-empty output directory")]
+    force: bool,
+
+    /// Empty the output directory before the run, so no outputs of earlier runs are left
+    #[arg(long = "clean", env = "DIFF_PDF_CLEAN", conflicts_with = "resume", help = "Remove earlier outputs from the output directory first")]
+    clean: bool,
+
+    /// Write checksums.json with SHA-256 of every output and input, the options and the PDFium version
+    #[arg(long = "checksums", env = "DIFF_PDF_CHECKSUMS", help = "Write a checksum manifest of outputs and inputs for later verification")]
+    checksums: bool,
+
+    /// Compare each document pair in a child process, so a PDFium crash fails only that pair
+    #[arg(long = "isolate", env = "DIFF_PDF_ISOLATE", help = "Run each document pair in its own worker process")]
+    isolate: bool,
+
+    /// Confine worker processes with OS resource limits and no network (implies --isolate)
+    #[cfg(feature = "sandbox")]
+    #[arg(long = "sandbox", env = "DIFF_PDF_SANDBOX", help = "Run workers under memory and CPU time limits without network access")]
+    sandbox: bool,
+
+    /// Address space limit of each sandboxed worker, in MiB
+    #[cfg(feature = "sandbox")]
+    #[arg(long = "sandbox-memory", value_name = "MIB", env = "DIFF_PDF_SANDBOX_MEMORY", default_value = "4096", help = "Memory ceiling per sandboxed worker (MiB)")]
+    sandbox_memory: u64,
+
+    /// CPU time limit of each sandboxed worker, in seconds
+    #[cfg(feature = "sandbox")]
+    #[arg(long = "sandbox-cpu-time", value_name = "SECONDS", env = "DIFF_PDF_SANDBOX_CPU_TIME", default_value = "600", help = "CPU time limit per sandboxed worker (seconds)")]
+    sandbox_cpu_time: u64,
+
+    /// SQLite database each run's comparisons are appended to, for `pdf_diff history`
+    #[cfg(feature = "history")]
+    #[arg(long = "history", value_name = "DB", env = "DIFF_PDF_HISTORY", help = "Record the run in a SQLite history database")]
+    history: Option<String>,
+
+    /// Shared library adding mask, preprocess or report stages to the pipeline (repeat for several)
+    #[cfg(feature = "plugins")]
+    #[arg(long = "plugin", value_name = "PATH", help = "Load a pipeline plugin (shared library)")]
+    plugins: Vec<String>,
+
+    // Set on the worker processes started by `--isolate`: the job to run, as JSON
+    #[arg(long = "worker", hide = true)]
+    worker: Option<String>,
+
+    // Where outputs are moved when the run is over, if they are written to a
+    // staging directory first; reports name files where they end up
+    #[arg(skip)]
+    final_output_dir: Option<String>,
+
+    /// How pages that render blank are handled
+    #[arg(long = "blank-pages", value_enum, default_value = "report", help = "Handling of blank pages: compare them, skip blank-only pages, or report them separately")]
+    blank_pages: BlankPolicy,
+
+    /// Don't diff pages whose old and new renders repeat an earlier page's
+    #[arg(long = "skip-duplicate-pages", help = "Skip diffing pages that duplicate an earlier page pair")]
+    skip_duplicate_pages: bool,
+
+    /// Area to leave out of the comparison on every page
+    #[arg(long = "ignore-region", value_name = "X,Y,WIDTH,HEIGHT", help = "Mask a page area, in points from the top-left corner, before comparing (repeatable)")]
+    ignore_regions: Vec<IgnoreRegion>,
+
+    /// Ignore rules file to use instead of the nearest `.diffpdfignore`
+    #[arg(long = "ignore-file", env = "DIFF_PDF_IGNORE_FILE", value_name = "PATH", help = "Ignore rules file (default: nearest .diffpdfignore)")]
+    ignore_file: Option<String>,
+
+    /// Don't look for a `.diffpdfignore` file
+    #[arg(long = "no-ignore-file", conflicts_with = "ignore_file", help = "Don't load ignore rules from a .diffpdfignore file")]
+    no_ignore_file: bool,
+
+    /// JSON file of approved page differences, which no longer count as changes
+    #[arg(long = "approvals", env = "DIFF_PDF_APPROVALS", value_name = "PATH", help = "File of approved page differences to ignore")]
+    approvals: Option<String>,
+
+    /// Approve the differences found in this run, so later runs ignore them until the pages change again
+    #[arg(long = "approve-changes", requires = "approvals", help = "Record this run's differences as approved")]
+    approve_changes: bool,
+
+    /// Abort on the first page that fails to render or diff
+    #[arg(long = "strict", env = "DIFF_PDF_STRICT", help = "Fail fast instead of replacing broken pages with placeholders")]
+    strict: bool,
+
+    #[command(flatten)]
+    limits: LimitArgs,
+
+    /// Treat pages rendered with substituted fonts as a failed comparison
+    #[arg(long = "fail-on-font-substitution", help = "Fail when a non-embedded font has to be substituted")]
+    fail_on_font_substitution: bool,
+
+    /// Treat a comparison with any page warning (font substitution, scaled or skipped pages, masked regions, ...) as failed
+    #[arg(long = "fail-on-warning", help = "Fail when any page has a warning")]
+    fail_on_warning: bool,
+
+    /// How much each signal counts towards a page's severity score; signals left out keep their default weight
+    #[arg(
+        long = "severity-weights",
+        value_name = "SIGNAL=WEIGHT,...",
+        env = "DIFF_PDF_SEVERITY_WEIGHTS",
+        default_value = "pixels=1,ssim=1,regions=1,registration=2",
+        help = "Weights of the pixels, ssim, regions and registration signals in the page severity score"
+    )]
+    severity_weights: SeverityWeights,
+
+    /// Treat a page whose severity score is above SCORE (0.0-1.0) as a failed comparison; 0 fails on any change
+    #[arg(long = "fail-severity", value_name = "SCORE", env = "DIFF_PDF_FAIL_SEVERITY", help = "Fail when a page's severity is above SCORE")]
+    fail_severity: Option<f64>,
+
+    /// Render every page twice and warn where the renders differ, a sign of flaky baseline comparisons
+    #[arg(long = "verify-deterministic", help = "Render each page twice and warn if the renders differ")]
+    verify_deterministic: bool,
+
+    /// Print how fast pages were rendered and diffed, to quantify performance changes such as a higher DPI
+    #[arg(long = "benchmark", env = "DIFF_PDF_BENCHMARK", help = "Report rendering and diffing throughput (pages/sec, MPix/sec)")]
+    benchmark: bool,
+
+    /// Shell command deciding whether the run passes: it reads the summary JSON on stdin and passes by exiting 0
+    #[arg(long = "verdict-command", value_name = "COMMAND", env = "DIFF_PDF_VERDICT_COMMAND", help = "Command that reads the run's summary JSON and decides pass/fail by its exit status")]
+    verdict_command: Option<String>,
+
+    /// Shell command run before any PDF is read, with every input PDF path as arguments (e.g. to decrypt them)
+    #[arg(long = "pre-hook", value_name = "COMMAND", env = "DIFF_PDF_PRE_HOOK", help = "Command run with the input PDF paths before comparing")]
+    pre_hook: Option<String>,
+
+    /// Shell command run after the outputs are written, with the path of the run's `summary.json` as argument (e.g. to publish results)
+    #[arg(long = "post-hook", value_name = "COMMAND", env = "DIFF_PDF_POST_HOOK", help = "Command run with the summary path after comparing")]
+    post_hook: Option<String>,
+
+    /// Write Prometheus metrics (pages processed, diff ratios, durations) to a file after each run
+    #[arg(long = "metrics-file", env = "DIFF_PDF_METRICS_FILE", value_name = "PATH", help = "Write Prometheus metrics to a file")]
+    metrics_file: Option<String>,
+
+    /// Re-run the comparison whenever either input file changes (same as the `watch` subcommand)
+    #[arg(short = 'w', long = "watch", help = "Watch the input files and re-compare on change")]
+    watch: bool,
+
+    /// Write flip videos alternating old/new renders of changed pages
+    #[cfg(feature = "video")]
+    #[arg(long = "video", value_enum, help = "Write flip videos of changed pages")]
+    video: Option<lib::video::VideoMode>,
+
+    /// Container/codec for flip videos
+    #[cfg(feature = "video")]
+    #[arg(long = "video-format", value_enum, default_value = "mp4", help = "Video format for flip videos")]
+    video_format: lib::video::VideoFormat,
+
+    /// Open a window with old/new/diff views of the pages once the comparison is done
+    #[cfg(feature = "gui")]
+    #[arg(long = "preview", conflicts_with = "watch", help = "Show the compared pages in a preview window")]
+    preview: bool,
+}
+
+// `--version` also names the PDFium build, since renders depend on it. The
+// library path can only come from the environment here, as the command line
+// hasn't been parsed yet.
+fn parse_args() -> Cli {
+    let exit_codes = lib::exit_code::exit_codes_help();
+    let mut command = Cli::command()
+        .after_help(exit_codes.clone())
+        .mut_subcommand("diff", |diff| diff.after_help(exit_codes.clone()))
+        .mut_subcommand("watch", |watch| watch.after_help(exit_codes));
+    // Describing the PDFium build means finding it on disk, so it is only
+    // done when `--version` asks for it
+    if std::env::args_os().skip(1).take_while(|argument| argument != "--").any(|argument| argument == "--version") {
+        let pdfium_path = std::env::var("DIFF_PDF_PDFIUM_PATH").unwrap_or_else(|_| lib::pdf::default_pdfium_path());
+        command = command.long_version(format!("{}\n{}", env!("CARGO_PKG_VERSION"), describe_pdfium(&pdfium_path)));
+    }
+    let matches = command.clone().get_matches();
+
+    let matches = match with_profile(&command, &matches) {
+        Ok(Some(arguments)) => command.get_matches_from(arguments),
+        Ok(None) => matches,
+        Err(message) => {
+            eprintln!("{}", message);
+            ExitCode::Usage.exit();
+        }
+    };
+    Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+}
+
+// The command line with the options of `--profile` added, except those given
+// on the command line or in the environment, which take precedence. `None`
+// without a profile.
+fn with_profile(command: &clap::Command, matches: &ArgMatches) -> Result<Option<Vec<OsString>>, String> {
+    let (command, matches) = match matches.subcommand() {
+        Some((name, matches)) => (command.find_subcommand(name).expect("matched subcommands exist"), matches),
+        None => (command, matches),
+    };
+    let Ok(Some(profile)) = matches.try_get_one::<String>("profile") else {
+        return Ok(None);
+    };
+
+    let config = match matches.get_one::<String>("config") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => std::env::current_dir()
+            .ok()
+            .and_then(|dir| find_config_file(&dir))
+            .or_else(lib::app_dirs::user_config_file),
+    };
+    let user_profiles = match config {
+        Some(path) => load_config(&path).map_err(|e| format!("Error reading configuration: {}", e))?,
+        None => Default::default(),
+    };
+
+    let mut options = resolve_profile(profile, &user_profiles)?;
+    for option in &options {
+        if !command.get_arguments().any(|arg| arg.get_long() == Some(option.name.as_str())) {
+            return Err(format!("Unknown option '--{}' in profile '{}'", option.name, profile));
+        }
+    }
+    options.retain(|option| {
+        let arg = command.get_arguments().find(|arg| arg.get_long() == Some(option.name.as_str())).expect("checked above");
+        !matches!(matches.value_source(arg.get_id().as_str()), Some(ValueSource::CommandLine | ValueSource::EnvVariable))
+    });
+
+    let mut arguments: Vec<OsString> = std::env::args_os().collect();
+    arguments.extend(profile_arguments(&options).into_iter().map(OsString::from));
+    Ok(Some(arguments))
+}
+
+fn main() {
+    let cli = parse_args();
+    let language = cli.lang.or_else(|| Language::from_locale(&|name| std::env::var(name).ok()));
+    lib::i18n::set_language(language.unwrap_or_default());
+
+    match cli.command {
+        None => diff_command(cli.diff),
+        Some(Command::Diff(args)) => diff_command(args),
+        Some(Command::Render(args)) => render_command(&args),
+        Some(Command::Info(args)) => info_command(&args),
+        Some(Command::Text(args)) => text_command(&args),
+        Some(Command::Watch(args)) => diff_command(DiffArgs { watch: true, ..args }),
+        Some(Command::Serve(args)) => serve_command(&args),
+        Some(Command::Approve(args)) => approve_command(&args),
+        Some(Command::Review(args)) => review_command(&args),
+        Some(Command::Verify(args)) => verify_command(&args),
+        Some(Command::ImagesDiff(args)) => images_diff_command(&args),
+        Some(Command::CompareReports(args)) => compare_reports_command(&args),
+        #[cfg(feature = "history")]
+        Some(Command::History(args)) => history_command(&args),
+    }
+}
+
+// Rasterize one document with the same rendering settings a diff would use
+fn render_command(args: &RenderArgs) {
+    if let Err(failure) = render_document(args) {
+        eprintln!("{}", failure);
+        failure.code.exit();
+    }
+}
+
+fn render_document(args: &RenderArgs) -> Result<(), Failure> {
+    let pdfium = create_pdfium_at(&args.pdfium_path).map_err(|e| format!("Error creating PDFium instance: {}", e))?;
+    let document = lib::pdf::load_pdf_document_with_limits(&pdfium, Path::new(&args.input), &args.limits.limits())
+        .map_err(|e| Failure::input(format!("Error loading {}: {}", args.input, e)))?;
+
+    let page_count = document.pages().len() as usize;
+    let pages = match &args.pages {
+        Some(ranges) => ranges.indices(page_count),
+        None => (0..page_count).collect(),
+    };
+    if pages.is_empty() {
+        return Err(Failure::usage(format!("No pages selected; {} has {} pages", args.input, page_count)));
+    }
+
+    let options = RenderOptions {
+        flags: args.render_flags.clone(),
+        limits: args.limits.limits(),
+        ..RenderOptions::new(args.dpi)
+    };
+    let title = Path::new(&args.input).file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+    std::fs::create_dir_all(&args.output_dir).map_err(|e| format!("Error creating {}: {}", args.output_dir, e))?;
+
+    // Pages are saved as they are rendered to keep memory flat on long documents
+    for index in pages.iter().copied() {
+        let image = lib::pdf::render_page(&document, index, &options)
+            .map_err(|e| Failure::rendering(format!("Error rendering page {}: {}", index + 1, e)))?;
+        let path = Path::new(&args.output_dir).join(format!("{}_page{}.png", title, index + 1));
+        let png = PngOptions { dpi: Some(args.dpi), ..args.png.options() };
+        lib::image_utils::save_png_with(&image, &path, png).map_err(|e| format!("Error saving {}: {}", path.display(), e))?;
+
+        if args.verbose {
+            println!("Saved page {} to {}", index + 1, path.display());
+        }
+    }
+
+    println!("Rendered {} of {} pages to '{}'", pages.len(), page_count, args.output_dir);
+    Ok(())
+}
+
+fn info_command(args: &InfoArgs) {
+    if let Err(failure) = print_document_info(args) {
+        eprintln!("{}", failure);
+        failure.code.exit();
+    }
+}
+
+fn print_document_info(args: &InfoArgs) -> Result<(), Failure> {
+    let pdfium = create_pdfium_at(&args.pdfium_path).map_err(|e| format!("Error creating PDFium instance: {}", e))?;
+    let document = lib::pdf::load_pdf_document(&pdfium, Path::new(&args.input))
+        .map_err(|e| Failure::input(format!("Error loading {}: {}", args.input, e)))?;
+    let info = lib::info::document_info(&document, &args.input);
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&info).map_err(|e| format!("Error writing JSON: {}", e))?;
+        println!("{}", json);
+    } else {
+        print!("{}", info);
+    }
+    Ok(())
+}
+
+fn text_command(args: &TextArgs) {
+    if let Err(failure) = print_document_text(args) {
+        eprintln!("{}", failure);
+        failure.code.exit();
+    }
+}
+
+fn print_document_text(args: &TextArgs) -> Result<(), Failure> {
+    let pdfium = create_pdfium_at(&args.pdfium_path).map_err(|e| format!("Error creating PDFium instance: {}", e))?;
+    let document = lib::pdf::load_pdf_document(&pdfium, Path::new(&args.input))
+        .map_err(|e| Failure::input(format!("Error loading {}: {}", args.input, e)))?;
+
+    let page_count = document.pages().len() as usize;
+    let pages = match &args.pages {
+        Some(ranges) => ranges.indices(page_count),
+        None => (0..page_count).collect(),
+    };
+    let text = lib::text::document_text(&document, &pages, args.json)
+        .map_err(|e| format!("Error extracting text from {}: {}", args.input, e))?;
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&text).map_err(|e| format!("Error writing JSON: {}", e))?;
+        println!("{}", json);
+    } else {
+        print!("{}", lib::text::plain_text(&text));
+    }
+    Ok(())
+}
+
+// Serve an existing output directory, e.g. one copied from a CI run
+fn serve_command(args: &ServeArgs) {
+    if !Path::new(&args.dir).is_dir() {
+        eprintln!("Error: Output directory does not exist: {}", args.dir);
+        ExitCode::Input.exit();
+    }
+
+    let url = format!("http://127.0.0.1:{}/", args.port);
+    println!("Serving {} at {} (press Ctrl-C to stop)", args.dir, url);
+
+    if args.open && let Err(e) = lib::server::open_in_browser(&url) {
+        eprintln!("Warning: {}", e);
+    }
+
+    if let Err(e) = lib::server::serve_directory(&args.dir, args.port, None) {
+        eprintln!("Error serving report: {}", e);
+        ExitCode::Internal.exit();
+    }
+}
+
+fn approve_command(args: &ApproveArgs) {
+    let target = match (&args.old_pdf, &args.baseline_dir) {
+        (Some(old_pdf), _) => old_pdf,
+        (None, Some(baseline_dir)) => baseline_dir,
+        (None, None) => unreachable!("clap requires --old or --baseline-dir"),
+    };
+
+    if let Some(baseline_dir) = &args.baseline_dir
+        && !Path::new(baseline_dir).is_dir()
+    {
+        eprintln!("Error: Baseline directory does not exist: {}", baseline_dir);
+        ExitCode::Input.exit();
+    }
+
+    match lib::batch::approve_baseline(Path::new(&args.new_pdf), Path::new(target)) {
+        Ok(path) => println!("Approved {} as baseline {}", args.new_pdf, path.display()),
+        Err(e) => {
+            eprintln!("Error approving {}: {}", args.new_pdf, e);
+            ExitCode::Internal.exit();
+        }
+    }
+}
+
+// Compare one pair of documents and let the user decide on every changed page.
+// Approved pages are recorded in the `--approvals` file, if given, so later
+// comparisons stop reporting them.
+fn review_command(args: &ReviewArgs) {
+    let diff = &args.diff;
+    let (Some(old_pdf), [new_pdf]) = (&diff.old_pdf, diff.new_pdfs.as_slice()) else {
+        eprintln!("Error: review compares one --old PDF with one --new PDF");
+        ExitCode::Usage.exit();
+    };
+
+    for path in [old_pdf, new_pdf] {
+        if !Path::new(path).exists() {
+            eprintln!("Error: PDF file does not exist: {}", path);
+            ExitCode::Input.exit();
+        }
+    }
+
+    let pool = match PdfiumPool::new(&diff.pdfium_path) {
+        Ok(pool) => pool,
+        Err(e) => {
+            eprintln!("Error creating PDFium instance: {}", e);
+            ExitCode::Internal.exit();
+        }
+    };
+
+    let comparison = plan_comparisons(&[old_pdf.clone(), new_pdf.clone()], ComparisonMode::AgainstFirst).remove(0);
+    let compared = match compare_pages(diff, &pool, &comparison, &diff.output_dir, &CancelToken::new()) {
+        Ok(compared) => compared,
+        Err(failure) => {
+            eprintln!("{}", failure);
+            failure.code.exit();
+        }
+    };
+
+    let mut review = Review::new(&compared.images, &compared.summary);
+    review.overlay = overlay_style(diff);
+    if review.pages.is_empty() {
+        println!("No changed pages to review");
+        return;
+    }
+
+    match lib::review::run_review(&mut review, args.image_protocol.detect()) {
+        Ok(true) => {}
+        Ok(false) => {
+            println!("Review cancelled; nothing was saved");
+            return;
+        }
+        Err(e) => {
+            eprintln!("Error running review: {}", e);
+            ExitCode::Internal.exit();
+        }
+    }
+
+    let decisions = ReviewDecisions {
+        old_pdf: old_pdf.clone(),
+        new_pdf: new_pdf.clone(),
+        decisions: review.decisions(),
+    };
+    if let Err(e) = lib::review::write_decisions(Path::new(&args.decisions), &decisions) {
+        eprintln!("Error writing decisions: {}", e);
+        ExitCode::Internal.exit();
+    }
+
+    let approved = review.approved_pages();
+    println!(
+        "Approved {} and rejected {} of {} pages; decisions saved to {}",
+        approved.len(),
+        decisions.decisions.len() - approved.len(),
+        review.pages.len(),
+        args.decisions
+    );
+
+    if let Some(approvals) = &diff.approvals {
+        match approve_pages(Path::new(approvals), &comparison.name, &compared.images, &approved) {
+            Ok(()) => println!("Approvals saved to {}", approvals),
+            Err(e) => {
+                eprintln!("Error recording approvals: {}", e);
+                ExitCode::Internal.exit();
+            }
+        }
+    }
+}
+
+// Check archived outputs against their checksum manifest
+fn verify_command(args: &VerifyArgs) {
+    let (checked, problems) = match lib::checksums::verify_checksums(&args.dir) {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::Input.exit();
+        }
+    };
+
+    if !problems.is_empty() {
+        for problem in &problems {
+            eprintln!("{}", problem);
+        }
+        eprintln!("Verification failed: {} problems in {}", problems.len(), args.dir);
+        ExitCode::Differences.exit();
+    }
+    println!("Verified {} files in {}", checked, args.dir);
+}
+
+// Tuning a setting or upgrading a generator shows up as pages whose
+// difference grew or shrank between two runs over the same documents
+fn compare_reports_command(args: &CompareReportsArgs) {
+    match compare_reports(args) {
+        Ok(worse) if worse > 0 && args.fail_on_worse => ExitCode::Differences.exit(),
+        Ok(_) => {}
+        Err(failure) => {
+            eprintln!("{}", failure);
+            failure.code.exit();
+        }
+    }
+}
+
+// Print the trends between two summaries, returning how many pages got worse
+fn compare_reports(args: &CompareReportsArgs) -> Result<usize, Failure> {
+    use lib::compare_reports::Trend;
+
+    let read = |path: &str| lib::compare_reports::read_summary(Path::new(path)).map_err(|e| Failure::input(format!("Error reading {}: {}", path, e)));
+    let trends = lib::compare_reports::compare_runs(&read(&args.old_summary)?, &read(&args.new_summary)?, args.min_change);
+    let worse = trends.count(Trend::Worse);
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&trends).map_err(|e| format!("Error writing JSON: {}", e))?;
+        println!("{}", json);
+        return Ok(worse);
+    }
+
+    let ratio = |ratio: Option<f64>| ratio.map_or("-".to_string(), |ratio| format!("{:.2}%", ratio * 100.0));
+    for comparison in &trends.comparisons {
+        println!(
+            "{} vs {}: {} better, {} worse",
+            comparison.old_pdf,
+            comparison.new_pdf,
+            comparison.count(Trend::Better),
+            comparison.count(Trend::Worse)
+        );
+        for (run, error) in [("old", &comparison.old_error), ("new", &comparison.new_error)] {
+            if let Some(error) = error {
+                println!("  failed in the {} run: {}", run, error);
+            }
+        }
+        if comparison.incomplete {
+            println!("  not every page was compared in both runs (sampled or interrupted)");
+        }
+        for page in &comparison.pages {
+            let trend = match page.trend {
+                Trend::Better => "better",
+                Trend::Worse => "worse",
+                Trend::OnlyOld => "only in the old run",
+                Trend::OnlyNew => "only in the new run",
+            };
+            println!("  page {}: {} -> {} ({})", page.page, ratio(page.old_ratio), ratio(page.new_ratio), trend);
+        }
+    }
+    for (run, pairs) in [("old", &trends.only_old), ("new", &trends.only_new)] {
+        for (old_pdf, new_pdf) in pairs {
+            println!("Only in the {} run: {} vs {}", run, old_pdf, new_pdf);
+        }
+    }
+    println!(
+        "{} pages better, {} worse across {} comparisons",
+        trends.count(Trend::Better),
+        worse,
+        trends.comparisons.len()
+    );
+    Ok(worse)
+}
+
+#[cfg(feature = "history")]
+fn history_command(args: &HistoryArgs) {
+    if let Err(failure) = show_history(args) {
+        eprintln!("{}", failure);
+        failure.code.exit();
+    }
+}
+
+// Print each pair of documents' runs, oldest first, then the pages that
+// changed in the most runs
+#[cfg(feature = "history")]
+fn show_history(args: &HistoryArgs) -> Result<(), Failure> {
+    if !Path::new(&args.database).is_file() {
+        return Err(Failure::input(format!("Error: {} is not a history database", args.database)));
+    }
+    let query_error = |e: rusqlite::Error| Failure::input(format!("Error reading {}: {}", args.database, e));
+    let history = lib::history::History::open_read_only(Path::new(&args.database)).map_err(query_error)?;
+    let document = args.document.as_deref();
+    let trend = history.trend(document).map_err(query_error)?;
+    let pages = history.most_changed_pages(document, args.top).map_err(query_error)?;
+
+    if args.json {
+        let json = serde_json::json!({ "trend": trend, "most_changed_pages": pages });
+        let json = serde_json::to_string_pretty(&json).map_err(|e| format!("Error writing JSON: {}", e))?;
+        println!("{}", json);
+        return Ok(());
+    }
+
+    if let Some(recorded) = history.last_recorded().map_err(query_error)? {
+        println!("Last run recorded {}", recorded);
+    }
+    let mut pair = None;
+    for point in &trend {
+        if pair != Some((&point.old_pdf, &point.new_pdf)) {
+            pair = Some((&point.old_pdf, &point.new_pdf));
+            println!("{} vs {}", point.old_pdf, point.new_pdf);
+        }
+        let outcome = match &point.error {
+            Some(error) => format!("failed: {}", error),
+            None => format!(
+                "{} of {} pages changed, mean {:.2}%, max {:.2}%{}",
+                point.changed_pages,
+                point.total_pages,
+                point.mean_ratio * 100.0,
+                point.max_ratio * 100.0,
+                if point.incomplete { " (not every page compared)" } else { "" }
+            ),
+        };
+        println!("  {}  {}", point.recorded, outcome);
+    }
+    if trend.is_empty() {
+        println!("No recorded comparisons{}", document.map_or(String::new(), |document| format!(" of documents matching '{}'", document)));
+    }
+
+    if !pages.is_empty() {
+        println!("Most frequently changed pages:");
+        for page in &pages {
+            println!(
+                "  {} vs {} page {}: changed in {} of {} runs (mean {:.2}%)",
+                page.old_pdf,
+                page.new_pdf,
+                page.page,
+                page.changed_runs,
+                page.runs,
+                page.mean_ratio * 100.0
+            );
+        }
+    }
+    Ok(())
+}
+
+// Screenshots and other image sets are diffed like pages, without PDFium
+fn images_diff_command(args: &ImagesDiffArgs) {
+    match diff_image_sets(args) {
+        Ok(0) => {}
+        Ok(_) => ExitCode::Differences.exit(),
+        Err(failure) => {
+            eprintln!("{}", failure);
+            failure.code.exit();
+        }
+    }
+}
+
+// Diff two image sets, returning how many images differ
+fn diff_image_sets(args: &ImagesDiffArgs) -> Result<usize, Failure> {
+    for dir in [&args.old_dir, &args.new_dir] {
+        if !Path::new(dir).is_dir() {
+            return Err(Failure::input(format!("Error: {} is not a directory", dir)));
+        }
+    }
+    let pairs = lib::raster::pair_by_name(Path::new(&args.old_dir), Path::new(&args.new_dir))
+        .map_err(|e| Failure::input(format!("Error listing images: {}", e)))?;
+    if pairs.is_empty() {
+        return Err(Failure::input(format!("No images in {} or {}", args.old_dir, args.new_dir)));
+    }
+    std::fs::create_dir_all(&args.output_dir).map_err(|e| format!("Error creating {}: {}", args.output_dir, e))?;
+
+    let open = |path: &Option<PathBuf>| -> Result<Option<DynamicImage>, Failure> {
+        match path {
+            Some(path) => image::open(path).map(Some).map_err(|e| Failure::input(format!("Error loading {}: {}", path.display(), e))),
+            None => Ok(None),
+        }
+    };
+
+    let mut changed = 0;
+    for (name, old_path, new_path) in &pairs {
+        let (old_image, new_image) = (open(old_path)?, open(new_path)?);
+        // A blank screenshot is still a screenshot, so compare it like any other
+        let status = PageStatus::with_policy(old_image.as_ref(), new_image.as_ref(), BlankPolicy::Change);
+        match status {
+            PageStatus::Unchanged => {
+                if args.verbose {
+                    println!("{}: unchanged", name);
+                }
+                continue;
+            }
+            PageStatus::Changed => {
+                let stem = Path::new(name).file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+                let diff_path = Path::new(&args.output_dir).join(format!("{}_diff.png", stem));
+                let diff = lib::image_utils::diff_images(vec![(old_image, new_image)], args.sensitivity)
+                    .map_err(|e| format!("Error diffing {}: {}", name, e))?;
+                if let Some(image) = diff.first() {
+                    lib::image_utils::save_png_with(image, &diff_path, args.png.options())
+                        .map_err(|e| format!("Error saving {}: {}", diff_path.display(), e))?;
+                }
+                println!("{}: changed, diff saved to {}", name, diff_path.display());
+            }
+            _ => println!("{}: {}", name, status.label()),
+        }
+        changed += 1;
+    }
+
+    println!("{} of {} images differ", changed, pairs.len());
+    Ok(changed)
+}
+
+fn diff_command(args: DiffArgs) {
+    JSON_OUTPUT.store(args.json, Ordering::Relaxed);
+    lib::hooks::set_output_to_stderr(args.json);
+    if let Some(job) = args.worker.clone() {
+        run_worker_job(args, &job);
+        return;
+    }
+
+    // Limits apply to worker processes, so sandboxing means isolating
+    #[cfg(feature = "sandbox")]
+    let args = DiffArgs {
+        isolate: args.isolate || args.sandbox,
+        ..args
+    };
+
+    if args.verbose {
+        say!("PDF Diff Tool v0.1.0");
+        say!("{}", describe_pdfium(&args.pdfium_path));
+        if let Some(profile) = &args.profile {
+            say!("Profile: {}", profile);
+        }
+        if let Some(old_pdf) = &args.old_pdf {
+            say!("Old PDF: {}", old_pdf);
+        }
+        if let Some(baseline_dir) = &args.baseline_dir {
+            say!("Baseline directory: {}", baseline_dir);
+        }
+        for new_pdf in &args.new_pdfs {
+            say!("New PDF: {}", new_pdf);
+        }
+        say!("Output directory: {}", args.output_dir);
+        say!("DPI: {}", args.dpi);
+        say!("Sensitivity: {}", args.sensitivity);
+        say!();
+    }
+
+    // Outputs for an object store are written to a staging directory and
+    // uploaded after each run
+    #[cfg(feature = "cloud")]
+    let upload = if lib::cloud::is_object_store_uri(&args.output_dir) {
+        let uri = match lib::cloud::ObjectStoreUri::parse(&args.output_dir) {
+            Ok(uri) => uri,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                ExitCode::Usage.exit();
+            }
+        };
+        let staging = lib::cloud::staging_dir(&uri);
+        std::fs::remove_dir_all(&staging).ok();
+        Some((uri, staging))
+    } else {
+        None
+    };
+    #[cfg(feature = "cloud")]
+    let args = match &upload {
+        Some((_, staging)) => DiffArgs { output_dir: staging.display().to_string(), ..args },
+        None => args,
+    };
+
+    #[cfg(not(feature = "cloud"))]
+    if args.output_dir.starts_with("s3://") {
+        eprintln!("Error: writing to {} needs a build with the `cloud` feature", args.output_dir);
+        ExitCode::Usage.exit();
+    }
+
+    // Outputs are written next to the output directory and moved into it
+    // when the run is over, so a run that crashes or is killed never leaves a
+    // half-written report there. `--resume` picks up earlier outputs and
+    // `--watch` keeps serving the directory, so they write in place.
+    let output_dir = args.output_dir.clone();
+    let staging = (!args.resume && !args.watch)
+        .then(|| lib::output_dir::staging_dir(Path::new(&output_dir)))
+        .flatten();
+    #[cfg(feature = "cloud")]
+    let staging = staging.filter(|_| upload.is_none());
+    if let Some(staging) = &staging {
+        std::fs::remove_dir_all(staging).ok();
+    }
+    let mut args = match &staging {
+        Some(staging) => DiffArgs {
+            output_dir: staging.display().to_string(),
+            final_output_dir: Some(output_dir.clone()),
+            ..args
+        },
+        None => args,
+    };
+
+    let path_old = args.old_pdf.as_deref().map(Path::new);
+
+    // Validate input files exist
+    if let Some(path_old) = path_old
+        && !path_old.exists()
+    {
+        eprintln!("Error: Old PDF file does not exist: {}", path_old.display());
+        ExitCode::Input.exit();
+    }
+
+    if let Some(manifest) = &args.manifest
+        && !Path::new(manifest).is_file()
+    {
+        eprintln!("Error: Manifest file does not exist: {}", manifest);
+        ExitCode::Input.exit();
+    }
+
+    if let Some(baseline_dir) = &args.baseline_dir {
+        if !Path::new(baseline_dir).is_dir() {
+            eprintln!("Error: Baseline directory does not exist: {}", baseline_dir);
+            ExitCode::Input.exit();
+        }
+
+        if args.new_pdfs.len() != 1 {
+            eprintln!("Error: --baseline-dir compares exactly one --new PDF");
+            ExitCode::Usage.exit();
+        }
+
+        // Baselines are ranked by rendering the new PDF
+        if matches!(lib::raster::reference_images(&args.new_pdfs[0]), Ok(Some(_))) {
+            eprintln!("Error: --baseline-dir needs a new PDF, not reference images");
+            ExitCode::Usage.exit();
+        }
+    }
+
+    let scale = output_scale(&args);
+    if !(scale > 0.0 && scale <= 1.0) {
+        eprintln!("Error: Saved images can only be scaled down; --output-scale must be in (0, 1] and --output-dpi at most --dpi");
+        ExitCode::Usage.exit();
+    }
+
+    if !(0.0..=1.0).contains(&args.overlay_opacity) {
+        eprintln!("Error: --overlay-opacity must be between 0 and 1");
+        ExitCode::Usage.exit();
+    }
+
+    for new_pdf in &args.new_pdfs {
+        match lib::raster::reference_images(new_pdf) {
+            Ok(Some(images)) if images.is_empty() => {
+                eprintln!("Error: No reference images in {}", new_pdf);
+                ExitCode::Input.exit();
+            }
+            Ok(Some(images)) => {
+                if let Some(missing) = images.iter().find(|image| !image.is_file()) {
+                    eprintln!("Error: Reference image does not exist: {}", missing.display());
+                    ExitCode::Input.exit();
+                }
+            }
+            Ok(None) if !Path::new(new_pdf).exists() => {
+                eprintln!("Error: New PDF file does not exist: {}", new_pdf);
+                ExitCode::Input.exit();
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Error: Could not read reference images in {}: {}", new_pdf, e);
+                ExitCode::Input.exit();
+            }
+        }
+    }
+
+    // Before any output is written, since suggesting is all a run does
+    if let Some(mode) = args.calibrate {
+        let calibration = match calibrate_sensitivity(&args) {
+            Ok(calibration) => calibration,
+            Err(failure) => {
+                eprintln!("Error: {}", failure);
+                failure.code.exit();
+            }
+        };
+        let noise = format!(
+            "Rendering noise: {:.1}% color difference over {} pages; suggested sensitivity {}",
+            calibration.noise * 100.0,
+            calibration.pages,
+            calibration.sensitivity
+        );
+        // The suggestion is the run's only result, so `--json` doesn't hide it
+        if mode == CalibrationMode::Suggest {
+            println!("{}", noise);
+            println!("Run with --sensitivity {} to use it, or --calibrate apply to measure it each run", calibration.sensitivity);
+            return;
+        }
+        say!("{}", noise);
+        args.sensitivity = calibration.sensitivity;
+    }
+
+    if let Err(failure) = prepare_output_dir(&args) {
+        eprintln!("Error: {}", failure);
+        failure.code.exit();
+    }
+
+    if args.verbose {
+        say!("Creating PDFium instance...");
+    }
+
+    // Concurrent comparisons take turns loading and rendering with PDFium
+    let pool = match PdfiumPool::new(&args.pdfium_path) {
+        Ok(pool) => pool,
+        Err(e) => {
+            eprintln!("Error creating PDFium instance: {}", e);
+            ExitCode::Internal.exit();
+        }
+    };
+
+    // The first Ctrl-C stops the run between pages and keeps what is done
+    let cancel = CancelToken::new();
+    if let Err(e) = cancel_on_interrupt(&cancel) {
+        eprintln!("Warning: Ctrl-C will stop without writing partial results: {}", e);
+    }
+
+    let metrics = Metrics::new();
+    let result = run_all(&args, &pool, &metrics, &cancel);
+    write_metrics_file(&args, &metrics);
+    let interrupted = cancel.is_cancelled();
+    // Nothing is left to stop gracefully, so Ctrl-C exits right away again
+    cancel.cancel();
+
+    // Interrupted runs are published too; their reports say they are partial
+    if let Some(staging) = &staging {
+        if let Err(e) = lib::output_dir::publish(staging, Path::new(&output_dir)) {
+            eprintln!("Error moving outputs from {} to {}: {}", staging.display(), output_dir, e);
+            ExitCode::Internal.exit();
+        }
+        if args.verbose {
+            say!("Moved outputs to '{}'", output_dir);
+        }
+    }
+    args.output_dir = output_dir;
+    args.final_output_dir = None;
+
+    #[cfg(feature = "cloud")]
+    if let Some(upload) = &upload {
+        if let Err(message) = upload_outputs(upload) {
+            eprintln!("{}", message);
+            if !args.watch {
+                ExitCode::Internal.exit();
+            }
+        }
+        // Nothing is left on disk unless it is still needed for serving
+        if interrupted || (!args.watch && args.serve.is_none()) {
+            std::fs::remove_dir_all(&upload.1).ok();
+        }
+    }
+
+    if interrupted {
+        exit_interrupted(result);
+    }
+
+    let status = match result {
+        Ok(status) => status,
+        Err(failure) => {
+            eprintln!("{}", failure);
+            if !args.watch {
+                failure.code.exit();
+            }
+            failure.code
+        }
+    };
+
+    let url = args.serve.map(|port| format!("http://127.0.0.1:{}/", port));
+
+    if args.watch {
+        // Serve in the background and push a reload to connected browsers after each run
+        let live_reload = lib::server::LiveReload::new();
+
+        if let (Some(port), Some(url)) = (args.serve, &url) {
+            let listener = match lib::server::bind(port) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    eprintln!("Error serving report: {}", e);
+                    ExitCode::Internal.exit();
+                }
+            };
+
+            let root = args.output_dir.clone();
+            let server_reload = live_reload.clone();
+            let server_metrics = metrics.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = lib::server::serve(listener, &root, Some(server_reload), Some(server_metrics)) {
+                    eprintln!("Error serving report: {}", e);
+                }
+            });
+
+            say!("Serving report at {} with live reload", url);
+            if args.open && let Err(e) = lib::server::open_in_browser(url) {
+                eprintln!("Warning: {}", e);
+            }
+        }
+
+        let new_files: Vec<String> = args.new_pdfs.iter().flat_map(|new_pdf| input_files(new_pdf)).collect();
+        let mut watched: Vec<&Path> = path_old.into_iter().collect();
+        watched.extend(new_files.iter().map(Path::new));
+        watched.extend(args.manifest.as_deref().map(Path::new));
+        let mut watcher = lib::watch::FileWatcher::new(&watched);
+        say!("Watching {} input files for changes (press Ctrl-C to stop)", watched.len());
+
+        loop {
+            watcher.wait_for_change();
+            say!("Change detected, re-comparing...");
+
+            cancel.reset();
+            let result = run_all(&args, &pool, &metrics, &cancel);
+            write_metrics_file(&args, &metrics);
+            let interrupted = cancel.is_cancelled();
+            cancel.cancel();
+
+            #[cfg(feature = "cloud")]
+            if let Some(upload) = &upload {
+                if let Err(message) = upload_outputs(upload) {
+                    eprintln!("{}", message);
+                }
+                if interrupted {
+                    std::fs::remove_dir_all(&upload.1).ok();
+                }
+            }
+
+            if interrupted {
+                exit_interrupted(result);
+            }
+
+            match result {
+                Ok(_) => live_reload.notify(),
+                Err(failure) => eprintln!("{}", failure),
+            }
+        }
+    }
+
+    if let (Some(port), Some(url)) = (args.serve, &url) {
+        say!("Serving report at {} (press Ctrl-C to stop)", url);
+
+        if args.open && let Err(e) = lib::server::open_in_browser(url) {
+            eprintln!("Warning: {}", e);
+        }
+
+        if let Err(e) = lib::server::serve_directory(&args.output_dir, port, Some(metrics)) {
+            eprintln!("Error serving report: {}", e);
+            ExitCode::Internal.exit();
+        }
+    }
+
+    status.exit();
+}
+
+// Rendering noise of the old PDF at the comparison's DPI and render flags
+fn calibrate_sensitivity(args: &DiffArgs) -> Result<Calibration, Failure> {
+    let old_pdf = args.old_pdf.as_deref().ok_or_else(|| Failure::usage("--calibrate needs an old PDF"))?;
+    let pdfium = create_pdfium_at(&args.pdfium_path).map_err(|e| format!("Error creating PDFium instance: {}", e))?;
+    let document = lib::pdf::load_pdf_document_with_limits(&pdfium, Path::new(old_pdf), &args.limits.limits())
+        .map_err(|e| Failure::input(format!("Error loading {}: {}", old_pdf, e)))?;
+    let options = RenderOptions {
+        flags: args.render_flags.clone(),
+        limits: args.limits.limits(),
+        ..RenderOptions::new(args.dpi)
+    };
+    lib::calibrate::calibrate(&document, &options).map_err(|e| Failure::rendering(format!("Error calibrating on {}: {}", old_pdf, e)))
+}
+
+// Run one comparison for a parent started with `--isolate` and report the
+// outcome through the job's result file
+fn run_worker_job(args: DiffArgs, job: &str) {
+    let job: WorkerJob = match serde_json::from_str(job) {
+        Ok(job) => job,
+        Err(e) => {
+            eprintln!("Error: invalid worker job: {}", e);
+            ExitCode::Internal.exit();
+        }
+    };
+    // The job's directory is the only one the worker writes to
+    let args = DiffArgs {
+        output_dir: job.output_dir.clone(),
+        final_output_dir: job.final_output_dir.clone(),
+        ..args
+    };
+
+    // The terminal interrupts the whole process group, so the worker stops
+    // gracefully along with its parent
+    let cancel = CancelToken::new();
+    cancel_on_interrupt(&cancel).ok();
+
+    // What is left of the parent's budget when the job started
+    let budget = job.output_budget.map(|limit| OutputBudget::new(limit, args.over_budget));
+    let result = PdfiumPool::new(&args.pdfium_path)
+        .map_err(|e| Failure::from(format!("Error creating PDFium instance: {}", e)))
+        .and_then(|pool| run_comparison(&args, &pool, &job.comparison, &job.output_dir, budget.as_ref(), &cancel));
+
+    if let Err(e) = write_worker_result(&job, &result) {
+        eprintln!("Error reporting result of {}: {}", job.comparison.name, e);
+        ExitCode::Internal.exit();
+    }
+}
+
+// Run every planned comparison. A single new PDF writes straight into the
+// output directory; several (or a manifest) get one subdirectory each plus
+// an index report. Returns the run's exit status.
+fn run_all(args: &DiffArgs, pool: &PdfiumPool, metrics: &Metrics, cancel: &CancelToken) -> Result<ExitCode, Failure> {
+    let comparisons = match &args.manifest {
+        Some(manifest) => {
+            let entries = load_manifest(Path::new(manifest)).map_err(|e| Failure::input(format!("Error reading manifest: {}", e)))?;
+            let comparisons = manifest_comparisons(&entries);
+            run_pre_hook(args, &input_pdfs(&comparisons))?;
+            comparisons
+        }
+        None => {
+            // Baselines are read to rank them, so the hook sees all of them
+            let mut inputs = match (&args.old_pdf, &args.baseline_dir) {
+                (Some(old_pdf), _) => vec![old_pdf.clone()],
+                (None, Some(baseline_dir)) => {
+                    list_pdfs(Path::new(baseline_dir)).map_err(|e| Failure::input(format!("Error reading baseline directory: {}", e)))?
+                }
+                (None, None) => vec![],
+            };
+            inputs.extend(args.new_pdfs.iter().flat_map(|new_pdf| input_files(new_pdf)));
+            run_pre_hook(args, &inputs)?;
+
+            let old_pdf = match (&args.old_pdf, &args.baseline_dir) {
+                (Some(old_pdf), _) => old_pdf.clone(),
+                (None, Some(baseline_dir)) => closest_baseline(args, pool, baseline_dir)?,
+                (None, None) => unreachable!("clap requires --old, --baseline-dir or --manifest"),
+            };
+
+            let mut documents = vec![old_pdf];
+            documents.extend(args.new_pdfs.iter().cloned());
+            plan_comparisons(&documents, args.n_way)
+        }
+    };
+
+    let budget = args.max_output_size.map(|size| OutputBudget::new(size.bytes, args.over_budget));
+    if !is_multi_comparison(args)
+        && let [comparison] = comparisons.as_slice()
+    {
+        let summary = timed_comparison(args, pool, metrics, comparison, &args.output_dir, budget.as_ref(), cancel)?;
+        write_checksums_file(args, &comparisons, cancel)?;
+        let summaries = [summary];
+        #[cfg(feature = "history")]
+        record_history(args, &summaries)?;
+        print_json_summary(args, &summaries)?;
+        run_post_hook(args, &summaries, cancel)?;
+        run_verdict(args, &summaries, cancel)?;
+        return Ok(run_status(&summaries));
+    }
+
+    let settings = output_settings(args);
+    let results = run_parallel(&comparisons, effective_jobs(args.jobs), |comparison| {
+        let output_dir = Path::new(&args.output_dir).join(&comparison.name).to_string_lossy().into_owned();
+        let fingerprint = lib::resume::fingerprint(comparison, &settings).ok();
+
+        if args.resume
+            && let Some(fingerprint) = &fingerprint
+            && let Some(summary) = lib::resume::load_completed(&output_dir, fingerprint)
+        {
+            say!("Skipping {} with {} (up to date)", comparison.old_pdf, comparison.new_pdf);
+            if let Some(budget) = &budget {
+                budget.record(dir_size(Path::new(&output_dir)));
+            }
+            return (output_dir, Some(Ok(summary)));
+        }
+
+        // Pairs not started before cancelling are left out of the index
+        if cancel.is_cancelled() {
+            return (output_dir, None);
+        }
+
+        say!("Comparing {} with {}", comparison.old_pdf, comparison.new_pdf);
+        lib::resume::clear_completed(&output_dir);
+        let result = timed_comparison(args, pool, metrics, comparison, &output_dir, budget.as_ref(), cancel);
+
+        // An interrupted comparison is redone by the next `--resume` run
+        if let (Ok(summary), Some(fingerprint)) = (&result, &fingerprint)
+            && !summary.interrupted
+            && let Err(e) = lib::resume::mark_completed(&output_dir, fingerprint, summary)
+        {
+            eprintln!("Warning: could not record completion of {}: {}", output_dir, e);
+        }
+        (output_dir, Some(result))
+    });
+
+    let mut summaries = vec![];
+    let mut dirs = vec![];
+    let mut first_failure = None;
+    for (comparison, (output_dir, result)) in comparisons.iter().zip(results) {
+        let Some(result) = result else {
+            continue;
+        };
+        let summary = match result {
+            Ok(summary) => summary,
+            // One broken pair shouldn't stop a batch run; it is reported at the end
+            Err(failure) if args.manifest.is_some() || args.isolate => {
+                eprintln!("{}", failure);
+                first_failure.get_or_insert(failure.code);
+                ComparisonSummary::failed(&comparison.old_pdf, &comparison.new_pdf, &final_path(args, &output_dir), &failure.message)
+            }
+            Err(failure) => return Err(failure),
+        };
+        summaries.push(summary);
+        dirs.push(comparison.name.clone());
+    }
+
+    if summaries.len() < comparisons.len() {
+        say!(
+            "Interrupted: {} of {} document pairs were not compared",
+            comparisons.len() - summaries.len(),
+            comparisons.len()
+        );
+    }
+
+    match lib::report::write_index_report(&summaries, &dirs, &args.output_dir) {
+        Ok(path) => say!("{}", tr!("cli-consolidated-report-saved", path = path.display())),
+        Err(e) => return Err(format!("Error writing consolidated report: {}", e).into()),
+    }
+
+    let aggregate = AggregateSummary::from_summaries(&summaries);
+    match lib::report::write_summary_json(&aggregate, &summaries, &args.output_dir) {
+        Ok(path) => say!("{}", tr!("cli-summary-saved", path = path.display())),
+        Err(e) => return Err(format!("Error writing summary: {}", e).into()),
+    }
+
+    let compared = tr!(
+        "cli-compared-documents",
+        total = aggregate.total_documents,
+        changed = aggregate.documents_with_differences,
+        failed = aggregate.failed_documents
+    );
+    say!("{}", compared);
+    let area = format!("{:.2}", aggregate.total_changed_area);
+    let changed = tr!(
+        "cli-changed-pages",
+        changed = aggregate.changed_pages,
+        total = aggregate.total_pages,
+        blank = aggregate.blank_pages,
+        area = area
+    );
+    say!("{}", changed);
+    if let Some(worst) = aggregate.worst_pages.first() {
+        say!(
+            "Most changed page: {} vs {} page {} ({:.2}%)",
+            worst.old_pdf,
+            worst.new_pdf,
+            worst.page,
+            worst.ratio * 100.0
+        );
+    }
+
+    print_json_summary(args, &summaries)?;
+
+    // Failed pairs are part of the evidence too
+    write_checksums_file(args, &comparisons, cancel)?;
+    #[cfg(feature = "history")]
+    record_history(args, &summaries)?;
+    run_post_hook(args, &summaries, cancel)?;
+    run_verdict(args, &summaries, cancel)?;
+
+    // The batch exits like its first failed pair would have on its own
+    if let Some(code) = first_failure {
+        return Err(Failure::new(
+            code,
+            format!("{} of {} comparisons failed", aggregate.failed_documents, aggregate.total_documents),
+        ));
+    }
+
+    Ok(run_status(&summaries))
+}
+
+// Exit status of a run that finished: whether any compared documents differ
+fn run_status(summaries: &[ComparisonSummary]) -> ExitCode {
+    if AggregateSummary::from_summaries(summaries).documents_with_differences > 0 {
+        ExitCode::Differences
+    } else {
+        ExitCode::Identical
+    }
+}
+
+// Files a document argument stands for: the PDF itself, or the reference
+// images listed in its place
+fn input_files(document: &str) -> Vec<String> {
+    match lib::raster::reference_images(document) {
+        Ok(Some(images)) => images.iter().map(|image| image.to_string_lossy().into_owned()).collect(),
+        _ => vec![document.to_string()],
+    }
+}
+
+// Every input file of the comparisons, each once
+fn input_pdfs(comparisons: &[Comparison]) -> Vec<String> {
+    let mut inputs: Vec<String> = vec![];
+    for comparison in comparisons {
+        for file in input_files(&comparison.old_pdf).into_iter().chain(input_files(&comparison.new_pdf)) {
+            if !inputs.contains(&file) {
+                inputs.push(file);
+            }
+        }
+    }
+    inputs
+}
+
+// Append the run's comparisons to the `--history` database
+#[cfg(feature = "history")]
+fn record_history(args: &DiffArgs, summaries: &[ComparisonSummary]) -> Result<(), String> {
+    let Some(path) = &args.history else {
+        return Ok(());
+    };
+    let recorded = lib::metadata::rfc3339(SystemTime::now());
+    lib::history::History::open(Path::new(path))
+        .and_then(|mut history| Ok(history.record_run(&recorded, summaries)?))
+        .map_err(|e| format!("Error recording the run in {}: {}", path, e))?;
+    if args.verbose {
+        say!("Recorded the run in {}", path);
+    }
+    Ok(())
+}
+
+// Print the run's summary for `--json`, the one thing it writes to stdout
+fn print_json_summary(args: &DiffArgs, summaries: &[ComparisonSummary]) -> Result<(), String> {
+    if !args.json {
+        return Ok(());
+    }
+
+    let aggregate = AggregateSummary::from_summaries(summaries);
+    let json = lib::report::summary_json(&aggregate, summaries).map_err(|e| format!("Error writing JSON summary: {}", e))?;
+    println!("{}", json);
+    Ok(())
+}
+
+// Run `--pre-hook` with the input PDFs before any of them is read
+fn run_pre_hook(args: &DiffArgs, inputs: &[String]) -> Result<(), String> {
+    let Some(command) = &args.pre_hook else {
+        return Ok(());
+    };
+
+    if args.verbose {
+        say!("Running pre-hook: {}", command);
+    }
+    lib::hooks::run_hook("Pre-hook", command, inputs)
+}
+
+// Run `--post-hook` with the run's summary file once the outputs are written.
+// A single comparison doesn't otherwise write a summary, so it is written
+// here. An interrupted run isn't worth publishing.
+fn run_post_hook(args: &DiffArgs, summaries: &[ComparisonSummary], cancel: &CancelToken) -> Result<(), String> {
+    let Some(command) = &args.post_hook else {
+        return Ok(());
+    };
+    if cancel.is_cancelled() {
+        return Ok(());
+    }
+
+    let aggregate = AggregateSummary::from_summaries(summaries);
+    let summary_path = lib::report::write_summary_json(&aggregate, summaries, &args.output_dir)
+        .map_err(|e| format!("Error writing summary: {}", e))?;
+
+    if args.verbose {
+        say!("Running post-hook: {}", command);
+    }
+    lib::hooks::run_hook("Post-hook", command, &[summary_path.display().to_string()])
+}
+
+// Ask `--verdict-command` whether a finished run passes. An interrupted run
+// has no verdict.
+fn run_verdict(args: &DiffArgs, summaries: &[ComparisonSummary], cancel: &CancelToken) -> Result<(), Failure> {
+    let Some(command) = &args.verdict_command else {
+        return Ok(());
+    };
+    if cancel.is_cancelled() {
+        return Ok(());
+    }
+
+    if args.verbose {
+        say!("Running verdict command: {}", command);
+    }
+    lib::verdict::run_verdict_command(command, summaries)?;
+    say!("Passed verdict command");
+    Ok(())
+}
+
+// Record digests of the outputs and inputs of a finished run with the options
+// that produced them, for `--checksums`. Partial outputs of an interrupted run
+// aren't worth vouching for.
+fn write_checksums_file(args: &DiffArgs, comparisons: &[Comparison], cancel: &CancelToken) -> Result<(), String> {
+    if !args.checksums || cancel.is_cancelled() {
+        return Ok(());
+    }
+
+    let mut inputs: Vec<String> = args.manifest.iter().cloned().collect();
+    inputs.extend(input_pdfs(comparisons));
+
+    let manifest = lib::checksums::ChecksumManifest {
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        pdfium: describe_pdfium(&args.pdfium_path),
+        arguments: std::env::args().skip(1).collect(),
+        environment: lib::checksums::option_environment(),
+        settings: output_settings(args),
+        inputs: lib::checksums::digest_inputs(&inputs).map_err(|e| format!("Error hashing input files: {}", e))?,
+        outputs: lib::checksums::digest_outputs(&args.output_dir).map_err(|e| format!("Error hashing output files: {}", e))?,
+    };
+    match lib::checksums::write_checksums(&manifest, &args.output_dir) {
+        Ok(path) => say!("Checksums saved to {}", path.display()),
+        Err(e) => return Err(format!("Error writing checksums: {}", e)),
+    }
+    Ok(())
+}
+
+// Command line settings that change what a comparison writes, used to decide
+// whether `--resume` can reuse earlier outputs. The fingerprint is its debug
+// form, so every field counts without being listed twice; the fields are
+// only ever read through it.
+#[derive(Debug)]
+#[allow(dead_code)]
+struct OutputSettings<'a> {
+    dpi: f32,
+    prescan_dpi: Option<f32>,
+    render_flags: &'a [RenderFlag],
+    pairing: PairingMode,
+    min_page_similarity: f64,
+    pair_pages: &'a Option<BTreeMap<usize, usize>>,
+    max_pages: Option<usize>,
+    top_changes: Option<usize>,
+    sample: &'a Option<Sampling>,
+    size_tolerance: &'a Option<Length>,
+    sensitivity: f32,
+    calibrate: &'a Option<CalibrationMode>,
+    icc_profile: &'a Option<String>,
+    rendering_intent: Intent,
+    spot_colors: bool,
+    normalize: &'a Option<Normalization>,
+    scan_pipeline: ScanMode,
+    binarize: &'a Option<Binarization>,
+    algorithm: Algorithm,
+    compare: ContentLayer,
+    crop_to_content: bool,
+    white_tolerance: u8,
+    alpha_threshold: u8,
+    export_crops: bool,
+    contact_sheet: bool,
+    html_report: bool,
+    report_pages_per_file: usize,
+    pdf_report: bool,
+    reports: &'a [ReportFormat],
+    no_metadata: bool,
+    diff_output: DiffOutput,
+    layout: Layout,
+    overlay: OverlayStyle,
+    png: PngOptions,
+    max_output_size: Option<u64>,
+    over_budget: OverBudget,
+    blank_pages: BlankPolicy,
+    skip_duplicate_pages: bool,
+    ignore_regions: &'a [IgnoreRegion],
+    ignore_file: &'a Option<String>,
+    no_ignore_file: bool,
+    approvals: &'a Option<String>,
+    approve_changes: bool,
+    strict: bool,
+    limits: InputLimits,
+    fail_on_font_substitution: bool,
+    fail_on_warning: bool,
+    severity_weights: &'a SeverityWeights,
+    fail_severity: Option<f64>,
+    verify_deterministic: bool,
+    auto_orient: bool,
+    #[cfg(feature = "video")]
+    video: &'a Option<lib::video::VideoMode>,
+    #[cfg(feature = "video")]
+    video_format: &'a lib::video::VideoFormat,
+    #[cfg(feature = "plugins")]
+    plugins: &'a [String],
+}
+
+fn output_settings(args: &DiffArgs) -> String {
+    let settings = OutputSettings {
+        dpi: args.dpi,
+        prescan_dpi: args.prescan_dpi,
+        render_flags: &args.render_flags,
+        pairing: args.pairing,
+        min_page_similarity: args.min_page_similarity,
+        pair_pages: &args.pair_pages,
+        max_pages: args.max_pages,
+        top_changes: args.top_changes,
+        sample: &args.sample,
+        size_tolerance: &args.size_tolerance,
+        sensitivity: args.sensitivity,
+        calibrate: &args.calibrate,
+        icc_profile: &args.icc_profile,
+        rendering_intent: args.rendering_intent,
+        spot_colors: args.spot_colors,
+        normalize: &args.normalize,
+        scan_pipeline: args.scan_pipeline,
+        binarize: &args.binarize,
+        algorithm: args.algorithm,
+        compare: args.compare,
+        crop_to_content: args.crop_to_content,
+        white_tolerance: args.white_tolerance,
+        alpha_threshold: args.alpha_threshold,
+        export_crops: args.export_crops,
+        contact_sheet: args.contact_sheet,
+        html_report: args.html_report,
+        report_pages_per_file: args.report_pages_per_file,
+        pdf_report: args.pdf_report,
+        reports: &args.reports,
+        no_metadata: args.no_metadata,
+        diff_output: diff_output(args),
+        layout: args.layout,
+        overlay: overlay_style(args),
+        png: png_options(args, args.dpi),
+        max_output_size: args.max_output_size.map(|size| size.bytes),
+        over_budget: args.over_budget,
+        blank_pages: args.blank_pages,
+        skip_duplicate_pages: args.skip_duplicate_pages,
+        ignore_regions: &args.ignore_regions,
+        ignore_file: &args.ignore_file,
+        no_ignore_file: args.no_ignore_file,
+        approvals: &args.approvals,
+        approve_changes: args.approve_changes,
+        strict: args.strict,
+        limits: args.limits.limits(),
+        fail_on_font_substitution: args.fail_on_font_substitution,
+        fail_on_warning: args.fail_on_warning,
+        severity_weights: &args.severity_weights,
+        fail_severity: args.fail_severity,
+        verify_deterministic: args.verify_deterministic,
+        auto_orient: args.auto_orient,
+        #[cfg(feature = "video")]
+        video: &args.video,
+        #[cfg(feature = "video")]
+        video_format: &args.video_format,
+        #[cfg(feature = "plugins")]
+        plugins: &args.plugins,
+    };
+    format!("{:?}", settings)
+}
+
+// Plugins given with `--plugin`, in order. Loading a library that is already
+// loaded just hands out the same copy, so each comparison loads its own.
+#[cfg(feature = "plugins")]
+fn load_plugins(args: &DiffArgs) -> Result<Vec<lib::plugin::Plugin>, String> {
+    args.plugins.iter().map(|path| lib::plugin::Plugin::load(path)).collect()
+}
+
+// Diff images left by an earlier, longer run would pass for pages of this
+// one, so outputs only go into an empty directory unless `--force` or
+// `--resume` allows otherwise. `--clean` empties it first, refusing
+// directories that hold more than outputs. Refusing is a usage error; failing
+// to read or clean the directory is an internal one.
+fn prepare_output_dir(args: &DiffArgs) -> Result<(), Failure> {
+    let output_dir = args.final_output_dir.as_ref().unwrap_or(&args.output_dir);
+    let dir = Path::new(output_dir);
+    if args.clean {
+        let inputs: Vec<PathBuf> = args
+            .old_pdf
+            .iter()
+            .chain(&args.new_pdfs)
+            .chain(&args.manifest)
+            .chain(&args.baseline_dir)
+            .chain(&args.approvals)
+            .map(PathBuf::from)
+            .collect();
+        lib::output_dir::check_cleanable(dir, &inputs).map_err(Failure::usage)?;
+        let removed = lib::output_dir::clean_dir(dir).map_err(|e| format!("Could not clean {}: {}", output_dir, e))?;
+        if args.verbose && removed > 0 {
+            say!("Removed {} entries left by earlier runs from '{}'", removed, output_dir);
+        }
+        return Ok(());
+    }
+
+    if args.force || args.resume {
+        return Ok(());
+    }
+    match lib::output_dir::has_entries(dir) {
+        Ok(false) => Ok(()),
+        Ok(true) => Err(Failure::usage(format!(
+            "Output directory {} is not empty; pass --clean to remove earlier outputs or --force to write into it anyway",
+            output_dir
+        ))),
+        Err(e) => Err(format!("Could not read output directory {}: {}", output_dir, e).into()),
+    }
+}
+
+// Where `dir`, written to during the run, ends up once the outputs are published
+fn final_path(args: &DiffArgs, dir: &str) -> String {
+    match (&args.final_output_dir, dir.strip_prefix(args.output_dir.as_str())) {
+        (Some(final_dir), Some(rest)) => format!("{}{}", final_dir, rest),
+        _ => dir.to_string(),
+    }
+}
+
+// Run one comparison and record its outcome and duration in the metrics
+fn timed_comparison(
+    args: &DiffArgs,
+    pool: &PdfiumPool,
+    metrics: &Metrics,
+    comparison: &Comparison,
+    output_dir: &str,
+    budget: Option<&OutputBudget>,
+    cancel: &CancelToken,
+) -> Result<ComparisonSummary, Failure> {
+    let started = Instant::now();
+    let result = if args.isolate {
+        run_isolated(args, comparison, output_dir, budget)
+    } else {
+        run_comparison(args, pool, comparison, output_dir, budget, cancel)
+    };
+
+    match &result {
+        Ok(summary) => metrics.record_comparison(summary, started.elapsed()),
+        Err(failure) => metrics.record_comparison(
+            &ComparisonSummary::failed(&comparison.old_pdf, &comparison.new_pdf, output_dir, &failure.message),
+            started.elapsed(),
+        ),
+    }
+    result
+}
+
+// Arguments the run was started with, for the reports' metadata. Workers
+// leave out the `--worker` job their parent added.
+fn run_arguments() -> Vec<String> {
+    let mut arguments = vec![];
+    let mut given = std::env::args().skip(1);
+    while let Some(argument) = given.next() {
+        match argument.as_str() {
+            "--worker" => {
+                given.next();
+            }
+            _ => arguments.push(argument),
+        }
+    }
+    arguments
+}
+
+// Run one comparison in a worker process, confined by `--sandbox` if given
+fn run_isolated(args: &DiffArgs, comparison: &Comparison, output_dir: &str, budget: Option<&OutputBudget>) -> Result<ComparisonSummary, Failure> {
+    #[allow(unused_mut)]
+    let mut command = worker_command(std::env::args_os().skip(1))?;
 
-    /// Path to the new PDF file
-    #[arg(short = 'n', long = "new", help = "Path to the new PDF file")]
-    new_pdf: String,
+    #[cfg(feature = "sandbox")]
+    if args.sandbox {
+        let limits = lib::sandbox::SandboxLimits {
+            memory: args.sandbox_memory * 1024 * 1024,
+            cpu_time: args.sandbox_cpu_time,
+        };
+        lib::sandbox::confine(&mut command, limits)?;
+    }
 
-    /// Output directory for diff images
-    #[arg(short = 'd', long = "output-dir", default_value = "output", help = "Directory to save diff images")]
-    output_dir: String,
+    let job = WorkerJob {
+        final_output_dir: args.final_output_dir.is_some().then(|| final_path(args, output_dir)),
+        output_budget: budget.map(|budget| budget.remaining()),
+        ..WorkerJob::new(comparison, output_dir)
+    };
+    let result = run_worker(command, &job);
+    if let Some(budget) = budget {
+        budget.record(dir_size(Path::new(output_dir)));
+    }
+    result
+}
 
-    /// DPI for rendering (higher = better quality, slower processing)
-    #[arg(long = "dpi", default_value = "300", help = "DPI for PDF rendering")]
-    dpi: f32,
+// Upload the staged outputs of a run to their object store
+#[cfg(feature = "cloud")]
+fn upload_outputs((uri, staging): &(lib::cloud::ObjectStoreUri, PathBuf)) -> Result<(), String> {
+    if !staging.is_dir() {
+        return Ok(());
+    }
 
-    /// Diff sensitivity (0.0-1.0, lower = more sensitive)
-    #[arg(long = "sensitivity", default_value = "0.12", help = "Diff sensitivity threshold")]
-    sensitivity: f32,
+    let uploaded = lib::cloud::upload_dir(staging, uri)
+        .map_err(|e| format!("Error uploading outputs to {}: {}", uri.object(""), e))?;
+    say!("Uploaded {} files to {}", uploaded, uri.object(""));
+    Ok(())
+}
 
-    /// Verbose output
-    #[arg(short = 'v', long = "verbose", help = "Enable verbose output")]
-    verbose: bool,
+// Leave after a cancelled run, once its partial outputs are written
+fn exit_interrupted(result: Result<ExitCode, Failure>) -> ! {
+    if let Err(failure) = result {
+        eprintln!("{}", failure);
+    }
+    eprintln!("Interrupted; outputs cover the pages compared before cancelling");
+    std::process::exit(INTERRUPTED_EXIT_CODE);
 }
 
-fn main() {
-    let args = Args::parse();
+// Write the metrics file, if one was asked for, after each run
+fn write_metrics_file(args: &DiffArgs, metrics: &Metrics) {
+    if let Some(path) = &args.metrics_file
+        && let Err(e) = metrics.write_file(path)
+    {
+        eprintln!("Warning: could not write metrics to {}: {}", path, e);
+    }
+}
 
-    if args.verbose {
-        println!("PDF Diff Tool v0.1.0");
-        println!("Old PDF: {}", args.old_pdf);
-        println!("New PDF: {}", args.new_pdf);
-        println!("Output directory: {}", args.output_dir);
-        println!("DPI: {}", args.dpi);
-        println!("Sensitivity: {}", args.sensitivity);
-        println!();
+// Factor saved images are resized by, from `--output-scale` or `--output-dpi`
+fn output_scale(args: &DiffArgs) -> f32 {
+    match (args.output_scale, args.output_dpi) {
+        (Some(scale), _) => scale,
+        (None, Some(dpi)) => dpi / args.dpi,
+        (None, None) => 1.0,
     }
+}
 
-    let path_old = Path::new(&args.old_pdf);
-    let path_new = Path::new(&args.new_pdf);
+fn diff_output(args: &DiffArgs) -> DiffOutput {
+    match args.diff_only {
+        true => DiffOutput::DiffOnly,
+        false => args.diff_output,
+    }
+}
 
-    // Validate input files exist
-    if !path_old.exists() {
-        eprintln!("Error: Old PDF file does not exist: {}", args.old_pdf);
-        std::process::exit(1);
+fn overlay_style(args: &DiffArgs) -> OverlayStyle {
+    OverlayStyle {
+        opacity: args.overlay_opacity,
+        blend: args.blend_mode,
     }
+}
 
-    if !path_new.exists() {
-        eprintln!("Error: New PDF file does not exist: {}", args.new_pdf);
-        std::process::exit(1);
+// PNG settings for images rendered at `dpi`
+fn png_options(args: &DiffArgs, dpi: f32) -> PngOptions {
+    PngOptions {
+        scale: output_scale(args),
+        dpi: Some(dpi),
+        ..args.png.options()
     }
+}
+
+// Whether a report format was asked for, by `--report` or its own flag
+fn wants_report(args: &DiffArgs, format: ReportFormat) -> bool {
+    let flag = match format {
+        ReportFormat::Html => args.html_report,
+        ReportFormat::Pdf => args.pdf_report,
+        ReportFormat::Csv | ReportFormat::Json | ReportFormat::Markdown => false,
+    };
+    flag || args.reports.contains(&format)
+}
+
+// Several comparisons are written to per-pair subdirectories with an index report
+fn is_multi_comparison(args: &DiffArgs) -> bool {
+    args.manifest.is_some() || args.new_pdfs.len() > 1
+}
+
+// Rank every PDF in the baseline directory by its distance to the new PDF,
+// record the ranking in the output directory and return the closest match
+fn closest_baseline(args: &DiffArgs, pool: &PdfiumPool, baseline_dir: &str) -> Result<String, String> {
+    let candidates = list_pdfs(Path::new(baseline_dir))
+        .map_err(|e| format!("Error reading baseline directory: {}", e))?;
+    let path_new = Path::new(&args.new_pdfs[0]);
 
     if args.verbose {
-        println!("Creating PDFium instance...");
+        say!("Ranking {} baseline PDFs at {} DPI...", candidates.len(), MATCH_DPI);
     }
 
-    let pdfium = match create_pdfium() {
-        Ok(pdfium) => pdfium,
+    let match_options = RenderOptions {
+        flags: args.render_flags.clone(),
+        limits: args.limits.limits(),
+        ..RenderOptions::new(MATCH_DPI)
+    };
+
+    let mut ranking = vec![];
+    for candidate in candidates {
+        let pdfium = pool.lease();
+        let (old_document, new_document) = lib::pdf::load_pdf_documents_with_limits(&pdfium, Path::new(&candidate), path_new, &match_options.limits)
+            .map_err(|e| format!("Error loading baseline {}: {}", candidate, e))?;
+        let images = lib::pdf::create_images_from_pdf_with_options(&old_document, &new_document, &match_options)
+            .map_err(|e| format!("Error rendering baseline {}: {}", candidate, e))?;
+
+        let distance = document_distance(&images);
+        if args.verbose {
+            say!("Baseline {}: distance {:.4}", candidate, distance);
+        }
+        ranking.push((candidate, distance));
+    }
+
+    ranking.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let ranking_text: String = ranking
+        .iter()
+        .map(|(candidate, distance)| format!("{:.6}\t{}\n", distance, candidate))
+        .collect();
+    let ranking_path = Path::new(&args.output_dir).join("baseline_match.txt");
+    std::fs::create_dir_all(&args.output_dir)
+        .and_then(|_| std::fs::write(&ranking_path, ranking_text))
+        .map_err(|e| format!("Error writing baseline ranking: {}", e))?;
+
+    let (closest, distance) = ranking
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("No PDF files found in baseline directory: {}", baseline_dir))?;
+    say!("Closest baseline: {} (distance {:.4})", closest, distance);
+
+    Ok(closest)
+}
+
+// Pages using fonts that aren't embedded render with whatever the machine has
+// installed, so their diffs aren't reliable across machines. Warnings are
+// numbered by position in `pairing`; pages that aren't compared are left out.
+fn font_substitution_warnings<'a>(
+    old_document: &PdfDocument<'a>,
+    new_document: Option<&PdfDocument<'a>>,
+    pairing: &PagePairs,
+) -> Vec<PageWarning> {
+    let mut warnings = vec![];
+    let documents = [("old", Some(old_document)), ("new", new_document)];
+    for (label, document) in documents.into_iter().filter_map(|(label, document)| Some((label, document?))) {
+        for (page, fonts) in document_substituted_fonts(document) {
+            let position = pairing.iter().position(|&(old, new)| {
+                let index = if label == "old" { old } else { new };
+                index == Some(page - 1)
+            });
+            let Some(position) = position else {
+                continue;
+            };
+
+            for font in fonts {
+                warnings.push(PageWarning {
+                    page: position + 1,
+                    message: format!("Font '{}' is not embedded in the {} PDF and was substituted", font, label),
+                });
+            }
+        }
+    }
+    warnings
+}
+
+// Spot inks pages add or remove, as warnings numbered by position in
+// `pairing`, with the inks either document as a whole gained or lost printed
+fn spot_color_changes(
+    comparison: &Comparison,
+    pairing: &[(Option<usize>, Option<usize>)],
+    images: &[(Option<DynamicImage>, Option<DynamicImage>)],
+) -> Result<Vec<PageWarning>, String> {
+    let old = spot_colors(Path::new(&comparison.old_pdf)).map_err(|e| format!("Error reading spot colors of {}: {}", comparison.old_pdf, e))?;
+    let new = spot_colors(Path::new(&comparison.new_pdf)).map_err(|e| format!("Error reading spot colors of {}: {}", comparison.new_pdf, e))?;
+
+    let all = |pages: &[BTreeSet<String>]| pages.iter().flatten().cloned().collect::<BTreeSet<String>>();
+    let (added, removed) = lib::spot::ink_changes(&all(&old), &all(&new));
+    if !added.is_empty() {
+        say!("Spot colors added: {}", added.join(", "));
+    }
+    if !removed.is_empty() {
+        say!("Spot colors removed: {}", removed.join(", "));
+    }
+
+    let compared = |position: usize| images.get(position).is_some_and(|(old, new)| old.is_some() || new.is_some());
+    Ok(lib::spot::spot_color_warnings(&old, &new, pairing, compared))
+}
+
+// How `--pairing` and `--pair-pages` match up pages
+fn page_pairing(args: &DiffArgs) -> PagePairing {
+    if let Some(map) = &args.pair_pages {
+        return PagePairing::Explicit(map.clone());
+    }
+    match args.pairing {
+        PairingMode::Index => PagePairing::ByIndex,
+        PairingMode::Label => PagePairing::ByLabel,
+        PairingMode::Auto | PairingMode::Similarity => PagePairing::BySimilarity {
+            min_similarity: args.min_page_similarity,
+        },
+    }
+}
+
+// Match up the pages of the two documents. Pairing by position is the default;
+// when page counts differ `--pairing auto` pairs pages by text similarity so an
+// inserted title page doesn't shift every later page out of alignment.
+fn pair_pages<'a>(args: &DiffArgs, old_document: &PdfDocument<'a>, new_document: &PdfDocument<'a>) -> Result<PagePairs, Failure> {
+    let (old_count, new_count) = (old_document.pages().len() as usize, new_document.pages().len() as usize);
+    let pairing = page_pairing(args);
+    if let PagePairing::Explicit(map) = &pairing {
+        return lib::pairing::explicit_pairing(map, old_count, new_count).map_err(Failure::usage);
+    }
+    if (args.pairing == PairingMode::Auto && old_count == new_count) || old_count == 0 || new_count == 0 {
+        return Ok(index_pairing(old_count, new_count));
+    }
+
+    match pairing.pair(old_document, new_document) {
+        Ok(pairs) => Ok(pairs),
         Err(e) => {
-            eprintln!("Error creating PDFium instance: {}", e);
-            std::process::exit(1);
+            eprintln!("Warning: could not pair pages by {} ({}); pairing by position", pairing.describe(), e);
+            Ok(index_pairing(old_count, new_count))
         }
+    }
+}
+
+// Ignore rules of `--ignore-region` plus those from `--ignore-file` or, unless
+// disabled, the nearest `.diffpdfignore` above the working directory
+fn ignore_sections(args: &DiffArgs) -> Result<Vec<IgnoreSection>, String> {
+    let mut sections = vec![];
+    if !args.ignore_regions.is_empty() {
+        sections.push(IgnoreSection {
+            files: None,
+            pages: None,
+            rules: args.ignore_regions.iter().copied().map(IgnoreRule::Region).collect(),
+        });
+    }
+
+    let path = match &args.ignore_file {
+        Some(path) => Some(PathBuf::from(path)),
+        None if args.no_ignore_file => None,
+        None => std::env::current_dir().ok().and_then(|dir| find_ignore_file(&dir)),
     };
+    if let Some(path) = path {
+        if args.verbose {
+            say!("Loading ignore rules from {}", path.display());
+        }
+        sections.extend(load_ignore_file(&path).map_err(|e| format!("Error reading ignore rules: {}", e))?);
+    }
+    Ok(sections)
+}
+
+// Paint the ignored areas of every compared page white on both sides. Text
+// rules are looked up on both pages of a pair and masked on both renders, so
+// text that changed is hidden where it was as well as where it is now.
+// Without a new PDF (reference images), only the old page's text is known.
+// Page ranges are matched against each side's own page number, and a rule
+// covering either page of a pair masks both renders.
+fn mask_ignored<'a>(
+    sections: &[IgnoreSection],
+    comparison: &Comparison,
+    old_document: &PdfDocument<'a>,
+    new_document: Option<&PdfDocument<'a>>,
+    pairing: &[(Option<usize>, Option<usize>)],
+    images: &mut [(Option<DynamicImage>, Option<DynamicImage>)],
+    dpi: f32,
+) -> Result<Vec<PageWarning>, String> {
+    let mut warnings = vec![];
+    for (position, ((old_image, new_image), &(old_index, new_index))) in images.iter_mut().zip(pairing).enumerate() {
+        if old_image.is_none() && new_image.is_none() {
+            continue;
+        }
+
+        let mut regions = vec![];
+        let mut patterns = vec![];
+        let covers = |section: &IgnoreSection, index: Option<usize>| {
+            index.is_some_and(|index| section.applies_to(&comparison.old_pdf, &comparison.new_pdf, index + 1))
+        };
+        let applicable = sections.iter().filter(|section| covers(section, old_index) || covers(section, new_index));
+        for rule in applicable.flat_map(|section| &section.rules) {
+            match rule {
+                IgnoreRule::Region(region) => regions.push(*region),
+                IgnoreRule::Text(pattern) => patterns.push(pattern.clone()),
+            }
+        }
+
+        if !patterns.is_empty() {
+            for (document, index) in [(Some(old_document), old_index), (new_document, new_index)] {
+                let (Some(document), Some(index)) = (document, index) else {
+                    continue;
+                };
+                let page = document
+                    .pages()
+                    .get(index as PdfPageIndex)
+                    .map_err(|e| format!("Error loading page {} for ignore rules: {:?}", index + 1, e))?;
+                let found = text_regions(&page, index + 1, &patterns).map_err(|e| format!("Error applying ignore rules: {}", e))?;
+                regions.extend(found);
+            }
+        }
+
+        for image in [old_image, new_image].into_iter().flatten() {
+            mask_regions(image, &regions, dpi);
+        }
+        if !regions.is_empty() {
+            warnings.push(PageWarning {
+                page: position + 1,
+                message: format!("{} region(s) masked by ignore rules; changes there are not reported", regions.len()),
+            });
+        }
+    }
+    Ok(warnings)
+}
+
+// Reduce both renders of every compared page to the text or graphics layer,
+// using the text boxes of both pages of the pair (the old page's alone
+// against reference images)
+fn isolate_layers<'a>(
+    layer: ContentLayer,
+    old_document: &PdfDocument<'a>,
+    new_document: Option<&PdfDocument<'a>>,
+    pairing: &[(Option<usize>, Option<usize>)],
+    images: &mut [(Option<DynamicImage>, Option<DynamicImage>)],
+    dpi: f32,
+) -> Result<(), String> {
+    for ((old_image, new_image), &(old_index, new_index)) in images.iter_mut().zip(pairing) {
+        if old_image.is_none() && new_image.is_none() {
+            continue;
+        }
+
+        let mut boxes = vec![];
+        for (document, index) in [(Some(old_document), old_index), (new_document, new_index)] {
+            let (Some(document), Some(index)) = (document, index) else {
+                continue;
+            };
+            let page = document
+                .pages()
+                .get(index as PdfPageIndex)
+                .map_err(|e| format!("Error loading page {} for --compare: {:?}", index + 1, e))?;
+            boxes.extend(text_boxes(&page, index + 1).map_err(|e| format!("Error finding text boxes: {}", e))?);
+        }
+
+        for image in [old_image, new_image].into_iter().flatten() {
+            isolate_layer(image, layer, &boxes, dpi);
+        }
+    }
+    Ok(())
+}
+
+// Page renders of one comparison, as compared, and the outcome, before any
+// outputs are written
+struct ComparedPages {
+    images: Vec<(Option<DynamicImage>, Option<DynamicImage>)>,
+    summary: ComparisonSummary,
+    // 1-based pages rendered with substituted fonts
+    substituted_pages: Vec<usize>,
+    // Set when one of the documents has no pages
+    empty_document: Option<String>,
+    // Text and images of the pages at each position, empty when the renders
+    // no longer line up with the PDF's page coordinates
+    contents: Vec<PageContent>,
+    // Plugins loaded for the comparison, whose report stage runs once the
+    // outputs are written
+    #[cfg(feature = "plugins")]
+    plugins: Vec<lib::plugin::Plugin>,
+}
+
+// Load, pair, render and preprocess the pages of a comparison
+fn compare_pages(
+    args: &DiffArgs,
+    pool: &PdfiumPool,
+    comparison: &Comparison,
+    output_dir: &str,
+    cancel: &CancelToken,
+) -> Result<ComparedPages, Failure> {
+    let path_old = Path::new(&comparison.old_pdf);
+    let path_new = Path::new(&comparison.new_pdf);
+    let dpi = comparison.dpi.unwrap_or(args.dpi);
 
     if args.verbose {
-        println!("Loading PDF documents...");
+        say!("Loading PDF documents...");
     }
 
-    let (old_document, new_document) = match lib::pdf::load_pdf_documents(&pdfium, path_old, path_new) {
+    // Reference images from another tool can take the place of the new PDF
+    let references = match lib::raster::reference_images(&comparison.new_pdf) {
+        Ok(Some(paths)) => Some(lib::raster::load_images(&paths).map_err(|e| Failure::input(format!("Error loading reference images: {}", e)))?),
+        Ok(None) => None,
+        Err(e) => return Err(Failure::input(format!("Error reading reference images: {}", e))),
+    };
+
+    let pdfium = pool.lease();
+    let limits = args.limits.limits();
+    let loaded = match &references {
+        Some(_) => lib::pdf::load_pdf_document_with_limits(&pdfium, path_old, &limits)
+            .map(|old| (old, None))
+            .map_err(|e| e.context("Old PDF")),
+        None => lib::pdf::load_pdf_documents_with_limits(&pdfium, path_old, path_new, &limits).map(|(old, new)| (old, Some(new))),
+    };
+    let (old_document, new_document) = match loaded.map_err(|e| e.to_string()) {
         Ok((old, new)) => {
             if args.verbose {
-                println!("Loaded {} pages from old PDF", old.pages().len());
-                println!("Loaded {} pages from new PDF", new.pages().len());
+                say!("Loaded {} pages from old PDF", old.pages().len());
+                match (&new, &references) {
+                    (Some(new), _) => say!("Loaded {} pages from new PDF", new.pages().len()),
+                    (None, Some(references)) => say!("Loaded {} reference images", references.len()),
+                    (None, None) => {}
+                }
             }
             (old, new)
         },
         Err(e) => {
-            eprintln!("Error loading PDF files: {}", e);
-            std::process::exit(1);
+            return Err(Failure::input(format!("Error loading PDF files: {}", e)));
+        }
+    };
+
+    let old_page_count = old_document.pages().len() as usize;
+    let (pairing, new_page_count) = match (&new_document, &references) {
+        (Some(new_document), _) => (pair_pages(args, &old_document, new_document)?, new_document.pages().len() as usize),
+        // Images have no text to pair by
+        (None, references) => {
+            let count = references.as_ref().map_or(0, Vec::len);
+            (index_pairing(old_page_count, count), count)
         }
     };
+    // A truncated upload can load as a PDF without pages. Its counterpart's
+    // pages are all reported as added or removed, and the run still fails.
+    let empty_document = match (old_page_count, new_page_count) {
+        (0, 0) => return Err(Failure::input(format!("Neither {} nor {} has any pages", comparison.old_pdf, comparison.new_pdf))),
+        (0, count) => Some(format!("Old PDF {} has no pages; all {} new pages are reported as added", comparison.old_pdf, count)),
+        (count, 0) => Some(format!("New PDF {} has no pages; all {} old pages are reported as removed", comparison.new_pdf, count)),
+        _ => None,
+    };
+    if let Some(message) = &empty_document {
+        eprintln!("Warning: {}", message);
+    }
+
+    let paired_by_position = pairing == index_pairing(old_page_count, new_page_count);
+    if args.verbose && !paired_by_position {
+        say!("Paired pages by {}:", page_pairing(args).describe());
+        for (old, new) in &pairing {
+            say!("  {}", lib::report::pair_label(*old, *new));
+        }
+    }
+
+    let page_count = pairing.len();
+    let pages = (args.sample.is_some() || args.max_pages.is_some())
+        .then(|| select_pages(page_count, args.sample, args.max_pages));
+    let sampling = pages
+        .as_ref()
+        .and_then(|pages| describe_selection(args.sample, args.max_pages, pages.len(), page_count));
+
+    let mut font_warnings = font_substitution_warnings(&old_document, new_document.as_ref(), &pairing);
+    let mut options = RenderOptions {
+        dpi,
+        flags: args.render_flags.clone(),
+        pages,
+        pairing: Some(pairing),
+        cancel: Some(cancel.clone()),
+        limits,
+        size_tolerance: args.size_tolerance.map_or(0.0, |tolerance| tolerance.points),
+    };
+
+    if let Some(pages) = &options.pages {
+        font_warnings.retain(|warning| pages.binary_search(&(warning.page - 1)).is_ok());
+    }
+    let mut warnings = vec![];
 
     if args.verbose {
-        println!("Converting PDF pages to images...");
+        if let Some(sampling) = &sampling {
+            say!("Sampling pages: {}", sampling);
+        }
+        say!("Converting PDF pages to images...");
     }
 
-    let images = match lib::pdf::create_images_from_pdf(&old_document, &new_document, args.dpi) {
-        Ok(images) => {
+    // Reference images have one resolution, so there is nothing to prescan
+    let prescan = match (args.prescan_dpi, &new_document) {
+        (Some(prescan_dpi), Some(new_document)) if prescan_dpi < dpi => {
             if args.verbose {
-                println!("Generated {} image pairs", images.len());
+                say!("Prescanning pages at {} DPI...", prescan_dpi);
             }
-            images
-        },
-        Err(e) => {
-            eprintln!("Error creating images from PDF: {}", e);
-            std::process::exit(1);
+            // Failed pages come back as placeholders that differ, so they are rendered again
+            let prescan_options = RenderOptions { dpi: prescan_dpi, ..options.clone() };
+            let (prescan, _) = lib::pdf::create_images_from_pdf_resilient(&old_document, new_document, &prescan_options);
+            Some(prescan)
+        }
+        _ => None,
+    };
+    let render_options = match &prescan {
+        Some(prescan) => {
+            let changed = lib::pdf::changed_positions(prescan);
+            if args.verbose {
+                let compared = prescan.iter().filter(|pair| pair.0.is_some() || pair.1.is_some()).count();
+                say!("{} of {} pages differ; rendering them at {} DPI", changed.len(), compared, dpi);
+            }
+            RenderOptions { pages: Some(changed), ..options.clone() }
+        }
+        None => options.clone(),
+    };
+
+    let images = if args.strict {
+        let rendered = match (&new_document, references) {
+            (Some(new_document), _) => lib::pdf::create_images_from_pdf_with_options(&old_document, new_document, &render_options),
+            (None, references) => lib::pdf::create_images_against_references(&old_document, references.unwrap_or_default(), &render_options),
+        };
+        match rendered {
+            Ok(images) => images,
+            Err(e) => {
+                return Err(Failure::rendering(format!("Error creating images from PDF: {}", e)));
+            }
+        }
+    } else {
+        let (images, errors) = match (&new_document, references) {
+            (Some(new_document), _) => lib::pdf::create_images_from_pdf_resilient(&old_document, new_document, &render_options),
+            (None, references) => lib::pdf::create_images_against_references_resilient(&old_document, references.unwrap_or_default(), &render_options),
+        };
+        warnings.extend(errors.into_iter().map(|(page, message)| PageWarning { page, message }));
+        images
+    };
+    // Kept prescan renders are scaled to `dpi`, so masks, layers, PNG
+    // resolutions and reports can assume it for every page
+    let images = match (prescan, args.prescan_dpi) {
+        (Some(prescan), Some(prescan_dpi)) => {
+            lib::pdf::merge_prescan(prescan, images, render_options.pages.as_deref().unwrap_or_default(), dpi / prescan_dpi)
         }
+        _ => images,
     };
 
     if args.verbose {
-        println!("Generating diff images...");
+        say!("Generated {} image pairs", images.len());
+    }
+    if let Some(new_document) = &new_document {
+        let stretched = lib::pdf::stretched_pages(&old_document, new_document, &options);
+        warnings.extend(stretched.into_iter().map(|(page, message)| PageWarning { page, message }));
     }
 
-    let diff_images = match lib::image_utils::diff_images(images, args.sensitivity) {
-        Ok(images) => {
-            if args.verbose {
-                println!("Generated {} diff images", images.len());
+    // Before anything compares pixels, so every later stage sees proofed colors
+    let mut images = images;
+    if let Some(profile) = &args.icc_profile {
+        if args.verbose {
+            say!("Proofing pages through {}...", profile);
+        }
+        let proof = ColorProof::from_file(Path::new(profile), args.rendering_intent).map_err(|e| Failure::input(e.to_string()))?;
+        lib::color::proof_pages(&mut images, &proof).map_err(|e| format!("Error proofing pages: {}", e))?;
+    }
+
+    // A moved appendix would otherwise show as every page from it on changing.
+    // Only a full comparison of equally long documents can be a pure reordering.
+    let reordering = (args.pairing == PairingMode::Auto
+        && new_document.is_some()
+        && paired_by_position
+        && old_page_count == new_page_count
+        && options.pages.is_none())
+    .then(|| lib::reorder::detect_reordering(&images))
+    .flatten();
+    if let Some(order) = &reordering {
+        say!("{}", tr!("cli-pages-reordered", order = lib::reorder::describe_reordering(order)));
+        lib::reorder::reorder_old_pages(&mut images, order);
+        let pairing = lib::reorder::reordered_pairing(order);
+        // Old pages now sit at other positions
+        font_warnings = font_substitution_warnings(&old_document, new_document.as_ref(), &pairing);
+        options.pairing = Some(pairing);
+    }
+    let mut substituted_pages: Vec<usize> = font_warnings.iter().map(|warning| warning.page).collect();
+    substituted_pages.sort_unstable();
+    substituted_pages.dedup();
+    warnings.extend(font_warnings);
+
+    // Read from the files, so inks used at 0% or hidden under other content count too
+    if args.spot_colors && new_document.is_some() {
+        let pairing = options.pairing.as_deref().unwrap_or_default();
+        warnings.extend(spot_color_changes(comparison, pairing, &images)?);
+    }
+
+    // Before masking changes the renders. Reference images aren't rendered,
+    // so there is nothing to check without a new PDF.
+    if args.verify_deterministic
+        && let Some(new_document) = &new_document
+    {
+        if args.verbose {
+            say!("Rendering pages again to check they are deterministic...");
+        }
+        // Pages kept from the prescan were scaled to `dpi`, not rendered at it
+        let verify_options = RenderOptions { pages: render_options.pages.clone(), ..options.clone() };
+        let unstable = lib::pdf::nondeterministic_pages(&old_document, new_document, &verify_options, &images);
+        warnings.extend(unstable.into_iter().map(|(page, message)| PageWarning { page, message }));
+    }
+
+    let ignore_sections = ignore_sections(args)?;
+    if !ignore_sections.is_empty() {
+        let pairing = options.pairing.as_deref().unwrap_or_default();
+        warnings.extend(mask_ignored(&ignore_sections, comparison, &old_document, new_document.as_ref(), pairing, &mut images, dpi)?);
+    }
+    if args.compare != ContentLayer::All {
+        let pairing = options.pairing.as_deref().unwrap_or_default();
+        isolate_layers(args.compare, &old_document, new_document.as_ref(), pairing, &mut images, dpi)?;
+    }
+
+    #[cfg(feature = "plugins")]
+    let plugins = load_plugins(args)?;
+    #[cfg(feature = "plugins")]
+    for plugin in &plugins {
+        if args.verbose && !plugin.stages().is_empty() {
+            say!("Plugin {} handles stages {:?}", plugin.path(), plugin.stages());
+        }
+        plugin.process_pages(lib::plugin::Stage::Mask, &mut images)?;
+    }
+
+    // Cropped and turned renders no longer line up with the PDF's page
+    // coordinates, so their changes can't be matched with text or images
+    let contents = if args.crop_to_content || args.auto_orient {
+        vec![]
+    } else {
+        let pairing = options.pairing.as_deref().unwrap_or_default();
+        lib::classify::pair_contents(&old_document, new_document.as_ref(), pairing, &images)
+            .map_err(|e| format!("Error finding page content: {}", e))?
+    };
+
+    let scans = match args.scan_pipeline {
+        ScanMode::Auto => {
+            let pairing = options.pairing.as_deref().unwrap_or_default();
+            lib::scan::pair_scans(&old_document, new_document.as_ref(), pairing, &images)
+                .map_err(|e| format!("Error detecting scanned pages: {}", e))?
+        }
+        ScanMode::Always => vec![true; images.len()],
+        ScanMode::Never => vec![],
+    };
+
+    // The rest works on the renders alone, so other comparisons can use PDFium
+    drop(old_document);
+    drop(new_document);
+    drop(pdfium);
+
+    // Scanner feeders flip pages; a turned page shouldn't read as a full-page change
+    if args.auto_orient {
+        warnings.extend(lib::orient::auto_orient(&mut images));
+    }
+
+    warnings.extend(lib::report::blank_page_warnings(&images, args.blank_pages));
+    warnings.extend(lib::duplicates::duplicate_page_warnings(&images));
+
+    if args.blank_pages == BlankPolicy::Skip {
+        for (position, pair) in images.iter_mut().enumerate() {
+            if BlankPolicy::is_blank_only(pair.0.as_ref(), pair.1.as_ref()) {
+                *pair = (None, None);
+                warnings.push(PageWarning {
+                    page: position + 1,
+                    message: "Diff skipped; the page is blank in every PDF it is in".to_string(),
+                });
             }
-            images
-        },
-        Err(e) => {
-            eprintln!("Error diffing images: {}", e);
-            std::process::exit(1);
         }
+    }
+    if args.skip_duplicate_pages {
+        for (position, earlier) in lib::duplicates::redundant_pairs(&images) {
+            images[position] = (None, None);
+            warnings.push(PageWarning {
+                page: position + 1,
+                message: format!("Diff skipped; old and new pages are the same as on page {}", earlier + 1),
+            });
+        }
+    }
+
+    let preprocessing = Preprocessing {
+        normalization: args.normalize,
+        binarization: args.binarize,
+        algorithm: args.algorithm,
+    };
+    #[allow(unused_mut)]
+    // Scans get their own pipeline, so born-digital pages of the same document
+    // keep the configured preprocessing
+    let (mut images, scanned) = lib::scan::preprocess_with_scans(images, &preprocessing, &scans);
+    if args.verbose && !scanned.is_empty() {
+        say!("Compared as scans:");
+        for page in &scanned {
+            let (dx, dy) = page.offset;
+            say!("  page {}: new page moved by {}, {} pixels to line up", page.position + 1, dx, dy);
+        }
+    }
+    #[cfg(feature = "plugins")]
+    for plugin in &plugins {
+        plugin.process_pages(lib::plugin::Stage::Preprocess, &mut images)?;
+    }
+
+    let images = if args.crop_to_content {
+        if args.verbose {
+            say!("Cropping pages to content...");
+        }
+        let detection = ContentDetection {
+            white_tolerance: args.white_tolerance,
+            alpha_threshold: args.alpha_threshold,
+        };
+        crop_pages_to_content(images, &detection)
+    } else {
+        images
     };
 
-    if args.verbose {
-        println!("Saving images to '{}'...", args.output_dir);
+    let mut summary = ComparisonSummary::with_blank_policy(
+        &comparison.old_pdf,
+        &comparison.new_pdf,
+        &final_path(args, output_dir),
+        &images,
+        dpi,
+        args.blank_pages,
+    );
+    summary.warnings = warnings;
+    summary.sampling = sampling;
+    summary.interrupted = cancel.is_cancelled();
+    summary.pdfium = Some(describe_pdfium(&args.pdfium_path));
+    summary.page_severities = lib::severity::page_severities(&images, &args.severity_weights);
+    if !paired_by_position || reordering.is_some() {
+        summary.page_pairs = options.pairing.clone().unwrap_or_default();
+    }
+    summary.reordered = reordering.is_some();
+
+    if let Some(approvals_path) = &args.approvals {
+        let approvals_path = Path::new(approvals_path);
+        if args.approve_changes {
+            match approve_changes(approvals_path, &comparison.name, &images) {
+                Ok(count) => say!("Approved {} changed pages in {}", count, approvals_path.display()),
+                Err(e) => {
+                    return Err(format!("Error recording approvals: {}", e).into());
+                }
+            }
+        }
+
+        let approvals = load_approvals(approvals_path).map_err(|e| format!("Error reading approvals: {}", e))?;
+        let approved = approved_pages(&approvals, &comparison.name, &images);
+        if args.verbose && !approved.is_empty() {
+            say!("Ignoring approved differences on {} pages", approved.len());
+        }
+        summary.approve_pages(&approved);
+    }
+
+    Ok(ComparedPages {
+        images,
+        summary,
+        substituted_pages,
+        empty_document,
+        contents,
+        #[cfg(feature = "plugins")]
+        plugins,
+    })
+}
+
+fn run_comparison(
+    args: &DiffArgs,
+    pool: &PdfiumPool,
+    comparison: &Comparison,
+    output_dir: &str,
+    budget: Option<&OutputBudget>,
+    cancel: &CancelToken,
+) -> Result<ComparisonSummary, Failure> {
+    let run_started = SystemTime::now();
+    let started = Instant::now();
+    let ComparedPages {
+        mut images,
+        mut summary,
+        substituted_pages,
+        empty_document,
+        contents,
+        #[cfg(feature = "plugins")]
+        plugins,
+    } = compare_pages(args, pool, comparison, output_dir, cancel)?;
+    let render_seconds = started.elapsed().as_secs_f64();
+    let rendered = lib::benchmark::compared_pixels(&images);
+
+    // Pages outside the top changes are left out of everything written, like
+    // pages skipped by sampling; the summary still counts them
+    if let Some(count) = args.top_changes {
+        let top = summary.top_changes(&images, count);
+        say!("Top {} changed pages:", top.len());
+        for &index in &top {
+            let ratio = summary.page_ratios.get(index).copied().unwrap_or(0.0);
+            say!("  page {}: {:.2}% changed", index + 1, ratio * 100.0);
+        }
+
+        for (index, pair) in images.iter_mut().enumerate() {
+            if !top.contains(&index) {
+                *pair = (None, None);
+            }
+        }
     }
 
-    let pdf_title = path_old.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+    let pdf_title = Path::new(&comparison.old_pdf).file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+    let png = png_options(args, comparison.dpi.unwrap_or(args.dpi));
+
+    if args.verbose {
+        say!("Generating diff images and saving them to '{}'...", output_dir);
+    }
 
-    match save_images(diff_images, pdf_title, &args.output_dir) {
-        Ok(()) => {
+    // Diffing happens up front so per-page failures make it into the report
+    let started = Instant::now();
+    let sensitivity = comparison.sensitivity.unwrap_or(args.sensitivity);
+    let saved = lib::image_utils::diff_and_save_images(
+        &images,
+        sensitivity,
+        pdf_title,
+        output_dir,
+        SaveOptions {
+            output: diff_output(args),
+            layout: args.layout,
+            png,
+            strict: args.strict,
+            quiet: args.json,
+            budget,
+        },
+        Some(cancel),
+    );
+    match saved {
+        Ok(saved) => {
+            summary.warnings.extend(saved.failures.into_iter().map(|(page, message)| PageWarning { page, message }));
+            if let Some(budget) = budget {
+                summary.warnings.extend(saved.reduced.iter().map(|&page| PageWarning {
+                    page,
+                    message: format!("Diff images {} to stay within --max-output-size", budget.mode().describe()),
+                }));
+            }
+            // Pages without diff images are left out of the reports
+            for &index in &saved.cancelled {
+                images[index] = (None, None);
+            }
+            summary.interrupted |= !saved.cancelled.is_empty();
             if args.verbose {
-                println!("Successfully saved all diff images!");
+                say!("Saved {} diff images", saved.written);
             } else {
-                println!("Diff images saved to '{}'", args.output_dir);
+                say!("{}", tr!("cli-diff-images-saved", dir = output_dir));
             }
-        },
+        }
         Err(e) => {
-            eprintln!("Error saving images: {}", e);
-            std::process::exit(1);
+            return Err(format!("Error diffing images: {}", e).into());
         }
     }
-}
 
+    if summary.interrupted {
+        eprintln!("Interrupted; writing reports for the pages compared so far");
+    }
+
+    summary.warnings.sort_by_key(|warning| warning.page);
+    for warning in &summary.warnings {
+        eprintln!("{}", tr!("cli-page-warning", page = warning.page, message = warning.message));
+    }
+
+    let timings = Timings {
+        render_seconds,
+        diff_seconds: started.elapsed().as_secs_f64(),
+    };
+    let mut report = DiffReport::new(&images, &summary, pdf_title, Some((diff_output(args), args.layout)), timings);
+    if args.no_metadata {
+        report.timings = None;
+    } else {
+        report.metadata = Some(RunMetadata::new(run_started, SystemTime::now(), run_arguments()));
+    }
+    report.classify_regions(&images, &contents, comparison.dpi.unwrap_or(args.dpi));
+
+    if args.benchmark {
+        let (pages, pixels) = rendered;
+        say!("Rendering: {}", Throughput { pages, pixels, seconds: timings.render_seconds });
+        let (pages, pixels) = lib::benchmark::compared_pixels(&images);
+        say!("Diffing: {}", Throughput { pages, pixels, seconds: timings.diff_seconds });
+    }
+
+    if args.export_crops {
+        let crops_dir = Path::new(output_dir).join("crops");
+
+        if args.verbose {
+            say!("Exporting changed region crops to '{}'...", crops_dir.display());
+        }
+
+        match lib::regions::export_region_crops(&images, pdf_title, &crops_dir, png, overlay_style(args)) {
+            Ok(count) => {
+                if args.verbose {
+                    say!("Exported crops for {} changed regions", count);
+                }
+            },
+            Err(e) => {
+                return Err(format!("Error exporting region crops: {}", e).into());
+            }
+        }
+    }
+
+    if args.contact_sheet {
+        let sheet_path = Path::new(output_dir).join(format!("{}_contact_sheet.png", pdf_title));
+
+        if args.verbose {
+            say!("Creating contact sheet...");
+        }
+
+        // Thumbnails have no true size to record
+        let sheet = lib::contact_sheet::create_contact_sheet(&images);
+        let saved = std::fs::create_dir_all(output_dir)
+            .map_err(|e| e.into())
+            .and_then(|_| lib::image_utils::save_png_with(&sheet, &sheet_path, PngOptions { dpi: None, ..png }));
+
+        match saved {
+            Ok(()) => say!("{}", tr!("cli-contact-sheet-saved", path = sheet_path.display())),
+            Err(e) => {
+                return Err(format!("Error saving contact sheet: {}", e).into());
+            }
+        }
+    }
+
+    // The consolidated N-way report links to each comparison's report
+    if wants_report(args, ReportFormat::Html) || args.serve.is_some() || is_multi_comparison(args) {
+        if args.verbose {
+            say!("Writing HTML report...");
+        }
+
+        match lib::report::write_html_report(&images, &report, output_dir, png, budget, args.report_pages_per_file) {
+            Ok(path) => say!("{}", tr!("cli-report-saved", kind = "HTML", path = path.display())),
+            Err(e) => {
+                return Err(format!("Error writing HTML report: {}", e).into());
+            }
+        }
+    }
+
+    if wants_report(args, ReportFormat::Csv) {
+        match lib::report::write_csv_report(&report, output_dir) {
+            Ok(path) => say!("{}", tr!("cli-report-saved", kind = "CSV", path = path.display())),
+            Err(e) => {
+                return Err(format!("Error writing CSV report: {}", e).into());
+            }
+        }
+    }
+
+    if wants_report(args, ReportFormat::Json) {
+        match lib::report::write_json_report(&report, output_dir) {
+            Ok(path) => say!("{}", tr!("cli-report-saved", kind = "JSON", path = path.display())),
+            Err(e) => {
+                return Err(format!("Error writing JSON report: {}", e).into());
+            }
+        }
+    }
+
+    if wants_report(args, ReportFormat::Markdown) {
+        match lib::report::write_markdown_report(&report, output_dir) {
+            Ok(path) => say!("{}", tr!("cli-report-saved", kind = "Markdown", path = path.display())),
+            Err(e) => {
+                return Err(format!("Error writing Markdown report: {}", e).into());
+            }
+        }
+    }
+
+    if wants_report(args, ReportFormat::Pdf) {
+        if args.verbose {
+            say!("Writing PDF report...");
+        }
+
+        match lib::pdf_report::write_pdf_report(&images, &summary, pdf_title, output_dir, comparison.dpi.unwrap_or(args.dpi), output_scale(args), overlay_style(args)) {
+            Ok(path) => say!("{}", tr!("cli-report-saved", kind = "PDF", path = path.display())),
+            Err(e) => {
+                return Err(format!("Error writing PDF report: {}", e).into());
+            }
+        }
+    }
+
+    #[cfg(feature = "video")]
+    if let Some(mode) = args.video {
+        let video_dir = Path::new(output_dir).join("video");
+
+        if args.verbose {
+            say!("Encoding flip videos to '{}'...", video_dir.display());
+        }
+
+        match lib::video::write_flip_videos(&images, pdf_title, &video_dir, mode, args.video_format) {
+            Ok(paths) => {
+                for path in paths {
+                    say!("Saved flip video to {}", path.display());
+                }
+            },
+            Err(e) => {
+                return Err(format!("Error writing flip videos: {}", e).into());
+            }
+        }
+    }
+
+    // Only a lone comparison gets a window; a batch would open one per pair
+    #[cfg(feature = "gui")]
+    if args.preview
+        && !is_multi_comparison(args)
+        && !summary.interrupted
+        && let Err(message) = lib::gui::show_preview(&images, &summary, pdf_title, overlay_style(args))
+    {
+        eprintln!("Warning: {}", message);
+    }
+
+    // Batch runs print their aggregate instead, after every pair is done
+    if !is_multi_comparison(args) && !args.json {
+        print!("{}", lib::term_table::page_table(&images, &summary, lib::term_table::use_color(args.no_color)));
+    }
 
+    // Plugin checks see the finished outputs, and fail the comparison like
+    // font substitution does
+    #[cfg(feature = "plugins")]
+    for plugin in &plugins {
+        plugin.report(&summary).map_err(Failure::differences)?;
+    }
 
+    // Outputs are still written so the report shows which pages are affected
+    if let Some(message) = empty_document {
+        return Err(Failure::input(message));
+    }
+    if args.fail_on_font_substitution && !substituted_pages.is_empty() {
+        return Err(Failure::differences(format!(
+            "Font substitution on {} page(s) comparing {} with {}",
+            substituted_pages.len(),
+            comparison.old_pdf,
+            comparison.new_pdf
+        )));
+    }
+    if let Some(threshold) = args.fail_severity {
+        let severe = summary.pages_above_severity(threshold);
+        if !severe.is_empty() {
+            let pages: Vec<String> = severe.iter().map(|page| page.to_string()).collect();
+            return Err(Failure::differences(format!(
+                "Severity above {} on page(s) {} comparing {} with {}",
+                threshold,
+                pages.join(", "),
+                comparison.old_pdf,
+                comparison.new_pdf
+            )));
+        }
+    }
+    if args.fail_on_warning && !summary.warnings.is_empty() {
+        let mut pages: Vec<String> = summary.warnings.iter().map(|warning| warning.page.to_string()).collect();
+        pages.dedup();
+        return Err(Failure::differences(format!(
+            "{} warning(s) on page(s) {} comparing {} with {}",
+            summary.warnings.len(),
+            pages.join(", "),
+            comparison.old_pdf,
+            comparison.new_pdf
+        )));
+    }
 
+    Ok(summary)
+}